@@ -0,0 +1,329 @@
+//! Offline artifact bundles for air-gapped installations.
+
+use crate::{
+    cache::stage,
+    checksum::sha256_file,
+    emoji,
+    error::Error,
+    host_triple::HostTriple,
+    lockfile::{LockedArtifact, Lockfile},
+    mirror::{download_verified, release_artifacts},
+    proxy::client,
+    targets::Target,
+    toolchain::gcc::{ESP32S2_GCC, ESP32S3_GCC, ESP32_GCC, RISCV_GCC},
+};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs::{read_to_string, write, File},
+    path::Path,
+};
+use tar::{Archive, Builder};
+use tempfile::TempDir;
+
+/// Name of the manifest describing the contents of a bundle archive.
+pub const BUNDLE_MANIFEST: &str = "bundle.json";
+/// Version of the bundle format written by this version of espup.
+const BUNDLE_FORMAT: u32 = 1;
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct BundleManifest {
+    /// Version of the bundle format.
+    pub format: u32,
+    /// Xtensa Rust toolchain version of the bundled artifacts.
+    pub toolchain_version: String,
+    /// Host triples the artifacts were bundled for.
+    pub hosts: Vec<String>,
+    /// Targets the artifacts were bundled for.
+    pub targets: Vec<String>,
+    /// Bundled artifacts.
+    pub artifacts: Vec<LockedArtifact>,
+}
+
+impl BundleManifest {
+    /// Returns the lockfile describing the bundled artifacts.
+    pub fn lockfile(&self) -> Lockfile {
+        Lockfile {
+            artifacts: self.artifacts.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Checks that the bundle provides the artifacts of an installation.
+    pub fn check(
+        &self,
+        bundle: &Path,
+        host_triple: &HostTriple,
+        targets: &HashSet<Target>,
+        toolchain_version: &str,
+    ) -> Result<(), Error> {
+        let mismatch = |what: String| Error::BundleMismatch(bundle.display().to_string(), what);
+        if self.toolchain_version != toolchain_version {
+            return Err(mismatch(format!("Xtensa Rust {toolchain_version}")));
+        }
+        if !self.hosts.contains(&host_triple.to_string()) {
+            return Err(mismatch(format!("host '{host_triple}'")));
+        }
+        if let Some(target) = targets
+            .iter()
+            .find(|target| !self.targets.contains(&target.to_string()))
+        {
+            return Err(mismatch(format!("target '{target}'")));
+        }
+        Ok(())
+    }
+}
+
+/// Returns true if the artifact of a component is required to install any of the targets.
+fn is_required(name: &str, targets: &HashSet<Target>) -> bool {
+    match name {
+        "Xtensa Rust" => targets.iter().any(|t| t.is_xtensa()),
+        ESP32_GCC => targets.contains(&Target::ESP32),
+        ESP32S2_GCC => targets.contains(&Target::ESP32S2),
+        ESP32S3_GCC => targets.contains(&Target::ESP32S3),
+        // ESP32S2 and ESP32S3 also install the RISC-V toolchain for their ULP coprocessor
+        RISCV_GCC => targets.iter().any(|t| t != &Target::ESP32),
+        _ => true,
+    }
+}
+
+/// Downloads every artifact required to install a version for the given hosts and targets into a
+/// single bundle archive.
+pub async fn create(
+    output: &Path,
+    toolchain_version: &str,
+    host_triples: &[HostTriple],
+    targets: &HashSet<Target>,
+) -> Result<BundleManifest, Error> {
+    let staging_dir = stage()?;
+    let client = client()?;
+    let mut manifest = BundleManifest {
+        format: BUNDLE_FORMAT,
+        toolchain_version: toolchain_version.to_string(),
+        hosts: host_triples.iter().map(|h| h.to_string()).collect(),
+        targets: targets.iter().map(|t| t.to_string()).collect(),
+        artifacts: Vec::new(),
+    };
+    manifest.targets.sort();
+    for artifact in release_artifacts(toolchain_version, host_triples)? {
+        if !is_required(&artifact.name, targets) {
+            continue;
+        }
+        // Only the artifacts matching their published checksums are trusted by the bundle
+        let (bytes, sha256) = download_verified(&client, &artifact).await?;
+        let file_name = artifact.file_name();
+        write(staging_dir.path().join(&file_name), &bytes)?;
+        manifest.artifacts.push(LockedArtifact {
+            name: artifact.name,
+            url: artifact.url,
+            file_name,
+            sha256,
+        });
+    }
+    pack(staging_dir.path(), &manifest, output)?;
+    Ok(manifest)
+}
+
+/// Writes the manifest and the artifacts staged in a directory to a bundle archive.
+fn pack(dir: &Path, manifest: &BundleManifest, output: &Path) -> Result<(), Error> {
+    let contents = serde_json::to_string_pretty(manifest).map_err(|_| Error::SerializeJson)?;
+    write(dir.join(BUNDLE_MANIFEST), contents)?;
+    let mut builder = Builder::new(File::create(output)?);
+    builder.append_path_with_name(dir.join(BUNDLE_MANIFEST), BUNDLE_MANIFEST)?;
+    for artifact in &manifest.artifacts {
+        builder.append_path_with_name(dir.join(&artifact.file_name), &artifact.file_name)?;
+    }
+    builder.into_inner()?.sync_all()?;
+    Ok(())
+}
+
+/// Extracts a bundle archive to the staging directory and verifies its artifacts against its
+/// manifest.
+pub fn extract(bundle: &Path) -> Result<(TempDir, BundleManifest), Error> {
+    info!("{} Extracting bundle '{}'", emoji::WRENCH, bundle.display());
+    let invalid = || Error::InvalidBundle(bundle.display().to_string());
+    let dir = stage()?;
+    let file = File::open(bundle).map_err(|_| invalid())?;
+    Archive::new(file)
+        .unpack(dir.path())
+        .map_err(|_| invalid())?;
+    let contents = read_to_string(dir.path().join(BUNDLE_MANIFEST)).map_err(|_| invalid())?;
+    let manifest: BundleManifest = serde_json::from_str(&contents).map_err(|_| invalid())?;
+    if manifest.format > BUNDLE_FORMAT {
+        return Err(invalid());
+    }
+    let report = verify(dir.path(), &manifest.lockfile())?;
+    if !report.is_complete() {
+        return Err(Error::IncompleteBundle(
+            bundle.display().to_string(),
+            report.missing.len(),
+            report.mismatched.len(),
+        ));
+    }
+    Ok((dir, manifest))
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct BundleReport {
+    /// Artifacts found with the expected digest.
+    pub verified: Vec<String>,
+    /// Artifacts missing from the bundle.
+    pub missing: Vec<String>,
+    /// Artifacts whose digest does not match the lockfile.
+    pub mismatched: Vec<String>,
+}
+
+impl BundleReport {
+    /// Returns true if every artifact of the lockfile is present with the expected digest.
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty() && self.mismatched.is_empty()
+    }
+}
+
+/// Verifies that a bundle directory contains every artifact required by a lockfile.
+pub fn verify(bundle_dir: &Path, lockfile: &Lockfile) -> Result<BundleReport, Error> {
+    if !bundle_dir.is_dir() {
+        return Err(Error::InvalidBundle(bundle_dir.display().to_string()));
+    }
+
+    let mut report = BundleReport::default();
+    for artifact in &lockfile.artifacts {
+        let path = bundle_dir.join(&artifact.file_name);
+        if !path.is_file() {
+            warn!(
+                "{} Artifact '{}' of '{}' is missing",
+                emoji::WARN,
+                artifact.file_name,
+                artifact.name
+            );
+            report.missing.push(artifact.file_name.clone());
+            continue;
+        }
+        let digest = sha256_file(&path)?;
+        if digest.eq_ignore_ascii_case(&artifact.sha256) {
+            debug!(
+                "{} Artifact '{}' verified",
+                emoji::DEBUG,
+                artifact.file_name
+            );
+            report.verified.push(artifact.file_name.clone());
+        } else {
+            warn!(
+                "{} Artifact '{}' of '{}' has digest '{}', expected '{}'",
+                emoji::WARN,
+                artifact.file_name,
+                artifact.name,
+                digest,
+                artifact.sha256
+            );
+            report.mismatched.push(artifact.file_name.clone());
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        bundle::{extract, is_required, pack, verify, BundleManifest, BUNDLE_FORMAT},
+        checksum::sha256,
+        host_triple::HostTriple,
+        lockfile::{LockedArtifact, Lockfile},
+        targets::{parse_targets, Target},
+    };
+    use std::collections::HashSet;
+
+    fn artifact(file_name: &str, sha256: String) -> LockedArtifact {
+        LockedArtifact {
+            name: file_name.to_string(),
+            url: format!("https://example.com/{file_name}"),
+            file_name: file_name.to_string(),
+            sha256,
+        }
+    }
+
+    #[test]
+    fn test_verify_bundle() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("ok.tar.xz"), "ok").unwrap();
+        std::fs::write(temp_dir.path().join("bad.tar.xz"), "bad").unwrap();
+        let lockfile = Lockfile {
+            artifacts: vec![
+                artifact("ok.tar.xz", sha256(b"ok")),
+                artifact("bad.tar.xz", sha256(b"good")),
+                artifact("missing.tar.xz", sha256(b"missing")),
+            ],
+            ..Default::default()
+        };
+
+        let report = verify(temp_dir.path(), &lockfile).unwrap();
+        assert_eq!(report.verified, vec!["ok.tar.xz"]);
+        assert_eq!(report.mismatched, vec!["bad.tar.xz"]);
+        assert_eq!(report.missing, vec!["missing.tar.xz"]);
+        assert!(!report.is_complete());
+
+        assert!(verify(&temp_dir.path().join("missing"), &lockfile).is_err());
+    }
+
+    #[test]
+    fn test_bundle_pack_and_extract() {
+        let targets = HashSet::from([Target::ESP32, Target::ESP32C3]);
+        assert!(is_required("Xtensa Rust", &targets));
+        assert!(is_required("LLVM", &targets));
+        assert!(is_required("xtensa-esp32-elf", &targets));
+        assert!(!is_required("xtensa-esp32s3-elf", &targets));
+        assert!(is_required("riscv32-esp-elf", &targets));
+        assert!(!is_required(
+            "riscv32-esp-elf",
+            &HashSet::from([Target::ESP32])
+        ));
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let staging_dir = temp_dir.path().join("staging");
+        std::fs::create_dir(&staging_dir).unwrap();
+        std::fs::write(staging_dir.join("llvm.tar.xz"), "llvm").unwrap();
+        let manifest = BundleManifest {
+            format: BUNDLE_FORMAT,
+            toolchain_version: "1.73.0.1".to_string(),
+            hosts: vec!["x86_64-unknown-linux-gnu".to_string()],
+            targets: vec!["esp32".to_string(), "esp32c3".to_string()],
+            artifacts: vec![artifact("llvm.tar.xz", sha256(b"llvm"))],
+        };
+        let bundle = temp_dir.path().join("bundle.tar");
+        pack(&staging_dir, &manifest, &bundle).unwrap();
+
+        let (dir, extracted) = extract(&bundle).unwrap();
+        assert_eq!(extracted, manifest);
+        assert!(dir.path().join("llvm.tar.xz").is_file());
+        let host_triple = HostTriple::X86_64UnknownLinuxGnu;
+        assert!(manifest
+            .check(&bundle, &host_triple, &targets, "1.73.0.1")
+            .is_ok());
+        assert!(manifest
+            .check(&bundle, &host_triple, &targets, "1.74.0.0")
+            .is_err());
+        assert!(manifest
+            .check(
+                &bundle,
+                &host_triple,
+                &parse_targets("all").unwrap(),
+                "1.73.0.1"
+            )
+            .is_err());
+        assert!(manifest
+            .check(
+                &bundle,
+                &HostTriple::Aarch64AppleDarwin,
+                &targets,
+                "1.73.0.1"
+            )
+            .is_err());
+
+        let mut corrupted = manifest.clone();
+        corrupted.artifacts[0].sha256 = sha256(b"other");
+        pack(&staging_dir, &corrupted, &bundle).unwrap();
+        assert!(extract(&bundle).is_err());
+    }
+}
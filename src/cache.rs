@@ -0,0 +1,699 @@
+//! Download cache, the index of its artifacts and its eviction policy.
+
+use crate::{
+    checksum::{sha256, sha256_file},
+    emoji,
+    error::Error,
+    home,
+};
+use fs4::FileExt;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    fs::{create_dir_all, read, read_dir, read_to_string, remove_file, File, Metadata},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+use tempfile::{NamedTempFile, TempDir};
+
+/// Name of the staging directory, inside the cache directory.
+const STAGING_DIR: &str = "tmp";
+/// Environment variable overriding the staging directory, also read by `--temp-dir`.
+pub const TEMP_DIR_VAR: &str = "ESPUP_TEMP_DIR";
+
+/// Number of hex digits of the URL digest prefixed to the cached artifacts.
+const URL_DIGEST_LEN: usize = 16;
+/// Name of the index of the cached artifacts, inside the cache directory.
+pub const INDEX_FILE: &str = "index.json";
+/// Version of the index format, an index of another version is rebuilt.
+const INDEX_VERSION: u32 = 1;
+/// Name of the file locking the index against the other espup processes sharing the cache,
+/// inside the cache directory.
+const INDEX_LOCK_FILE: &str = ".index.lock";
+/// Resolution of the last use of the cached artifacts, so reading them does not rewrite the index
+/// every time.
+const LAST_USED_RESOLUTION: u64 = 60 * 60;
+
+static STAGING_DIR_OVERRIDE: Mutex<Option<PathBuf>> = Mutex::new(None);
+static DOWNLOAD_CACHE: AtomicBool = AtomicBool::new(true);
+/// Serializes the updates of the index by the components installed in parallel, the lock file
+/// serializes them across processes.
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EvictionPolicy {
+    /// Evict the least recently used artifacts first.
+    #[default]
+    Lru,
+    /// Never evict artifacts.
+    None,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct CacheConfig {
+    /// Maximum size of the cache, in MiB.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_size_mb: Option<u64>,
+    /// Eviction policy applied when the cache exceeds its maximum size.
+    #[serde(default)]
+    pub eviction: EvictionPolicy,
+    /// Versions whose artifacts are never evicted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pinned: Vec<String>,
+    /// Directory where artifacts are staged before being installed, instead of `<cache>/tmp`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub staging_dir: Option<PathBuf>,
+}
+
+impl CacheConfig {
+    /// Returns the maximum size of the cache, in bytes.
+    pub fn max_size(&self) -> Option<u64> {
+        self.max_size_mb.map(|mb| mb * 1024 * 1024)
+    }
+
+    /// Returns true if the artifact belongs to a pinned version.
+    pub fn is_pinned(&self, name: &str) -> bool {
+        self.pinned.iter().any(|version| name.contains(version))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    /// Path of the cached artifact.
+    pub path: PathBuf,
+    /// Size of the artifact, in bytes.
+    pub size: u64,
+    /// Last time the artifact was used.
+    pub last_used: SystemTime,
+    /// Release version of the artifact, `None` if it is not known.
+    pub version: Option<String>,
+}
+
+impl CacheEntry {
+    /// Returns the file name of the cached artifact.
+    pub fn name(&self) -> String {
+        file_name(&self.path)
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// File name of the cached artifact, inside the cache directory.
+    pub file: String,
+    /// URL the artifact was downloaded from, `None` for the artifacts cached before the index.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Release version of the artifact, e.g. the tag of its GitHub release.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// SHA256 digest of the artifact, `None` for the artifacts cached before the index.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Size of the artifact, in bytes.
+    pub size: u64,
+    /// Modification time of the artifact when it was hashed, in nanoseconds since the Unix
+    /// epoch, so it is only hashed again once modified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+    /// Last time the artifact was used, in seconds since the Unix epoch.
+    pub last_used: u64,
+}
+
+impl IndexEntry {
+    /// Creates the entry of an artifact downloaded from a URL.
+    fn new(file: String, url: &str, sha256: String, size: u64, modified: u64) -> Self {
+        Self {
+            file,
+            url: Some(url.to_string()),
+            version: release_version(url),
+            sha256: Some(sha256),
+            size,
+            modified: Some(modified),
+            last_used: unix_secs(SystemTime::now()),
+        }
+    }
+
+    /// Returns the indexed digest of an artifact, if its size and modification time show it did
+    /// not change since it was hashed.
+    fn digest_of(&self, size: u64, modified: u64) -> Option<&str> {
+        (self.size == size && self.modified == Some(modified))
+            .then_some(self.sha256.as_deref())
+            .flatten()
+    }
+}
+
+/// Index of the cached artifacts, so the cache is looked up, reported and evicted without
+/// scanning and hashing the cache directory.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct CacheIndex {
+    /// Version of the index format.
+    pub version: u32,
+    /// Cached artifacts.
+    pub entries: Vec<IndexEntry>,
+}
+
+impl CacheIndex {
+    /// Returns the path of the index of a cache directory.
+    pub fn path(cache_dir: &Path) -> PathBuf {
+        cache_dir.join(INDEX_FILE)
+    }
+
+    /// Loads the index of a cache directory, leaving out the artifacts removed since.
+    ///
+    /// A missing or unreadable index, e.g. of a cache filled by an older espup version, is
+    /// rebuilt from the cache directory.
+    pub fn load(cache_dir: &Path) -> Result<Self, Error> {
+        let index = read_to_string(Self::path(cache_dir))
+            .ok()
+            .and_then(|contents| serde_json::from_str::<Self>(&contents).ok())
+            .filter(|index| index.version == INDEX_VERSION);
+        let Some(mut index) = index else {
+            debug!(
+                "{} Rebuilding the index of '{}'",
+                emoji::DEBUG,
+                cache_dir.display()
+            );
+            return Self::rebuild(cache_dir);
+        };
+        index
+            .entries
+            .retain(|entry| cache_dir.join(&entry.file).is_file());
+        Ok(index)
+    }
+
+    /// Builds the index from the artifacts stored in a cache directory.
+    fn rebuild(cache_dir: &Path) -> Result<Self, Error> {
+        let mut index = Self {
+            version: INDEX_VERSION,
+            entries: Vec::new(),
+        };
+        if !cache_dir.exists() {
+            return Ok(index);
+        }
+        for entry in read_dir(cache_dir)? {
+            let entry = entry?;
+            let file = entry.file_name().to_string_lossy().to_string();
+            let metadata = entry.metadata()?;
+            // Temporary files of the downloads in progress are indexed once persisted
+            if !metadata.is_file() || file.starts_with('.') || file == INDEX_FILE {
+                continue;
+            }
+            let last_used = metadata
+                .accessed()
+                .or_else(|_| metadata.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            index.entries.push(IndexEntry {
+                file,
+                size: metadata.len(),
+                last_used: unix_secs(last_used),
+                ..Default::default()
+            });
+        }
+        Ok(index)
+    }
+
+    /// Saves the index in a cache directory.
+    ///
+    /// The index is written to a temporary file first, so concurrent runs never read a
+    /// partially written index.
+    pub fn save(&self, cache_dir: &Path) -> Result<(), Error> {
+        let contents = serde_json::to_string_pretty(self).map_err(|_| Error::SerializeJson)?;
+        let mut file = cache_file(cache_dir)?;
+        file.write_all(contents.as_bytes())?;
+        file.persist(Self::path(cache_dir)).map_err(|e| e.error)?;
+        Ok(())
+    }
+
+    /// Returns the entry of a cached artifact, by its file name.
+    pub fn find(&self, file: &str) -> Option<&IndexEntry> {
+        self.entries.iter().find(|entry| entry.file == file)
+    }
+
+    /// Adds or replaces the entry of a cached artifact.
+    pub fn insert(&mut self, entry: IndexEntry) {
+        self.remove(&entry.file);
+        self.entries.push(entry);
+    }
+
+    /// Removes the entry of a cached artifact.
+    pub fn remove(&mut self, file: &str) {
+        self.entries.retain(|entry| entry.file != file);
+    }
+
+    /// Records the use of a cached artifact, with the digest of its contents.
+    ///
+    /// Returns false if the digest does not match the indexed one, i.e. the artifact is corrupted.
+    fn record_use(
+        &mut self,
+        file: &str,
+        url: &str,
+        digest: String,
+        size: u64,
+        modified: u64,
+    ) -> bool {
+        let Some(entry) = self.entries.iter_mut().find(|entry| entry.file == file) else {
+            self.insert(IndexEntry::new(
+                file.to_string(),
+                url,
+                digest,
+                size,
+                modified,
+            ));
+            return true;
+        };
+        if entry
+            .sha256
+            .as_ref()
+            .is_some_and(|sha256| *sha256 != digest)
+        {
+            return false;
+        }
+        entry.url.get_or_insert_with(|| url.to_string());
+        entry.version = entry.version.take().or_else(|| release_version(url));
+        entry.sha256 = Some(digest);
+        entry.size = size;
+        entry.modified = Some(modified);
+        let now = unix_secs(SystemTime::now());
+        if now.saturating_sub(entry.last_used) >= LAST_USED_RESOLUTION {
+            entry.last_used = now;
+        }
+        true
+    }
+}
+
+/// Updates the index of a cache directory, which is only written if it changed.
+///
+/// The index is locked for the whole update, so the updates of concurrent espup processes are
+/// not lost.
+fn update_index<T>(cache_dir: &Path, f: impl FnOnce(&mut CacheIndex) -> T) -> Result<T, Error> {
+    let _guard = INDEX_LOCK.lock().unwrap();
+    create_dir_all(cache_dir)
+        .map_err(|_| Error::CreateDirectory(cache_dir.display().to_string()))?;
+    // Released when the file is closed
+    let lock = File::create(cache_dir.join(INDEX_LOCK_FILE))?;
+    lock.lock_exclusive()?;
+    let mut index = CacheIndex::load(cache_dir)?;
+    let loaded = index.clone();
+    let result = f(&mut index);
+    if index != loaded {
+        index.save(cache_dir)?;
+    }
+    Ok(result)
+}
+
+/// Returns the release version of an artifact, from the tag of its GitHub release URL.
+fn release_version(url: &str) -> Option<String> {
+    let mut segments = url.split('/');
+    segments.find(|segment| *segment == "download")?;
+    segments
+        .next()
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+}
+
+/// Returns a time in seconds since the Unix epoch.
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// Returns the modification time of a file in nanoseconds since the Unix epoch, 0 if unknown.
+fn modified_nanos(metadata: &Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_nanos() as u64)
+}
+
+/// Returns the file name of a cached artifact.
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Returns the default cache directory.
+pub fn get_cache_dir() -> PathBuf {
+    home::cache_dir().join("espup")
+}
+
+/// Sets the directory where artifacts are staged, `None` restores the default one.
+pub fn set_staging_dir(staging_dir: Option<PathBuf>) {
+    *STAGING_DIR_OVERRIDE.lock().unwrap() = staging_dir;
+}
+
+/// Returns the directory where artifacts are staged with the given configuration, unless
+/// `ESPUP_TEMP_DIR` overrides it.
+pub fn configured_staging_dir(config: &CacheConfig) -> PathBuf {
+    env::var_os(TEMP_DIR_VAR)
+        .map(PathBuf::from)
+        .or_else(|| config.staging_dir.clone())
+        .unwrap_or_else(|| get_cache_dir().join(STAGING_DIR))
+}
+
+/// Returns the directory where artifacts are staged, creating it if needed.
+///
+/// Defaults to `<cache>/tmp` rather than the system temporary directory, which is often a small
+/// tmpfs or mounted as `noexec`.
+pub fn get_staging_dir() -> Result<PathBuf, Error> {
+    let staging_dir = STAGING_DIR_OVERRIDE
+        .lock()
+        .unwrap()
+        .clone()
+        .or_else(|| env::var_os(TEMP_DIR_VAR).map(PathBuf::from))
+        .unwrap_or_else(|| get_cache_dir().join(STAGING_DIR));
+    create_dir_all(&staging_dir)
+        .map_err(|_| Error::CreateDirectory(staging_dir.display().to_string()))?;
+    Ok(staging_dir)
+}
+
+/// Creates a temporary directory in the staging directory, removed when dropped.
+pub fn stage() -> Result<TempDir, Error> {
+    stage_in(&get_staging_dir()?)
+}
+
+/// Creates a temporary directory in a staging directory, removed when dropped.
+fn stage_in(staging_dir: &Path) -> Result<TempDir, Error> {
+    debug!(
+        "{} Staging artifacts in '{}'",
+        emoji::DEBUG,
+        staging_dir.display()
+    );
+    Ok(TempDir::new_in(staging_dir)?)
+}
+
+/// Enables or disables reading and storing the downloaded artifacts in the cache.
+pub fn set_download_cache(enabled: bool) {
+    DOWNLOAD_CACHE.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns true if the downloaded artifacts are read from and stored in the cache.
+pub fn is_download_cache_enabled() -> bool {
+    DOWNLOAD_CACHE.load(Ordering::Relaxed)
+}
+
+/// Returns the path of the cached copy of an artifact.
+///
+/// The file name of the artifact is prefixed with a digest of its URL, so artifacts with the
+/// same name from different releases or mirrors do not collide.
+pub fn cached_artifact_path(cache_dir: &Path, url: &str) -> PathBuf {
+    let file_name = url.rsplit('/').next().unwrap_or(url);
+    let digest = sha256(url.as_bytes());
+    cache_dir.join(format!("{}-{file_name}", &digest[..URL_DIGEST_LEN]))
+}
+
+/// Reads the cached copy of an artifact, returning `None` if it is not cached.
+///
+/// A copy that does not match the digest recorded in the index is discarded. The cache is keyed by
+/// URL, so a copy that does not match the expected digest, when it is known, e.g. of an artifact
+/// republished under the same URL, is not returned either and gets replaced by the next download.
+///
+/// The copy is only hashed if it was modified since it was indexed.
+pub fn read_cached(
+    cache_dir: &Path,
+    url: &str,
+    expected: Option<&str>,
+) -> Result<Option<Vec<u8>>, Error> {
+    let path = cached_artifact_path(cache_dir, url);
+    let Some(metadata) = path.metadata().ok().filter(Metadata::is_file) else {
+        return Ok(None);
+    };
+    debug!("{} Reading cached '{}'", emoji::DEBUG, path.display());
+    let bytes = read(&path)?;
+    let file = file_name(&path);
+    let size = bytes.len() as u64;
+    let modified = modified_nanos(&metadata);
+    let indexed = CacheIndex::load(cache_dir)?
+        .find(&file)
+        .and_then(|entry| entry.digest_of(size, modified))
+        .map(str::to_string);
+    let digest = indexed.unwrap_or_else(|| sha256(&bytes));
+    if expected.is_some_and(|expected| !expected.eq_ignore_ascii_case(&digest)) {
+        debug!(
+            "{} Cached '{}' does not match the expected digest",
+            emoji::DEBUG,
+            path.display()
+        );
+        return Ok(None);
+    }
+    match update_index(cache_dir, |index| {
+        let intact = index.record_use(&file, url, digest, size, modified);
+        if !intact {
+            index.remove(&file);
+        }
+        intact
+    }) {
+        Ok(true) => {}
+        Ok(false) => {
+            debug!(
+                "{} Discarding corrupted cached '{}'",
+                emoji::DEBUG,
+                path.display()
+            );
+            remove_file(&path)?;
+            return Ok(None);
+        }
+        // The cached copy is still usable without its index entry
+        Err(e) => debug!("{} Failed to update the cache index: {}", emoji::DEBUG, e),
+    }
+    Ok(Some(bytes))
+}
+
+/// Stores a copy of an artifact in the cache.
+///
+/// The artifact is written to a temporary file first, so concurrent installations never read a
+/// partially written artifact.
+pub fn store_cached(cache_dir: &Path, url: &str, bytes: &[u8]) -> Result<(), Error> {
+    let mut file = cache_file(cache_dir)?;
+    file.write_all(bytes)?;
+    persist_cached(file, cache_dir, url)
+}
+
+/// Creates a temporary file in the cache, to write an artifact into while it is downloaded.
+pub fn cache_file(cache_dir: &Path) -> Result<NamedTempFile, Error> {
+    create_dir_all(cache_dir)
+        .map_err(|_| Error::CreateDirectory(cache_dir.display().to_string()))?;
+    Ok(NamedTempFile::new_in(cache_dir)?)
+}
+
+/// Stores a fully written temporary file as the cached copy of an artifact, and records it in
+/// the index.
+pub fn persist_cached(file: NamedTempFile, cache_dir: &Path, url: &str) -> Result<(), Error> {
+    let path = cached_artifact_path(cache_dir, url);
+    file.persist(&path).map_err(|e| e.error)?;
+    let metadata = path.metadata()?;
+    let entry = IndexEntry::new(
+        file_name(&path),
+        url,
+        sha256_file(&path)?,
+        metadata.len(),
+        modified_nanos(&metadata),
+    );
+    update_index(cache_dir, |index| index.insert(entry))
+}
+
+/// Lists the artifacts stored in a cache directory, least recently used first.
+pub fn entries(cache_dir: &Path) -> Result<Vec<CacheEntry>, Error> {
+    let mut entries: Vec<CacheEntry> = CacheIndex::load(cache_dir)?
+        .entries
+        .into_iter()
+        .map(|entry| CacheEntry {
+            path: cache_dir.join(&entry.file),
+            size: entry.size,
+            last_used: SystemTime::UNIX_EPOCH + Duration::from_secs(entry.last_used),
+            version: entry.version,
+        })
+        .collect();
+    entries.sort_by_key(|entry| entry.last_used);
+    Ok(entries)
+}
+
+/// Returns the total size, in bytes, of the cache entries.
+pub fn usage(entries: &[CacheEntry]) -> u64 {
+    entries.iter().map(|entry| entry.size).sum()
+}
+
+/// Selects the entries to evict so the cache fits within its maximum size.
+fn select_evictions<'a>(entries: &'a [CacheEntry], config: &CacheConfig) -> Vec<&'a CacheEntry> {
+    let mut evictions = Vec::new();
+    let Some(max_size) = config.max_size() else {
+        return evictions;
+    };
+    if config.eviction == EvictionPolicy::None {
+        return evictions;
+    }
+    let mut size = usage(entries);
+    let mut candidates: Vec<&CacheEntry> = entries
+        .iter()
+        .filter(|entry| !config.is_pinned(&entry.name()))
+        .collect();
+    candidates.sort_by_key(|entry| entry.last_used);
+    for entry in candidates {
+        if size <= max_size {
+            break;
+        }
+        size -= entry.size;
+        evictions.push(entry);
+    }
+    evictions
+}
+
+/// Evicts artifacts from the cache according to the configured policy.
+pub fn enforce_limits(cache_dir: &Path, config: &CacheConfig) -> Result<Vec<PathBuf>, Error> {
+    let entries = entries(cache_dir)?;
+    let mut evicted = Vec::new();
+    for entry in select_evictions(&entries, config) {
+        info!(
+            "{} Evicting '{}' from the cache",
+            emoji::WRENCH,
+            entry.path.display()
+        );
+        remove_file(&entry.path)?;
+        evicted.push(entry.path.clone());
+    }
+    if !evicted.is_empty() {
+        update_index(cache_dir, |index| {
+            for path in &evicted {
+                index.remove(&file_name(path));
+            }
+        })?;
+    }
+    debug!("{} Evicted {} artifacts", emoji::DEBUG, evicted.len());
+    Ok(evicted)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cache::{
+            cached_artifact_path, entries, read_cached, select_evictions, stage_in, store_cached,
+            CacheConfig, CacheEntry, CacheIndex, EvictionPolicy, INDEX_FILE,
+        },
+        checksum::sha256,
+    };
+    use std::{
+        fs::{create_dir_all, remove_file, write},
+        path::PathBuf,
+        time::{Duration, SystemTime},
+    };
+
+    fn entry(name: &str, size_mb: u64, age: u64) -> CacheEntry {
+        CacheEntry {
+            path: PathBuf::from(name),
+            size: size_mb * 1024 * 1024,
+            last_used: SystemTime::UNIX_EPOCH + Duration::from_secs(1000 - age),
+            version: None,
+        }
+    }
+
+    #[test]
+    fn test_select_evictions() {
+        let entries = vec![
+            entry("rust-1.72.0.0.tar.xz", 3, 10),
+            entry("rust-1.73.0.0.tar.xz", 3, 5),
+            entry("llvm-esp-16.tar.xz", 3, 1),
+        ];
+        let mut config = CacheConfig::default();
+        assert!(select_evictions(&entries, &config).is_empty());
+
+        config.max_size_mb = Some(6);
+        let evictions = select_evictions(&entries, &config);
+        assert_eq!(evictions.len(), 1);
+        assert_eq!(evictions[0].name(), "rust-1.72.0.0.tar.xz");
+
+        config.pinned = vec!["1.72.0.0".to_string()];
+        let evictions = select_evictions(&entries, &config);
+        assert_eq!(evictions.len(), 1);
+        assert_eq!(evictions[0].name(), "rust-1.73.0.0.tar.xz");
+
+        config.eviction = EvictionPolicy::None;
+        assert!(select_evictions(&entries, &config).is_empty());
+    }
+
+    #[test]
+    fn test_stage() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let staging_dir = temp_dir.path().join("staging");
+        create_dir_all(&staging_dir).unwrap();
+        let staged = stage_in(&staging_dir).unwrap();
+        assert!(staged.path().starts_with(&staging_dir));
+        let staged_path = staged.path().to_path_buf();
+        drop(staged);
+        assert!(!staged_path.exists());
+    }
+
+    #[test]
+    fn test_cached_artifacts() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let cache_dir = temp_dir.path().join("cache");
+        let url = "https://github.com/esp-rs/rust-build/releases/download/v1.73.0.1/rust-src-1.73.0.1.tar.xz";
+        let mirrored_url = "https://mirror.example.com/esp-rs/rust-build/releases/download/v1.73.0.1/rust-src-1.73.0.1.tar.xz";
+
+        let path = cached_artifact_path(&cache_dir, url);
+        assert!(path.starts_with(&cache_dir));
+        assert!(path
+            .to_str()
+            .unwrap()
+            .ends_with("-rust-src-1.73.0.1.tar.xz"));
+        assert_ne!(path, cached_artifact_path(&cache_dir, mirrored_url));
+
+        assert_eq!(read_cached(&cache_dir, url, None).unwrap(), None);
+        store_cached(&cache_dir, url, b"rust-src").unwrap();
+        assert_eq!(
+            read_cached(&cache_dir, url, None).unwrap(),
+            Some(b"rust-src".to_vec())
+        );
+        assert_eq!(
+            read_cached(&cache_dir, url, Some(&sha256(b"rust-src"))).unwrap(),
+            Some(b"rust-src".to_vec())
+        );
+        assert_eq!(read_cached(&cache_dir, mirrored_url, None).unwrap(), None);
+
+        // A copy of another release published under the same URL is not reused
+        assert_eq!(
+            read_cached(&cache_dir, url, Some(&sha256(b"rust-src 2"))).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_cache_index() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let cache_dir = temp_dir.path().join("cache");
+        let url = "https://github.com/espressif/crosstool-NG/releases/download/esp-12.2.0_20230208/riscv32-esp-elf-12.2.0_20230208-x86_64-linux-gnu.tar.xz";
+        store_cached(&cache_dir, url, b"gcc").unwrap();
+
+        let index = CacheIndex::load(&cache_dir).unwrap();
+        let file = cached_artifact_path(&cache_dir, url);
+        let file = file.file_name().unwrap().to_str().unwrap();
+        let entry = index.find(file).unwrap();
+        assert_eq!(entry.url.as_deref(), Some(url));
+        assert_eq!(entry.version.as_deref(), Some("esp-12.2.0_20230208"));
+        assert_eq!(entry.sha256, Some(sha256(b"gcc")));
+        assert_eq!(entry.size, 3);
+        let cached = entries(&cache_dir).unwrap();
+        assert_eq!(cached.len(), 1);
+        assert_eq!(cached[0].version.as_deref(), Some("esp-12.2.0_20230208"));
+
+        // A copy modified since it was indexed is hashed again, and discarded if corrupted
+        write(cache_dir.join(file), b"gcc!").unwrap();
+        assert_eq!(read_cached(&cache_dir, url, None).unwrap(), None);
+        assert!(entries(&cache_dir).unwrap().is_empty());
+
+        // The index of a cache filled before it existed is rebuilt from the cache directory
+        write(cache_dir.join("0123456789abcdef-llvm.tar.xz"), b"llvm").unwrap();
+        remove_file(cache_dir.join(INDEX_FILE)).unwrap();
+        let index = CacheIndex::load(&cache_dir).unwrap();
+        assert_eq!(index.entries.len(), 1);
+        assert_eq!(index.entries[0].sha256, None);
+    }
+}
@@ -0,0 +1,135 @@
+//! Artifact checksum support.
+
+use crate::error::Error;
+use sha2::{Digest, Sha256};
+use std::{fs::File, io, path::Path};
+
+/// Source of the SHA256 digest an artifact is verified against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Checksum {
+    /// Digest known in advance, e.g. declared in the configuration.
+    Digest(String),
+    /// URL of the checksums published with the artifact, in the format used by `sha256sum`.
+    Published(String),
+}
+
+/// Returns the hex encoded SHA256 digest of some bytes.
+pub fn sha256(bytes: &[u8]) -> String {
+    to_hex(&Sha256::digest(bytes))
+}
+
+/// Returns the hex encoded SHA256 digest of a file.
+pub fn sha256_file(path: &Path) -> Result<String, Error> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+/// SHA256 digest computed incrementally, for artifacts hashed while they are downloaded.
+#[derive(Default)]
+pub struct Sha256Digest(Sha256);
+
+impl Sha256Digest {
+    /// Hashes the next bytes.
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    /// Returns the hex encoded digest of the hashed bytes.
+    pub fn finish(self) -> String {
+        to_hex(&self.0.finalize())
+    }
+}
+
+/// Finds the digest of a file in a checksums list, in the format used by `sha256sum`.
+pub fn parse_checksums(checksums: &str, file_name: &str) -> Option<String> {
+    checksums.lines().find_map(|line| {
+        let (digest, name) = line.trim().split_once(char::is_whitespace)?;
+        let name = name.trim_start().trim_start_matches('*');
+        (name == file_name).then(|| digest.to_lowercase())
+    })
+}
+
+/// Finds the digest of a file in the checksums downloaded from a URL: a checksums list, or a
+/// `<file>.sha256` sidecar file, which may only contain the digest of the file it is named after.
+pub fn find_digest(checksums_url: &str, checksums: &str, file_name: &str) -> Option<String> {
+    let checksums_name = checksums_url.rsplit('/').next().unwrap_or(checksums_url);
+    let sidecar = checksums.trim();
+    if checksums_name == format!("{file_name}.sha256")
+        && sidecar.len() == 64
+        && sidecar.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return Some(sidecar.to_lowercase());
+    }
+    parse_checksums(checksums, file_name)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::checksum::{find_digest, parse_checksums, sha256, sha256_file, Sha256Digest};
+
+    #[test]
+    fn test_sha256() {
+        let digest = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        assert_eq!(sha256(b"hello"), digest);
+        let mut incremental = Sha256Digest::default();
+        incremental.update(b"hel");
+        incremental.update(b"lo");
+        assert_eq!(incremental.finish(), digest);
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file = temp_dir.path().join("hello.txt");
+        std::fs::write(&file, "hello").unwrap();
+        assert_eq!(sha256_file(&file).unwrap(), digest);
+        assert!(sha256_file(&temp_dir.path().join("missing.txt")).is_err());
+    }
+
+    #[test]
+    fn test_parse_checksums() {
+        let checksums = "\
+0123abcd  rust-1.73.0.1-x86_64-unknown-linux-gnu.tar.xz
+4567EF01 *rust-src-1.73.0.1.tar.xz
+";
+        assert_eq!(
+            parse_checksums(checksums, "rust-1.73.0.1-x86_64-unknown-linux-gnu.tar.xz"),
+            Some("0123abcd".to_string())
+        );
+        assert_eq!(
+            parse_checksums(checksums, "rust-src-1.73.0.1.tar.xz"),
+            Some("4567ef01".to_string())
+        );
+        assert_eq!(parse_checksums(checksums, "rust-src.tar.xz"), None);
+
+        let sidecar = format!("{}\n", "AB".repeat(32));
+        assert_eq!(
+            find_digest(
+                "https://example.com/espflash.tar.gz.sha256",
+                &sidecar,
+                "espflash.tar.gz"
+            ),
+            Some("ab".repeat(32))
+        );
+        // The sidecar file of another artifact
+        assert_eq!(
+            find_digest(
+                "https://example.com/espflash.tar.gz.sha256",
+                &sidecar,
+                "ldproxy.tar.gz"
+            ),
+            None
+        );
+        assert_eq!(
+            find_digest(
+                "https://example.com/SHA256SUMS",
+                checksums,
+                "rust-src-1.73.0.1.tar.xz"
+            ),
+            Some("4567ef01".to_string())
+        );
+    }
+}
@@ -1,11 +1,207 @@
 use crate::{
+    config::Config,
+    footprint::parse_size,
+    lockfile::DEFAULT_LOCKFILE,
+    prompt::{is_non_interactive, set_non_interactive},
+    proxy::{set_proxy, ProxySettings},
     targets::{parse_targets, Target},
+    throttle::parse_rate,
+    timeout::{set_timeouts, TimeoutConfig},
+    tls::{set_tls, TlsSettings},
     toolchain::rust::XtensaRust,
 };
-use clap::Parser;
+use clap::{Command, Parser, Subcommand};
 use clap_complete::Shell;
 use std::{collections::HashSet, path::PathBuf};
 
+/// Sets default values as the defaults of the matching arguments of a command and of its
+/// subcommands, so the values given in the command line still take precedence.
+pub fn with_defaults(mut command: Command, defaults: &[(&'static str, String)]) -> Command {
+    for (id, value) in defaults {
+        if command.get_arguments().any(|arg| arg.get_id() == id) {
+            command = command.mut_arg(id, |arg| arg.default_value(value.clone()));
+        }
+    }
+    let subcommands: Vec<String> = command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect();
+    for name in subcommands {
+        command = command.mut_subcommand(name, |subcommand| with_defaults(subcommand, defaults));
+    }
+    command
+}
+
+#[derive(Debug, Parser)]
+pub struct AuthOpts {
+    #[command(subcommand)]
+    pub subcommand: AuthSubCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AuthSubCommand {
+    /// Stores the GitHub token, or the credentials of a mirror, in the keyring of the system.
+    Login(AuthLoginOpts),
+    /// Removes the GitHub token, or the credentials of a mirror, from the keyring of the system.
+    Logout(AuthLogoutOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct AuthLoginOpts {
+    /// Verbosity level of the logs.
+    #[arg(short = 'l', long, default_value = "info", value_parser = ["debug", "info", "warn", "error"])]
+    pub log_level: String,
+    /// Mirror whose credentials are stored instead of the GitHub token.
+    ///
+    /// The credentials are used for every mirror, or OCI registry, served by the same host.
+    #[arg(short = 'm', long, requires = "username")]
+    pub mirror: Option<String>,
+    /// User name of the mirror credentials.
+    #[arg(short = 'u', long, requires = "mirror")]
+    pub username: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+pub struct AuthLogoutOpts {
+    /// Verbosity level of the logs.
+    #[arg(short = 'l', long, default_value = "info", value_parser = ["debug", "info", "warn", "error"])]
+    pub log_level: String,
+    /// Mirror whose credentials are removed instead of the GitHub token.
+    #[arg(short = 'm', long)]
+    pub mirror: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+pub struct BundleOpts {
+    #[command(subcommand)]
+    pub subcommand: BundleSubCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BundleSubCommand {
+    /// Downloads every artifact required to install a version into a portable bundle archive.
+    Create(BundleCreateOpts),
+    /// Verifies that a bundle directory contains every artifact required by a lockfile.
+    Verify(BundleVerifyOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct BundleCreateOpts {
+    /// Target triples of the hosts whose artifacts are bundled. Defaults to the current host.
+    #[arg(long, value_delimiter = ',', value_parser = ["x86_64-unknown-linux-gnu", "aarch64-unknown-linux-gnu", "x86_64-pc-windows-msvc", "x86_64-pc-windows-gnu" , "x86_64-apple-darwin" , "aarch64-apple-darwin"])]
+    pub hosts: Vec<String>,
+    /// Verbosity level of the logs.
+    #[arg(short = 'l', long, default_value = "info", value_parser = ["debug", "info", "warn", "error"])]
+    pub log_level: String,
+    /// Comma or space separated list of targets [esp32,esp32c2,esp32c3,esp32c6,esp32h2,esp32s2,esp32s3,all].
+    #[arg(short = 't', long, default_value = "all", value_parser = parse_targets)]
+    pub targets: HashSet<Target>,
+    /// Xtensa Rust toolchain version. Defaults to the latest version.
+    #[arg(short = 'v', long, value_parser = XtensaRust::parse_version_syntax)]
+    pub toolchain_version: Option<String>,
+    /// Log the metadata of every HTTP request and response.
+    #[arg(long)]
+    pub trace_http: bool,
+    #[command(flatten)]
+    pub network: NetworkOpts,
+    /// Path of the bundle archive to create.
+    pub output: PathBuf,
+}
+
+/// Network options of the commands downloading release artifacts, as in `install`.
+#[derive(Debug, Parser)]
+pub struct NetworkOpts {
+    /// PEM bundle of the certificate authorities to trust for downloads and GitHub queries, e.g. the one of a proxy re-signing TLS traffic.
+    ///
+    /// Replaces the native certificate store, unless `--native-certs` is also used.
+    #[arg(long)]
+    pub ca_cert: Option<PathBuf>,
+    /// Seconds to wait for a connection to be established [default: 30].
+    #[arg(long)]
+    pub connect_timeout: Option<u64>,
+    /// Trust the native certificate store of the system along with the `--ca-cert` bundle.
+    #[arg(long, requires = "ca_cert")]
+    pub native_certs: bool,
+    /// Proxy used for every request, as an `http://`, `https://`, `socks5://` or `socks5h://` URL.
+    ///
+    /// Defaults to the `HTTPS_PROXY` or `ALL_PROXY` environment variables.
+    #[arg(long)]
+    pub proxy: Option<String>,
+    /// Proxy credentials, as `user[:password]`.
+    #[arg(long)]
+    pub proxy_user: Option<String>,
+    /// Seconds to wait for a response, or the next chunk of a download, before failing [default: 60].
+    #[arg(long)]
+    pub read_timeout: Option<u64>,
+}
+
+impl NetworkOpts {
+    /// Applies the proxy, the certificate authorities and the timeouts to every request, falling
+    /// back to the configuration file.
+    pub fn apply(&self, config: &Config) {
+        set_proxy(ProxySettings {
+            url: self.proxy.clone().or_else(|| config.proxy.clone()),
+            credentials: self.proxy_user.clone(),
+        });
+        set_tls(TlsSettings {
+            ca_cert: self.ca_cert.clone(),
+            native_certs: self.native_certs,
+        });
+        set_timeouts(TimeoutConfig {
+            connect: self.connect_timeout.or(config.timeouts.connect),
+            read: self.read_timeout.or(config.timeouts.read),
+        });
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct BundleVerifyOpts {
+    /// Verbosity level of the logs.
+    #[arg(short = 'l', long, default_value = "info", value_parser = ["debug", "info", "warn", "error"])]
+    pub log_level: String,
+    /// Path to the lockfile describing the required artifacts.
+    #[arg(short = 'k', long, default_value = DEFAULT_LOCKFILE)]
+    pub lockfile: PathBuf,
+    /// Directory containing the bundled artifacts.
+    pub dir: PathBuf,
+}
+
+#[derive(Debug, Parser)]
+pub struct CacheOpts {
+    #[command(subcommand)]
+    pub subcommand: CacheSubCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CacheSubCommand {
+    /// Shows the disk usage and eviction policy of the download cache.
+    Status(CacheStatusOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct CacheStatusOpts {
+    /// Verbosity level of the logs.
+    #[arg(short = 'l', long, default_value = "info", value_parser = ["debug", "info", "warn", "error"])]
+    pub log_level: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct CheckOpts {
+    /// Target triple of the host.
+    #[arg(short = 'd', long, value_parser = ["x86_64-unknown-linux-gnu", "aarch64-unknown-linux-gnu", "x86_64-pc-windows-msvc", "x86_64-pc-windows-gnu" , "x86_64-apple-darwin" , "aarch64-apple-darwin"])]
+    pub default_host: Option<String>,
+    /// Verbosity level of the logs.
+    #[arg(short = 'l', long, default_value = "info", value_parser = ["debug", "info", "warn", "error"])]
+    pub log_level: String,
+    /// Xtensa Rust toolchain name.
+    #[arg(short = 'a', long, default_value = "esp")]
+    pub name: String,
+    /// Format of the output on stdout. With `json`, the result is emitted as a JSON event and the
+    /// logs are only written to stderr.
+    #[arg(long, default_value = "text", value_parser = ["text", "json"])]
+    pub output: String,
+}
+
 #[derive(Debug, Parser)]
 pub struct CompletionsOpts {
     /// Verbosity level of the logs.
@@ -15,28 +211,261 @@ pub struct CompletionsOpts {
     pub shell: Shell,
 }
 
+#[derive(Debug, Parser)]
+pub struct DoctorOpts {
+    /// Explains where each variable exported by espup is set: shell profiles, the export file,
+    /// direnv, IDE settings or Cargo configurations.
+    #[arg(long)]
+    pub env_scope: bool,
+    /// Relative or full path for the export file generated by espup. If no path is provided, the file under home directory is used.
+    #[arg(short = 'f', long)]
+    pub export_file: Option<PathBuf>,
+    /// Verbosity level of the logs.
+    #[arg(short = 'l', long, default_value = "info", value_parser = ["debug", "info", "warn", "error"])]
+    pub log_level: String,
+    /// Xtensa Rust toolchain name.
+    #[arg(short = 'a', long, default_value = "esp")]
+    pub name: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct ExplainOpts {
+    /// Language of the documentation. Defaults to the language of the system.
+    #[arg(short = 'L', long)]
+    pub language: Option<String>,
+    /// Verbosity level of the logs.
+    #[arg(short = 'l', long, default_value = "info", value_parser = ["debug", "info", "warn", "error"])]
+    pub log_level: String,
+    /// Error code to explain, either the full code or its last segment. If no code is provided, the documented codes are listed.
+    pub code: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+pub struct GhaInstallOpts {
+    #[command(flatten)]
+    pub install: InstallOpts,
+    /// Resolve the versions and print the cache key without installing anything, e.g. to restore the cache before the installation.
+    #[arg(long)]
+    pub key_only: bool,
+}
+
 #[derive(Debug, Parser)]
 pub struct InstallOpts {
+    /// Directory where `activate` and `deactivate` scripts are generated along with the export file.
+    ///
+    /// Sourcing `activate` saves the previous values of the variables it sets, which `deactivate` restores, to switch between espup environments and other toolchains in the same shell.
+    #[arg(long)]
+    pub activate_dir: Option<PathBuf>,
+    /// Directory the artifacts are resolved from when installing with `--offline`.
+    #[arg(long, requires = "offline")]
+    pub artifact_dir: Option<PathBuf>,
+    /// PEM bundle of the certificate authorities to trust for downloads and GitHub queries, e.g. the one of a proxy re-signing TLS traffic.
+    ///
+    /// Replaces the native certificate store, unless `--native-certs` is also used.
+    #[arg(long)]
+    pub ca_cert: Option<PathBuf>,
+    /// Use defaults suited for CI.
+    ///
+    /// Skips the update check, only installs the LLVM libraries, does not modify the user environment, appends the exports to `--env-file` (`$GITHUB_ENV` by default), prints a JSON summary to stdout and never evicts cached artifacts.
+    #[arg(long)]
+    pub ci: bool,
+    /// Seconds to wait for a connection to be established [default: 30].
+    #[arg(long)]
+    pub connect_timeout: Option<u64>,
     /// Target triple of the host.
     #[arg(short = 'd', long, value_parser = ["x86_64-unknown-linux-gnu", "aarch64-unknown-linux-gnu", "x86_64-pc-windows-msvc", "x86_64-pc-windows-gnu" , "x86_64-apple-darwin" , "aarch64-apple-darwin"])]
     pub default_host: Option<String>,
+    /// Treat any warning found during the installation as an error.
+    #[arg(long)]
+    pub deny_warnings: bool,
+    /// Distribution server replacing GitHub, for both the release artifacts and the API queries.
+    ///
+    /// The artifacts are served under the same paths as in `https://github.com` and the API endpoints under `<server>/api`, e.g. `<server>/api/repos/esp-rs/rust-build/releases`.
+    #[arg(long, env = "ESPUP_DIST_SERVER")]
+    pub dist_server: Option<String>,
+    /// Print the resolved versions, the downloaded URLs, the written directories and the exported
+    /// environment variables, without installing anything.
+    #[arg(long, conflicts_with_all = ["print_install_script", "rebuild_exports_only"])]
+    pub dry_run: bool,
+    /// Environment file where the exports are appended as `KEY=VALUE` lines, such as `$GITHUB_ENV`.
+    #[arg(long)]
+    pub env_file: Option<PathBuf>,
     /// Relative or full path for the export file that will be generated. If no path is provided, the file will be generated under home directory (https://docs.rs/dirs/latest/dirs/fn.home_dir.html).
     #[arg(short = 'f', long)]
     pub export_file: Option<PathBuf>,
+    /// Only writes the environment to the export file, without changing the environment variables of the user on Windows hosts, e.g. on managed machines.
+    #[arg(long)]
+    pub export_file_only: bool,
     /// Extends the LLVM installation.
     ///
-    /// This will install the whole LLVM instead of only installing the libs.
+    /// This will install the whole LLVM, with clang, its headers and tools, instead of only installing the libs, and add its `bin` directory to the `PATH`.
     #[arg(short = 'e', long)]
     pub extended_llvm: bool,
+    /// Whether `--extended-llvm` was given by the user, which the `--ci` preset keeps.
+    #[arg(skip)]
+    pub extended_llvm_given: bool,
+    /// Comma separated list of extra crates to install, as `name[@version]`.
+    ///
+    /// Prebuilt binaries published in the GitHub release of the crate are preferred and verified against their published checksums, falling back to `cargo install`.
+    #[arg(long, value_delimiter = ',')]
+    pub extra_crates: Vec<String>,
+    /// Mirror to fall back to when GitHub, or the mirror in use, is unreachable. Can be repeated, the mirrors are tried in order.
+    ///
+    /// The mirrors listed in the configuration file are tried next. A mirror also serves the GitHub API endpoints under `<mirror>/api`, as with `--dist-server`.
+    #[arg(long = "fallback-mirror")]
+    pub fallback_mirrors: Vec<String>,
+    /// Install without network access from a bundle archive created with `espup bundle create`.
+    ///
+    /// Without `--toolchain-version`, the Xtensa Rust version of the bundle is installed.
+    #[arg(long, conflicts_with_all = ["artifact_dir", "offline"])]
+    pub from_bundle: Option<PathBuf>,
+    /// Install even if the disk space check estimates that the staging or toolchain directories
+    /// lack space, only warning about it.
+    #[arg(long)]
+    pub force: bool,
+    /// Fail if a deprecated flag is used, instead of warning and migrating it.
+    #[arg(long)]
+    pub forbid_deprecated: bool,
+    /// Fail instead of accessing the network.
+    ///
+    /// Release metadata, checksums and artifacts are only resolved from the download cache, so the installation is deterministic and any hidden network dependency is reported.
+    #[arg(long, conflicts_with = "no_cache")]
+    pub frozen: bool,
+    /// GitHub token used to authenticate the API queries, raising the rate limit.
+    #[arg(long, env = "GITHUB_TOKEN", hide_env_values = true)]
+    pub github_token: Option<String>,
+    /// Maximum number of components downloaded and installed in parallel.
+    #[arg(short = 'j', long, default_value = "4", value_parser = clap::value_parser!(u64).range(1..))]
+    pub jobs: u64,
+    /// Maximum download throughput, in bytes per second with an optional K, M or G suffix, e.g. `500K`.
+    ///
+    /// The limit is shared by every download running in parallel.
+    #[arg(long, value_parser = parse_rate)]
+    pub limit_rate: Option<u64>,
+    /// Lockfile recording the resolved versions and artifacts of the installation, updated once
+    /// installed. No lockfile is written without it, and `--locked` reads `espup.lock` by default.
+    ///
+    /// In GitHub Actions, the cache key is derived from the lockfile, if it exists, instead of the
+    /// installation options.
+    #[arg(short = 'k', long)]
+    pub lockfile: Option<PathBuf>,
+    /// Install exactly the versions and artifacts of the lockfile, failing if anything resolves
+    /// differently.
+    #[arg(long, conflicts_with = "rebuild_exports_only")]
+    pub locked: bool,
     /// Verbosity level of the logs.
     #[arg(short = 'l', long, default_value = "info", value_parser = ["debug", "info", "warn", "error"])]
     pub log_level: String,
+    /// Maximum installed size, in bytes with an optional K, M or G suffix, e.g. `4G`.
+    ///
+    /// Optional pieces such as the extended LLVM tools are left out to fit it, and the installation fails with an estimated breakdown if it still does not fit.
+    #[arg(long, value_parser = parse_size)]
+    pub max_install_size: Option<u64>,
+    /// Mirror of the GitHub releases used to download the artifacts.
+    ///
+    /// Either an HTTP(S) URL, a `s3://bucket/prefix` mirror created with `espup mirror push` or an `oci://registry/namespace` registry with the artifacts pushed as ORAS artifacts, `<namespace>/<owner>/<repo>:<tag>`. Defaults to the preferred mirror of the configuration file.
+    #[arg(short = 'm', long)]
+    pub mirror: Option<String>,
     /// Xtensa Rust toolchain name.
     #[arg(short = 'a', long, default_value = "esp")]
     pub name: String,
+    /// Trust the native certificate store of the system along with the `--ca-cert` bundle.
+    #[arg(long, requires = "ca_cert")]
+    pub native_certs: bool,
     /// Nightly Rust toolchain version.
     #[arg(short = 'n', long, default_value = "nightly")]
     pub nightly_version: String,
+    /// Install without network access, resolving every artifact from `--artifact-dir` by its file name.
+    ///
+    /// No GitHub query is made: without `--toolchain-version`, the latest Xtensa Rust version found in the artifact directory is installed.
+    #[arg(long, requires = "artifact_dir")]
+    pub offline: bool,
+    /// Prefetch the metadata of the newest Xtensa Rust releases in the background, so `espup check` and `espup update` can plan an update when GitHub is unreachable.
+    #[arg(long)]
+    pub prefetch: bool,
+    /// Always download the artifacts, without reading or storing them in the download cache.
+    #[arg(long)]
+    pub no_cache: bool,
+    /// Do not replace the identical files shared by the installed GCC and LLVM toolchains with hardlinks.
+    #[arg(long)]
+    pub no_dedup: bool,
+    /// Do not run the post-install hooks of the configuration file.
+    #[arg(long)]
+    pub no_hooks: bool,
+    /// Print the URL of every artifact of the installation, along with its SHA256 digest and its
+    /// size, one per line, instead of installing.
+    ///
+    /// Each line is `<url> <sha256> <size>`, with `-` when the digest is not published or the size
+    /// is not known, to drive the synchronization of mirrors. Artifacts are never downloaded.
+    #[arg(long, conflicts_with_all = ["dry_run", "output", "print_install_script", "rebuild_exports_only"])]
+    pub only_print_urls: bool,
+    /// Format of the output on stdout. With `json`, the progress is emitted as one JSON event per
+    /// line and the logs are only written to stderr.
+    #[arg(long, default_value = "text", value_parser = ["text", "json"], conflicts_with = "print_install_script")]
+    pub output: String,
+    /// Set the Xtensa Rust toolchain as rustup override for the current directory only.
+    ///
+    /// The override is removed when uninstalling. `--override-current-dir` is still accepted.
+    #[arg(long, alias = "override-current-dir")]
+    pub set_override: bool,
+    /// Whether only the given targets are refreshed, keeping the other installed ones.
+    ///
+    /// Set by `espup update` when `--targets` is given on the command line.
+    #[arg(skip)]
+    pub partial_targets: bool,
+    /// Print a standalone shell, or PowerShell, script replaying the resolved installation steps
+    /// instead of installing.
+    ///
+    /// The script downloads every artifact from its URL, checks its SHA256 digest, extracts it and writes the export file.
+    #[arg(long)]
+    pub print_install_script: bool,
+    /// Project whose `rust-toolchain.toml` declares the toolchain to install.
+    ///
+    /// A nightly channel sets the nightly version and any other channel the toolchain name, while
+    /// the declared Rust targets set the targets.
+    #[arg(long)]
+    pub project: Option<PathBuf>,
+    /// Proxy used for every request, as an `http://`, `https://`, `socks5://` or `socks5h://` URL.
+    ///
+    /// Defaults to the `HTTPS_PROXY` or `ALL_PROXY` environment variables.
+    #[arg(long)]
+    pub proxy: Option<String>,
+    /// Proxy credentials, as `user[:password]`.
+    #[arg(long)]
+    pub proxy_user: Option<String>,
+    /// Seconds to wait for a response, or the next chunk of a download, before failing [default: 60].
+    #[arg(long)]
+    pub read_timeout: Option<u64>,
+    /// Only regenerate the export file from the manifest of the existing installation.
+    ///
+    /// No network access is required and no toolchain is reinstalled.
+    #[arg(long)]
+    pub rebuild_exports_only: bool,
+    /// Number of parallel connections used to download each large artifact, fetching a range of it each.
+    ///
+    /// Only used when the server supports range requests. The reassembled artifact is verified against its published checksum.
+    #[arg(long, default_value = "1", value_parser = clap::value_parser!(u64).range(1..=16))]
+    pub segments: u64,
+    /// Set the Xtensa Rust toolchain as the default rustup toolchain.
+    ///
+    /// The previous default toolchain is restored when uninstalling.
+    #[arg(long)]
+    pub set_default: bool,
+    /// Shell of the export file on Windows hosts, PowerShell by default.
+    ///
+    /// With `cmd`, the export file is a batch file, `export-<name>.bat` by default, to be run with `call` from `cmd.exe` scripts.
+    #[arg(long, value_parser = ["powershell", "cmd"])]
+    pub shell: Option<String>,
+    /// Skip the installation of the GCC toolchains, as `--std` does.
+    #[arg(long)]
+    pub skip_gcc: bool,
+    /// Skip the installation of LLVM, which is only required to generate bindings with bindgen.
+    #[arg(long)]
+    pub skip_llvm: bool,
+    /// Skip the installation of the `rust-src` component, which is required to build `core` for
+    /// the Xtensa targets. On Windows, it is bundled with the Xtensa Rust toolchain.
+    #[arg(long)]
+    pub skip_rust_src: bool,
     /// Only install toolchains required for STD applications.
     ///
     /// With this option, espup will skip GCC installation (it will be handled by esp-idf-sys), hence you won't be able to build no_std applications.
@@ -45,17 +474,274 @@ pub struct InstallOpts {
     /// Comma or space separated list of targets [esp32,esp32c2,esp32c3,esp32c6,esp32h2,esp32s2,esp32s3,all].
     #[arg(short = 't', long, default_value = "all", value_parser = parse_targets)]
     pub targets: HashSet<Target>,
+    /// Directory where the artifacts are downloaded and extracted before being installed,
+    /// instead of the `cache.staging_dir` of the configuration file or `<cache>/tmp`.
+    #[arg(long, env = "ESPUP_TEMP_DIR")]
+    pub temp_dir: Option<PathBuf>,
+    /// Log the metadata of every HTTP request and response.
+    #[arg(long)]
+    pub trace_http: bool,
     /// Xtensa Rust toolchain version.
-    #[arg(short = 'v', long, value_parser = XtensaRust::parse_version)]
+    #[arg(short = 'v', long)]
     pub toolchain_version: Option<String>,
+    /// Path of a JSON file where the warnings found during the installation will be written.
+    #[arg(long)]
+    pub warnings_json: Option<PathBuf>,
+    /// Install the `rust-docs` component of the Xtensa Rust toolchain, so `rustup doc +esp` opens
+    /// the local standard library documentation.
+    ///
+    /// On Windows, the toolchain is installed from a single bundle and this option has no effect.
+    #[arg(long)]
+    pub with_docs: bool,
+    /// Write the installed toolchain to the `rust-toolchain.toml` of the project, keeping its other
+    /// settings.
+    #[arg(long, requires = "project")]
+    pub write_toolchain_file: bool,
+}
+
+impl InstallOpts {
+    /// Returns true if the GCC toolchains are installed.
+    pub fn installs_gcc(&self) -> bool {
+        !self.std && !self.skip_gcc
+    }
+
+    /// Applies the defaults of the `--ci` preset to the options not given by the user, and
+    /// disables the prompts.
+    pub fn apply_ci_preset(&mut self) {
+        if !self.extended_llvm_given {
+            self.extended_llvm = false;
+        }
+        if self.env_file.is_none() {
+            self.env_file = std::env::var_os("GITHUB_ENV").map(PathBuf::from);
+        }
+        // Assumed answers given with `--yes` are kept
+        if !is_non_interactive() {
+            set_non_interactive(true, false);
+        }
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct ListOpts {
+    /// Verbosity level of the logs.
+    #[arg(short = 'l', long, default_value = "info", value_parser = ["debug", "info", "warn", "error"])]
+    pub log_level: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct MirrorOpts {
+    #[command(subcommand)]
+    pub subcommand: MirrorSubCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MirrorSubCommand {
+    /// Benchmarks the configured mirrors and stores the fastest one as the preferred mirror.
+    Bench(MirrorBenchOpts),
+    /// Uploads the artifacts of a toolchain version, and a lockfile describing them, to an S3 bucket.
+    Push(MirrorPushOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct MirrorBenchOpts {
+    /// Verbosity level of the logs.
+    #[arg(short = 'l', long, default_value = "info", value_parser = ["debug", "info", "warn", "error"])]
+    pub log_level: String,
+    /// Additional mirror to benchmark, besides the default and the configured ones.
+    #[arg(short = 'm', long = "mirror")]
+    pub mirrors: Vec<String>,
+    /// Only report the results, without storing the preferred mirror in the configuration file.
+    #[arg(long)]
+    pub no_save: bool,
+    /// Log the metadata of every HTTP request and response.
+    #[arg(long)]
+    pub trace_http: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct MirrorPushOpts {
+    /// Destination of the mirror, as `s3://bucket/prefix`.
+    pub destination: String,
+    /// Target triples of the hosts whose artifacts are uploaded.
+    #[arg(long, required = true, value_delimiter = ',', value_parser = ["x86_64-unknown-linux-gnu", "aarch64-unknown-linux-gnu", "x86_64-pc-windows-msvc", "x86_64-pc-windows-gnu" , "x86_64-apple-darwin" , "aarch64-apple-darwin"])]
+    pub hosts: Vec<String>,
+    /// Verbosity level of the logs.
+    #[arg(short = 'l', long, default_value = "info", value_parser = ["debug", "info", "warn", "error"])]
+    pub log_level: String,
+    /// Xtensa Rust toolchain version.
+    #[arg(short = 'v', long, value_parser = XtensaRust::parse_version_syntax)]
+    pub toolchain_version: String,
+    /// Log the metadata of every HTTP request and response.
+    #[arg(long)]
+    pub trace_http: bool,
+    #[command(flatten)]
+    pub network: NetworkOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct PinsOpts {
+    #[command(subcommand)]
+    pub subcommand: PinsSubCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum PinsSubCommand {
+    /// Prints the default LLVM, GCC and nightly versions, as a TOML file that overrides them when
+    /// saved as `pins.toml` next to the configuration file.
+    Show(PinsShowOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct PinsShowOpts {
+    /// Print the versions bundled with espup, ignoring the local `pins.toml` file.
+    #[arg(long)]
+    pub bundled: bool,
+    /// Verbosity level of the logs.
+    #[arg(short = 'l', long, default_value = "info", value_parser = ["debug", "info", "warn", "error"])]
+    pub log_level: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct PruneOpts {
+    /// Only list the reclaimable items, without removing them.
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Keep the artifacts of the download cache.
+    #[arg(long)]
+    pub keep_cache: bool,
+    /// Verbosity level of the logs.
+    #[arg(short = 'l', long, default_value = "info", value_parser = ["debug", "info", "warn", "error"])]
+    pub log_level: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct RollbackOpts {
+    #[command(flatten)]
+    pub install: InstallOpts,
+    /// Lists the recorded installations, the most recent first, without rolling back.
+    #[arg(long)]
+    pub list: bool,
+    /// Number of installations to go back, `1` being the previous one.
+    #[arg(long, default_value_t = 1)]
+    pub steps: usize,
+}
+
+#[derive(Debug, Parser)]
+pub struct SchemaOpts {
+    /// Verbosity level of the logs.
+    #[arg(short = 'l', long, default_value = "info", value_parser = ["debug", "info", "warn", "error"])]
+    pub log_level: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct SelfOpts {
+    #[command(subcommand)]
+    pub subcommand: SelfSubCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SelfSubCommand {
+    /// Runs the end-to-end tests of an installation and reports them in the JUnit XML format.
+    Test(SelfTestOpts),
+    /// Updates espup to its latest release.
+    Update(SelfUpdateOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct SelfTestOpts {
+    /// Relative or full path for the export file generated by espup. If no path is provided, the file under home directory is used.
+    #[arg(short = 'f', long)]
+    pub export_file: Option<PathBuf>,
+    /// Verbosity level of the logs.
+    #[arg(short = 'l', long, default_value = "info", value_parser = ["debug", "info", "warn", "error"])]
+    pub log_level: String,
+    /// Xtensa Rust toolchain name.
+    #[arg(short = 'a', long, default_value = "esp")]
+    pub name: String,
+    /// Path of the JUnit XML report. If no path is provided, the report is printed to stdout.
+    #[arg(short = 'r', long)]
+    pub report: Option<PathBuf>,
+    /// Comma or space separated list of targets to test, the installed ones if not provided.
+    #[arg(short = 't', long, value_parser = parse_targets)]
+    pub targets: Option<HashSet<Target>>,
+}
+
+#[derive(Debug, Parser)]
+pub struct SelfUpdateOpts {
+    /// Only check whether a newer release is available, without installing it.
+    #[arg(long)]
+    pub check: bool,
+    /// Verbosity level of the logs.
+    #[arg(short = 'l', long, default_value = "info", value_parser = ["debug", "info", "warn", "error"])]
+    pub log_level: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct TargetsOpts {
+    #[command(subcommand)]
+    pub subcommand: TargetsSubCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TargetsSubCommand {
+    /// Reports the chip targets supported by an Xtensa Rust release.
+    Supported(TargetsSupportedOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct TargetsSupportedOpts {
+    /// Verbosity level of the logs.
+    #[arg(short = 'l', long, default_value = "info", value_parser = ["debug", "info", "warn", "error"])]
+    pub log_level: String,
+    /// Xtensa Rust toolchain version.
+    #[arg(short = 'v', long, value_parser = XtensaRust::parse_version_syntax)]
+    pub toolchain_version: String,
+    /// Log the metadata of every HTTP request and response.
+    #[arg(long)]
+    pub trace_http: bool,
+    #[command(flatten)]
+    pub network: NetworkOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct TuiOpts {
+    /// Target triple of the host.
+    #[arg(short = 'd', long, value_parser = ["x86_64-unknown-linux-gnu", "aarch64-unknown-linux-gnu", "x86_64-pc-windows-msvc", "x86_64-pc-windows-gnu" , "x86_64-apple-darwin" , "aarch64-apple-darwin"])]
+    pub default_host: Option<String>,
+    /// Xtensa Rust toolchain selected when the dashboard opens.
+    #[arg(short = 'a', long, default_value = "esp")]
+    pub name: String,
 }
 
 #[derive(Debug, Parser)]
 pub struct UninstallOpts {
+    /// Print the removed toolchains and directories, without uninstalling anything.
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Verbosity level of the logs.
+    #[arg(short = 'l', long, default_value = "info", value_parser = ["debug", "info", "warn", "error"])]
+    pub log_level: String,
+    /// Xtensa Rust toolchain name.
+    #[arg(short = 'a', long, default_value = "esp")]
+    pub name: String,
+    /// Format of the output on stdout. With `json`, the progress is emitted as one JSON event per
+    /// line and the logs are only written to stderr.
+    #[arg(long, default_value = "text", value_parser = ["text", "json"])]
+    pub output: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct VerifyOpts {
+    /// Target triple of the host.
+    #[arg(short = 'd', long, value_parser = ["x86_64-unknown-linux-gnu", "aarch64-unknown-linux-gnu", "x86_64-pc-windows-msvc", "x86_64-pc-windows-gnu" , "x86_64-apple-darwin" , "aarch64-apple-darwin"])]
+    pub default_host: Option<String>,
     /// Verbosity level of the logs.
     #[arg(short = 'l', long, default_value = "info", value_parser = ["debug", "info", "warn", "error"])]
     pub log_level: String,
     /// Xtensa Rust toolchain name.
     #[arg(short = 'a', long, default_value = "esp")]
     pub name: String,
+    /// Comma or space separated list of targets to verify, the installed ones if not provided.
+    #[arg(short = 't', long, value_parser = parse_targets)]
+    pub targets: Option<HashSet<Target>>,
 }
@@ -0,0 +1,331 @@
+//! Configuration file support.
+//!
+//! The configuration of the user is layered over a system-wide configuration file, so
+//! administrators can preset mirrors, proxies or pinned versions for every user of a machine.
+
+use crate::{
+    cache::CacheConfig, emoji, error::Error, home, hooks::HooksConfig, pins::Pins,
+    timeout::TimeoutConfig, toolchain::plugin::PluginConfig,
+};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    fs::{create_dir_all, read_to_string, write},
+    path::{Path, PathBuf},
+};
+use toml::{Table, Value};
+
+/// Name of the configuration file of the user.
+const CONFIG_FILE: &str = "espup.toml";
+/// Name of the system-wide configuration file.
+const SYSTEM_CONFIG_FILE: &str = "config.toml";
+/// Directory of the system-wide configuration file, on Unix hosts.
+const SYSTEM_CONFIG_DIR: &str = "/etc/espup";
+/// Directory of the system-wide configuration file, on Windows hosts without `%ProgramData%`.
+const DEFAULT_PROGRAM_DATA: &str = r"C:\ProgramData";
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct Config {
+    /// Download cache settings.
+    #[serde(default)]
+    pub cache: CacheConfig,
+    /// Default values of the command line arguments, e.g.
+    /// `defaults = { name = "esp-1.76", targets = ["esp32", "esp32c3"] }`.
+    #[serde(default)]
+    pub defaults: Defaults,
+    /// Commands run after a successful installation or update, e.g.
+    /// `hooks = { post_install = ["./scripts/regenerate-cargo-config.sh"] }`.
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    /// Additional mirrors of the GitHub releases, benchmarked by `espup mirror bench` and tried in
+    /// order when the source in use is unreachable.
+    #[serde(default)]
+    pub mirrors: Vec<String>,
+    /// Component versions overriding the defaults of espup, e.g.
+    /// `pins = { llvm = "esp-17.0.1_20231207", gcc = "13.2.0_20230928" }`.
+    #[serde(default)]
+    pub pins: Pins,
+    /// Mirror used to download artifacts, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preferred_mirror: Option<String>,
+    /// Proxy used for every request, overridden by `--proxy`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    /// Third-party components installed along with the toolchains.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub plugins: Vec<PluginConfig>,
+    /// Network timeouts in seconds, e.g. `timeouts = { connect = 30, read = 60 }`.
+    #[serde(default)]
+    pub timeouts: TimeoutConfig,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct Defaults {
+    /// Xtensa Rust toolchain name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Targets to install.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<String>,
+    /// Path of the export file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub export_file: Option<PathBuf>,
+}
+
+impl Defaults {
+    /// Returns the default values, by the id of the argument they apply to.
+    pub fn arguments(&self) -> Vec<(&'static str, String)> {
+        let mut arguments = Vec::new();
+        if let Some(name) = &self.name {
+            arguments.push(("name", name.clone()));
+        }
+        if !self.targets.is_empty() {
+            arguments.push(("targets", self.targets.join(",")));
+        }
+        if let Some(export_file) = &self.export_file {
+            arguments.push(("export_file", export_file.display().to_string()));
+        }
+        arguments
+    }
+}
+
+impl Config {
+    /// Returns the path of the configuration file, `~/.config/espup/espup.toml` on Linux, the
+    /// system-wide one if there is no home.
+    pub fn path() -> PathBuf {
+        home::config_dir()
+            .map(|dir| dir.join("espup").join(CONFIG_FILE))
+            .unwrap_or_else(Self::system_path)
+    }
+
+    /// Returns the path of the system-wide configuration file, `/etc/espup/config.toml` on Unix
+    /// hosts.
+    pub fn system_path() -> PathBuf {
+        let dir = if cfg!(windows) {
+            PathBuf::from(env::var_os("ProgramData").unwrap_or_else(|| DEFAULT_PROGRAM_DATA.into()))
+                .join("espup")
+        } else {
+            PathBuf::from(SYSTEM_CONFIG_DIR)
+        };
+        dir.join(SYSTEM_CONFIG_FILE)
+    }
+
+    /// Loads the configuration of the user layered over the system-wide one, returning the
+    /// default configuration if neither exists.
+    pub fn load() -> Result<Self, Error> {
+        Self::load_layered(&Self::system_path(), &Self::path())
+    }
+
+    /// Loads the configuration of the user only, to be modified and saved back.
+    pub fn load_user() -> Result<Self, Error> {
+        Self::load_from(&Self::path())
+    }
+
+    /// Loads the configuration from `user_path` layered over the one from `system_path`.
+    ///
+    /// The values of the user take precedence, tables are merged key by key and arrays are
+    /// concatenated, the items of the user first.
+    pub fn load_layered(system_path: &Path, user_path: &Path) -> Result<Self, Error> {
+        let mut config = Value::Table(Table::new());
+        for path in [system_path, user_path] {
+            if !path.exists() {
+                continue;
+            }
+            debug!(
+                "{} Loading configuration from '{}'",
+                emoji::DEBUG,
+                path.display()
+            );
+            let invalid = || Error::ReadConfig(path.display().to_string());
+            let layer: Table = read_to_string(path)?.parse().map_err(|_| invalid())?;
+            let layer = Value::Table(layer);
+            // Each layer is validated on its own, so errors point to the invalid file
+            layer.clone().try_into::<Self>().map_err(|_| invalid())?;
+            merge(&mut config, layer);
+        }
+        config
+            .try_into()
+            .map_err(|_| Error::ReadConfig(user_path.display().to_string()))
+    }
+
+    /// Loads the configuration from the given path.
+    pub fn load_from(path: &Path) -> Result<Self, Error> {
+        if !path.exists() {
+            debug!(
+                "{} No configuration file found in '{}', using defaults",
+                emoji::DEBUG,
+                path.display()
+            );
+            return Ok(Self::default());
+        }
+        let contents = read_to_string(path)?;
+        toml::from_str(&contents).map_err(|_| Error::ReadConfig(path.display().to_string()))
+    }
+
+    /// Saves the configuration file.
+    pub fn save(&self) -> Result<(), Error> {
+        self.save_to(&Self::path())
+    }
+
+    /// Saves the configuration to the given path.
+    pub fn save_to(&self, path: &Path) -> Result<(), Error> {
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)
+                .map_err(|_| Error::CreateDirectory(parent.display().to_string()))?;
+        }
+        let contents = toml::to_string_pretty(self)
+            .map_err(|_| Error::WriteConfig(path.display().to_string()))?;
+        write(path, contents).map_err(|_| Error::WriteConfig(path.display().to_string()))?;
+        debug!(
+            "{} Configuration saved in '{}'",
+            emoji::DEBUG,
+            path.display()
+        );
+        Ok(())
+    }
+}
+
+/// Merges a configuration layer into the lower precedence ones.
+fn merge(base: &mut Value, layer: Value) {
+    match (base, layer) {
+        (Value::Table(base), Value::Table(layer)) => {
+            for (key, value) in layer {
+                match base.get_mut(&key) {
+                    Some(base) => merge(base, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (Value::Array(base), Value::Array(layer)) => {
+            let lower = std::mem::replace(base, layer);
+            for item in lower {
+                if !base.contains(&item) {
+                    base.push(item);
+                }
+            }
+        }
+        (base, layer) => *base = layer,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cli::{with_defaults, InstallOpts},
+        config::Config,
+        targets::Target,
+    };
+    use clap::{CommandFactory, FromArgMatches};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_config_load_and_save() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("espup").join("espup.toml");
+
+        // Missing file returns the default configuration
+        assert_eq!(Config::load_from(&path).unwrap(), Config::default());
+
+        // Saved configuration is loaded back
+        let config = Config {
+            mirrors: vec!["https://mirror.example.com".to_string()],
+            preferred_mirror: Some("https://mirror.example.com".to_string()),
+            ..Default::default()
+        };
+        config.save_to(&path).unwrap();
+        assert_eq!(Config::load_from(&path).unwrap(), config);
+
+        // Invalid contents return an error
+        std::fs::write(&path, "not toml").unwrap();
+        assert!(Config::load_from(&path).is_err());
+    }
+
+    #[test]
+    fn test_config_load_layered() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let system_path = temp_dir.path().join("config.toml");
+        let user_path = temp_dir.path().join("espup.toml");
+        std::fs::write(
+            &system_path,
+            r#"
+            mirrors = ["https://mirror.corp.example.com"]
+            proxy = "http://proxy.corp.example.com:3128"
+
+            [pins]
+            llvm = "esp-17.0.1_20231207"
+            gcc = "13.2.0_20230928"
+            "#,
+        )
+        .unwrap();
+
+        // The system-wide configuration applies without a configuration of the user
+        let config = Config::load_layered(&system_path, &user_path).unwrap();
+        assert_eq!(
+            config.proxy,
+            Some("http://proxy.corp.example.com:3128".to_string())
+        );
+
+        std::fs::write(
+            &user_path,
+            r#"
+            mirrors = ["https://mirror.example.com"]
+            pins = { gcc = "12.2.0_20230208" }
+            "#,
+        )
+        .unwrap();
+        let config = Config::load_layered(&system_path, &user_path).unwrap();
+        assert_eq!(
+            config.mirrors,
+            vec![
+                "https://mirror.example.com".to_string(),
+                "https://mirror.corp.example.com".to_string()
+            ]
+        );
+        assert_eq!(config.pins.llvm, Some("esp-17.0.1_20231207".to_string()));
+        assert_eq!(config.pins.gcc, Some("12.2.0_20230208".to_string()));
+        assert_eq!(
+            config.proxy,
+            Some("http://proxy.corp.example.com:3128".to_string())
+        );
+
+        std::fs::write(&system_path, "not toml").unwrap();
+        assert!(Config::load_layered(&system_path, &user_path).is_err());
+    }
+
+    #[test]
+    fn test_config_defaults() {
+        let config: Config = toml::from_str(
+            r#"
+            [defaults]
+            name = "esp-team"
+            targets = ["esp32", "esp32c3"]
+            export_file = "/opt/esp/export-esp.sh"
+            "#,
+        )
+        .unwrap();
+        let defaults = config.defaults.arguments();
+        let parse = |args: &[&str]| {
+            let command = with_defaults(InstallOpts::command(), &defaults);
+            InstallOpts::from_arg_matches(&command.get_matches_from([&["install"], args].concat()))
+                .unwrap()
+        };
+
+        let args = parse(&[]);
+        assert_eq!(args.name, "esp-team");
+        assert_eq!(args.targets.len(), 2);
+        assert!(args.targets.contains(&Target::ESP32C3));
+        assert_eq!(
+            args.export_file,
+            Some(PathBuf::from("/opt/esp/export-esp.sh"))
+        );
+
+        // The command line overrides the configuration file
+        let args = parse(&["--name", "esp", "-t", "esp32s3"]);
+        assert_eq!(args.name, "esp");
+        assert_eq!(args.targets.len(), 1);
+        assert!(args.targets.contains(&Target::ESP32S3));
+    }
+}
@@ -0,0 +1,199 @@
+//! Credentials stored in the keyring of the system: the Keychain on macOS, the Secret Service on
+//! Linux and the Credential Manager on Windows.
+//!
+//! The keyring is only available when espup is built with the `keyring` feature.
+
+use crate::error::Error;
+use reqwest::{RequestBuilder, Url};
+use std::{fmt, sync::Mutex};
+
+/// Service the credentials are stored under.
+#[cfg(feature = "keyring")]
+const SERVICE: &str = "espup";
+/// Account of the GitHub token.
+#[cfg(feature = "keyring")]
+const GITHUB_TOKEN_ACCOUNT: &str = "github-token";
+/// Prefix of the accounts of the mirror credentials, followed by the host of the mirror.
+#[cfg(feature = "keyring")]
+const MIRROR_ACCOUNT_PREFIX: &str = "mirror:";
+
+/// GitHub token already read from the keyring.
+static GITHUB_TOKEN: Mutex<Option<Option<String>>> = Mutex::new(None);
+/// Mirror credentials already read from the keyring, by host.
+static MIRROR_CREDENTIALS: Mutex<Vec<(String, Option<String>)>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Credential {
+    /// Token authenticating the GitHub API queries.
+    GithubToken,
+    /// `user:password` credentials of the mirrors served by a host.
+    Mirror(String),
+}
+
+impl Credential {
+    /// Returns the credentials of the host serving a mirror URL.
+    pub fn for_mirror(mirror: &str) -> Result<Self, Error> {
+        mirror_host(mirror)
+            .map(Self::Mirror)
+            .ok_or_else(|| Error::InvalidMirrorUrl(mirror.to_string()))
+    }
+
+    /// Returns the keyring account of the credential.
+    #[cfg(feature = "keyring")]
+    fn account(&self) -> String {
+        match self {
+            Self::GithubToken => GITHUB_TOKEN_ACCOUNT.to_string(),
+            Self::Mirror(host) => format!("{MIRROR_ACCOUNT_PREFIX}{host}"),
+        }
+    }
+}
+
+impl fmt::Display for Credential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::GithubToken => write!(f, "GitHub token"),
+            Self::Mirror(host) => write!(f, "credentials of '{host}'"),
+        }
+    }
+}
+
+/// Returns the host of a URL, with its port if it is not the default one.
+fn mirror_host(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    Some(match url.port() {
+        Some(port) => format!("{host}:{port}"),
+        None => host.to_string(),
+    })
+}
+
+/// Splits `user:password` credentials.
+fn split_credentials(credentials: &str) -> (&str, Option<&str>) {
+    match credentials.split_once(':') {
+        Some((username, password)) => (username, Some(password)),
+        None => (credentials, None),
+    }
+}
+
+#[cfg(feature = "keyring")]
+fn entry(credential: &Credential) -> Result<keyring::Entry, Error> {
+    keyring::Entry::new(SERVICE, &credential.account())
+        .map_err(|e| Error::KeyringUnavailable(e.to_string()))
+}
+
+/// Stores a credential in the keyring, replacing the previous one.
+#[cfg(feature = "keyring")]
+pub fn store(credential: &Credential, secret: &str) -> Result<(), Error> {
+    entry(credential)?
+        .set_password(secret)
+        .map_err(|e| Error::KeyringUnavailable(e.to_string()))
+}
+
+/// Reads a credential from the keyring, returning `None` if it is not stored.
+#[cfg(feature = "keyring")]
+pub fn load(credential: &Credential) -> Result<Option<String>, Error> {
+    match entry(credential)?.get_password() {
+        Ok(secret) => Ok(Some(secret)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(Error::KeyringUnavailable(e.to_string())),
+    }
+}
+
+/// Removes a credential from the keyring, returning `false` if it was not stored.
+#[cfg(feature = "keyring")]
+pub fn delete(credential: &Credential) -> Result<bool, Error> {
+    match entry(credential)?.delete_password() {
+        Ok(()) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(e) => Err(Error::KeyringUnavailable(e.to_string())),
+    }
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn store(_credential: &Credential, _secret: &str) -> Result<(), Error> {
+    Err(Error::KeyringUnavailable(
+        "espup was built without the 'keyring' feature".to_string(),
+    ))
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn load(_credential: &Credential) -> Result<Option<String>, Error> {
+    Ok(None)
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn delete(_credential: &Credential) -> Result<bool, Error> {
+    Err(Error::KeyringUnavailable(
+        "espup was built without the 'keyring' feature".to_string(),
+    ))
+}
+
+/// Returns the GitHub token stored in the keyring, if any.
+///
+/// The keyring is only read once.
+pub fn github_token() -> Option<String> {
+    GITHUB_TOKEN
+        .lock()
+        .unwrap()
+        .get_or_insert_with(|| load(&Credential::GithubToken).ok().flatten())
+        .clone()
+}
+
+/// Adds the credentials stored for the host of the URL, if any, to a request.
+///
+/// The keyring is only read once per host.
+pub fn authorize(request: RequestBuilder, url: &str) -> RequestBuilder {
+    let Some(host) = mirror_host(url) else {
+        return request;
+    };
+    let mut cache = MIRROR_CREDENTIALS.lock().unwrap();
+    let credentials = match cache.iter().find(|(cached, _)| *cached == host) {
+        Some((_, credentials)) => credentials.clone(),
+        None => {
+            let credentials = load(&Credential::Mirror(host.clone())).ok().flatten();
+            cache.push((host, credentials.clone()));
+            credentials
+        }
+    };
+    match credentials {
+        Some(credentials) => {
+            let (username, password) = split_credentials(&credentials);
+            request.basic_auth(username, password)
+        }
+        None => request,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::credentials::{mirror_host, split_credentials, Credential};
+
+    #[test]
+    fn test_credential_accounts() {
+        assert_eq!(
+            mirror_host("https://mirror.example.com/espressif/releases"),
+            Some("mirror.example.com".to_string())
+        );
+        assert_eq!(
+            mirror_host("http://10.0.0.2:8080/"),
+            Some("10.0.0.2:8080".to_string())
+        );
+        assert_eq!(mirror_host("mirror.example.com"), None);
+        assert_eq!(
+            Credential::for_mirror("https://mirror.example.com:443/esp").unwrap(),
+            Credential::Mirror("mirror.example.com".to_string())
+        );
+        assert!(Credential::for_mirror("not a url").is_err());
+        #[cfg(feature = "keyring")]
+        {
+            assert_eq!(
+                Credential::Mirror("mirror.example.com".to_string()).account(),
+                "mirror:mirror.example.com"
+            );
+            assert_eq!(Credential::GithubToken.account(), "github-token");
+        }
+
+        assert_eq!(split_credentials("ci:s3:cret"), ("ci", Some("s3:cret")));
+        assert_eq!(split_credentials("ci"), ("ci", None));
+    }
+}
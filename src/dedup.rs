@@ -0,0 +1,166 @@
+//! Deduplication of the files shared by the installed toolchains.
+
+use crate::{checksum::sha256_file, emoji, error::Error};
+use log::debug;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fs::{self, hard_link, rename, Metadata},
+    path::{Path, PathBuf},
+};
+
+/// Suffix of the links created before replacing a duplicate.
+const LINK_SUFFIX: &str = "espup-link";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DedupReport {
+    /// Number of duplicates replaced by hardlinks.
+    pub linked: usize,
+    /// Bytes saved by the hardlinks.
+    pub saved: u64,
+}
+
+/// Identity of the file contents on disk, files sharing it are already hardlinked.
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Permissions of a file, only files with the same permissions can share their contents.
+#[cfg(unix)]
+fn file_mode(metadata: &Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode()
+}
+
+#[cfg(not(unix))]
+fn file_mode(metadata: &Metadata) -> u32 {
+    u32::from(metadata.permissions().readonly())
+}
+
+/// Collects the regular files of a directory, without following symlinks.
+fn collect_files(dir: &Path, files: &mut Vec<(PathBuf, Metadata)>) -> Result<(), Error> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = fs::symlink_metadata(entry.path())?;
+        if metadata.is_dir() {
+            collect_files(&entry.path(), files)?;
+        } else if metadata.is_file() && metadata.len() > 0 {
+            files.push((entry.path(), metadata));
+        }
+    }
+    Ok(())
+}
+
+/// Replaces `duplicate` with a hardlink to `original`.
+fn link(original: &Path, duplicate: &Path) -> std::io::Result<()> {
+    let mut link_path = duplicate.as_os_str().to_owned();
+    link_path.push(format!(".{LINK_SUFFIX}"));
+    let link_path = PathBuf::from(link_path);
+    hard_link(original, &link_path)?;
+    rename(&link_path, duplicate).map_err(|e| {
+        let _ = fs::remove_file(&link_path);
+        e
+    })
+}
+
+/// Replaces the identical files found in the given directories with hardlinks to a single copy.
+///
+/// Files are grouped by size and permissions before being hashed, so only candidates are read.
+/// When the filesystem does not support hardlinks, the files are left untouched.
+pub fn hardlink_duplicates(dirs: &[PathBuf]) -> Result<DedupReport, Error> {
+    let mut files = Vec::new();
+    for dir in dirs.iter().filter(|dir| dir.is_dir()) {
+        collect_files(dir, &mut files)?;
+    }
+
+    let mut candidates: HashMap<(u64, u32), Vec<(PathBuf, Metadata)>> = HashMap::new();
+    for (path, metadata) in files {
+        candidates
+            .entry((metadata.len(), file_mode(&metadata)))
+            .or_default()
+            .push((path, metadata));
+    }
+
+    let mut report = DedupReport::default();
+    for ((size, _), group) in candidates.into_iter().filter(|(_, g)| g.len() > 1) {
+        let mut originals: HashMap<String, (PathBuf, Option<(u64, u64)>)> = HashMap::new();
+        for (path, metadata) in group {
+            let digest = sha256_file(&path)?;
+            let id = file_id(&metadata);
+            match originals.entry(digest) {
+                Entry::Vacant(entry) => {
+                    entry.insert((path, id));
+                }
+                Entry::Occupied(entry) => {
+                    let (original, original_id) = entry.get();
+                    if id.is_some() && id == *original_id {
+                        continue;
+                    }
+                    if let Err(e) = link(original, &path) {
+                        debug!(
+                            "{} Failed to hardlink '{}' to '{}': {}",
+                            emoji::DEBUG,
+                            path.display(),
+                            original.display(),
+                            e
+                        );
+                        return Ok(report);
+                    }
+                    report.linked += 1;
+                    report.saved += size;
+                }
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dedup::{hardlink_duplicates, DedupReport};
+    use std::fs::{create_dir_all, read_to_string, write};
+
+    #[test]
+    fn test_hardlink_duplicates() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let gcc = temp_dir.path().join("xtensa-esp32-elf");
+        let riscv_gcc = temp_dir.path().join("riscv32-esp-elf");
+        create_dir_all(gcc.join("lib")).unwrap();
+        create_dir_all(riscv_gcc.join("lib")).unwrap();
+        write(gcc.join("lib").join("libstdc++.so"), "runtime").unwrap();
+        write(riscv_gcc.join("lib").join("libstdc++.so"), "runtime").unwrap();
+        write(gcc.join("lib").join("libgcc.a"), "xtensa").unwrap();
+        write(riscv_gcc.join("lib").join("libgcc.a"), "riscv!").unwrap();
+
+        let dirs = vec![gcc.clone(), riscv_gcc.clone()];
+        assert_eq!(
+            hardlink_duplicates(&dirs).unwrap(),
+            DedupReport {
+                linked: 1,
+                saved: 7
+            }
+        );
+        assert_eq!(
+            read_to_string(riscv_gcc.join("lib").join("libstdc++.so")).unwrap(),
+            "runtime"
+        );
+        assert_eq!(
+            read_to_string(riscv_gcc.join("lib").join("libgcc.a")).unwrap(),
+            "riscv!"
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let metadata = std::fs::metadata(gcc.join("lib").join("libstdc++.so")).unwrap();
+            assert_eq!(metadata.nlink(), 2);
+            // Files that are already hardlinked are skipped
+            assert_eq!(hardlink_duplicates(&dirs).unwrap(), DedupReport::default());
+        }
+    }
+}
@@ -0,0 +1,207 @@
+//! Deprecated command line flags, migrated to their replacements before the arguments are parsed.
+
+use crate::{
+    error::Error,
+    warnings::{self, WarningKind},
+};
+use std::{ffi::OsString, sync::Mutex};
+
+/// Deprecated flag of `install` and `update`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeprecatedFlag {
+    /// Deprecated long flag.
+    pub flag: &'static str,
+    /// Flag replacing it, `None` if the flag is ignored.
+    pub replacement: Option<&'static str>,
+    /// If `true`, the flag takes a value, which is dropped along with an ignored flag.
+    pub takes_value: bool,
+    /// Release deprecating the flag.
+    pub since: &'static str,
+    /// Release removing the flag.
+    pub removal: &'static str,
+    /// Reason of the deprecation.
+    pub reason: &'static str,
+}
+
+impl DeprecatedFlag {
+    /// Returns the warning shown when the flag is used.
+    pub fn message(&self) -> String {
+        let action = match self.replacement {
+            Some(replacement) => format!("use '{replacement}' instead"),
+            None => "it is ignored".to_string(),
+        };
+        format!(
+            "'{}' is deprecated since {} and will be removed in {}, {}: {}",
+            self.flag, self.since, self.removal, action, self.reason
+        )
+    }
+}
+
+/// Deprecated flags, with their replacements and removal timelines.
+pub const DEPRECATED_FLAGS: &[DeprecatedFlag] = &[
+    DeprecatedFlag {
+        flag: "--esp-idf-version",
+        replacement: None,
+        takes_value: true,
+        since: "0.5.0",
+        removal: "0.7.0",
+        reason: "ESP-IDF is installed by esp-idf-sys when building std applications",
+    },
+    DeprecatedFlag {
+        flag: "--profile-minimal",
+        replacement: None,
+        takes_value: false,
+        since: "0.5.0",
+        removal: "0.7.0",
+        reason: "the nightly toolchain is always installed with the minimal profile",
+    },
+];
+
+/// Subcommands accepting the deprecated flags, which report them. The flags given to the other
+/// subcommands are left as they are, so they are rejected instead of being dropped silently.
+const MIGRATED_SUBCOMMANDS: [&str; 3] = ["install", "update", "gha-install"];
+/// Global options taking a value, which may precede the subcommand.
+const GLOBAL_VALUE_OPTIONS: [&str; 2] = ["--cargo-home", "--rustup-home"];
+
+static USED: Mutex<Vec<DeprecatedFlag>> = Mutex::new(Vec::new());
+
+/// Replaces the deprecated flags of the arguments with their replacements, returning the migrated
+/// arguments and the deprecated flags used.
+fn migrate(
+    args: impl IntoIterator<Item = OsString>,
+    flags: &[DeprecatedFlag],
+) -> (Vec<OsString>, Vec<DeprecatedFlag>) {
+    let mut migrated = Vec::new();
+    let mut used = Vec::new();
+    let mut args = args.into_iter();
+    // The binary name
+    migrated.extend(args.next());
+    while let Some(arg) = args.next() {
+        let text = arg.to_string_lossy().into_owned();
+        migrated.push(arg);
+        if GLOBAL_VALUE_OPTIONS.contains(&text.as_str()) {
+            migrated.extend(args.next());
+        } else if !text.starts_with('-') {
+            if !MIGRATED_SUBCOMMANDS.contains(&text.as_str()) {
+                migrated.extend(args);
+                return (migrated, used);
+            }
+            break;
+        }
+    }
+    while let Some(arg) = args.next() {
+        // Positional arguments follow `--`
+        if arg == "--" {
+            migrated.push(arg);
+            migrated.extend(args.by_ref());
+            break;
+        }
+        let text = arg.to_string_lossy().into_owned();
+        let (name, value) = match text.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (text.as_str(), None),
+        };
+        let Some(flag) = flags.iter().find(|flag| flag.flag == name) else {
+            migrated.push(arg);
+            continue;
+        };
+        if !used.contains(flag) {
+            used.push(*flag);
+        }
+        match (flag.replacement, value) {
+            (Some(replacement), Some(value)) => {
+                migrated.push(format!("{replacement}={value}").into());
+            }
+            (Some(replacement), None) => migrated.push(replacement.into()),
+            (None, None) if flag.takes_value => {
+                args.next();
+            }
+            (None, _) => {}
+        }
+    }
+    (migrated, used)
+}
+
+/// Migrates the deprecated flags of the command line arguments, which are reported by [`report`].
+pub fn migrate_args(args: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+    let (args, used) = migrate(args, DEPRECATED_FLAGS);
+    *USED.lock().unwrap() = used;
+    args
+}
+
+/// Warns about the deprecated flags used, failing instead if they are forbidden.
+pub fn report(forbid: bool) -> Result<(), Error> {
+    let used = USED.lock().unwrap().clone();
+    if forbid && !used.is_empty() {
+        let flags: Vec<&str> = used.iter().map(|flag| flag.flag).collect();
+        return Err(Error::ForbiddenDeprecatedFlags(flags.join(", ")));
+    }
+    for flag in used {
+        warnings::push(WarningKind::Deprecated, flag.message());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::deprecation::{migrate, DeprecatedFlag, DEPRECATED_FLAGS};
+    use std::ffi::OsString;
+
+    #[test]
+    fn test_migrate() {
+        let renamed = DeprecatedFlag {
+            flag: "--toolchain-path",
+            replacement: Some("--export-file"),
+            takes_value: true,
+            since: "0.5.0",
+            removal: "0.7.0",
+            reason: "renamed",
+        };
+        let flags = [renamed, DEPRECATED_FLAGS[0], DEPRECATED_FLAGS[1]];
+        let args = [
+            "espup",
+            "install",
+            "--toolchain-path=export.sh",
+            "--esp-idf-version",
+            "v5.1",
+            "--profile-minimal",
+            "--targets",
+            "esp32",
+            "--",
+            "--profile-minimal",
+        ]
+        .map(OsString::from);
+        let (migrated, used) = migrate(args, &flags);
+        assert_eq!(
+            migrated,
+            [
+                "espup",
+                "install",
+                "--export-file=export.sh",
+                "--targets",
+                "esp32",
+                "--",
+                "--profile-minimal",
+            ]
+            .map(OsString::from)
+        );
+        assert_eq!(used, flags);
+
+        // The flags of the other subcommands are rejected when parsing them
+        let args = [
+            "espup",
+            "--rustup-home",
+            "install",
+            "uninstall",
+            "--profile-minimal",
+        ]
+        .map(OsString::from);
+        let (migrated, used) = migrate(args.clone(), &flags);
+        assert_eq!(migrated, args);
+        assert!(used.is_empty());
+        assert_eq!(
+            renamed.message(),
+            "'--toolchain-path' is deprecated since 0.5.0 and will be removed in 0.7.0, use '--export-file' instead: renamed"
+        );
+    }
+}
@@ -0,0 +1,767 @@
+//! Diagnostics of the environment set up by espup.
+
+use crate::{
+    env::ExportItem,
+    github::get_token,
+    manifest::InstallManifest,
+    mirror::{api_url, GITHUB_API},
+    platform::Platform,
+    proxy::client,
+    shims::Shim,
+    timeout::read,
+    toolchain::rust::{parse_rustup_version, RustupVersion},
+};
+use std::{
+    env::{self, consts::EXE_SUFFIX},
+    ffi::OsStr,
+    fs::{read_dir, read_to_string},
+    path::{Path, PathBuf},
+};
+use strum::Display;
+
+/// Variables audited when there is no installation manifest listing the exported ones.
+pub const DEFAULT_VARIABLES: [&str; 3] = ["PATH", "LIBCLANG_PATH", "CLANG_PATH"];
+
+/// Shell profiles, relative to the home directory.
+pub(crate) const USER_PROFILES: [&str; 9] = [
+    ".profile",
+    ".bash_profile",
+    ".bash_login",
+    ".bashrc",
+    ".zprofile",
+    ".zshrc",
+    ".config/fish/config.fish",
+    "Documents/PowerShell/Microsoft.PowerShell_profile.ps1",
+    "Documents/WindowsPowerShell/Microsoft.PowerShell_profile.ps1",
+];
+/// System-wide profiles.
+const SYSTEM_PROFILES: [&str; 3] = ["/etc/profile", "/etc/environment", "/etc/bash.bashrc"];
+/// Directory of the system-wide profile snippets.
+const SYSTEM_PROFILE_DIR: &str = "/etc/profile.d";
+/// Per-project files, looked up in the current directory and its ancestors.
+const PROJECT_FILES: [(&str, Scope); 4] = [
+    (".envrc", Scope::Direnv),
+    (".vscode/settings.json", Scope::Ide),
+    (".cargo/config.toml", Scope::CargoConfig),
+    (".cargo/config", Scope::CargoConfig),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+#[strum(serialize_all = "kebab-case")]
+pub enum Scope {
+    /// Shell profile of the user.
+    UserProfile,
+    /// System-wide shell profile.
+    SystemProfile,
+    /// Export file generated by espup.
+    ExportFile,
+    /// direnv `.envrc` of a project.
+    Direnv,
+    /// IDE settings of a project.
+    Ide,
+    /// `[env]` section of a Cargo configuration.
+    CargoConfig,
+    /// Environment of the user stored in the Windows registry.
+    Registry,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// Scope of the file.
+    pub scope: Scope,
+    /// Path of the file.
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assignment {
+    /// Variable being set.
+    pub variable: String,
+    /// Scope of the file setting it.
+    pub scope: Scope,
+    /// Path of the file setting it.
+    pub path: PathBuf,
+    /// Line number of the assignment, starting at 1.
+    pub line: usize,
+    /// Contents of the line.
+    pub text: String,
+}
+
+/// Outcome of a diagnostic check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnosis {
+    /// Name of the check.
+    pub check: &'static str,
+    /// If `true`, no problem was found.
+    pub passed: bool,
+    /// Description of the state found.
+    pub detail: String,
+    /// Suggested fix of the problem found.
+    pub fix: Option<String>,
+}
+
+impl Diagnosis {
+    fn pass(check: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            check,
+            passed: true,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn fail(check: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            check,
+            passed: false,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// Returns the fix of an environment not loaded from the export file.
+fn load_export_file(export_file: &Path) -> String {
+    format!(
+        "Load the export file in your shell, e.g. '. {}'",
+        export_file.display()
+    )
+}
+
+/// Checks the output of `rustup --version`, `None` if rustup was not found.
+pub fn diagnose_rustup(version: Option<&str>) -> Diagnosis {
+    const CHECK: &str = "rustup";
+    let Some(version) = version else {
+        return Diagnosis::fail(
+            CHECK,
+            "rustup was not found",
+            "Install rustup from https://rustup.rs/",
+        );
+    };
+    match parse_rustup_version(version) {
+        RustupVersion::Full => Diagnosis::pass(CHECK, version.lines().next().unwrap_or_default()),
+        RustupVersion::Limited(version) => Diagnosis::fail(
+            CHECK,
+            format!(
+                "'{version}' is an old rustup or a distribution wrapper with limited functionality"
+            ),
+            "Install rustup from https://rustup.rs/",
+        ),
+    }
+}
+
+/// Checks that the files and directories of the installation exist.
+pub fn diagnose_toolchain(install_path: &Path, manifest: Option<&InstallManifest>) -> Diagnosis {
+    const CHECK: &str = "toolchain";
+    let Some(manifest) = manifest else {
+        return Diagnosis::fail(
+            CHECK,
+            format!("No installation was found in '{}'", install_path.display()),
+            "Run 'espup install'",
+        );
+    };
+    let mut expected: Vec<PathBuf> = Vec::new();
+    if manifest.xtensa_rust_version.is_some() {
+        expected.push(install_path.join("bin").join(format!("rustc{EXE_SUFFIX}")));
+    }
+    for export in &manifest.exports {
+        match export {
+            ExportItem::PathAdd(dir) => expected.push(PathBuf::from(dir)),
+            ExportItem::EnvSet { key, value } if key.ends_with("_PATH") => {
+                expected.push(PathBuf::from(value))
+            }
+            _ => {}
+        }
+    }
+    let missing: Vec<String> = expected
+        .iter()
+        .filter(|path| !path.exists())
+        .map(|path| path.display().to_string())
+        .collect();
+    if missing.is_empty() {
+        Diagnosis::pass(
+            CHECK,
+            format!(
+                "Xtensa Rust {} and LLVM {} are installed in '{}'",
+                manifest.xtensa_rust_version.as_deref().unwrap_or("-"),
+                manifest.llvm_version,
+                install_path.display()
+            ),
+        )
+    } else {
+        Diagnosis::fail(
+            CHECK,
+            format!("Missing: {}", missing.join(", ")),
+            "Run 'espup install' to repair the installation",
+        )
+    }
+}
+
+/// Checks that the homes of cargo and rustup are the ones used by the installation, which differ
+/// when e.g. an IDE is launched with another environment. The homes are given as the values of
+/// `CARGO_HOME` and `RUSTUP_HOME`, `None` if they are not set.
+pub fn diagnose_homes(
+    manifest: &InstallManifest,
+    cargo_home: Option<PathBuf>,
+    rustup_home: Option<PathBuf>,
+    home_dir: &Path,
+    platform: &dyn Platform,
+) -> Diagnosis {
+    const CHECK: &str = "homes";
+    let mut details = Vec::new();
+    let mut fixes = Vec::new();
+    for (variable, recorded, current, default) in [
+        ("CARGO_HOME", &manifest.cargo_home, cargo_home, ".cargo"),
+        ("RUSTUP_HOME", &manifest.rustup_home, rustup_home, ".rustup"),
+    ] {
+        // Installations of older espup versions do not record their homes
+        let Some(recorded) = recorded else {
+            continue;
+        };
+        let resolved = current.clone().unwrap_or_else(|| home_dir.join(default));
+        if &resolved == recorded {
+            continue;
+        }
+        details.push(match current {
+            Some(current) => format!(
+                "{variable} is '{}' but the installation used '{}'",
+                current.display(),
+                recorded.display()
+            ),
+            None => format!(
+                "{variable} is not set, so it defaults to '{}', but the installation used '{}'",
+                resolved.display(),
+                recorded.display()
+            ),
+        });
+        fixes.push(format!(
+            "'{}'",
+            platform.export_var(variable, &recorded.display().to_string())
+        ));
+    }
+    if details.is_empty() {
+        Diagnosis::pass(CHECK, "CARGO_HOME and RUSTUP_HOME match the installation")
+    } else {
+        Diagnosis::fail(
+            CHECK,
+            details.join("; "),
+            format!(
+                "Set the same homes in the environment running the tools, e.g. the one launching your IDE: {}",
+                fixes.join(" and ")
+            ),
+        )
+    }
+}
+
+/// Checks that `LIBCLANG_PATH` is set to the value exported by the installation.
+pub fn diagnose_libclang(
+    exports: &[ExportItem],
+    value: Option<String>,
+    export_file: &Path,
+) -> Diagnosis {
+    const CHECK: &str = "LIBCLANG_PATH";
+    let expected = exports.iter().find_map(|export| match export {
+        ExportItem::EnvSet { key, value } if key == "LIBCLANG_PATH" => Some(value),
+        _ => None,
+    });
+    match (value, expected) {
+        (None, _) => Diagnosis::fail(
+            CHECK,
+            "LIBCLANG_PATH is not set in this shell",
+            load_export_file(export_file),
+        ),
+        (Some(value), Some(expected)) if Path::new(&value) != Path::new(expected) => {
+            Diagnosis::fail(
+                CHECK,
+                format!("LIBCLANG_PATH is '{value}' instead of '{expected}'"),
+                load_export_file(export_file),
+            )
+        }
+        (Some(value), _) if !Path::new(&value).exists() => Diagnosis::fail(
+            CHECK,
+            format!("LIBCLANG_PATH is '{value}', which does not exist"),
+            "Run 'espup install' to repair the installation",
+        ),
+        (Some(value), _) => Diagnosis::pass(CHECK, value),
+    }
+}
+
+/// Checks that the directories exported by the installation, such as the GCC binaries, are in
+/// the `PATH`.
+pub fn diagnose_path(
+    exports: &[ExportItem],
+    path: Option<&OsStr>,
+    export_file: &Path,
+) -> Diagnosis {
+    const CHECK: &str = "PATH";
+    let dirs: Vec<PathBuf> = path.map(env::split_paths).into_iter().flatten().collect();
+    let missing: Vec<&str> = exports
+        .iter()
+        .filter_map(|export| match export {
+            ExportItem::PathAdd(dir) if !dirs.iter().any(|d| d == Path::new(dir)) => {
+                Some(dir.as_str())
+            }
+            _ => None,
+        })
+        .collect();
+    if missing.is_empty() {
+        Diagnosis::pass(CHECK, "The exported directories are in the PATH")
+    } else {
+        Diagnosis::fail(
+            CHECK,
+            format!("Not in the PATH: {}", missing.join(", ")),
+            load_export_file(export_file),
+        )
+    }
+}
+
+/// Checks that the export file contains the exports of the installation.
+pub fn diagnose_export_file(
+    export_file: &Path,
+    exports: &[ExportItem],
+    platform: &dyn Platform,
+) -> Diagnosis {
+    const CHECK: &str = "export file";
+    const FIX: &str = "Run 'espup install --rebuild-exports-only'";
+    let Ok(contents) = read_to_string(export_file) else {
+        return Diagnosis::fail(
+            CHECK,
+            format!("'{}' does not exist", export_file.display()),
+            FIX,
+        );
+    };
+    let lines: Vec<&str> = contents.lines().map(str::trim).collect();
+    let outdated = exports
+        .iter()
+        .map(|export| export.render(platform))
+        .filter(|line| {
+            let windows_line = line.replace('/', r"\");
+            !lines.contains(&line.as_str()) && !lines.contains(&windows_line.as_str())
+        })
+        .count();
+    if outdated == 0 {
+        Diagnosis::pass(CHECK, export_file.display().to_string())
+    } else {
+        Diagnosis::fail(
+            CHECK,
+            format!(
+                "'{}' is missing {outdated} exports of the installation",
+                export_file.display()
+            ),
+            FIX,
+        )
+    }
+}
+
+/// Checks that the shims of the version managers intercepting `cargo` and `rustc`, along with
+/// whether they resolve `+<toolchain>`, defer to rustup.
+pub fn diagnose_shims(shims: &[(Shim, bool)], toolchain: &str) -> Diagnosis {
+    const CHECK: &str = "version managers";
+    if let Some((shim, _)) = shims.iter().find(|(_, resolves)| !resolves) {
+        return Diagnosis::fail(CHECK, shim.unresolved(toolchain), shim.manager.fix());
+    }
+    match shims.first() {
+        Some((shim, _)) => Diagnosis::pass(
+            CHECK,
+            format!("The {} shims resolve '+{toolchain}'", shim.manager),
+        ),
+        None => Diagnosis::pass(CHECK, "No version manager intercepts cargo or rustc"),
+    }
+}
+
+/// Checks that the GitHub API, or the distribution server replacing it, is reachable.
+pub async fn diagnose_github() -> Diagnosis {
+    const CHECK: &str = "GitHub";
+    let url = api_url(&format!("{GITHUB_API}/rate_limit"));
+    let unreachable = |e: String| {
+        Diagnosis::fail(
+            CHECK,
+            format!("'{url}' is unreachable: {e}"),
+            "Check your network connection, or configure a proxy with '--proxy' or a mirror with '--mirror'",
+        )
+    };
+    let client = match client() {
+        Ok(client) => client,
+        Err(e) => return unreachable(e.to_string()),
+    };
+    let mut request = client.get(&url);
+    if let Some(token) = get_token() {
+        request = request.bearer_auth(token);
+    }
+    let resp = match read(&url, request.send()).await {
+        Ok(resp) => resp,
+        Err(e) => return unreachable(e.to_string()),
+    };
+    let remaining = resp
+        .headers()
+        .get("x-ratelimit-remaining")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    match remaining.as_deref() {
+        Some("0") => Diagnosis::fail(
+            CHECK,
+            "The GitHub API rate limit is exhausted",
+            if get_token().is_some() {
+                "Wait for the rate limit to reset"
+            } else {
+                "Set the GITHUB_TOKEN environment variable to raise the rate limit"
+            },
+        ),
+        _ if !resp.status().is_success() => unreachable(resp.status().to_string()),
+        Some(remaining) => Diagnosis::pass(
+            CHECK,
+            format!("'{url}' is reachable, {remaining} requests remaining"),
+        ),
+        None => Diagnosis::pass(CHECK, format!("'{url}' is reachable")),
+    }
+}
+
+/// Returns the variables set by the exports of an installation.
+pub fn exported_variables(exports: &[ExportItem]) -> Vec<String> {
+    let mut variables = Vec::new();
+    for export in exports {
+        let variable = match export {
+            ExportItem::PathAdd(_) => "PATH",
+            ExportItem::EnvSet { key, .. } => key.as_str(),
+            ExportItem::SourceLine(_) => continue,
+        };
+        if !variables.iter().any(|v| v == variable) {
+            variables.push(variable.to_string());
+        }
+    }
+    variables
+}
+
+/// Returns the locations where the environment may be set, in the order they are usually loaded.
+pub fn known_locations(home_dir: &Path, current_dir: &Path, export_file: &Path) -> Vec<Location> {
+    let mut locations: Vec<Location> = SYSTEM_PROFILES
+        .iter()
+        .map(|path| Location {
+            scope: Scope::SystemProfile,
+            path: PathBuf::from(path),
+        })
+        .collect();
+    if let Ok(entries) = read_dir(SYSTEM_PROFILE_DIR) {
+        let mut snippets: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect();
+        snippets.sort();
+        locations.extend(snippets.into_iter().map(|path| Location {
+            scope: Scope::SystemProfile,
+            path,
+        }));
+    }
+    locations.extend(USER_PROFILES.iter().map(|path| Location {
+        scope: Scope::UserProfile,
+        path: home_dir.join(path),
+    }));
+    locations.push(Location {
+        scope: Scope::ExportFile,
+        path: export_file.to_path_buf(),
+    });
+    // Outermost directories first, as the innermost files take precedence
+    let mut ancestors: Vec<&Path> = current_dir.ancestors().collect();
+    ancestors.reverse();
+    for dir in ancestors {
+        locations.extend(PROJECT_FILES.iter().map(|(path, scope)| Location {
+            scope: *scope,
+            path: dir.join(path),
+        }));
+    }
+    locations.retain(|location| location.path.is_file());
+    locations.dedup();
+    locations
+}
+
+/// Returns true if a line of a shell, direnv, IDE or Cargo configuration file sets the variable.
+fn assigns(line: &str, variable: &str) -> bool {
+    let line = line.trim();
+    if line.starts_with('#') || line.starts_with("//") {
+        return false;
+    }
+    // direnv helper prepending a directory to the `PATH`
+    if variable == "PATH" && line.starts_with("PATH_add ") {
+        return true;
+    }
+    let mut rest = line;
+    while let Some(index) = rest.find(variable) {
+        let before = rest[..index].chars().last();
+        let after = rest[index + variable.len()..].trim_start_matches('"');
+        let is_word = before.map_or(true, |c| !(c.is_alphanumeric() || c == '_'))
+            && !after.starts_with(|c: char| c.is_alphanumeric() || c == '_');
+        if is_word {
+            let after = after.trim_start();
+            let prefix = &rest[..index];
+            if after.starts_with('=')
+                || after.starts_with(':')
+                || prefix.ends_with("set -gx ")
+                || prefix.ends_with("set -x ")
+            {
+                return true;
+            }
+        }
+        rest = &rest[index + variable.len()..];
+    }
+    false
+}
+
+/// Scans the locations for the lines setting any of the variables.
+pub fn scan(locations: &[Location], variables: &[String]) -> Vec<Assignment> {
+    let mut assignments = Vec::new();
+    for location in locations {
+        let Ok(contents) = read_to_string(&location.path) else {
+            continue;
+        };
+        for (number, line) in contents.lines().enumerate() {
+            for variable in variables {
+                if assigns(line, variable) {
+                    assignments.push(Assignment {
+                        variable: variable.clone(),
+                        scope: location.scope,
+                        path: location.path.clone(),
+                        line: number + 1,
+                        text: line.trim().to_string(),
+                    });
+                }
+            }
+        }
+    }
+    assignments
+}
+
+#[cfg(windows)]
+/// Returns the variables set in the environment of the user stored in the registry.
+pub fn registry_assignments(variables: &[String]) -> Vec<Assignment> {
+    use winreg::{enums::HKEY_CURRENT_USER, RegKey};
+
+    let Ok(environment_key) = RegKey::predef(HKEY_CURRENT_USER).open_subkey("Environment") else {
+        return Vec::new();
+    };
+    variables
+        .iter()
+        .filter_map(|variable| {
+            let value: String = environment_key.get_value(variable).ok()?;
+            Some(Assignment {
+                variable: variable.clone(),
+                scope: Scope::Registry,
+                path: PathBuf::from(r"HKEY_CURRENT_USER\Environment"),
+                line: 0,
+                text: format!("{variable}={value}"),
+            })
+        })
+        .collect()
+}
+
+#[cfg(not(windows))]
+pub fn registry_assignments(_variables: &[String]) -> Vec<Assignment> {
+    Vec::new()
+}
+
+/// Returns the profiles that source the export file.
+pub fn sourcing_profiles(locations: &[Location], export_file: &Path) -> Vec<PathBuf> {
+    let Some(file_name) = export_file.file_name().and_then(|name| name.to_str()) else {
+        return Vec::new();
+    };
+    locations
+        .iter()
+        .filter(|location| location.scope != Scope::ExportFile)
+        .filter(|location| {
+            read_to_string(&location.path).is_ok_and(|contents| {
+                contents
+                    .lines()
+                    .map(str::trim)
+                    .any(|line| !line.starts_with('#') && line.contains(file_name))
+            })
+        })
+        .map(|location| location.path.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        doctor::{
+            assigns, diagnose_export_file, diagnose_homes, diagnose_libclang, diagnose_path,
+            diagnose_rustup, diagnose_shims, exported_variables, known_locations, scan,
+            sourcing_profiles, Scope,
+        },
+        env::ExportItem,
+        manifest::InstallManifest,
+        platform::{Arch, Linux},
+        shims::{Shim, VersionManager},
+    };
+    use std::{
+        env::join_paths,
+        fs::{create_dir_all, write},
+        path::PathBuf,
+    };
+
+    #[test]
+    fn test_assigns() {
+        assert!(assigns(
+            "export LIBCLANG_PATH=\"/esp/lib\"",
+            "LIBCLANG_PATH"
+        ));
+        assert!(assigns(
+            "$Env:LIBCLANG_PATH = \"C:/esp/lib\"",
+            "LIBCLANG_PATH"
+        ));
+        assert!(assigns("set -gx LIBCLANG_PATH /esp/lib", "LIBCLANG_PATH"));
+        assert!(assigns("LIBCLANG_PATH = \"/esp/lib\"", "LIBCLANG_PATH"));
+        assert!(assigns(
+            "  \"LIBCLANG_PATH\": \"/esp/lib\",",
+            "LIBCLANG_PATH"
+        ));
+        assert!(assigns("export PATH=\"/esp/bin:$PATH\"", "PATH"));
+        assert!(!assigns("# export LIBCLANG_PATH=/esp/lib", "LIBCLANG_PATH"));
+        assert!(!assigns("export LIBCLANG_PATH=\"/esp/lib\"", "PATH"));
+        assert!(!assigns("echo $LIBCLANG_PATH", "LIBCLANG_PATH"));
+        assert!(assigns("PATH_add /esp/bin", "PATH"));
+
+        let exports = vec![
+            ExportItem::PathAdd("/esp/bin".to_string()),
+            ExportItem::EnvSet {
+                key: "LIBCLANG_PATH".to_string(),
+                value: "/esp/lib".to_string(),
+            },
+            ExportItem::PathAdd("/gcc/bin".to_string()),
+            ExportItem::SourceLine(". /esp/env.sh".to_string()),
+        ];
+        assert_eq!(exported_variables(&exports), vec!["PATH", "LIBCLANG_PATH"]);
+    }
+
+    #[test]
+    fn test_scan() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let home_dir = temp_dir.path().join("home");
+        let project_dir = home_dir.join("project");
+        create_dir_all(project_dir.join(".vscode")).unwrap();
+        let export_file = home_dir.join("export-esp.sh");
+        write(
+            &export_file,
+            "export LIBCLANG_PATH=\"/esp/lib\"\nexport PATH=\"/esp/bin:$PATH\"\n",
+        )
+        .unwrap();
+        write(home_dir.join(".bashrc"), ". $HOME/export-esp.sh\n").unwrap();
+        write(project_dir.join(".envrc"), "export LIBCLANG_PATH=/other\n").unwrap();
+        write(
+            project_dir.join(".vscode").join("settings.json"),
+            "{\n  \"terminal.integrated.env.linux\": { \"LIBCLANG_PATH\": \"/ide\" }\n}\n",
+        )
+        .unwrap();
+
+        let locations = known_locations(&home_dir, &project_dir, &export_file);
+        let assignments = scan(&locations, &["LIBCLANG_PATH".to_string()]);
+        let scopes: Vec<Scope> = assignments
+            .iter()
+            .filter(|assignment| assignment.path.starts_with(temp_dir.path()))
+            .map(|assignment| assignment.scope)
+            .collect();
+        assert_eq!(scopes, vec![Scope::ExportFile, Scope::Direnv, Scope::Ide]);
+        assert_eq!(
+            assignments
+                .iter()
+                .find(|a| a.scope == Scope::Direnv)
+                .unwrap()
+                .line,
+            1
+        );
+
+        assert_eq!(
+            sourcing_profiles(&locations, &export_file),
+            vec![home_dir.join(".bashrc")]
+        );
+    }
+
+    #[test]
+    fn test_diagnose() {
+        assert!(!diagnose_rustup(None).passed);
+        assert!(diagnose_rustup(Some("rustup 1.26.0 (5af9b9484 2023-04-05)")).passed);
+        let limited = diagnose_rustup(Some("rustup 1.18.3"));
+        assert!(!limited.passed && limited.fix.is_some());
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let lib = temp_dir.path().join("lib");
+        let bin = temp_dir.path().join("bin");
+        create_dir_all(&lib).unwrap();
+        let exports = vec![
+            ExportItem::PathAdd(bin.display().to_string()),
+            ExportItem::EnvSet {
+                key: "LIBCLANG_PATH".to_string(),
+                value: lib.display().to_string(),
+            },
+        ];
+        let export_file = temp_dir.path().join("export-esp.sh");
+
+        assert!(diagnose_libclang(&exports, Some(lib.display().to_string()), &export_file).passed);
+        assert!(!diagnose_libclang(&exports, None, &export_file).passed);
+        assert!(!diagnose_libclang(&exports, Some("/other".to_string()), &export_file).passed);
+
+        let path = join_paths([bin.as_path(), "/usr/bin".as_ref()]).unwrap();
+        assert!(diagnose_path(&exports, Some(&path), &export_file).passed);
+        assert!(!diagnose_path(&exports, Some("/usr/bin".as_ref()), &export_file).passed);
+
+        assert!(!diagnose_export_file(&export_file, &exports, &Linux(Arch::X86_64)).passed);
+        write(
+            &export_file,
+            format!("export LIBCLANG_PATH=\"{}\"\n", lib.display()),
+        )
+        .unwrap();
+        let outdated = diagnose_export_file(&export_file, &exports, &Linux(Arch::X86_64));
+        assert!(!outdated.passed);
+        assert!(outdated.detail.contains("missing 1 exports"));
+
+        let shim = Shim {
+            tool: "cargo",
+            manager: VersionManager::Asdf,
+            path: PathBuf::from("/home/esp/.asdf/shims/cargo"),
+        };
+        assert!(diagnose_shims(&[], "esp").passed);
+        assert!(diagnose_shims(&[(shim.clone(), true)], "esp").passed);
+        let unresolved = diagnose_shims(&[(shim, false)], "esp");
+        assert!(!unresolved.passed);
+        assert!(unresolved.fix.unwrap().contains("asdf global rust system"));
+    }
+
+    #[test]
+    fn test_diagnose_homes() {
+        let platform = Linux(Arch::X86_64);
+        let home_dir = PathBuf::from("/home/esp");
+        let manifest = InstallManifest {
+            cargo_home: Some(home_dir.join(".cargo")),
+            rustup_home: Some(PathBuf::from("/opt/rustup")),
+            ..Default::default()
+        };
+        assert!(
+            diagnose_homes(
+                &manifest,
+                None,
+                Some(PathBuf::from("/opt/rustup")),
+                &home_dir,
+                &platform
+            )
+            .passed
+        );
+        let diagnosis = diagnose_homes(&manifest, None, None, &home_dir, &platform);
+        assert!(!diagnosis.passed);
+        assert_eq!(
+            diagnosis.detail,
+            "RUSTUP_HOME is not set, so it defaults to '/home/esp/.rustup', but the installation used '/opt/rustup'"
+        );
+        assert!(diagnosis
+            .fix
+            .unwrap()
+            .ends_with("'export RUSTUP_HOME=\"/opt/rustup\"'"));
+        // Installations without recorded homes are not checked
+        assert!(
+            diagnose_homes(
+                &InstallManifest::default(),
+                None,
+                None,
+                &home_dir,
+                &platform
+            )
+            .passed
+        );
+    }
+}
@@ -1,14 +1,15 @@
 //! Environment variables set up and export file support.
 
-use crate::{emoji, error::Error};
-use directories::BaseDirs;
+use crate::{emoji, error::Error, home::home_dir, platform::Platform};
 use log::{info, warn};
-#[cfg(windows)]
-use std::env;
+use serde::{Deserialize, Serialize};
 use std::{
-    fs::File,
+    collections::HashMap,
+    env,
+    fs::{create_dir_all, read_to_string, write, File, OpenOptions},
     io::Write,
     path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
 };
 #[cfg(windows)]
 use winreg::{
@@ -17,38 +18,373 @@ use winreg::{
 };
 
 #[cfg(windows)]
-const DEFAULT_EXPORT_FILE: &str = "export-esp.ps1";
+const EXPORT_FILE_EXTENSION: &str = "ps1";
 #[cfg(not(windows))]
-const DEFAULT_EXPORT_FILE: &str = "export-esp.sh";
+const EXPORT_FILE_EXTENSION: &str = "sh";
+/// Extension of the export files written for `cmd.exe`.
+const BATCH_FILE_EXTENSION: &str = "bat";
+/// Variable holding the name of the environment activated by an activation script.
+const ACTIVE_ENV_VAR: &str = "ESPUP_ENV";
+/// Prefix of the variables holding the values saved by an activation script.
+const BACKUP_PREFIX: &str = "_ESPUP_OLD_";
+
+static PERSIST_ENVIRONMENT: AtomicBool = AtomicBool::new(true);
+
+/// Environment change required by an installed component.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub enum ExportItem {
+    /// Directory prepended to the `PATH`.
+    PathAdd(String),
+    /// Environment variable set to a value.
+    EnvSet { key: String, value: String },
+    /// Line written verbatim to the export file.
+    SourceLine(String),
+}
+
+/// Shell an export file is written for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportShell {
+    /// Shell of the host: a POSIX shell, or PowerShell on Windows.
+    #[default]
+    Native,
+    /// Windows `cmd.exe`, as a batch file.
+    Cmd,
+}
+
+impl ExportShell {
+    /// Returns the shell selected by `--shell`.
+    pub fn from_arg(shell: Option<&str>) -> Self {
+        match shell {
+            Some("cmd") => Self::Cmd,
+            _ => Self::Native,
+        }
+    }
+
+    /// Returns the extension of the export files.
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Native => EXPORT_FILE_EXTENSION,
+            Self::Cmd => BATCH_FILE_EXTENSION,
+        }
+    }
+
+    /// Returns the prefix of the comments.
+    fn comment(&self) -> &'static str {
+        match self {
+            Self::Native => "#",
+            Self::Cmd => "@rem",
+        }
+    }
+}
+
+/// Escapes the characters expanded by `cmd.exe` inside a quoted `set`.
+fn cmd_escape(value: &str) -> String {
+    value.replace('%', "%%")
+}
+
+impl ExportItem {
+    /// Renders the item as a line of a `cmd.exe` batch file, without echoing it.
+    pub fn render_cmd(&self) -> String {
+        match self {
+            ExportItem::PathAdd(dir) => format!("@set \"PATH={};%PATH%\"", cmd_escape(dir)),
+            ExportItem::EnvSet { key, value } => format!("@set \"{key}={}\"", cmd_escape(value)),
+            ExportItem::SourceLine(line) => format!("@rem {line}"),
+        }
+    }
+
+    /// Renders the item as a line of the export file of the platform.
+    pub fn render(&self, platform: &dyn Platform) -> String {
+        match self {
+            ExportItem::PathAdd(dir) => platform.export_path(dir),
+            ExportItem::EnvSet { key, value } => platform.export_var(key, value),
+            ExportItem::SourceLine(line) => line.clone(),
+        }
+    }
+
+    /// Returns the variable and value set by the item, prepending the added directories to the
+    /// previous value of the `PATH` returned by `lookup`.
+    fn assignment(
+        &self,
+        platform: &dyn Platform,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Option<(String, String)> {
+        match self {
+            ExportItem::PathAdd(dir) => {
+                let value = match lookup("PATH") {
+                    Some(path) => format!("{dir}{}{path}", platform.path_separator()),
+                    None => dir.clone(),
+                };
+                Some(("PATH".to_string(), value))
+            }
+            ExportItem::EnvSet { key, value } => Some((key.clone(), value.clone())),
+            ExportItem::SourceLine(_) => None,
+        }
+    }
+}
+
+/// Enables or disables writing the environment variables of the user on Windows hosts, the
+/// export file is the only output when disabled.
+pub fn set_persist_environment(enabled: bool) {
+    PERSIST_ENVIRONMENT.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns true if the environment variables of the user are written on Windows hosts.
+pub fn is_persisting_environment() -> bool {
+    PERSIST_ENVIRONMENT.load(Ordering::Relaxed)
+}
 
-#[cfg(windows)]
 /// Sets an environment variable for the current user.
+///
+/// The variable is only written to the registry on Windows hosts, it is set in the environment of
+/// the process elsewhere.
 pub fn set_environment_variable(key: &str, value: &str) -> Result<(), Error> {
     env::set_var(key, value);
+    if !is_persisting_environment() {
+        return Ok(());
+    }
 
-    let hkcu = RegKey::predef(HKEY_CURRENT_USER);
-    let environment_key = hkcu.open_subkey_with_flags("Environment", KEY_WRITE)?;
-    environment_key.set_value(key, &value)?;
+    #[cfg(windows)]
+    {
+        let hkcu = RegKey::predef(HKEY_CURRENT_USER);
+        let environment_key = hkcu.open_subkey_with_flags("Environment", KEY_WRITE)?;
+        environment_key.set_value(key, &value)?;
+    }
     Ok(())
 }
 
-#[cfg(windows)]
 /// Deletes an environment variable for the current user.
+///
+/// The variable is only deleted from the registry on Windows hosts, it is removed from the
+/// environment of the process elsewhere.
 pub fn delete_environment_variable(key: &str) -> Result<(), Error> {
     if env::var_os(key).is_none() {
         return Ok(());
     }
 
     env::remove_var(key);
+    if !is_persisting_environment() {
+        return Ok(());
+    }
+
+    #[cfg(windows)]
+    {
+        let hkcu = RegKey::predef(HKEY_CURRENT_USER);
+        let environment_key = hkcu.open_subkey_with_flags("Environment", KEY_READ | KEY_WRITE)?;
+        environment_key.delete_value(key)?;
+    }
+    Ok(())
+}
+
+/// Applies the exports of an installed component to the environment, on the platforms applying
+/// them: the variables are set for the current user, and the directories are prepended to the
+/// `PATH` of the process, which is written back once the installation completes.
+pub fn apply_exports(exports: &[ExportItem], platform: &dyn Platform) -> Result<(), Error> {
+    if !platform.applies_exports() {
+        return Ok(());
+    }
+    for export in exports {
+        match export {
+            ExportItem::PathAdd(dir) => {
+                let path = match env::var("PATH") {
+                    Ok(path) => format!(
+                        "{}{}{path}",
+                        platform.native_path(dir),
+                        platform.path_separator()
+                    ),
+                    Err(_) => platform.native_path(dir),
+                };
+                env::set_var("PATH", path);
+            }
+            ExportItem::EnvSet { key, value } => {
+                set_environment_variable(key, &platform.native_path(value))?
+            }
+            ExportItem::SourceLine(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Normalizes a Windows path so it can be compared with the values stored in the registry.
+fn normalize_windows_path(path: &str) -> String {
+    path.replace('/', r"\")
+        .trim_end_matches('\\')
+        .to_lowercase()
+}
+
+/// Returns the changes undoing the exports of an installation, given the current value of each
+/// variable returned by `lookup`: the new value of each variable, or `None` to delete it.
+///
+/// Only the values set by the exports are removed, variables changed since are left untouched.
+pub fn cleared_variables(
+    exports: &[ExportItem],
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Vec<(String, Option<String>)> {
+    let mut changes: Vec<(String, Option<String>)> = Vec::new();
+    let added: Vec<String> = exports
+        .iter()
+        .filter_map(|export| match export {
+            ExportItem::PathAdd(dir) => Some(normalize_windows_path(dir)),
+            _ => None,
+        })
+        .collect();
+    if let Some(path) = lookup("PATH").filter(|_| !added.is_empty()) {
+        let entries: Vec<&str> = path.split(';').collect();
+        let kept: Vec<&str> = entries
+            .iter()
+            .filter(|entry| !added.contains(&normalize_windows_path(entry)))
+            .copied()
+            .collect();
+        if kept.len() != entries.len() {
+            changes.push(("PATH".to_string(), Some(kept.join(";"))));
+        }
+    }
+    for export in exports {
+        if let ExportItem::EnvSet { key, value } = export {
+            let current = lookup(key);
+            if current.is_some_and(|current| {
+                normalize_windows_path(&current) == normalize_windows_path(value)
+            }) {
+                changes.push((key.clone(), None));
+            }
+        }
+    }
+    changes
+}
+
+/// Returns the exports pointing inside a directory, given the current value of each variable
+/// returned by `lookup`: the `PATH` entries and the given variables, to clear the ones of the
+/// installations without a manifest.
+pub fn exports_within(
+    dir: &Path,
+    keys: &[&str],
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Vec<ExportItem> {
+    let dir = normalize_windows_path(&dir.display().to_string());
+    let is_within = |value: &str| {
+        let value = normalize_windows_path(value);
+        value == dir || value.starts_with(&format!(r"{dir}\"))
+    };
+    let mut exports: Vec<ExportItem> = lookup("PATH")
+        .unwrap_or_default()
+        .split(';')
+        .filter(|entry| is_within(entry))
+        .map(|entry| ExportItem::PathAdd(entry.to_string()))
+        .collect();
+    for key in keys {
+        if let Some(value) = lookup(key).filter(|value| is_within(value)) {
+            exports.push(ExportItem::EnvSet {
+                key: key.to_string(),
+                value,
+            });
+        }
+    }
+    exports
+}
+
+/// Removes the variables set by the exports of an installation from the environment of the user,
+/// on the platforms applying them, and notifies the running applications of the change.
+pub fn clear_exports(exports: &[ExportItem], platform: &dyn Platform) -> Result<(), Error> {
+    if !platform.applies_exports() {
+        return Ok(());
+    }
+    // The process environment is also updated, as it is written back to the registry later on
+    for (key, value) in cleared_variables(exports, |key| env::var(key).ok()) {
+        match value {
+            Some(value) => env::set_var(key, value),
+            None => env::remove_var(key),
+        }
+    }
+    if !is_persisting_environment() {
+        return Ok(());
+    }
+    #[cfg(windows)]
+    clear_user_exports(exports)?;
+    Ok(())
+}
 
+#[cfg(windows)]
+/// Removes the variables set by the exports of an installation from the registry.
+fn clear_user_exports(exports: &[ExportItem]) -> Result<(), Error> {
     let hkcu = RegKey::predef(HKEY_CURRENT_USER);
     let environment_key = hkcu.open_subkey_with_flags("Environment", KEY_READ | KEY_WRITE)?;
-    environment_key.delete_value(key)?;
+    let changes = cleared_variables(exports, |key| environment_key.get_value(key).ok());
+    if changes.is_empty() {
+        return Ok(());
+    }
+    for (key, value) in &changes {
+        match value {
+            Some(value) => {
+                info!(
+                    "{} Updating '{}' in the user environment",
+                    emoji::WRENCH,
+                    key
+                );
+                environment_key.set_value(key, value)?;
+            }
+            None => {
+                info!(
+                    "{} Deleting '{}' from the user environment",
+                    emoji::WRENCH,
+                    key
+                );
+                environment_key.delete_value(key)?;
+            }
+        }
+    }
+    broadcast_environment_change();
     Ok(())
 }
 
-/// Returns the absolute path to the export file, uses the DEFAULT_EXPORT_FILE if no arg is provided.
-pub fn get_export_file(export_file: Option<PathBuf>) -> Result<PathBuf, Error> {
+#[cfg(windows)]
+/// Notifies the running applications, such as Explorer, that the user environment changed.
+fn broadcast_environment_change() {
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        SendMessageTimeoutW, HWND_BROADCAST, SMTO_ABORTIFHUNG, WM_SETTINGCHANGE,
+    };
+
+    let environment: Vec<u16> = "Environment".encode_utf16().chain([0]).collect();
+    let mut result = 0;
+    // SAFETY: the parameter is a null-terminated string outliving the call
+    unsafe {
+        SendMessageTimeoutW(
+            HWND_BROADCAST,
+            WM_SETTINGCHANGE,
+            0,
+            environment.as_ptr() as isize,
+            SMTO_ABORTIFHUNG,
+            5000,
+            &mut result,
+        );
+    }
+}
+
+/// Returns the name of the default export file of a toolchain, e.g. `export-esp.sh`.
+pub fn default_export_file(name: &str, shell: ExportShell) -> String {
+    format!("export-{name}.{}", shell.extension())
+}
+
+/// Returns the first line of the export file of a toolchain.
+fn export_file_header(name: &str, shell: ExportShell) -> String {
+    format!(
+        "{} Environment of the '{name}' toolchain, generated by espup",
+        shell.comment()
+    )
+}
+
+/// Returns true if the file is the export file generated by espup for a toolchain.
+pub fn is_generated_export_file(export_file: &Path, name: &str, shell: ExportShell) -> bool {
+    read_to_string(export_file)
+        .map(|contents| contents.lines().next() == Some(export_file_header(name, shell).as_str()))
+        .unwrap_or(false)
+}
+
+/// Returns the absolute path to the export file, the default one of the toolchain in the home
+/// directory if no arg is provided.
+pub fn get_export_file(
+    export_file: Option<PathBuf>,
+    name: &str,
+    shell: ExportShell,
+) -> Result<PathBuf, Error> {
     if let Some(export_file) = export_file {
         if export_file.is_dir() {
             return Err(Error::InvalidDestination(export_file.display().to_string()));
@@ -60,20 +396,29 @@ pub fn get_export_file(export_file: Option<PathBuf>) -> Result<PathBuf, Error> {
             Ok(current_dir.join(export_file))
         }
     } else {
-        Ok(BaseDirs::new()
-            .unwrap()
-            .home_dir()
-            .join(DEFAULT_EXPORT_FILE))
+        home_dir()
+            .map(|home| home.join(default_export_file(name, shell)))
+            .ok_or_else(|| Error::MissingHomeDirectory("--export-file".to_string()))
     }
 }
 
 /// Creates the export file with the necessary environment variables.
-pub fn create_export_file(export_file: &PathBuf, exports: &[String]) -> Result<(), Error> {
+pub fn create_export_file(
+    export_file: &PathBuf,
+    name: &str,
+    exports: &[ExportItem],
+    platform: &dyn Platform,
+    shell: ExportShell,
+) -> Result<(), Error> {
     info!("{} Creating export file", emoji::WRENCH);
     let mut file = File::create(export_file)?;
+    writeln!(file, "{}", export_file_header(name, shell))?;
     for e in exports.iter() {
-        #[cfg(windows)]
-        let e = e.replace('/', r"\");
+        let e = match shell {
+            ExportShell::Native => e.render(platform),
+            ExportShell::Cmd => e.render_cmd(),
+        };
+        let e = platform.native_path(&e);
         file.write_all(e.as_bytes())?;
         file.write_all(b"\n")?;
     }
@@ -81,10 +426,100 @@ pub fn create_export_file(export_file: &PathBuf, exports: &[String]) -> Result<(
     Ok(())
 }
 
+/// Appends the exports as `KEY=VALUE` lines to an environment file, such as `$GITHUB_ENV`.
+///
+/// Returns the appended lines.
+pub fn append_env_file(
+    env_file: &Path,
+    exports: &[ExportItem],
+    platform: &dyn Platform,
+) -> Result<Vec<String>, Error> {
+    info!(
+        "{} Appending exports to '{}'",
+        emoji::WRENCH,
+        env_file.display()
+    );
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(env_file)?;
+    let mut values: HashMap<String, String> = HashMap::new();
+    let mut lines = Vec::new();
+    for export in exports {
+        let lookup = |key: &str| values.get(key).cloned().or_else(|| env::var(key).ok());
+        if let Some((key, value)) = export.assignment(platform, lookup) {
+            let line = format!("{key}={value}");
+            writeln!(file, "{line}")?;
+            lines.push(line);
+            values.insert(key, value);
+        }
+    }
+    Ok(lines)
+}
+
+/// Returns the `activate` and `deactivate` scripts of an environment.
+///
+/// The activation saves the previous value of each variable it changes, deactivating any
+/// environment activated before, and the deactivation restores them, or unsets the variables
+/// that were not set.
+pub fn activation_scripts(
+    name: &str,
+    exports: &[ExportItem],
+    platform: &dyn Platform,
+    deactivate_script: &Path,
+) -> (String, String) {
+    let mut variables: Vec<&str> = Vec::new();
+    for export in exports {
+        let variable = match export {
+            ExportItem::PathAdd(_) => "PATH",
+            ExportItem::EnvSet { key, .. } => key.as_str(),
+            ExportItem::SourceLine(_) => continue,
+        };
+        if !variables.contains(&variable) {
+            variables.push(variable);
+        }
+    }
+    variables.push(ACTIVE_ENV_VAR);
+
+    let mut activate =
+        vec![platform.source_if_set(ACTIVE_ENV_VAR, &deactivate_script.display().to_string())];
+    let mut deactivate = Vec::new();
+    for variable in &variables {
+        let backup = format!("{BACKUP_PREFIX}{variable}");
+        activate.push(platform.save_var(variable, &backup));
+        deactivate.push(platform.restore_var(variable, &backup));
+    }
+    activate.extend(exports.iter().map(|export| export.render(platform)));
+    activate.push(platform.export_var(ACTIVE_ENV_VAR, name));
+    (activate.join("\n") + "\n", deactivate.join("\n") + "\n")
+}
+
+/// Creates the `activate` and `deactivate` scripts of an environment in a directory.
+pub fn create_activation_scripts(
+    dir: &Path,
+    name: &str,
+    exports: &[ExportItem],
+    platform: &dyn Platform,
+) -> Result<(), Error> {
+    create_dir_all(dir).map_err(|_| Error::CreateDirectory(dir.display().to_string()))?;
+    let extension = platform.activation_extension();
+    let activate_script = dir.join(format!("activate.{extension}"));
+    let deactivate_script = dir.join(format!("deactivate.{extension}"));
+    info!(
+        "{} Creating activation scripts in '{}'",
+        emoji::WRENCH,
+        dir.display()
+    );
+    let (activate, deactivate) = activation_scripts(name, exports, platform, &deactivate_script);
+    let activate = platform.native_path(&activate);
+    write(activate_script, activate)?;
+    write(deactivate_script, deactivate)?;
+    Ok(())
+}
+
 /// Instructions to export the environment variables.
-pub fn export_environment(export_file: &Path) -> Result<(), Error> {
-    #[cfg(windows)]
-    if cfg!(windows) {
+pub fn export_environment(export_file: &Path, platform: &dyn Platform) -> Result<(), Error> {
+    if platform.applies_exports() && is_persisting_environment() {
         set_environment_variable("PATH", &env::var("PATH").unwrap())?;
         warn!(
             "{} Your environments variables have been updated! Shell may need to be restarted for changes to be effective.",
@@ -95,50 +530,129 @@ pub fn export_environment(export_file: &Path) -> Result<(), Error> {
             emoji::INFO,
             export_file.display()
         );
+        return Ok(());
     }
-    #[cfg(unix)]
-    if cfg!(unix) {
+    let command = if export_file
+        .extension()
+        .is_some_and(|ext| ext == BATCH_FILE_EXTENSION)
+    {
+        "call"
+    } else {
+        "."
+    };
+    if platform.applies_exports() {
         warn!(
-            "{} Please, set up the environment variables by running: '. {}'",
+            "{} Your environment variables were left untouched, set them up by running: '{} {}'",
             emoji::INFO,
+            command,
             export_file.display()
         );
+    } else {
         warn!(
-            "{} This step must be done every time you open a new terminal.",
-            emoji::WARN
+            "{} Please, set up the environment variables by running: '{} {}'",
+            emoji::INFO,
+            command,
+            export_file.display()
         );
     }
+    warn!(
+        "{} This step must be done every time you open a new terminal.",
+        emoji::WARN
+    );
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::env::{create_export_file, get_export_file, DEFAULT_EXPORT_FILE};
+    use crate::{
+        env::{
+            activation_scripts, cleared_variables, create_export_file, default_export_file,
+            exports_within, get_export_file, is_generated_export_file, ExportItem, ExportShell,
+        },
+        host_triple::HostTriple,
+    };
     use directories::BaseDirs;
-    use std::{env::current_dir, path::PathBuf};
+    use std::{
+        env::current_dir,
+        path::{Path, PathBuf},
+    };
 
     #[test]
     #[allow(unused_variables)]
     fn test_get_export_file() {
         // No arg provided
         let home_dir = BaseDirs::new().unwrap().home_dir().to_path_buf();
-        let export_file = home_dir.join(DEFAULT_EXPORT_FILE);
-        assert!(matches!(get_export_file(None), Ok(export_file)));
+        let export_file = home_dir.join(default_export_file("esp", ExportShell::Native));
+        assert!(matches!(
+            get_export_file(None, "esp", ExportShell::Native),
+            Ok(export_file)
+        ));
+        assert!(default_export_file("esp-nightly", ExportShell::Native)
+            .starts_with("export-esp-nightly."));
+        assert_eq!(
+            default_export_file("esp", ExportShell::Cmd),
+            "export-esp.bat"
+        );
         // Relative path
         let current_dir = current_dir().unwrap();
         let export_file = current_dir.join("export.sh");
         assert!(matches!(
-            get_export_file(Some(PathBuf::from("export.sh"))),
+            get_export_file(Some(PathBuf::from("export.sh")), "esp", ExportShell::Native),
             Ok(export_file)
         ));
         // Absolute path
         let export_file = PathBuf::from("/home/user/export.sh");
         assert!(matches!(
-            get_export_file(Some(PathBuf::from("/home/user/export.sh"))),
+            get_export_file(
+                Some(PathBuf::from("/home/user/export.sh")),
+                "esp",
+                ExportShell::Native
+            ),
             Ok(export_file)
         ));
         // Path is a directory instead of a file
-        assert!(get_export_file(Some(home_dir)).is_err());
+        assert!(get_export_file(Some(home_dir), "esp", ExportShell::Native).is_err());
+    }
+
+    #[test]
+    fn test_activation_scripts() {
+        let platform = HostTriple::X86_64UnknownLinuxGnu.platform();
+        let exports = vec![
+            ExportItem::PathAdd("/esp/bin".to_string()),
+            ExportItem::EnvSet {
+                key: "LIBCLANG_PATH".to_string(),
+                value: "/esp/lib".to_string(),
+            },
+            ExportItem::PathAdd("/gcc/bin".to_string()),
+        ];
+        let (activate, deactivate) =
+            activation_scripts("esp", &exports, platform, Path::new("/env/deactivate.sh"));
+        let activate: Vec<&str> = activate.lines().collect();
+        assert_eq!(
+            activate[0],
+            "if [ -n \"${ESPUP_ENV+x}\" ]; then . '/env/deactivate.sh'; fi"
+        );
+        // Every variable is saved before being changed
+        assert_eq!(
+            activate[1],
+            "if [ -n \"${PATH+x}\" ]; then export _ESPUP_OLD_PATH=\"$PATH\"; else unset _ESPUP_OLD_PATH; fi"
+        );
+        assert!(activate[2].contains("_ESPUP_OLD_LIBCLANG_PATH"));
+        assert!(activate[3].contains("_ESPUP_OLD_ESPUP_ENV"));
+        assert_eq!(
+            &activate[4..],
+            [
+                "export PATH=\"/esp/bin:$PATH\"",
+                "export LIBCLANG_PATH=\"/esp/lib\"",
+                "export PATH=\"/gcc/bin:$PATH\"",
+                "export ESPUP_ENV=\"esp\"",
+            ]
+        );
+        assert_eq!(
+            deactivate.lines().next().unwrap(),
+            "if [ -n \"${_ESPUP_OLD_PATH+x}\" ]; then export PATH=\"$_ESPUP_OLD_PATH\"; unset _ESPUP_OLD_PATH; else unset PATH; fi"
+        );
+        assert_eq!(deactivate.lines().count(), 3);
     }
 
     #[test]
@@ -146,22 +660,162 @@ mod tests {
         // Creates the export file and writes the correct content to it
         let temp_dir = tempfile::TempDir::new().unwrap();
         let export_file = temp_dir.path().join("export.sh");
+        let platform = HostTriple::X86_64UnknownLinuxGnu.platform();
         let exports = vec![
-            "export VAR1=value1".to_string(),
-            "export VAR2=value2".to_string(),
+            ExportItem::EnvSet {
+                key: "VAR1".to_string(),
+                value: "value1".to_string(),
+            },
+            ExportItem::PathAdd("esp".to_string()),
+            ExportItem::SourceLine("# comment".to_string()),
         ];
-        create_export_file(&export_file, &exports).unwrap();
-        let contents = std::fs::read_to_string(export_file).unwrap();
-        assert_eq!(contents, "export VAR1=value1\nexport VAR2=value2\n");
+        create_export_file(
+            &export_file,
+            "esp-nightly",
+            &exports,
+            platform,
+            ExportShell::Native,
+        )
+        .unwrap();
+        let contents = std::fs::read_to_string(&export_file).unwrap();
+        assert_eq!(
+            contents,
+            "# Environment of the 'esp-nightly' toolchain, generated by espup\nexport VAR1=\"value1\"\nexport PATH=\"esp:$PATH\"\n# comment\n"
+        );
+        assert!(is_generated_export_file(
+            &export_file,
+            "esp-nightly",
+            ExportShell::Native
+        ));
+        assert!(!is_generated_export_file(
+            &export_file,
+            "esp",
+            ExportShell::Native
+        ));
+
+        // Batch files for `cmd.exe` quote the values and escape the expanded characters
+        let batch_file = temp_dir.path().join("export.bat");
+        let exports = vec![
+            ExportItem::EnvSet {
+                key: "LIBCLANG_PATH".to_string(),
+                value: "C:/Program Files/100%/libclang.dll".to_string(),
+            },
+            ExportItem::PathAdd("C:/esp/bin".to_string()),
+        ];
+        create_export_file(&batch_file, "esp", &exports, platform, ExportShell::Cmd).unwrap();
+        let contents = std::fs::read_to_string(&batch_file).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(
+            lines[0],
+            "@rem Environment of the 'esp' toolchain, generated by espup"
+        );
+        assert!(lines[1].starts_with("@set \"LIBCLANG_PATH=C:"));
+        assert!(lines[1].contains("100%%"));
+        assert!(lines[2].ends_with(";%PATH%\""));
+        assert!(is_generated_export_file(
+            &batch_file,
+            "esp",
+            ExportShell::Cmd
+        ));
 
         // Returns the correct error when it fails to create the export file (it already exists)
         let temp_dir = tempfile::TempDir::new().unwrap();
         let export_file = temp_dir.path().join("export.sh");
         std::fs::create_dir_all(&export_file).unwrap();
+        assert!(
+            create_export_file(&export_file, "esp", &exports, platform, ExportShell::Native)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_export_item_assignment() {
+        let linux = HostTriple::X86_64UnknownLinuxGnu.platform();
+        let windows = HostTriple::X86_64PcWindowsMsvc.platform();
+        let lookup = |key: &str| (key == "PATH").then(|| "/usr/bin".to_string());
+        let path_add = ExportItem::PathAdd("/esp/bin".to_string());
+        assert_eq!(
+            path_add.assignment(linux, lookup),
+            Some(("PATH".to_string(), "/esp/bin:/usr/bin".to_string()))
+        );
+        assert_eq!(
+            path_add.assignment(windows, lookup),
+            Some(("PATH".to_string(), "/esp/bin;/usr/bin".to_string()))
+        );
+        assert_eq!(
+            path_add.assignment(linux, |_| None),
+            Some(("PATH".to_string(), "/esp/bin".to_string()))
+        );
+        let env_set = ExportItem::EnvSet {
+            key: "LIBCLANG_PATH".to_string(),
+            value: "/esp/lib".to_string(),
+        };
+        assert_eq!(
+            env_set.assignment(linux, lookup),
+            Some(("LIBCLANG_PATH".to_string(), "/esp/lib".to_string()))
+        );
+        assert_eq!(env_set.render(windows), "$Env:LIBCLANG_PATH = \"/esp/lib\"");
+        assert_eq!(
+            ExportItem::SourceLine("# comment".to_string()).assignment(linux, lookup),
+            None
+        );
+    }
+
+    #[test]
+    fn test_cleared_variables() {
         let exports = vec![
-            "export VAR1=value1".to_string(),
-            "export VAR2=value2".to_string(),
+            ExportItem::EnvSet {
+                key: "LIBCLANG_PATH".to_string(),
+                value: "C:/Users/esp/.rustup/toolchains/esp/xtensa-esp32-elf-clang/lib".to_string(),
+            },
+            ExportItem::EnvSet {
+                key: "CLANG_PATH".to_string(),
+                value: "C:/esp/clang".to_string(),
+            },
+            ExportItem::PathAdd("C:/esp/bin".to_string()),
+            ExportItem::SourceLine("# comment".to_string()),
         ];
-        assert!(create_export_file(&export_file, &exports).is_err());
+        let lookup = |key: &str| match key {
+            "PATH" => Some(r"C:\Windows;C:\esp\bin\;C:\Tools".to_string()),
+            "LIBCLANG_PATH" => {
+                Some(r"C:\Users\esp\.rustup\toolchains\esp\xtensa-esp32-elf-clang\lib".to_string())
+            }
+            // Changed by the user since the installation
+            "CLANG_PATH" => Some(r"C:\other\clang".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            cleared_variables(&exports, lookup),
+            vec![
+                ("PATH".to_string(), Some(r"C:\Windows;C:\Tools".to_string())),
+                ("LIBCLANG_PATH".to_string(), None),
+            ]
+        );
+        assert!(cleared_variables(&exports, |_| None).is_empty());
+    }
+
+    #[test]
+    fn test_exports_within() {
+        let lookup = |key: &str| match key {
+            "PATH" => Some(r"C:\Windows;C:\esp\clang\bin;C:\esp\clang-other".to_string()),
+            "LIBCLANG_PATH" => Some("C:/esp/clang/lib".to_string()),
+            // Changed by the user since the installation
+            "CLANG_PATH" => Some(r"C:\other\clang".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            exports_within(
+                Path::new(r"C:\esp\clang"),
+                &["LIBCLANG_PATH", "CLANG_PATH"],
+                lookup
+            ),
+            vec![
+                ExportItem::PathAdd(r"C:\esp\clang\bin".to_string()),
+                ExportItem::EnvSet {
+                    key: "LIBCLANG_PATH".to_string(),
+                    value: "C:/esp/clang/lib".to_string(),
+                },
+            ]
+        );
     }
 }
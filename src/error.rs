@@ -4,14 +4,115 @@ use crate::emoji;
 
 #[derive(Debug, miette::Diagnostic, thiserror::Error)]
 pub enum Error {
+    #[diagnostic(code(espup::toolchain::artifact_not_found))]
+    #[error("{} Artifact '{0}' was not found.", emoji::ERROR)]
+    ArtifactNotFound(String),
+
+    #[diagnostic(code(espup::bundle::bundle_mismatch))]
+    #[error("{} Bundle '{0}' does not provide {1}.", emoji::ERROR)]
+    BundleMismatch(String, String),
+
+    #[diagnostic(code(espup::toolchain::checksum_mismatch))]
+    #[error(
+        "{} Checksum mismatch for '{0}': expected '{1}', got '{2}'. The download may be corrupted or truncated.",
+        emoji::ERROR
+    )]
+    ChecksumMismatch(String, String, String),
+
     #[diagnostic(code(espup::toolchain::create_directory))]
     #[error("{} Creating directory '{0}' failed", emoji::ERROR)]
     CreateDirectory(String),
 
+    #[diagnostic(code(espup::warnings::denied_warnings))]
+    #[error(
+        "{} Found {0} warning(s) and '--deny-warnings' is enabled.",
+        emoji::ERROR
+    )]
+    DeniedWarnings(usize),
+
+    #[diagnostic(code(espup::toolchain::graph::dependency_cycle))]
+    #[error("{} Circular dependency between the components: {0}", emoji::ERROR)]
+    DependencyCycle(String),
+
+    #[diagnostic(code(espup::doctor::failed_diagnostics))]
+    #[error(
+        "{} {0} diagnostic check(s) failed, apply the suggested fixes and run 'espup doctor' again.",
+        emoji::ERROR
+    )]
+    FailedDiagnostics(usize),
+
+    #[diagnostic(code(espup::deprecation::forbidden))]
+    #[error(
+        "{} Deprecated flags are forbidden, but '{0}' were used. Run without '--forbid-deprecated' to see their replacements.",
+        emoji::ERROR
+    )]
+    ForbiddenDeprecatedFlags(String),
+
+    #[diagnostic(code(espup::frozen::network_access))]
+    #[error(
+        "{} '{0}' requires network access, which is forbidden by '--frozen'. Run the installation once without '--frozen' to populate the caches.",
+        emoji::ERROR
+    )]
+    FrozenNetworkAccess(String),
+
     #[diagnostic(code(espup::toolchain::rust::query_github))]
     #[error("{} Failed to query GitHub API.", emoji::ERROR)]
     GithubQuery,
 
+    #[diagnostic(code(espup::toolchain::rust::github_rate_limit))]
+    #[error(
+        "{} GitHub API rate limit of {0} exceeded, requests will succeed again in {1}. Set the 'GITHUB_TOKEN' environment variable or use '--github-token' to increase the limit. Meanwhile, 'espup install --rebuild-exports-only', 'espup uninstall', 'espup bundle verify' and 'espup explain' do not require the API.",
+        emoji::ERROR
+    )]
+    GithubRateLimit(String, String),
+
+    #[diagnostic(code(espup::toolchain::rust::github_token))]
+    #[error(
+        "{} The GitHub token was rejected. Check the value of '--github-token' or the 'GITHUB_TOKEN' environment variable.",
+        emoji::ERROR
+    )]
+    GithubToken,
+
+    #[diagnostic(code(espup::hooks::hook_failed))]
+    #[error(
+        "{} Post-install hook '{0}' failed: {1}. The installation itself completed.",
+        emoji::ERROR
+    )]
+    HookFailed(String, String),
+
+    #[diagnostic(code(espup::preflight::host_requirement))]
+    #[error("{} {0} artifacts require {1} ≥ {2}; detected {3}", emoji::ERROR)]
+    HostRequirement(String, String, String, String),
+
+    #[diagnostic(code(espup::toolchain::rust::incompatible_binary))]
+    #[error("{} '{0}' cannot be executed on this host: {1}", emoji::ERROR)]
+    IncompatibleBinary(String, String),
+
+    #[diagnostic(code(espup::bundle::incomplete_bundle))]
+    #[error(
+        "{} Bundle '{0}' is incomplete: {1} missing and {2} mismatched artifacts.",
+        emoji::ERROR
+    )]
+    IncompleteBundle(String, usize, usize),
+
+    #[diagnostic(code(espup::segmented::incomplete_download))]
+    #[error(
+        "{} Download of '{0}' is incomplete: received {1} of {2} bytes.",
+        emoji::ERROR
+    )]
+    IncompleteDownload(String, u64, u64),
+
+    #[diagnostic(code(espup::verify::incomplete_installation))]
+    #[error(
+        "{} {0} chips are not fully supported by the installed GCC toolchains.",
+        emoji::ERROR
+    )]
+    IncompleteInstallation(usize),
+
+    #[diagnostic(code(espup::toolchain::extra::install_extra_crate))]
+    #[error("{} Failed to install the extra crate '{0}'.", emoji::ERROR)]
+    InstallExtraCrate(String),
+
     #[diagnostic(code(espup::toolchain::rust::install_riscv_target))]
     #[error(
         "{} Failed to Install RISC-V targets for '{0}' toolchain.",
@@ -19,6 +120,62 @@ pub enum Error {
     )]
     InstallRiscvTarget(String),
 
+    #[diagnostic(code(espup::footprint::install_too_large))]
+    #[error(
+        "{} The installation needs about {0}, more than the maximum of {1}:\n{2}\nSkip components with `--skip-llvm`, `--skip-gcc` or `--skip-rust-src`, or install fewer targets.",
+        emoji::ERROR
+    )]
+    InstallTooLarge(String, String, String),
+
+    #[diagnostic(code(espup::preflight::insufficient_disk_space))]
+    #[error(
+        "{} Not enough disk space for '{0}': about {1} required, {2} available. {3} Use `--force` to install anyway.",
+        emoji::ERROR
+    )]
+    InsufficientDiskSpace(String, String, String, String),
+
+    #[diagnostic(code(espup::toolchain::rust::riscv_target_not_installed))]
+    #[error(
+        "{} rustup reported success but '{1}' is still missing from the '{0}' toolchain. It may be a distribution wrapper with limited functionality: install rustup from https://rustup.rs/ or run '{2}'.",
+        emoji::ERROR
+    )]
+    RiscvTargetNotInstalled(String, String, String),
+
+    #[diagnostic(code(espup::tls::invalid_ca_cert))]
+    #[error(
+        "{} Certificate bundle '{0}' does not exist or does not contain any PEM certificate.",
+        emoji::ERROR
+    )]
+    InvalidCaCert(String),
+
+    #[diagnostic(code(espup::bundle::invalid_bundle))]
+    #[error(
+        "{} Bundle '{0}' does not exist or is not a valid bundle.",
+        emoji::ERROR
+    )]
+    InvalidBundle(String),
+
+    #[diagnostic(code(espup::pins::invalid_pin))]
+    #[error(
+        "{} Invalid {0} pin '{1}' in the configuration file. Use a release name such as 'esp-17.0.1_20231207' for LLVM or '13.2.0_20230928' for GCC.",
+        emoji::ERROR
+    )]
+    InvalidPin(String, String),
+
+    #[diagnostic(code(espup::pins::invalid_pins_file))]
+    #[error(
+        "{} Invalid pins file '{0}': {1}. Run `espup pins show --bundled` to print the bundled versions.",
+        emoji::ERROR
+    )]
+    InvalidPinsFile(String, String),
+
+    #[diagnostic(code(espup::toolchain::plugin::invalid_plugin))]
+    #[error(
+        "{} Invalid plugin '{0}': its URL does not point to a file.",
+        emoji::ERROR
+    )]
+    InvalidPlugin(String),
+
     #[diagnostic(code(espup::ivalid_destination))]
     #[error(
         "{} Invalid export file destination: '{0}'. Please, use an absolute or releative path (including the file and its extension).",
@@ -26,6 +183,13 @@ pub enum Error {
     )]
     InvalidDestination(String),
 
+    #[diagnostic(code(espup::credentials::invalid_mirror_url))]
+    #[error(
+        "{} Invalid mirror URL: '{0}'. Please, use the URL of the mirror, e.g. 'https://mirror.example.com'.",
+        emoji::ERROR
+    )]
+    InvalidMirrorUrl(String),
+
     #[diagnostic(code(espup::toolchain::rust::invalid_version))]
     #[error(
         "{} Invalid toolchain version '{0}'. Verify that the format is correct: '<major>.<minor>.<patch>.<subpatch>' or '<major>.<minor>.<patch>', and that the release exists in https://github.com/esp-rs/rust-build/releases",
@@ -36,6 +200,31 @@ pub enum Error {
     #[error(transparent)]
     IoError(#[from] std::io::Error),
 
+    #[diagnostic(code(espup::credentials::keyring_unavailable))]
+    #[error("{} The system keyring is unavailable: {0}", emoji::ERROR)]
+    KeyringUnavailable(String),
+
+    #[diagnostic(code(espup::home::missing_home_directory))]
+    #[error(
+        "{} The home directory could not be determined. Set the 'HOME' environment variable or use '{0}'.",
+        emoji::ERROR
+    )]
+    MissingHomeDirectory(String),
+
+    #[diagnostic(code(espup::manifest::missing_manifest))]
+    #[error(
+        "{} Failed to read installation manifest '{0}'. Please, run 'espup install' first.",
+        emoji::ERROR
+    )]
+    MissingManifest(String),
+
+    #[diagnostic(code(espup::offline::missing_offline_toolchain))]
+    #[error(
+        "{} No Xtensa Rust toolchain for this host was found in the artifact directory '{0}'. Add its artifacts or use '--toolchain-version'.",
+        emoji::ERROR
+    )]
+    MissingOfflineToolchain(String),
+
     #[diagnostic(code(espup::toolchain::rust::missing_rust))]
     #[error(
         "{} Rust is not installed. Please, install Rust via rustup: https://rustup.rs/",
@@ -43,10 +232,96 @@ pub enum Error {
     )]
     MissingRust,
 
+    #[diagnostic(code(espup::lockfile::lockfile_mismatch))]
+    #[error(
+        "{} The resolved {0} '{2}' does not match '{1}' in the lockfile. Run the installation without '--locked' to update the lockfile.",
+        emoji::ERROR
+    )]
+    LockfileMismatch(String, String, String),
+
+    #[diagnostic(code(espup::lockfile::read_lockfile))]
+    #[error("{} Failed to read lockfile '{0}'.", emoji::ERROR)]
+    ReadLockfile(String),
+
+    #[diagnostic(code(espup::project::read_toolchain_file))]
+    #[error(
+        "{} Failed to read toolchain file '{0}'. Make sure it declares a '[toolchain]' table.",
+        emoji::ERROR
+    )]
+    ReadToolchainFile(String),
+
     #[diagnostic(code(espup::remove_directory))]
     #[error("{} Failed to remove '{0}' directory.", emoji::ERROR)]
     RemoveDirectory(String),
 
+    #[diagnostic(code(espup::mirror::mirror_upload))]
+    #[error(
+        "{} Failed to upload to '{0}'. Make sure the AWS CLI is installed and has access to the bucket.",
+        emoji::ERROR
+    )]
+    MirrorUpload(String),
+
+    #[diagnostic(code(espup::proxy::invalid_proxy))]
+    #[error(
+        "{} Invalid proxy '{0}'. Use an `http://`, `https://`, `socks5://` or `socks5h://` URL.",
+        emoji::ERROR
+    )]
+    InvalidProxy(String),
+
+    #[diagnostic(code(espup::throttle::invalid_rate))]
+    #[error(
+        "{} Invalid rate '{0}', expected a number of bytes per second with an optional K, M or G suffix, e.g. '500K'.",
+        emoji::ERROR
+    )]
+    InvalidRate(String),
+
+    #[diagnostic(code(espup::footprint::invalid_size))]
+    #[error(
+        "{} Invalid size '{0}', expected a number of bytes with an optional K, M or G suffix, e.g. '4G'.",
+        emoji::ERROR
+    )]
+    InvalidSize(String),
+
+    #[diagnostic(code(espup::toolchain::missing_checksum))]
+    #[error(
+        "{} No SHA256 digest is available for '{0}', refusing to install it without verifying it.",
+        emoji::ERROR
+    )]
+    MissingChecksum(String),
+
+    #[diagnostic(code(espup::preflight::noexec_mount))]
+    #[error(
+        "{} '{0}' is on '{1}', which is mounted with `noexec` and does not allow executing binaries. {2}",
+        emoji::ERROR
+    )]
+    NoExecMount(String, String, String),
+
+    #[diagnostic(code(espup::oci::oci_registry))]
+    #[error("{} OCI registry error: {0}", emoji::ERROR)]
+    OciRegistry(String),
+
+    #[diagnostic(code(espup::mirror::no_mirror_available))]
+    #[error("{} None of the mirrors could be benchmarked.", emoji::ERROR)]
+    NoMirrorAvailable,
+
+    #[diagnostic(code(espup::manifest::no_previous_installation))]
+    #[error(
+        "{} The installation to roll back '{0}' to is not recorded. Run 'espup rollback --list' to list the recorded ones.",
+        emoji::ERROR
+    )]
+    NoPreviousInstallation(String),
+
+    #[diagnostic(code(espup::prompt::non_interactive))]
+    #[error(
+        "{} '{0}' requires an answer, but prompts are disabled by '--non-interactive'. Run with '--yes' to confirm it.",
+        emoji::ERROR
+    )]
+    NonInteractive(String),
+
+    #[diagnostic(code(espup::config::read_config))]
+    #[error("{} Failed to read configuration file '{0}'.", emoji::ERROR)]
+    ReadConfig(String),
+
     #[error(transparent)]
     RewquestError(#[from] reqwest::Error),
 
@@ -54,14 +329,51 @@ pub enum Error {
     #[error("{} Error detecting rustup: {0}", emoji::ERROR)]
     RustupDetection(String),
 
+    #[diagnostic(code(espup::selftest::self_test_failed))]
+    #[error("{} {0} self-test case(s) failed.", emoji::ERROR)]
+    SelfTestFailed(usize),
+
+    #[diagnostic(code(espup::selfupdate::self_update))]
+    #[error("{} Failed to update espup: {0}", emoji::ERROR)]
+    SelfUpdate(String),
+
     #[diagnostic(code(espup::toolchain::rust::serialize_json))]
     #[error("{} Failed to serialize json from string.", emoji::ERROR)]
     SerializeJson,
 
+    #[diagnostic(code(espup::toolchain::rust::set_default_toolchain))]
+    #[error("{} Failed to set '{0}' as default toolchain.", emoji::ERROR)]
+    SetDefaultToolchain(String),
+
+    #[diagnostic(code(espup::toolchain::rust::set_override))]
+    #[error("{} Failed to update the toolchain override of '{0}'.", emoji::ERROR)]
+    SetOverride(String),
+
+    #[diagnostic(code(espup::timeout::timeout))]
+    #[error(
+        "{} Request to '{0}' timed out after {1} seconds. Increase the timeouts with `--connect-timeout` and `--read-timeout`.",
+        emoji::ERROR
+    )]
+    Timeout(String, u64),
+
     #[diagnostic(code(espup::toolchain::rust::uninstall_riscv_target))]
     #[error("{} Failed to uninstall RISC-V target.", emoji::ERROR)]
     UninstallRiscvTarget,
 
+    #[diagnostic(code(espup::lockfile::unlocked_artifact))]
+    #[error(
+        "{} Artifact '{0}' is not in the lockfile. Run the installation without '--locked' to add it to the lockfile.",
+        emoji::ERROR
+    )]
+    UnlockedArtifact(String),
+
+    #[diagnostic(code(espup::explain::unknown_error_code))]
+    #[error(
+        "{} No documentation available for error code '{0}'. Run 'espup explain' to list the documented codes.",
+        emoji::ERROR
+    )]
+    UnknownErrorCode(String),
+
     #[diagnostic(code(espup::toolchain::unsupported_file_extension))]
     #[error("{} Unsuported file extension: '{0}'", emoji::ERROR)]
     UnsuportedFileExtension(String),
@@ -71,8 +383,20 @@ pub enum Error {
     UnsupportedHostTriple(String),
 
     #[diagnostic(code(espup::targets::unsupported_target))]
-    #[error("{} Target '{0}' is not supported", emoji::ERROR)]
-    UnsupportedTarget(String),
+    #[error("{} Target '{0}' is not supported. {1}", emoji::ERROR)]
+    UnsupportedTarget(String, String),
+
+    #[diagnostic(code(espup::config::write_config))]
+    #[error("{} Failed to write configuration file '{0}'.", emoji::ERROR)]
+    WriteConfig(String),
+
+    #[diagnostic(code(espup::lockfile::write_lockfile))]
+    #[error("{} Failed to write lockfile '{0}'.", emoji::ERROR)]
+    WriteLockfile(String),
+
+    #[diagnostic(code(espup::project::write_toolchain_file))]
+    #[error("{} Failed to write toolchain file '{0}'.", emoji::ERROR)]
+    WriteToolchainFile(String),
 
     #[diagnostic(code(espup::toolchain::rust::rust))]
     #[error("{} Failed to install 'rust' component of Xtensa Rust", emoji::ERROR)]
@@ -0,0 +1,130 @@
+//! Offline documentation of the errors, embedded in the binary.
+
+use crate::error::Error;
+use std::env;
+
+/// Language used when a page is not available in the requested one.
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+struct Page {
+    /// Diagnostic code of the error.
+    code: &'static str,
+    /// Language of the page.
+    language: &'static str,
+    /// Contents of the page.
+    text: &'static str,
+}
+
+macro_rules! page {
+    ($code:literal, $language:literal, $file:literal) => {
+        Page {
+            code: $code,
+            language: $language,
+            text: include_str!(concat!("../docs/errors/", $language, "/", $file, ".md")),
+        }
+    };
+}
+
+const PAGES: &[Page] = &[
+    page!(
+        "espup::toolchain::create_directory",
+        "en",
+        "create_directory"
+    ),
+    page!(
+        "espup::preflight::host_requirement",
+        "en",
+        "host_requirement"
+    ),
+    page!(
+        "espup::toolchain::rust::incompatible_binary",
+        "en",
+        "incompatible_binary"
+    ),
+    page!(
+        "espup::toolchain::rust::invalid_version",
+        "en",
+        "invalid_version"
+    ),
+    page!("espup::toolchain::rust::missing_rust", "en", "missing_rust"),
+    page!("espup::toolchain::rust::missing_rust", "es", "missing_rust"),
+    page!("espup::toolchain::rust::query_github", "en", "query_github"),
+    page!("espup::toolchain::rust::query_github", "es", "query_github"),
+    page!(
+        "espup::host_triple::unsupported_host_triple",
+        "en",
+        "unsupported_host_triple"
+    ),
+    page!(
+        "espup::targets::unsupported_target",
+        "en",
+        "unsupported_target"
+    ),
+    page!(
+        "espup::targets::unsupported_target",
+        "es",
+        "unsupported_target"
+    ),
+];
+
+/// Returns true if the code matches the diagnostic code, either fully or by its last segment.
+fn matches_code(page_code: &str, code: &str) -> bool {
+    page_code == code || page_code.rsplit("::").next() == Some(code)
+}
+
+/// Returns the language of the user, based on the `LC_ALL`, `LC_MESSAGES` and `LANG` variables.
+pub fn detect_language() -> String {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .and_then(|value| {
+            value
+                .split(['_', '.', '-'])
+                .next()
+                .map(|language| language.to_lowercase())
+        })
+        .filter(|language| language != "c" && language != "posix")
+        .unwrap_or_else(|| DEFAULT_LANGUAGE.to_string())
+}
+
+/// Returns the documentation of an error in the given language, falling back to English.
+pub fn explain(code: &str, language: &str) -> Result<&'static str, Error> {
+    let pages: Vec<&Page> = PAGES
+        .iter()
+        .filter(|page| matches_code(page.code, code))
+        .collect();
+    pages
+        .iter()
+        .find(|page| page.language == language)
+        .or_else(|| pages.iter().find(|page| page.language == DEFAULT_LANGUAGE))
+        .map(|page| page.text)
+        .ok_or_else(|| Error::UnknownErrorCode(code.to_string()))
+}
+
+/// Returns the diagnostic codes with available documentation.
+pub fn codes() -> Vec<&'static str> {
+    let mut codes: Vec<&str> = PAGES.iter().map(|page| page.code).collect();
+    codes.sort();
+    codes.dedup();
+    codes
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::explain::{codes, explain};
+
+    #[test]
+    fn test_explain() {
+        let english = explain("espup::toolchain::rust::missing_rust", "en").unwrap();
+        assert!(english.contains("How to fix it"));
+        assert_eq!(explain("missing_rust", "en").unwrap(), english);
+        assert!(explain("missing_rust", "es")
+            .unwrap()
+            .contains("Cómo solucionarlo"));
+        // Falls back to English
+        assert_eq!(explain("missing_rust", "de").unwrap(), english);
+        assert!(explain("unknown_code", "en").is_err());
+        assert!(codes().contains(&"espup::targets::unsupported_target"));
+    }
+}
@@ -0,0 +1,192 @@
+//! Estimated disk footprint of an installation, checked against `--max-install-size` in
+//! constrained environments such as small cloud dev containers.
+//!
+//! The footprint is computed from the sizes of the artifacts to download, announced by their
+//! servers, falling back to estimates when a size is not known, e.g. behind a proxy that does not
+//! forward it.
+
+use crate::{emoji, error::Error, throttle::parse_rate};
+use log::info;
+use std::cmp::Reverse;
+
+/// Bytes in a MiB.
+const MIB: u64 = 1024 * 1024;
+/// Approximate ratio between the size of the extracted artifacts and the size of the archives.
+const EXPANSION_RATIO: u64 = 4;
+/// Name of the LLVM tools added by `--extended-llvm`.
+pub const LLVM_EXTENDED_TOOLS: &str = "LLVM extended tools";
+/// Estimated size of the Xtensa Rust artifact, when its server does not announce it.
+pub const XTENSA_RUST_SIZE: u64 = 150 * MIB;
+/// Estimated size of the `rust-src` artifact, when its server does not announce it.
+pub const RUST_SRC_SIZE: u64 = 12 * MIB;
+/// Name of the `rust-docs` component added by `--with-docs`.
+pub const RUST_DOCS: &str = "rust-docs";
+/// Estimated installed size of the `rust-docs` component, which is part of the Xtensa Rust
+/// artifact and does not add to the download.
+pub const RUST_DOCS_SIZE: u64 = 400 * MIB;
+/// Estimated size of the `libclang` artifact, when its server does not announce it.
+pub const LLVM_SIZE: u64 = 40 * MIB;
+/// Estimated size of the full LLVM artifact selected by `--extended-llvm`, when its server does
+/// not announce it.
+pub const LLVM_EXTENDED_SIZE: u64 = 215 * MIB;
+/// Estimated installed size of the RISC-V targets, including a minimal nightly toolchain,
+/// installed by rustup rather than downloaded from a release.
+pub const RISCV_TARGETS_SIZE: u64 = 350 * MIB;
+/// Estimated size of a GCC artifact, when its server does not announce it.
+pub const GCC_SIZE: u64 = 90 * MIB;
+
+/// Parses a size in bytes, with an optional `K`, `M` or `G` suffix, e.g. `4G`.
+pub fn parse_size(size: &str) -> Result<u64, Error> {
+    parse_rate(size).map_err(|_| Error::InvalidSize(size.to_string()))
+}
+
+/// Formats a size in MiB.
+pub fn format_size(bytes: u64) -> String {
+    format!("{:.0} MiB", bytes as f64 / MIB as f64)
+}
+
+/// Returns the estimated size of the extracted contents of an archive.
+pub fn installed_size(archive_size: u64) -> u64 {
+    archive_size * EXPANSION_RATIO
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FootprintItem {
+    /// Name of the installed piece.
+    pub name: String,
+    /// Estimated installed size, in bytes.
+    pub size: u64,
+    /// Size of the artifacts downloaded to install the piece, in bytes.
+    pub download_size: u64,
+    /// Whether the piece can be left out to fit the maximum size.
+    pub optional: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Footprint {
+    /// Installed pieces.
+    pub items: Vec<FootprintItem>,
+}
+
+impl Footprint {
+    /// Adds a required piece downloaded as an archive of the given size.
+    pub fn add_archive(&mut self, name: impl Into<String>, archive_size: u64) {
+        self.add(name, installed_size(archive_size), archive_size);
+    }
+
+    /// Adds a required piece.
+    pub fn add(&mut self, name: impl Into<String>, size: u64, download_size: u64) {
+        self.items.push(FootprintItem {
+            name: name.into(),
+            size,
+            download_size,
+            optional: false,
+        });
+    }
+
+    /// Adds a piece that can be left out to fit the maximum size.
+    pub fn add_optional(&mut self, name: impl Into<String>, size: u64, download_size: u64) {
+        self.items.push(FootprintItem {
+            name: name.into(),
+            size,
+            download_size,
+            optional: true,
+        });
+    }
+
+    /// Returns the estimated installed size, in bytes.
+    pub fn total(&self) -> u64 {
+        self.items.iter().map(|item| item.size).sum()
+    }
+
+    /// Returns the size of the artifacts downloaded to install the pieces, in bytes.
+    pub fn download_size(&self) -> u64 {
+        self.items.iter().map(|item| item.download_size).sum()
+    }
+
+    /// Removes a piece, e.g. because it is already installed.
+    pub fn remove(&mut self, name: &str) {
+        self.items.retain(|item| item.name != name);
+    }
+
+    /// Returns the size of each piece, one per line, largest first.
+    pub fn breakdown(&self) -> String {
+        let mut items: Vec<&FootprintItem> = self.items.iter().collect();
+        items.sort_by_key(|item| Reverse(item.size));
+        items
+            .iter()
+            .map(|item| {
+                format!(
+                    "  {}: {}{}",
+                    item.name,
+                    format_size(item.size),
+                    if item.optional { " (optional)" } else { "" }
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Leaves out optional pieces, largest first, until the footprint fits the maximum size.
+    ///
+    /// Returns the names of the pieces left out, or an error with the breakdown of the
+    /// footprint if it does not fit even without them.
+    pub fn fit(&mut self, max_size: u64) -> Result<Vec<String>, Error> {
+        let mut trimmed = Vec::new();
+        while self.total() > max_size {
+            let Some(index) = self
+                .items
+                .iter()
+                .enumerate()
+                .filter(|(_, item)| item.optional)
+                .max_by_key(|(_, item)| item.size)
+                .map(|(index, _)| index)
+            else {
+                return Err(Error::InstallTooLarge(
+                    format_size(self.total()),
+                    format_size(max_size),
+                    self.breakdown(),
+                ));
+            };
+            let item = self.items.remove(index);
+            info!(
+                "{} Leaving out {} ({}) to fit the maximum installation size",
+                emoji::INFO,
+                item.name,
+                format_size(item.size)
+            );
+            trimmed.push(item.name);
+        }
+        Ok(trimmed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::footprint::{parse_size, Footprint, LLVM_EXTENDED_TOOLS, MIB};
+
+    #[test]
+    fn test_fit() {
+        assert_eq!(parse_size("2G").unwrap(), 2048 * MIB);
+        assert!(parse_size("lots").is_err());
+
+        let mut footprint = Footprint::default();
+        footprint.add_archive("LLVM", 40 * MIB);
+        footprint.add_optional(LLVM_EXTENDED_TOOLS, 700 * MIB, 175 * MIB);
+        footprint.add_optional("docs", 10 * MIB, 0);
+        footprint.add_archive("GCC", 90 * MIB);
+        assert_eq!(footprint.total(), 1230 * MIB);
+        assert_eq!(footprint.download_size(), 305 * MIB);
+        assert!(footprint
+            .breakdown()
+            .starts_with("  LLVM extended tools: 700 MiB (optional)"));
+
+        assert!(footprint.clone().fit(2048 * MIB).unwrap().is_empty());
+        // Only the largest optional piece is left out when it is enough
+        let mut trimmed = footprint.clone();
+        assert_eq!(trimmed.fit(1000 * MIB).unwrap(), vec![LLVM_EXTENDED_TOOLS]);
+        assert_eq!(trimmed.total(), 530 * MIB);
+        assert_eq!(trimmed.download_size(), 130 * MIB);
+        assert!(footprint.fit(400 * MIB).is_err());
+    }
+}
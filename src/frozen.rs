@@ -0,0 +1,43 @@
+//! Frozen installations, resolving everything from the caches and failing on any network access.
+
+use crate::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static FROZEN: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether network access is forbidden.
+pub fn set_frozen(frozen: bool) {
+    FROZEN.store(frozen, Ordering::Relaxed);
+}
+
+/// Returns true if network access is forbidden.
+pub fn is_frozen() -> bool {
+    FROZEN.load(Ordering::Relaxed)
+}
+
+/// Returns the error of a network access to `resource`, if it is forbidden.
+fn check_network(frozen: bool, resource: &str) -> Result<(), Error> {
+    if frozen {
+        return Err(Error::FrozenNetworkAccess(resource.to_string()));
+    }
+    Ok(())
+}
+
+/// Fails if network access is forbidden, as `resource` is not cached and has to be fetched.
+pub fn ensure_network(resource: &str) -> Result<(), Error> {
+    check_network(is_frozen(), resource)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{error::Error, frozen::check_network};
+
+    #[test]
+    fn test_check_network() {
+        assert!(check_network(false, "https://github.com").is_ok());
+        assert!(matches!(
+            check_network(true, "https://github.com"),
+            Err(Error::FrozenNetworkAccess(resource)) if resource == "https://github.com"
+        ));
+    }
+}
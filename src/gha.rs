@@ -0,0 +1,245 @@
+//! GitHub Actions support: cache keys, cache restoration and step outputs.
+
+use crate::{
+    checksum::sha256, cli::InstallOpts, emoji, error::Error, host_triple::HostTriple,
+    manifest::InstallManifest,
+};
+use log::info;
+use std::{
+    env,
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// Prefix of the cache keys.
+const CACHE_KEY_PREFIX: &str = "espup";
+/// Number of hex digits of the digest used in the cache keys.
+const CACHE_KEY_DIGEST_LEN: usize = 16;
+
+/// Returns the cache key of an installation of the resolved Xtensa Rust version, derived from the
+/// lockfile if there is one and from the installation options otherwise.
+pub fn cache_key(
+    host_triple: &HostTriple,
+    toolchain_version: &str,
+    lockfile: Option<&str>,
+    args: &InstallOpts,
+) -> String {
+    let source = match lockfile {
+        Some(lockfile) => format!("{host_triple}|{toolchain_version}|{lockfile}"),
+        None => {
+            let mut targets: Vec<String> = args.targets.iter().map(|t| t.to_string()).collect();
+            targets.sort();
+            let mut source = format!(
+                "{}|{}|{}|{}|{}|{}|{}",
+                host_triple,
+                args.name,
+                toolchain_version,
+                args.nightly_version,
+                targets.join(","),
+                !args.installs_gcc(),
+                args.extended_llvm
+            );
+            // Only the installations skipping components have different keys
+            for (skipped, component) in [(args.skip_llvm, "llvm"), (args.skip_rust_src, "rust-src")]
+            {
+                if skipped {
+                    source.push_str(&format!("|skip-{component}"));
+                }
+            }
+            if args.with_docs {
+                source.push_str("|with-docs");
+            }
+            source
+        }
+    };
+    let digest = sha256(source.as_bytes());
+    format!(
+        "{CACHE_KEY_PREFIX}-{host_triple}-{}",
+        &digest[..CACHE_KEY_DIGEST_LEN]
+    )
+}
+
+/// Returns true if a restored installation satisfies the installation options.
+pub fn is_cache_hit(manifest: &InstallManifest, args: &InstallOpts) -> bool {
+    let version_matches = match &args.toolchain_version {
+        Some(version) => manifest.xtensa_rust_version.as_deref() == Some(version.as_str()),
+        None => true,
+    };
+    // The restored installation may have more components than required, but not fewer
+    let components_match = (manifest.extended_llvm || !args.extended_llvm)
+        && (!manifest.skip_llvm || args.skip_llvm)
+        && (!manifest.skip_rust_src || args.skip_rust_src)
+        && (manifest.gcc_release.is_some() || !args.installs_gcc());
+    version_matches
+        && components_match
+        && manifest.nightly_version == args.nightly_version
+        && args
+            .targets
+            .iter()
+            .all(|target| manifest.targets.contains(&target.to_string()))
+}
+
+/// Returns the file where the step outputs are written, from `$GITHUB_OUTPUT`.
+pub fn get_output_file() -> Option<PathBuf> {
+    env::var_os("GITHUB_OUTPUT").map(PathBuf::from)
+}
+
+/// Appends the step outputs to the output file.
+pub fn write_outputs(output_file: &Path, outputs: &[(&str, String)]) -> Result<(), Error> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(output_file)?;
+    for (name, value) in outputs {
+        info!("{} Output '{}': {}", emoji::INFO, name, value);
+        writeln!(file, "{name}={value}")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cli::InstallOpts,
+        gha::{cache_key, is_cache_hit, write_outputs},
+        host_triple::HostTriple,
+        manifest::InstallManifest,
+    };
+    use clap::Parser;
+
+    const VERSION: &str = "1.73.0.1";
+
+    fn install_opts(args: &[&str]) -> InstallOpts {
+        InstallOpts::parse_from([&["install"], args].concat())
+    }
+
+    #[test]
+    fn test_cache_key() {
+        let host_triple = HostTriple::X86_64UnknownLinuxGnu;
+        let args = install_opts(&["-t", "esp32,esp32c3"]);
+        let key = cache_key(&host_triple, VERSION, None, &args);
+        assert!(key.starts_with("espup-x86_64-unknown-linux-gnu-"));
+        // Target order does not matter
+        assert_eq!(
+            cache_key(
+                &host_triple,
+                VERSION,
+                None,
+                &install_opts(&["-t", "esp32c3,esp32"])
+            ),
+            key
+        );
+        assert_ne!(
+            cache_key(
+                &host_triple,
+                VERSION,
+                None,
+                &install_opts(&["-t", "esp32,esp32c3", "--skip-llvm"])
+            ),
+            key
+        );
+        assert_eq!(
+            cache_key(
+                &host_triple,
+                VERSION,
+                None,
+                &install_opts(&["-t", "esp32,esp32c3", "--skip-gcc"])
+            ),
+            cache_key(
+                &host_triple,
+                VERSION,
+                None,
+                &install_opts(&["-t", "esp32,esp32c3", "--std"])
+            )
+        );
+        assert_ne!(
+            cache_key(&host_triple, VERSION, None, &install_opts(&["-t", "esp32"])),
+            key
+        );
+        assert_ne!(cache_key(&host_triple, VERSION, Some("{}"), &args), key);
+        // A new Xtensa Rust release does not restore the installation of the previous one
+        assert_ne!(cache_key(&host_triple, "1.74.0.0", None, &args), key);
+        assert_ne!(
+            cache_key(
+                &HostTriple::Aarch64UnknownLinuxGnu,
+                VERSION,
+                Some("{}"),
+                &args
+            ),
+            cache_key(&host_triple, VERSION, Some("{}"), &args)
+        );
+    }
+
+    #[test]
+    fn test_is_cache_hit() {
+        let manifest = InstallManifest {
+            host_triple: "x86_64-unknown-linux-gnu".to_string(),
+            llvm_version: "esp-16.0.0-20230516".to_string(),
+            nightly_version: "nightly".to_string(),
+            targets: vec!["esp32".to_string(), "esp32c3".to_string()],
+            xtensa_rust_version: Some("1.73.0.1".to_string()),
+            exports: Vec::new(),
+            rustup_artifacts: Default::default(),
+            previous_default: None,
+            overrides: Vec::new(),
+            gcc_release: Some("13.2.0_20230928".to_string()),
+            extended_llvm: false,
+            skip_llvm: false,
+            skip_rust_src: false,
+            history: Vec::new(),
+            cargo_home: None,
+            rustup_home: None,
+            export_file_only: false,
+            export_files: Vec::new(),
+            profile_edits: Vec::new(),
+        };
+        assert!(is_cache_hit(&manifest, &install_opts(&["-t", "esp32"])));
+        assert!(is_cache_hit(
+            &manifest,
+            &install_opts(&["-t", "esp32", "-v", "1.73.0.1"])
+        ));
+        assert!(!is_cache_hit(
+            &manifest,
+            &install_opts(&["-t", "esp32", "-v", "1.74.0.0"])
+        ));
+        assert!(!is_cache_hit(&manifest, &install_opts(&["-t", "esp32s3"])));
+        assert!(!is_cache_hit(
+            &manifest,
+            &install_opts(&["-t", "esp32", "--extended-llvm"])
+        ));
+        assert!(is_cache_hit(
+            &manifest,
+            &install_opts(&["-t", "esp32", "--skip-llvm"])
+        ));
+        let skipped = InstallManifest {
+            skip_llvm: true,
+            ..manifest
+        };
+        assert!(!is_cache_hit(&skipped, &install_opts(&["-t", "esp32"])));
+        let without_gcc = InstallManifest {
+            gcc_release: None,
+            ..skipped
+        };
+        assert!(is_cache_hit(
+            &without_gcc,
+            &install_opts(&["-t", "esp32", "--skip-llvm", "--std"])
+        ));
+        assert!(!is_cache_hit(
+            &without_gcc,
+            &install_opts(&["-t", "esp32", "--skip-llvm"])
+        ));
+    }
+
+    #[test]
+    fn test_write_outputs() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let output_file = temp_dir.path().join("output");
+        write_outputs(&output_file, &[("cache-hit", "true".to_string())]).unwrap();
+        write_outputs(&output_file, &[("cache-key", "espup-key".to_string())]).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(output_file).unwrap(),
+            "cache-hit=true\ncache-key=espup-key\n"
+        );
+    }
+}
@@ -0,0 +1,369 @@
+//! GitHub API authentication, rate limit, response cache and release assets support.
+
+use crate::{checksum::sha256, credentials::github_token, error::Error, host_triple::HostTriple};
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    fs::{create_dir_all, read_to_string},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use strum::IntoEnumIterator;
+use tempfile::NamedTempFile;
+
+/// Number of remaining requests under which a warning is shown.
+pub const RATE_LIMIT_WARNING_THRESHOLD: u64 = 10;
+
+/// Environment variable the GitHub token is read from.
+pub const GITHUB_TOKEN_VAR: &str = "GITHUB_TOKEN";
+
+static TOKEN: Mutex<Option<String>> = Mutex::new(None);
+
+/// Sets the token used to authenticate the GitHub API requests.
+pub fn set_token(token: Option<String>) {
+    *TOKEN.lock().unwrap() = token;
+}
+
+/// Returns the token used to authenticate the GitHub API requests, falling back to the
+/// `GITHUB_TOKEN` environment variable and the token stored with `espup auth login`.
+pub fn get_token() -> Option<String> {
+    first_token([
+        TOKEN.lock().unwrap().clone(),
+        env::var(GITHUB_TOKEN_VAR).ok(),
+    ])
+    .or_else(github_token)
+}
+
+/// Returns the first token set and not empty, by priority.
+fn first_token(tokens: impl IntoIterator<Item = Option<String>>) -> Option<String> {
+    tokens.into_iter().flatten().find(|token| !token.is_empty())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Maximum number of requests per hour.
+    pub limit: Option<u64>,
+    /// Number of requests remaining in the current window.
+    pub remaining: u64,
+    /// Time when the current window resets, in seconds since the Unix epoch.
+    pub reset: u64,
+}
+
+impl RateLimit {
+    /// Parses the rate limit from the `X-RateLimit-*` headers of a response.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let parse = |name: &str| -> Option<u64> { headers.get(name)?.to_str().ok()?.parse().ok() };
+        Some(Self {
+            limit: parse("x-ratelimit-limit"),
+            remaining: parse("x-ratelimit-remaining")?,
+            reset: parse("x-ratelimit-reset")?,
+        })
+    }
+
+    /// Returns true if there are no requests left in the current window.
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining == 0
+    }
+
+    /// Returns true if the remaining requests are close to the limit.
+    pub fn is_low(&self) -> bool {
+        self.remaining <= RATE_LIMIT_WARNING_THRESHOLD
+    }
+
+    /// Returns the time left until the rate limit resets, relative to `now` in seconds since the Unix epoch.
+    pub fn reset_in(&self, now: u64) -> Duration {
+        Duration::from_secs(self.reset.saturating_sub(now))
+    }
+
+    /// Describes the maximum number of requests.
+    pub fn describe_limit(&self) -> String {
+        match self.limit {
+            Some(limit) => format!("{limit} requests per hour"),
+            None => "unknown requests per hour".to_string(),
+        }
+    }
+
+    /// Describes when requests will succeed again.
+    pub fn describe_reset(&self) -> String {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        format_duration(self.reset_in(now))
+    }
+}
+
+/// Directory, inside the cache directory, where the GitHub API responses are cached.
+pub(crate) const API_CACHE_DIR: &str = "api";
+
+/// GitHub API response, revalidated with its `ETag` before being reused.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct CachedResponse {
+    /// `ETag` header of the response.
+    pub etag: String,
+    /// Body of the response.
+    pub body: String,
+}
+
+/// Returns the path of the cached response of an API URL.
+fn cached_response_path(cache_dir: &Path, url: &str) -> PathBuf {
+    cache_dir
+        .join(API_CACHE_DIR)
+        .join(format!("{}.json", &sha256(url.as_bytes())[..16]))
+}
+
+/// Reads the cached response of an API URL, returning `None` if it is not cached.
+pub fn read_cached_response(cache_dir: &Path, url: &str) -> Option<CachedResponse> {
+    let contents = read_to_string(cached_response_path(cache_dir, url)).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+/// Stores the response of an API URL in the cache.
+pub fn store_cached_response(
+    cache_dir: &Path,
+    url: &str,
+    response: &CachedResponse,
+) -> Result<(), Error> {
+    let path = cached_response_path(cache_dir, url);
+    let dir = path.parent().unwrap();
+    create_dir_all(dir).map_err(|_| Error::CreateDirectory(dir.display().to_string()))?;
+    let contents = serde_json::to_string(response).map_err(|_| Error::SerializeJson)?;
+    // Concurrent queries never read a partially written response
+    let mut file = NamedTempFile::new_in(dir)?;
+    file.write_all(contents.as_bytes())?;
+    file.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// Release download URL prefix.
+const RELEASE_DOWNLOAD_PREFIX: &str = "https://github.com/";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReleaseAsset {
+    /// Repository owner.
+    pub owner: String,
+    /// Repository name.
+    pub repo: String,
+    /// Release tag.
+    pub tag: String,
+    /// Asset file name.
+    pub file_name: String,
+}
+
+impl ReleaseAsset {
+    /// Parses a `https://github.com/<owner>/<repo>/releases/download/<tag>/<file>` URL.
+    pub fn from_url(url: &str) -> Option<Self> {
+        let path = url.strip_prefix(RELEASE_DOWNLOAD_PREFIX)?;
+        let parts: Vec<&str> = path.split('/').collect();
+        match parts.as_slice() {
+            [owner, repo, "releases", "download", tag, file_name] => Some(Self {
+                owner: owner.to_string(),
+                repo: repo.to_string(),
+                tag: tag.to_string(),
+                file_name: file_name.to_string(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns the GitHub API URL of the release.
+    pub fn release_api_url(&self) -> String {
+        format!(
+            "https://api.github.com/repos/{}/{}/releases/tags/{}",
+            self.owner, self.repo, self.tag
+        )
+    }
+}
+
+/// Splits an asset name in lowercase tokens.
+fn tokens(name: &str) -> Vec<String> {
+    name.to_lowercase()
+        .split(['-', '_', '.'])
+        .filter(|token| !token.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Returns the hosts an asset is built for, from the longest host name it contains, e.g. its host
+/// triple or the architecture names of the GCC and LLVM releases. Empty for assets that do not
+/// depend on the host, like `rust-src`.
+fn asset_hosts(name: &str) -> Vec<HostTriple> {
+    let name = name.to_lowercase();
+    let names = |host_triple: &HostTriple| {
+        let platform = host_triple.platform();
+        [
+            host_triple.to_string(),
+            platform.gcc_arch().to_string(),
+            platform.llvm_arch().to_string(),
+        ]
+    };
+    let Some(longest) = HostTriple::iter()
+        .flat_map(|host_triple| names(&host_triple))
+        .filter(|host| name.contains(host.as_str()))
+        .max_by_key(String::len)
+    else {
+        return Vec::new();
+    };
+    HostTriple::iter()
+        .filter(|host_triple| names(host_triple).contains(&longest))
+        .collect()
+}
+
+/// Finds the asset that best matches the expected file name, sharing at least half of its tokens.
+///
+/// Only the assets built for the host of the expected file name are considered, so a renamed
+/// asset is never replaced with the one of another host.
+pub fn best_asset_match<'a>(expected: &str, assets: &'a [String]) -> Result<&'a str, Error> {
+    let expected_hosts = asset_hosts(expected);
+    let expected_tokens = tokens(expected);
+    assets
+        .iter()
+        .filter(|asset| {
+            let hosts = asset_hosts(asset);
+            hosts.is_empty() && expected_hosts.is_empty()
+                || hosts.iter().any(|host| expected_hosts.contains(host))
+        })
+        .map(|asset| {
+            let asset_tokens = tokens(asset);
+            let score = expected_tokens
+                .iter()
+                .filter(|token| asset_tokens.contains(token))
+                .count();
+            (score, asset_tokens.len(), asset.as_str())
+        })
+        .filter(|(score, _, _)| *score * 2 >= expected_tokens.len() && *score > 0)
+        // Highest score first, and the fewest extra tokens on ties
+        .max_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)))
+        .map(|(_, _, asset)| asset)
+        .ok_or_else(|| Error::ArtifactNotFound(expected.to_string()))
+}
+
+/// Formats a duration as minutes and seconds.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}m {}s", secs / 60, secs % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::github::{
+        best_asset_match, first_token, format_duration, read_cached_response,
+        store_cached_response, CachedResponse, RateLimit, ReleaseAsset,
+    };
+    use reqwest::header::HeaderMap;
+    use std::time::Duration;
+
+    #[test]
+    fn test_rate_limit_from_headers() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(RateLimit::from_headers(&headers), None);
+
+        headers.insert("x-ratelimit-limit", "60".parse().unwrap());
+        headers.insert("x-ratelimit-remaining", "0".parse().unwrap());
+        headers.insert("x-ratelimit-reset", "1700000725".parse().unwrap());
+        let rate_limit = RateLimit::from_headers(&headers).unwrap();
+        assert_eq!(
+            rate_limit,
+            RateLimit {
+                limit: Some(60),
+                remaining: 0,
+                reset: 1700000725
+            }
+        );
+        assert!(rate_limit.is_exhausted());
+        assert!(rate_limit.is_low());
+        assert_eq!(rate_limit.reset_in(1700000000), Duration::from_secs(725));
+        assert_eq!(rate_limit.reset_in(1800000000), Duration::ZERO);
+        assert_eq!(format_duration(Duration::from_secs(725)), "12m 5s");
+        assert_eq!(rate_limit.describe_limit(), "60 requests per hour");
+    }
+
+    #[test]
+    fn test_cached_response() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let url = "https://api.github.com/repos/esp-rs/rust-build/releases";
+        assert_eq!(read_cached_response(temp_dir.path(), url), None);
+
+        let response = CachedResponse {
+            etag: "W/\"abc\"".to_string(),
+            body: "[]".to_string(),
+        };
+        store_cached_response(temp_dir.path(), url, &response).unwrap();
+        assert_eq!(read_cached_response(temp_dir.path(), url), Some(response));
+        assert_eq!(
+            read_cached_response(temp_dir.path(), &format!("{url}/latest")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_token() {
+        let token = |token: &str| Some(token.to_string());
+        assert_eq!(
+            first_token([token("ghp_flag"), token("ghp_env")]),
+            token("ghp_flag")
+        );
+        // Empty tokens are ignored
+        assert_eq!(first_token([token(""), token("ghp_env")]), token("ghp_env"));
+        assert_eq!(first_token([None, token("")]), None);
+    }
+
+    #[test]
+    fn test_release_asset_from_url() {
+        let asset = ReleaseAsset::from_url(
+            "https://github.com/esp-rs/rust-build/releases/download/v1.72.0.0/rust-src-1.72.0.0.tar.xz",
+        )
+        .unwrap();
+        assert_eq!(asset.owner, "esp-rs");
+        assert_eq!(asset.repo, "rust-build");
+        assert_eq!(asset.tag, "v1.72.0.0");
+        assert_eq!(asset.file_name, "rust-src-1.72.0.0.tar.xz");
+        assert_eq!(
+            asset.release_api_url(),
+            "https://api.github.com/repos/esp-rs/rust-build/releases/tags/v1.72.0.0"
+        );
+        assert!(ReleaseAsset::from_url("https://example.com/file.tar.xz").is_none());
+    }
+
+    #[test]
+    fn test_best_asset_match() {
+        let assets = vec![
+            "rust-1.72.0.0-x86_64-unknown-linux-gnu.tar.xz".to_string(),
+            "rust-1.72.0.0-aarch64-apple-darwin.tar.xz".to_string(),
+            "rust-src-1.72.0.0.tar.xz".to_string(),
+        ];
+        assert_eq!(
+            best_asset_match("rust-1.72.0.0-x86_64-linux-gnu.tar.xz", &assets).unwrap(),
+            "rust-1.72.0.0-x86_64-unknown-linux-gnu.tar.xz"
+        );
+        assert_eq!(
+            best_asset_match("rust-src-1.72.0.0.tar.gz", &assets).unwrap(),
+            "rust-src-1.72.0.0.tar.xz"
+        );
+        assert!(best_asset_match("llvm-esp-16-win64.zip", &assets).is_err());
+        // The assets of other hosts are never matched, however many tokens they share
+        assert!(
+            best_asset_match("rust-1.72.0.0-aarch64-unknown-linux-gnu.tar.xz", &assets).is_err()
+        );
+        assert!(best_asset_match("rust-1.72.0.0-x86_64-apple-darwin.tar.xz", &assets).is_err());
+        let assets = vec![
+            "llvm-esp-16.0.0-macos-arm64.tar.xz".to_string(),
+            "llvm-esp-16.0.0-macos.tar.xz".to_string(),
+        ];
+        assert_eq!(
+            best_asset_match("llvm-esp-16.0.0-20230516-macos-arm64.tar.xz", &assets).unwrap(),
+            "llvm-esp-16.0.0-macos-arm64.tar.xz"
+        );
+        assert_eq!(
+            best_asset_match(
+                "llvm-esp-16.0.0-20230516-x86_64-apple-darwin.tar.xz",
+                &assets
+            )
+            .unwrap(),
+            "llvm-esp-16.0.0-macos.tar.xz"
+        );
+    }
+}
@@ -0,0 +1,129 @@
+//! Home, cache and configuration directories, with fallbacks for the minimal containers where
+//! `directories::BaseDirs` can not determine them.
+
+use crate::error::Error;
+use directories::BaseDirs;
+use std::{
+    env,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+/// Returns the home directory of the user, from `HOME` or `USERPROFILE` if the system does not
+/// provide it.
+pub fn home_dir() -> Option<PathBuf> {
+    if let Some(base_dirs) = BaseDirs::new() {
+        return Some(base_dirs.home_dir().to_path_buf());
+    }
+    ["HOME", "USERPROFILE"]
+        .into_iter()
+        .filter_map(env::var_os)
+        .map(PathBuf::from)
+        .find(|dir| dir.is_absolute())
+}
+
+/// Returns the directory named by an environment variable, or the given subdirectory of the home.
+fn fallback_dir(var: Option<OsString>, home_dir: Option<&Path>, subdir: &str) -> Option<PathBuf> {
+    var.map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| home_dir.map(|home| home.join(subdir)))
+}
+
+/// Returns the cache directory of the user, the temporary directory if there is no home.
+pub fn cache_dir() -> PathBuf {
+    if let Some(base_dirs) = BaseDirs::new() {
+        return base_dirs.cache_dir().to_path_buf();
+    }
+    fallback_dir(
+        env::var_os("XDG_CACHE_HOME"),
+        home_dir().as_deref(),
+        ".cache",
+    )
+    .unwrap_or_else(env::temp_dir)
+}
+
+/// Returns the configuration directory of the user, `None` if there is no home.
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(base_dirs) = BaseDirs::new() {
+        return Some(base_dirs.config_dir().to_path_buf());
+    }
+    fallback_dir(
+        env::var_os("XDG_CONFIG_HOME"),
+        home_dir().as_deref(),
+        ".config",
+    )
+}
+
+/// Returns the options to use instead of the missing homes of cargo and rustup.
+fn missing_homes(
+    cargo_home: Option<OsString>,
+    rustup_home: Option<OsString>,
+    home_dir: Option<&Path>,
+) -> Vec<&'static str> {
+    if home_dir.is_some() {
+        return Vec::new();
+    }
+    [("--cargo-home", cargo_home), ("--rustup-home", rustup_home)]
+        .into_iter()
+        .filter(|(_, var)| var.is_none())
+        .map(|(option, _)| option)
+        .collect()
+}
+
+/// Sets the homes of cargo and rustup, which the rustup processes also use.
+pub fn set_homes(cargo_home: Option<&Path>, rustup_home: Option<&Path>) {
+    if let Some(cargo_home) = cargo_home {
+        env::set_var("CARGO_HOME", cargo_home);
+    }
+    if let Some(rustup_home) = rustup_home {
+        env::set_var("RUSTUP_HOME", rustup_home);
+    }
+}
+
+/// Checks that the homes of cargo and rustup can be determined.
+pub fn check_homes() -> Result<(), Error> {
+    let missing = missing_homes(
+        env::var_os("CARGO_HOME"),
+        env::var_os("RUSTUP_HOME"),
+        home_dir().as_deref(),
+    );
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::MissingHomeDirectory(missing.join("' and '")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::home::{fallback_dir, missing_homes};
+    use std::{ffi::OsString, path::Path};
+
+    #[test]
+    fn test_fallbacks() {
+        let home = Path::new("/home/esp");
+        assert_eq!(
+            fallback_dir(None, Some(home), ".cache"),
+            Some(home.join(".cache"))
+        );
+        assert_eq!(
+            fallback_dir(Some(OsString::from("/var/cache")), Some(home), ".cache"),
+            Some(Path::new("/var/cache").to_path_buf())
+        );
+        // Relative directories are ignored, as in the XDG specification
+        assert_eq!(
+            fallback_dir(Some(OsString::from("cache")), None, ".cache"),
+            None
+        );
+
+        assert!(missing_homes(None, None, Some(home)).is_empty());
+        assert_eq!(
+            missing_homes(None, None, None),
+            vec!["--cargo-home", "--rustup-home"]
+        );
+        assert_eq!(
+            missing_homes(Some(OsString::from("/opt/cargo")), None, None),
+            vec!["--rustup-home"]
+        );
+    }
+}
@@ -0,0 +1,144 @@
+//! Commands declared in the configuration file and run after a successful installation or
+//! update, e.g. to regenerate a `.cargo/config.toml` or notify a provisioning system.
+
+use crate::{emoji, env::ExportItem, error::Error, locale::command, manifest::InstallManifest};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{
+    env::{join_paths, split_paths, var_os},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct HooksConfig {
+    /// Shell commands run, in order, after a successful installation or update.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_install: Vec<String>,
+}
+
+/// Returns the environment of the hooks: the resolved versions as `ESPUP_*` variables, along
+/// with the exports of the installation.
+pub fn hook_env(
+    name: &str,
+    install_path: &Path,
+    export_file: &Path,
+    manifest: &InstallManifest,
+) -> Vec<(String, String)> {
+    let mut env = vec![
+        ("ESPUP_TOOLCHAIN".to_string(), name.to_string()),
+        (
+            "ESPUP_TOOLCHAIN_DIR".to_string(),
+            install_path.display().to_string(),
+        ),
+        (
+            "ESPUP_EXPORT_FILE".to_string(),
+            export_file.display().to_string(),
+        ),
+        (
+            "ESPUP_LLVM_VERSION".to_string(),
+            manifest.llvm_version.clone(),
+        ),
+        (
+            "ESPUP_NIGHTLY_VERSION".to_string(),
+            manifest.nightly_version.clone(),
+        ),
+        ("ESPUP_TARGETS".to_string(), manifest.targets.join(",")),
+    ];
+    if let Some(version) = &manifest.xtensa_rust_version {
+        env.push(("ESPUP_XTENSA_RUST_VERSION".to_string(), version.clone()));
+    }
+    if let Some(release) = &manifest.gcc_release {
+        env.push(("ESPUP_GCC_RELEASE".to_string(), release.clone()));
+    }
+    let mut path_dirs = Vec::new();
+    for export in &manifest.exports {
+        match export {
+            ExportItem::PathAdd(dir) => path_dirs.push(PathBuf::from(dir)),
+            ExportItem::EnvSet { key, value } => env.push((key.clone(), value.clone())),
+            ExportItem::SourceLine(_) => {}
+        }
+    }
+    if !path_dirs.is_empty() {
+        if let Some(path) = var_os("PATH") {
+            path_dirs.extend(split_paths(&path));
+        }
+        if let Ok(path) = join_paths(path_dirs) {
+            env.push(("PATH".to_string(), path.to_string_lossy().into_owned()));
+        }
+    }
+    env
+}
+
+/// Runs the post-install hooks in the system shell, stopping at the first one that fails.
+pub fn run_hooks(hooks: &[String], env: &[(String, String)]) -> Result<(), Error> {
+    for hook in hooks {
+        info!("{} Running post-install hook '{}'", emoji::WRENCH, hook);
+        let mut shell = if cfg!(windows) {
+            let mut shell = command("cmd");
+            shell.arg("/C");
+            shell
+        } else {
+            let mut shell = command("sh");
+            shell.arg("-c");
+            shell
+        };
+        let status = shell
+            .arg(hook)
+            .envs(env.iter().map(|(key, value)| (key, value)))
+            .status()
+            .map_err(|e| Error::HookFailed(hook.clone(), e.to_string()))?;
+        if !status.success() {
+            return Err(Error::HookFailed(hook.clone(), status.to_string()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        env::ExportItem,
+        hooks::{hook_env, run_hooks},
+        manifest::InstallManifest,
+    };
+    use std::path::Path;
+
+    #[test]
+    fn test_hooks() {
+        let manifest = InstallManifest {
+            llvm_version: "esp-16.0.0-20230516".to_string(),
+            targets: vec!["esp32".to_string(), "esp32c3".to_string()],
+            xtensa_rust_version: Some("1.77.0.0".to_string()),
+            exports: vec![
+                ExportItem::PathAdd("/llvm/bin".to_string()),
+                ExportItem::EnvSet {
+                    key: "LIBCLANG_PATH".to_string(),
+                    value: "/llvm/lib".to_string(),
+                },
+            ],
+            ..Default::default()
+        };
+        let env = hook_env(
+            "esp",
+            Path::new("/toolchains/esp"),
+            Path::new("/export-esp.sh"),
+            &manifest,
+        );
+        let value = |key: &str| {
+            env.iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, value)| value.as_str())
+        };
+        assert_eq!(value("ESPUP_TOOLCHAIN"), Some("esp"));
+        assert_eq!(value("ESPUP_TARGETS"), Some("esp32,esp32c3"));
+        assert_eq!(value("ESPUP_XTENSA_RUST_VERSION"), Some("1.77.0.0"));
+        assert_eq!(value("ESPUP_GCC_RELEASE"), None);
+        assert_eq!(value("LIBCLANG_PATH"), Some("/llvm/lib"));
+        assert!(value("PATH").unwrap().starts_with("/llvm/bin"));
+
+        if cfg!(unix) {
+            assert!(run_hooks(&["test \"$ESPUP_TOOLCHAIN\" = esp".to_string()], &env).is_ok());
+            assert!(run_hooks(&["exit 3".to_string()], &env).is_err());
+        }
+    }
+}
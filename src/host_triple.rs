@@ -4,9 +4,9 @@ use crate::error::Error;
 use guess_host_triple::guess_host_triple;
 use miette::Result;
 use std::str::FromStr;
-use strum::{Display, EnumString};
+use strum::{Display, EnumIter, EnumString};
 
-#[derive(Display, Debug, Clone, EnumString, Default)]
+#[derive(Display, Debug, Clone, EnumIter, EnumString, Default, PartialEq, Eq)]
 pub enum HostTriple {
     /// 64-bit Linux
     #[strum(serialize = "x86_64-unknown-linux-gnu")]
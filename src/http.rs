@@ -0,0 +1,88 @@
+//! HTTP requests tracing support.
+
+use crate::emoji;
+use log::info;
+use reqwest::{header::HeaderMap, Method, StatusCode};
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+/// Headers whose values are never logged.
+const SENSITIVE_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+];
+
+static TRACE_HTTP: AtomicBool = AtomicBool::new(false);
+
+/// Enables or disables the tracing of the HTTP requests.
+pub fn set_tracing(enabled: bool) {
+    TRACE_HTTP.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns true if the HTTP requests are traced.
+pub fn is_tracing() -> bool {
+    TRACE_HTTP.load(Ordering::Relaxed)
+}
+
+/// Formats the headers, redacting the values of the sensitive ones.
+pub fn redact_headers(headers: &HeaderMap) -> String {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if SENSITIVE_HEADERS.contains(&name.as_str()) {
+                "<redacted>".to_string()
+            } else {
+                value.to_str().unwrap_or("<binary>").to_string()
+            };
+            format!("{name}: {value}")
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Traces an outgoing request.
+pub fn trace_request(method: &Method, url: &str, headers: &HeaderMap) {
+    if is_tracing() {
+        info!(
+            "{} HTTP request: {} {} [{}]",
+            emoji::DEBUG,
+            method,
+            url,
+            redact_headers(headers)
+        );
+    }
+}
+
+/// Traces a received response.
+pub fn trace_response(url: &str, status: StatusCode, headers: &HeaderMap, elapsed: Duration) {
+    if is_tracing() {
+        info!(
+            "{} HTTP response: {} {} in {} ms [{}]",
+            emoji::DEBUG,
+            status,
+            url,
+            elapsed.as_millis(),
+            redact_headers(headers)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::redact_headers;
+    use reqwest::header::{self, HeaderMap};
+
+    #[test]
+    fn test_redact_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::USER_AGENT, "espup".parse().unwrap());
+        headers.insert(header::AUTHORIZATION, "Bearer secret".parse().unwrap());
+        let redacted = redact_headers(&headers);
+        assert_eq!(redacted, "user-agent: espup, authorization: <redacted>");
+        assert!(!redacted.contains("secret"));
+    }
+}
@@ -1,21 +1,85 @@
+pub mod bundle;
+pub mod cache;
+pub mod checksum;
 pub mod cli;
+pub mod config;
+pub mod credentials;
+pub mod dedup;
+pub mod deprecation;
+pub mod doctor;
 pub mod emoji;
 pub mod env;
 pub mod error;
+pub mod explain;
+pub mod footprint;
+pub mod frozen;
+pub mod gha;
+pub mod github;
+pub mod home;
+pub mod hooks;
 pub mod host_triple;
+pub mod http;
+pub mod list;
+pub mod locale;
+pub mod lockfile;
+pub mod manifest;
+pub mod mirror;
+pub mod oci;
+pub mod offline;
+pub mod output;
+pub mod pins;
+pub mod platform;
+pub mod prefetch;
+pub mod preflight;
+pub mod profile;
+pub mod progress;
+pub mod project;
+pub mod prompt;
+pub mod proxy;
+pub mod prune;
+pub mod resume;
+pub mod script;
+pub mod segmented;
+pub mod selfcheck;
+pub mod selftest;
+pub mod selfupdate;
+pub mod shims;
+pub mod stream;
 pub mod targets;
+pub mod throttle;
+pub mod timeout;
+pub mod tls;
 pub mod toolchain;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod verify;
+pub mod warnings;
 
 pub mod logging {
+    use crate::{progress::set_interactive, prompt::is_non_interactive};
     use env_logger::{Builder, Env, WriteStyle};
+    use std::io::{stderr, IsTerminal};
 
     /// Initializes the logger
+    ///
+    /// When the logs are piped, e.g. to the log of a CI job, or in the non-interactive mode, they
+    /// are written without colors and the progress is only logged at milestones, unless
+    /// `RUST_LOG_STYLE` says otherwise.
     pub fn initialize_logger(log_level: &str) {
-        Builder::from_env(Env::default().default_filter_or(log_level))
+        let interactive = stderr().is_terminal() && !is_non_interactive();
+        set_interactive(interactive);
+        let env = Env::default().default_filter_or(log_level);
+        let mut builder = Builder::new();
+        builder
             .format_target(false)
             .format_timestamp_secs()
-            .write_style(WriteStyle::Always)
-            .init();
+            .write_style(if interactive {
+                WriteStyle::Always
+            } else {
+                WriteStyle::Never
+            })
+            .parse_env(env);
+        builder.init();
     }
 }
 
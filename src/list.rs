@@ -0,0 +1,143 @@
+//! Toolchains installed by espup, found by scanning the rustup toolchains directory.
+
+use crate::{
+    manifest::{InstallManifest, MANIFEST_FILE},
+    toolchain::{
+        gcc::{ESP32S2_GCC, ESP32S3_GCC, ESP32_GCC, RISCV_GCC},
+        llvm::CLANG_NAME,
+        rust::VERSION_FILE,
+    },
+};
+use std::{
+    fs::{read_dir, read_to_string},
+    path::{Path, PathBuf},
+};
+
+/// Directories of the GCC toolchains, inside the toolchain directory.
+pub(crate) const GCC_NAMES: [&str; 4] = [ESP32_GCC, ESP32S2_GCC, ESP32S3_GCC, RISCV_GCC];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstalledToolchain {
+    /// Toolchain name.
+    pub name: String,
+    /// Toolchain directory.
+    pub path: PathBuf,
+    /// Xtensa Rust version, `None` if only the RISC-V targets are installed.
+    pub xtensa_rust_version: Option<String>,
+    /// Installed LLVM versions.
+    pub llvm_versions: Vec<String>,
+    /// Installed GCC toolchains, with their release.
+    pub gcc: Vec<(String, String)>,
+    /// If `true`, the installation has an installation manifest.
+    pub has_manifest: bool,
+}
+
+/// Returns the names of the subdirectories of a directory, sorted.
+pub(crate) fn subdirectories(dir: &Path) -> Vec<String> {
+    let Ok(entries) = read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    names.sort();
+    names
+}
+
+/// Returns the installation of espup in a toolchain directory, `None` if espup did not install it.
+fn inspect(path: &Path) -> Option<InstalledToolchain> {
+    let name = path.file_name()?.to_str()?.to_string();
+    let manifest = InstallManifest::load(path).ok();
+    let xtensa_rust_version = manifest
+        .as_ref()
+        .and_then(|manifest| manifest.xtensa_rust_version.clone())
+        .or_else(|| {
+            read_to_string(path.join(VERSION_FILE))
+                .ok()
+                .map(|version| version.trim().to_string())
+        });
+    let llvm_versions = subdirectories(&path.join(CLANG_NAME));
+    let gcc: Vec<(String, String)> = GCC_NAMES
+        .iter()
+        .flat_map(|gcc| {
+            subdirectories(&path.join(gcc)).into_iter().map(|release| {
+                let release = release.strip_prefix("esp-").unwrap_or(&release).to_string();
+                (gcc.to_string(), release)
+            })
+        })
+        .collect();
+    let has_manifest = path.join(MANIFEST_FILE).is_file();
+    if !has_manifest && xtensa_rust_version.is_none() && llvm_versions.is_empty() && gcc.is_empty()
+    {
+        return None;
+    }
+    Some(InstalledToolchain {
+        name,
+        path: path.to_path_buf(),
+        xtensa_rust_version,
+        llvm_versions,
+        gcc,
+        has_manifest,
+    })
+}
+
+/// Returns the toolchains installed by espup in the rustup toolchains directory, sorted by name.
+pub fn installed_toolchains(toolchains_dir: &Path) -> Vec<InstalledToolchain> {
+    subdirectories(toolchains_dir)
+        .iter()
+        .filter_map(|name| inspect(&toolchains_dir.join(name)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        list::installed_toolchains,
+        manifest::InstallManifest,
+        toolchain::{gcc::RISCV_GCC, llvm::CLANG_NAME, rust::VERSION_FILE},
+    };
+    use std::fs::{create_dir_all, write};
+
+    #[test]
+    fn test_installed_toolchains() {
+        let toolchains_dir = tempfile::TempDir::new().unwrap();
+        let esp = toolchains_dir.path().join("esp");
+        create_dir_all(esp.join(VERSION_FILE).parent().unwrap()).unwrap();
+        write(esp.join(VERSION_FILE), "1.76.0.1\n").unwrap();
+        create_dir_all(esp.join(CLANG_NAME).join("esp-17.0.1_20240112")).unwrap();
+        create_dir_all(esp.join(RISCV_GCC).join("esp-13.2.0_20230928")).unwrap();
+        let riscv = toolchains_dir.path().join("riscv");
+        create_dir_all(&riscv).unwrap();
+        InstallManifest {
+            llvm_version: "esp-17.0.1_20240112".to_string(),
+            ..Default::default()
+        }
+        .save(&riscv)
+        .unwrap();
+        create_dir_all(
+            toolchains_dir
+                .path()
+                .join("stable-x86_64-unknown-linux-gnu/bin"),
+        )
+        .unwrap();
+
+        let installed = installed_toolchains(toolchains_dir.path());
+        assert_eq!(installed.len(), 2);
+        assert_eq!(installed[0].name, "esp");
+        assert_eq!(
+            installed[0].xtensa_rust_version.as_deref(),
+            Some("1.76.0.1")
+        );
+        assert_eq!(installed[0].llvm_versions, vec!["esp-17.0.1_20240112"]);
+        assert_eq!(
+            installed[0].gcc,
+            vec![(RISCV_GCC.to_string(), "13.2.0_20230928".to_string())]
+        );
+        assert!(!installed[0].has_manifest);
+        assert_eq!(installed[1].name, "riscv");
+        assert_eq!(installed[1].xtensa_rust_version, None);
+        assert!(installed[1].has_manifest);
+    }
+}
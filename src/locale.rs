@@ -0,0 +1,92 @@
+//! Locale-independent execution of external tools, so their output can be parsed.
+
+use std::{ffi::OsStr, process::Command};
+
+/// Environment variables forcing the untranslated messages and formats of the C locale.
+const C_LOCALE_VARS: &[(&str, &str)] = &[("LC_ALL", "C"), ("LANG", "C"), ("LANGUAGE", "")];
+/// Environment variable selecting the language of the MSVC tools, `1033` being English.
+const VSLANG: (&str, &str) = ("VSLANG", "1033");
+
+/// Returns a command running the program in the C locale.
+///
+/// On Windows, the MSVC tools are also switched to English, as they follow the language of the
+/// system instead of the locale variables.
+pub fn command(program: impl AsRef<OsStr>) -> Command {
+    let mut command = Command::new(program);
+    command.envs(C_LOCALE_VARS.iter().copied());
+    if cfg!(windows) {
+        command.env(VSLANG.0, VSLANG.1);
+    }
+    command
+}
+
+/// Decodes the output of a tool.
+///
+/// Localized Windows consoles may print UTF-16, detected by its byte order mark or an ASCII first
+/// character, or an OEM code page, whose ASCII characters are kept while the others are replaced.
+pub fn decode_output(bytes: &[u8]) -> String {
+    let utf16 = match bytes {
+        [0xFF, 0xFE, rest @ ..] => Some(rest),
+        [low, 0, ..] if *low != 0 && bytes.len() % 2 == 0 => Some(bytes),
+        _ => None,
+    };
+    let output = match utf16 {
+        Some(bytes) => {
+            let units: Vec<u16> = bytes
+                .chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        None => String::from_utf8_lossy(bytes).to_string(),
+    };
+    output.trim_start_matches('\u{feff}').to_string()
+}
+
+/// Returns the versions reported by `rustc --version`, e.g. `1.76.0-nightly` and `1.76.0.1` for
+/// `rustc 1.76.0-nightly (88269fa9e 2024-02-09) (1.76.0.1)`.
+pub fn rustc_versions(output: &str) -> Vec<String> {
+    let Some(line) = output
+        .lines()
+        .map(str::trim)
+        .find(|line| line.starts_with("rustc "))
+    else {
+        return Vec::new();
+    };
+    line.split_whitespace()
+        .skip(1)
+        .map(|token| token.trim_matches(|c| c == '(' || c == ')'))
+        .filter(|token| token.contains('.') && token.starts_with(|c: char| c.is_ascii_digit()))
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::locale::{decode_output, rustc_versions};
+
+    #[test]
+    fn test_decode_output() {
+        let output = "rustc 1.76.0-nightly (88269fa9e 2024-02-09) (1.76.0.1)\r\n";
+        let utf16: Vec<u8> = output.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        assert_eq!(decode_output(&utf16), output);
+        let bom: Vec<u8> = [0xFF, 0xFE].into_iter().chain(utf16).collect();
+        assert_eq!(decode_output(&bom), output);
+        assert_eq!(decode_output(output.as_bytes()), output);
+        // CP850 output of a French console
+        assert_eq!(
+            decode_output(b"Version install\x82e 1.76.0.1"),
+            "Version install\u{fffd}e 1.76.0.1"
+        );
+
+        assert_eq!(
+            rustc_versions(&decode_output(&bom)),
+            vec!["1.76.0-nightly".to_string(), "1.76.0.1".to_string()]
+        );
+        assert_eq!(
+            rustc_versions("rustc 1.76.0 (07dca489a 2024-02-04)"),
+            vec!["1.76.0".to_string()]
+        );
+        assert!(rustc_versions("error: toolchain 'esp' is not installed").is_empty());
+    }
+}
@@ -0,0 +1,315 @@
+//! Lockfile describing the resolved versions and the artifacts of an installation.
+
+use crate::{error::Error, script::ScriptStep};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{read_to_string, write},
+    path::Path,
+    sync::Mutex,
+};
+
+/// Default name of the lockfile.
+pub const DEFAULT_LOCKFILE: &str = "espup.lock";
+
+/// SHA256 digests, by URL, of the artifacts locked by the lockfile of the installation.
+static LOCKED: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+/// SHA256 digests, by URL, of the artifacts verified while downloading them.
+static VERIFIED: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+/// Sets the locked artifacts the downloads are verified against, e.g. the ones of the lockfile or
+/// of the bundle of the installation.
+pub fn set_locked<'a>(artifacts: impl IntoIterator<Item = &'a LockedArtifact>) {
+    *LOCKED.lock().unwrap() = digests(artifacts);
+}
+
+/// Returns the SHA256 digests of artifacts, by URL.
+fn digests<'a>(
+    artifacts: impl IntoIterator<Item = &'a LockedArtifact>,
+) -> BTreeMap<String, String> {
+    artifacts
+        .into_iter()
+        .map(|artifact| (artifact.url.clone(), artifact.sha256.clone()))
+        .collect()
+}
+
+/// Returns the digest of an artifact locked by the lockfile of the installation.
+pub fn locked_digest(url: &str) -> Option<String> {
+    LOCKED.lock().unwrap().get(url).cloned()
+}
+
+/// Records the digest of an artifact once it is verified, so it can be locked.
+pub fn record_verified(url: &str, sha256: &str) {
+    VERIFIED
+        .lock()
+        .unwrap()
+        .insert(url.to_string(), sha256.to_string());
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct Lockfile {
+    /// Xtensa Rust version, including its subpatch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub toolchain_version: Option<String>,
+    /// LLVM release.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub llvm_version: Option<String>,
+    /// GCC release, `None` if GCC is not installed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gcc_release: Option<String>,
+    /// Artifacts required by the installation.
+    #[serde(default)]
+    pub artifacts: Vec<LockedArtifact>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct LockedArtifact {
+    /// Name of the component the artifact belongs to.
+    pub name: String,
+    /// URL the artifact is downloaded from.
+    pub url: String,
+    /// File name of the artifact.
+    pub file_name: String,
+    /// Hex encoded SHA256 digest of the artifact.
+    pub sha256: String,
+}
+
+impl Lockfile {
+    /// Loads a lockfile.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let contents =
+            read_to_string(path).map_err(|_| Error::ReadLockfile(path.display().to_string()))?;
+        serde_json::from_str(&contents).map_err(|_| Error::ReadLockfile(path.display().to_string()))
+    }
+
+    /// Saves the lockfile.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|_| Error::WriteLockfile(path.display().to_string()))?;
+        write(path, contents).map_err(|_| Error::WriteLockfile(path.display().to_string()))
+    }
+
+    /// Returns true if both lockfiles resolve the same versions.
+    fn same_versions(&self, other: &Lockfile) -> bool {
+        self.toolchain_version == other.toolchain_version
+            && self.llvm_version == other.llvm_version
+            && self.gcc_release == other.gcc_release
+    }
+
+    /// Checks that a resolved installation does not deviate from the lockfile.
+    ///
+    /// A version locked but no longer resolved, e.g. GCC, or resolved but not locked, is a
+    /// deviation too.
+    pub fn check(&self, resolved: &Lockfile) -> Result<(), Error> {
+        for (what, locked, resolved) in [
+            (
+                "Xtensa Rust version",
+                &self.toolchain_version,
+                &resolved.toolchain_version,
+            ),
+            ("LLVM release", &self.llvm_version, &resolved.llvm_version),
+            ("GCC release", &self.gcc_release, &resolved.gcc_release),
+        ] {
+            if locked != resolved {
+                let describe = |version: &Option<String>| {
+                    version.clone().unwrap_or_else(|| "none".to_string())
+                };
+                return Err(Error::LockfileMismatch(
+                    what.to_string(),
+                    describe(locked),
+                    describe(resolved),
+                ));
+            }
+        }
+        for artifact in &resolved.artifacts {
+            match self
+                .artifacts
+                .iter()
+                .find(|locked| locked.url == artifact.url)
+            {
+                Some(locked) if locked.sha256 == artifact.sha256 => {}
+                Some(locked) => {
+                    return Err(Error::LockfileMismatch(
+                        format!("SHA256 digest of '{}'", artifact.file_name),
+                        locked.sha256.clone(),
+                        artifact.sha256.clone(),
+                    ))
+                }
+                None => return Err(Error::UnlockedArtifact(artifact.url.clone())),
+            }
+        }
+        Ok(())
+    }
+
+    /// Records a resolved installation in the lockfile.
+    ///
+    /// While the versions do not change, the artifacts of the other hosts are kept, so a single
+    /// lockfile can be shared by every host.
+    pub fn update(&mut self, resolved: Lockfile) {
+        if !self.same_versions(&resolved) {
+            *self = resolved;
+            return;
+        }
+        for artifact in resolved.artifacts {
+            match self
+                .artifacts
+                .iter_mut()
+                .find(|locked| locked.url == artifact.url)
+            {
+                Some(locked) => *locked = artifact,
+                None => self.artifacts.push(artifact),
+            }
+        }
+    }
+}
+
+/// Returns the artifacts fetched by the steps of an installation, named after the component
+/// introducing them.
+///
+/// The artifacts without a published digest are only included once downloaded and verified.
+pub fn locked_artifacts(steps: &[ScriptStep]) -> Vec<LockedArtifact> {
+    let mut artifacts: Vec<LockedArtifact> = Vec::new();
+    let mut name = String::new();
+    for step in steps {
+        match step {
+            ScriptStep::Comment(comment) => name = comment.clone(),
+            ScriptStep::Fetch { url, sha256, .. } => {
+                let Some(sha256) = sha256
+                    .clone()
+                    .or_else(|| VERIFIED.lock().unwrap().get(url).cloned())
+                else {
+                    continue;
+                };
+                if !artifacts.iter().any(|known| &known.url == url) {
+                    artifacts.push(LockedArtifact {
+                        name: name.clone(),
+                        url: url.clone(),
+                        file_name: url.rsplit('/').next().unwrap_or(url).to_string(),
+                        sha256,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    artifacts
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        env::ExportItem,
+        error::Error,
+        lockfile::{digests, locked_artifacts, record_verified, LockedArtifact, Lockfile},
+        script::ScriptStep,
+    };
+
+    #[test]
+    fn test_lockfile_load_and_save() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("espup.lock");
+        assert!(Lockfile::load(&path).is_err());
+
+        let lockfile = Lockfile {
+            artifacts: vec![LockedArtifact {
+                name: "LLVM".to_string(),
+                url: "https://example.com/llvm.tar.xz".to_string(),
+                file_name: "llvm.tar.xz".to_string(),
+                sha256: "00".repeat(32),
+            }],
+            ..Default::default()
+        };
+        lockfile.save(&path).unwrap();
+        assert_eq!(Lockfile::load(&path).unwrap(), lockfile);
+    }
+
+    #[test]
+    fn test_lockfile_check_and_update() {
+        let fetch = |url: &str, sha256: &str| ScriptStep::Fetch {
+            url: url.to_string(),
+            sha256: Some(sha256.to_string()),
+            file: "artifact.tar.xz".to_string(),
+        };
+        let resolved = |gcc: &str, steps: &[ScriptStep]| Lockfile {
+            toolchain_version: Some("1.76.0.1".to_string()),
+            llvm_version: Some("esp-17.0.1_20240112".to_string()),
+            gcc_release: Some(gcc.to_string()),
+            artifacts: locked_artifacts(steps),
+        };
+        let linux = resolved(
+            "13.2.0_20230928",
+            &[
+                ScriptStep::Comment("LLVM".to_string()),
+                fetch("https://example.com/llvm-linux.tar.xz", "aa"),
+                fetch("https://example.com/llvm-linux.tar.xz", "aa"),
+                ScriptStep::Export(ExportItem::PathAdd("/esp".to_string())),
+            ],
+        );
+        assert_eq!(linux.artifacts.len(), 1);
+        assert_eq!(linux.artifacts[0].name, "LLVM");
+        assert_eq!(linux.artifacts[0].file_name, "llvm-linux.tar.xz");
+
+        // The artifacts of every host are kept while the versions do not change
+        let mut lockfile = Lockfile::default();
+        lockfile.update(linux.clone());
+        let macos = resolved(
+            "13.2.0_20230928",
+            &[fetch("https://example.com/llvm-macos.tar.xz", "bb")],
+        );
+        lockfile.update(macos.clone());
+        assert_eq!(lockfile.artifacts.len(), 2);
+        assert!(lockfile.check(&linux).is_ok());
+        assert!(lockfile.check(&macos).is_ok());
+
+        assert!(matches!(
+            lockfile.check(&resolved("14.2.0_20241119", &[])),
+            Err(Error::LockfileMismatch(what, _, _)) if what == "GCC release"
+        ));
+        assert!(matches!(
+            lockfile.check(&resolved(
+                "13.2.0_20230928",
+                &[fetch("https://example.com/llvm-linux.tar.xz", "cc")]
+            )),
+            Err(Error::LockfileMismatch(_, locked, resolved)) if locked == "aa" && resolved == "cc"
+        ));
+        assert!(matches!(
+            lockfile.check(&resolved(
+                "13.2.0_20230928",
+                &[fetch("https://example.com/llvm-windows.zip", "dd")]
+            )),
+            Err(Error::UnlockedArtifact(_))
+        ));
+        // GCC is locked but no longer installed
+        let without_gcc = Lockfile {
+            gcc_release: None,
+            ..linux.clone()
+        };
+        assert!(matches!(
+            lockfile.check(&without_gcc),
+            Err(Error::LockfileMismatch(what, _, resolved)) if what == "GCC release" && resolved == "none"
+        ));
+        assert!(without_gcc.check(&linux).is_err());
+
+        // The locked digests are used to verify the downloads
+        assert_eq!(
+            digests(&lockfile.artifacts)
+                .get("https://example.com/llvm-macos.tar.xz")
+                .map(String::as_str),
+            Some("bb")
+        );
+        assert!(digests(&[]).is_empty());
+
+        // Artifacts without a published digest are locked once verified
+        let unpublished = [ScriptStep::Fetch {
+            url: "https://example.com/rust-src.tar.xz".to_string(),
+            sha256: None,
+            file: "rust-src.tar.xz".to_string(),
+        }];
+        assert!(locked_artifacts(&unpublished).is_empty());
+        record_verified("https://example.com/rust-src.tar.xz", "ee");
+        assert_eq!(locked_artifacts(&unpublished)[0].sha256, "ee");
+
+        lockfile.update(resolved("14.2.0_20241119", &[]));
+        assert!(lockfile.artifacts.is_empty());
+    }
+}
@@ -1,42 +1,380 @@
-use clap::{CommandFactory, Parser};
-#[cfg(windows)]
-use espup::env::set_environment_variable;
+use clap::{parser::ValueSource, CommandFactory, FromArgMatches, Parser};
 use espup::{
-    cli::{CompletionsOpts, InstallOpts, UninstallOpts},
+    bundle::{create as bundle_create, verify as bundle_verify},
+    cache::{
+        configured_staging_dir, entries as cache_entries, get_cache_dir, usage as cache_usage,
+    },
+    cli::{
+        with_defaults, AuthOpts, AuthSubCommand, BundleOpts, BundleSubCommand, CacheOpts,
+        CacheSubCommand, CheckOpts, CompletionsOpts, DoctorOpts, ExplainOpts, GhaInstallOpts,
+        InstallOpts, ListOpts, MirrorOpts, MirrorSubCommand, PinsOpts, PinsSubCommand, PruneOpts,
+        RollbackOpts, SchemaOpts, SelfOpts, SelfSubCommand, TargetsOpts, TargetsSubCommand,
+        UninstallOpts, VerifyOpts,
+    },
+    config::Config,
+    credentials::{delete as delete_credential, store as store_credential, Credential},
+    deprecation::{migrate_args, report as report_deprecations},
+    doctor::{
+        diagnose_export_file, diagnose_github, diagnose_homes, diagnose_libclang, diagnose_path,
+        diagnose_rustup, diagnose_shims, diagnose_toolchain, exported_variables, known_locations,
+        registry_assignments, scan as scan_env, sourcing_profiles, DEFAULT_VARIABLES,
+    },
     emoji,
+    env::{
+        clear_exports, get_export_file, is_generated_export_file, set_persist_environment,
+        ExportShell,
+    },
     error::Error,
+    explain::{codes as explained_codes, detect_language, explain as explain_error},
+    gha::{cache_key, get_output_file, is_cache_hit, write_outputs},
+    github::format_duration,
+    home::{check_homes, home_dir, set_homes},
+    host_triple::get_host_triple,
+    http::set_tracing,
+    list::installed_toolchains,
+    locale,
+    lockfile::{Lockfile, DEFAULT_LOCKFILE},
     logging::initialize_logger,
+    manifest::InstallManifest,
+    mirror::{benchmark_mirrors, push as mirror_push, resolve_mirror, DEFAULT_MIRROR},
+    output::{emit, set_output, Event, SCHEMA},
+    pins::{set_pins, PinTable, BUNDLED_PINS},
+    platform::Platform,
+    prefetch::{prefetch, UpdatePlan},
+    profile::{cleanups, ProfileCleanup},
+    project::ProjectToolchain,
+    prompt::{confirm, read_secret, set_non_interactive, NON_INTERACTIVE_VAR},
+    prune::reclaimable,
+    selfcheck::find_issues,
+    selftest::{compile_smoke_test, junit_report, resolve_toolchain, TestCase},
+    selfupdate::{is_newer, old_binary_path, replace_binary, SelfRelease},
+    shims::{data_dirs, find_shims, resolves_toolchain, Shim},
+    targets::{parse_targets, Target},
     toolchain::{
-        gcc::uninstall_gcc_toolchains, install as toolchain_install, llvm::Llvm,
-        rust::get_rustup_home,
+        gcc::uninstall_gcc_toolchains,
+        install as toolchain_install,
+        llvm::{Llvm, CLANG_NAME},
+        rollback as toolchain_rollback,
+        rust::{
+            get_default_toolchain, get_rustup_home, set_default_toolchain, unset_override,
+            RiscVTarget, XtensaRust,
+        },
     },
     update::check_for_update,
+    verify::verify_target,
+    warnings,
 };
-use log::info;
+#[cfg(feature = "tui")]
+use espup::{cli::TuiOpts, tui::run as run_dashboard};
+use log::{info, warn};
 use miette::Result;
-use std::{env, fs::remove_dir_all};
+use std::{
+    env,
+    fs::{read_to_string, remove_dir_all, remove_file},
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::SystemTime,
+};
+use strum::IntoEnumIterator;
 
 #[derive(Parser)]
 #[command(about, version)]
 struct Cli {
+    /// Cargo home directory, required if the home directory can not be determined.
+    #[arg(long, global = true, env = "CARGO_HOME")]
+    cargo_home: Option<PathBuf>,
+    /// Rustup home directory, required if the home directory can not be determined.
+    #[arg(long, global = true, env = "RUSTUP_HOME")]
+    rustup_home: Option<PathBuf>,
+    /// Never prompts, failing instead of waiting for input, and writes plain logs without colors
+    /// or progress redraws, e.g. for unattended provisioning.
+    #[arg(long, global = true, env = NON_INTERACTIVE_VAR)]
+    non_interactive: bool,
+    /// Answers yes to every confirmation, implies `--non-interactive`.
+    #[arg(short = 'y', long, global = true)]
+    yes: bool,
     #[command(subcommand)]
     subcommand: SubCommand,
 }
 
 #[derive(Parser)]
 pub enum SubCommand {
+    /// Stores credentials in the keyring of the system.
+    Auth(AuthOpts),
+    /// Creates and verifies offline artifact bundles for air-gapped installations.
+    Bundle(BundleOpts),
+    /// Inspects the download cache.
+    Cache(CacheOpts),
+    /// Checks whether a newer Xtensa Rust toolchain is available.
+    Check(CheckOpts),
     /// Generate completions for the given shell.
     Completions(CompletionsOpts),
+    /// Diagnoses the environment set up by espup.
+    Doctor(DoctorOpts),
+    /// Explains an error code, using the documentation embedded in espup.
+    Explain(ExplainOpts),
+    /// Installs Espressif Rust ecosystem in GitHub Actions, reusing a restored cache when possible.
+    GhaInstall(Box<GhaInstallOpts>),
     /// Installs Espressif Rust ecosystem.
     // We use a Box here to make clippy happy (see https://rust-lang.github.io/rust-clippy/master/index.html#large_enum_variant)
     Install(Box<InstallOpts>),
+    /// Lists the toolchains installed by espup, with their LLVM and GCC versions.
+    List(ListOpts),
+    /// Manages the mirrors used to download the toolchains.
+    Mirror(MirrorOpts),
+    /// Shows the default component versions of this espup release.
+    Pins(PinsOpts),
+    /// Removes the unused LLVM and GCC versions, orphaned temporary files and cached artifacts.
+    Prune(PruneOpts),
+    /// Rolls back to a previously installed Xtensa Rust, LLVM and GCC combination.
+    Rollback(Box<RollbackOpts>),
+    /// Prints the JSON schema of the events emitted with `--output json`.
+    Schema(SchemaOpts),
+    /// Manages the espup installation itself.
+    #[command(name = "self")]
+    SelfCommand(SelfOpts),
+    /// Queries the chip targets supported by the Xtensa Rust releases.
+    Targets(TargetsOpts),
+    /// Shows a dashboard of the installed toolchains, with one-key update, verify and prune
+    /// actions.
+    #[cfg(feature = "tui")]
+    Tui(TuiOpts),
     /// Uninstalls Espressif Rust ecosystem.
     Uninstall(UninstallOpts),
     /// Updates Xtensa Rust toolchain.
     Update(Box<InstallOpts>),
+    /// Verifies that the installed GCC toolchains provide the multilib variants, runtime libraries
+    /// and linker scripts of each chip.
+    Verify(VerifyOpts),
 }
 
-/// Updates Xtensa Rust toolchain.
+/// Manages the credentials stored in the keyring of the system
+async fn auth(args: AuthOpts) -> Result<()> {
+    match args.subcommand {
+        AuthSubCommand::Login(args) => {
+            initialize_logger(&args.log_level);
+            let credential = match &args.mirror {
+                Some(mirror) => Credential::for_mirror(&resolve_mirror(mirror))?,
+                None => Credential::GithubToken,
+            };
+            let prompt = match &args.username {
+                Some(username) => format!("Password of '{username}': "),
+                None => "GitHub token: ".to_string(),
+            };
+            let secret = read_secret(&prompt)?;
+            if secret.is_empty() {
+                warn!("{} Empty {}, nothing stored", emoji::WARN, credential);
+                return Ok(());
+            }
+            let secret = match args.username {
+                Some(username) => format!("{username}:{secret}"),
+                None => secret,
+            };
+            store_credential(&credential, &secret)?;
+            info!("{} Stored the {} in the keyring", emoji::CHECK, credential);
+        }
+        AuthSubCommand::Logout(args) => {
+            initialize_logger(&args.log_level);
+            let credential = match &args.mirror {
+                Some(mirror) => Credential::for_mirror(&resolve_mirror(mirror))?,
+                None => Credential::GithubToken,
+            };
+            if delete_credential(&credential)? {
+                info!(
+                    "{} Removed the {} from the keyring",
+                    emoji::CHECK,
+                    credential
+                );
+            } else {
+                warn!("{} No {} is stored in the keyring", emoji::WARN, credential);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Manages offline artifact bundles
+async fn bundle(args: BundleOpts) -> Result<()> {
+    match args.subcommand {
+        BundleSubCommand::Create(args) => {
+            initialize_logger(&args.log_level);
+            check_for_update(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+            set_tracing(args.trace_http);
+
+            let host_triples = if args.hosts.is_empty() {
+                vec![get_host_triple(None)?]
+            } else {
+                args.hosts
+                    .iter()
+                    .map(|host| get_host_triple(Some(host.clone())))
+                    .collect::<Result<Vec<_>, _>>()?
+            };
+            let config = Config::load()?;
+            args.network.apply(&config);
+            set_pins(config.pins)?;
+            let toolchain_version = match args.toolchain_version {
+                Some(toolchain_version) => XtensaRust::parse_version(&toolchain_version)?,
+                None => XtensaRust::get_latest_version().await?,
+            };
+            info!(
+                "{} Bundling Xtensa Rust {} into '{}'",
+                emoji::DISC,
+                toolchain_version,
+                args.output.display()
+            );
+            let manifest = bundle_create(
+                &args.output,
+                &toolchain_version,
+                &host_triples,
+                &args.targets,
+            )
+            .await?;
+            info!(
+                "{} Bundled {} artifacts. Install them with `espup install --from-bundle {}`",
+                emoji::CHECK,
+                manifest.artifacts.len(),
+                args.output.display()
+            );
+            Ok(())
+        }
+        BundleSubCommand::Verify(args) => {
+            initialize_logger(&args.log_level);
+            check_for_update(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+
+            info!(
+                "{} Verifying bundle '{}' against '{}'",
+                emoji::DISC,
+                args.dir.display(),
+                args.lockfile.display()
+            );
+            let lockfile = Lockfile::load(&args.lockfile)?;
+            let report = bundle_verify(&args.dir, &lockfile)?;
+            if !report.is_complete() {
+                return Err(Error::IncompleteBundle(
+                    args.dir.display().to_string(),
+                    report.missing.len(),
+                    report.mismatched.len(),
+                )
+                .into());
+            }
+            info!(
+                "{} Bundle successfully verified: {} artifacts",
+                emoji::CHECK,
+                report.verified.len()
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Inspects the download cache
+async fn cache(args: CacheOpts) -> Result<()> {
+    match args.subcommand {
+        CacheSubCommand::Status(args) => {
+            initialize_logger(&args.log_level);
+            check_for_update(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+
+            let config = Config::load()?.cache;
+            let cache_dir = get_cache_dir();
+            let entries = cache_entries(&cache_dir)?;
+            let usage = cache_usage(&entries);
+            let to_mib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+
+            info!("{} Cache directory: '{}'", emoji::INFO, cache_dir.display());
+            match config.max_size() {
+                Some(max_size) => info!(
+                    "{} Usage: {:.2} MiB of {:.2} MiB ({} artifacts)",
+                    emoji::INFO,
+                    to_mib(usage),
+                    to_mib(max_size),
+                    entries.len()
+                ),
+                None => info!(
+                    "{} Usage: {:.2} MiB, no size limit ({} artifacts)",
+                    emoji::INFO,
+                    to_mib(usage),
+                    entries.len()
+                ),
+            }
+            info!("{} Eviction policy: {:?}", emoji::INFO, config.eviction);
+            for entry in &entries {
+                let name = entry.name();
+                info!(
+                    "{}   {} ({}{:.2} MiB){}",
+                    emoji::INFO,
+                    name,
+                    entry
+                        .version
+                        .as_ref()
+                        .map(|version| format!("{version}, "))
+                        .unwrap_or_default(),
+                    to_mib(entry.size),
+                    if config.is_pinned(&name) {
+                        " [pinned]"
+                    } else {
+                        ""
+                    }
+                );
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Checks whether a newer Xtensa Rust toolchain is available
+async fn check(args: CheckOpts) -> Result<()> {
+    initialize_logger(&args.log_level);
+    set_output(&args.output);
+
+    let host_triple = get_host_triple(args.default_host)?;
+    let install_path = get_rustup_home().join("toolchains").join(&args.name);
+    let installed = InstallManifest::load(&install_path)
+        .ok()
+        .and_then(|manifest| manifest.xtensa_rust_version);
+    let plan = match prefetch(host_triple.clone()).await {
+        Ok(plan) => plan,
+        Err(e) => {
+            let plan = UpdatePlan::load(&host_triple).ok_or(e)?;
+            warn!(
+                "{} GitHub is unreachable, using the metadata prefetched {} ago",
+                emoji::WARN,
+                format_duration(plan.age())
+            );
+            plan
+        }
+    };
+
+    emit(Event::Check {
+        name: args.name.clone(),
+        installed: installed.clone(),
+        latest: plan.latest().map(str::to_string),
+    });
+    match (installed.as_deref(), plan.latest()) {
+        (_, None) => warn!(
+            "{} No Xtensa Rust release is available for '{}'",
+            emoji::WARN,
+            host_triple
+        ),
+        (None, Some(latest)) => info!(
+            "{} '{}' is not installed, 'espup install' would install Xtensa Rust {}",
+            emoji::INFO,
+            args.name,
+            latest
+        ),
+        (Some(installed), Some(latest)) if installed == latest => {
+            info!("{} Xtensa Rust {} is up to date", emoji::CHECK, installed)
+        }
+        (Some(installed), Some(latest)) => info!(
+            "{} 'espup update' would update Xtensa Rust {} to {}",
+            emoji::INFO,
+            installed,
+            latest
+        ),
+    }
+    Ok(())
+}
+
+/// Generates the completions for the given shell
 async fn completions(args: CompletionsOpts) -> Result<()> {
     initialize_logger(&args.log_level);
     check_for_update(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
@@ -59,62 +397,1172 @@ async fn completions(args: CompletionsOpts) -> Result<()> {
     Ok(())
 }
 
+/// Diagnoses the environment set up by espup
+async fn doctor(args: DoctorOpts) -> Result<()> {
+    initialize_logger(&args.log_level);
+
+    let mut install_path = get_rustup_home().join("toolchains").join(&args.name);
+    let mut manifest = InstallManifest::load(&install_path).ok();
+    // With a diverging `RUSTUP_HOME`, the installation may be found in the default home
+    if manifest.is_none() {
+        if let Some(home_dir) = home_dir() {
+            let default_path = home_dir.join(".rustup").join("toolchains").join(&args.name);
+            if let Ok(default_manifest) = InstallManifest::load(&default_path) {
+                install_path = default_path;
+                manifest = Some(default_manifest);
+            }
+        }
+    }
+    let export_file = get_export_file(args.export_file, &args.name, ExportShell::Native)?;
+
+    let rustup_version = locale::command("rustup")
+        .arg("--version")
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .map(|output| locale::decode_output(&output.stdout));
+    let mut diagnoses = vec![
+        diagnose_rustup(rustup_version.as_deref()),
+        diagnose_toolchain(&install_path, manifest.as_ref()),
+    ];
+    if let Some(manifest) = &manifest {
+        let platform = get_host_triple(Some(manifest.host_triple.clone()))?.platform();
+        diagnoses.push(diagnose_homes(
+            manifest,
+            env::var_os("CARGO_HOME").map(PathBuf::from),
+            env::var_os("RUSTUP_HOME").map(PathBuf::from),
+            &home_dir().unwrap_or_default(),
+            platform,
+        ));
+        diagnoses.push(diagnose_export_file(
+            &export_file,
+            &manifest.exports,
+            platform,
+        ));
+        diagnoses.push(diagnose_libclang(
+            &manifest.exports,
+            env::var("LIBCLANG_PATH").ok(),
+            &export_file,
+        ));
+        diagnoses.push(diagnose_path(
+            &manifest.exports,
+            env::var_os("PATH").as_deref(),
+            &export_file,
+        ));
+    }
+    let shims: Vec<(Shim, bool)> = find_shims(env::var_os("PATH").as_deref(), &data_dirs())
+        .into_iter()
+        .map(|shim| {
+            let resolves = resolves_toolchain(shim.tool, &args.name);
+            (shim, resolves)
+        })
+        .collect();
+    diagnoses.push(diagnose_shims(&shims, &args.name));
+    diagnoses.push(diagnose_github().await);
+    for diagnosis in &diagnoses {
+        if diagnosis.passed {
+            info!("{} {}: {}", emoji::CHECK, diagnosis.check, diagnosis.detail);
+        } else {
+            warn!("{} {}: {}", emoji::ERROR, diagnosis.check, diagnosis.detail);
+            if let Some(fix) = &diagnosis.fix {
+                warn!("{}   Fix: {}", emoji::INFO, fix);
+            }
+        }
+    }
+    let failed = diagnoses.iter().filter(|d| !d.passed).count();
+    if !args.env_scope {
+        return report_diagnoses(failed);
+    }
+
+    let variables = match &manifest {
+        Some(manifest) if !manifest.exports.is_empty() => exported_variables(&manifest.exports),
+        _ => DEFAULT_VARIABLES.iter().map(|v| v.to_string()).collect(),
+    };
+    let home_dir = home_dir().unwrap_or_default();
+    let current_dir = env::current_dir().map_err(Error::from)?;
+    let locations = known_locations(&home_dir, &current_dir, &export_file);
+    let mut assignments = scan_env(&locations, &variables);
+    assignments.extend(registry_assignments(&variables));
+
+    for variable in &variables {
+        match env::var(variable) {
+            Ok(value) => info!("{} {}={}", emoji::INFO, variable, value),
+            Err(_) => info!("{} {} is not set in this shell", emoji::INFO, variable),
+        }
+        let setters: Vec<_> = assignments
+            .iter()
+            .filter(|assignment| &assignment.variable == variable)
+            .collect();
+        for assignment in &setters {
+            info!(
+                "{}   [{}] {}:{}: {}",
+                emoji::INFO,
+                assignment.scope,
+                assignment.path.display(),
+                assignment.line,
+                assignment.text
+            );
+        }
+        let mut scopes: Vec<_> = setters.iter().map(|assignment| assignment.scope).collect();
+        scopes.dedup();
+        if setters.is_empty() {
+            warn!(
+                "{} {} is not set in any known location",
+                emoji::WARN,
+                variable
+            );
+        } else if scopes.len() > 1 {
+            warn!(
+                "{} {} is set in several scopes, the last one loaded takes precedence",
+                emoji::WARN,
+                variable
+            );
+        }
+    }
+
+    let profiles = sourcing_profiles(&locations, &export_file);
+    if profiles.is_empty() {
+        warn!(
+            "{} No shell profile sources '{}'",
+            emoji::WARN,
+            export_file.display()
+        );
+    }
+    for profile in profiles {
+        info!(
+            "{} '{}' sources the export file",
+            emoji::INFO,
+            profile.display()
+        );
+    }
+    report_diagnoses(failed)
+}
+
+/// Fails if any diagnostic check failed.
+fn report_diagnoses(failed: usize) -> Result<()> {
+    if failed > 0 {
+        return Err(Error::FailedDiagnostics(failed).into());
+    }
+    Ok(())
+}
+
+/// Explains an error code
+async fn explain(args: ExplainOpts) -> Result<()> {
+    initialize_logger(&args.log_level);
+
+    match args.code {
+        Some(code) => {
+            let language = args.language.unwrap_or_else(detect_language);
+            println!("{}", explain_error(&code, &language)?);
+        }
+        None => {
+            info!("{} Documented error codes:", emoji::INFO);
+            for code in explained_codes() {
+                println!("{code}");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Repairs the state left behind by interrupted runs, so it does not compound across runs
+fn check_integrity() {
+    let cache_dir = get_cache_dir();
+    let staging_dir = configured_staging_dir(&Config::load().unwrap_or_default().cache);
+    let toolchains_dir = get_rustup_home().join("toolchains");
+    for issue in find_issues(&cache_dir, &staging_dir, &toolchains_dir, SystemTime::now()) {
+        if !issue.is_repairable() {
+            warn!("{} Found {}", emoji::WARN, issue);
+            continue;
+        }
+        match issue.repair() {
+            Ok(()) => info!("{} Removed {}", emoji::WRENCH, issue),
+            Err(e) => warn!("{} Failed to remove {}: {}", emoji::WARN, issue, e),
+        }
+    }
+}
+
+/// Applies the toolchain declared by the project, if any, to the installation options
+fn apply_project(args: &mut InstallOpts) -> Result<()> {
+    if let Some(project) = &args.project {
+        ProjectToolchain::load(project)?.apply(args);
+    }
+    Ok(())
+}
+
+/// Installs the Rust for ESP chips environment in GitHub Actions
+async fn gha_install(args: GhaInstallOpts) -> Result<()> {
+    let mut install_args = args.install;
+    initialize_logger(&install_args.log_level);
+    install_args.apply_ci_preset();
+    apply_project(&mut install_args)?;
+    set_tracing(install_args.trace_http);
+    check_integrity();
+
+    let host_triple = get_host_triple(install_args.default_host.clone())?;
+    // The lockfile is only read when requested
+    let lockfile_path = install_args
+        .lockfile
+        .clone()
+        .or_else(|| install_args.locked.then(|| PathBuf::from(DEFAULT_LOCKFILE)));
+    let lockfile = lockfile_path
+        .as_ref()
+        .and_then(|path| read_to_string(path).ok());
+    // The installation is keyed on the resolved Xtensa Rust version, which is then installed, so
+    // a new release is never served the cached installation of the previous one
+    let toolchain_version = match &install_args.toolchain_version {
+        Some(version) => XtensaRust::parse_version(version)?,
+        None => match lockfile_path
+            .as_ref()
+            .and_then(|path| Lockfile::load(path).ok())
+            .and_then(|lockfile| lockfile.toolchain_version)
+        {
+            Some(version) => version,
+            None => XtensaRust::get_latest_version().await?,
+        },
+    };
+    install_args.toolchain_version = Some(toolchain_version.clone());
+    let key = cache_key(
+        &host_triple,
+        &toolchain_version,
+        lockfile.as_deref(),
+        &install_args,
+    );
+    let rustup_toolchains = get_rustup_home().join("toolchains");
+    let install_path = rustup_toolchains.join(&install_args.name);
+    if args.key_only {
+        println!("{key}");
+        if let Some(output_file) = get_output_file() {
+            write_outputs(
+                &output_file,
+                &[
+                    ("cache-key", key),
+                    ("cache-path", rustup_toolchains.display().to_string()),
+                    ("toolchain-path", install_path.display().to_string()),
+                ],
+            )?;
+        }
+        return Ok(());
+    }
+    let cache_hit = InstallManifest::load(&install_path)
+        .map(|manifest| is_cache_hit(&manifest, &install_args))
+        .unwrap_or(false);
+    if cache_hit {
+        info!(
+            "{} Reusing the installation restored from the cache in '{}'",
+            emoji::CHECK,
+            install_path.display()
+        );
+        install_args.rebuild_exports_only = true;
+    } else {
+        info!("{} Installing the Espressif Rust ecosystem", emoji::DISC);
+    }
+    let export_file = get_export_file(
+        install_args.export_file.clone(),
+        &install_args.name,
+        ExportShell::from_arg(install_args.shell.as_deref()),
+    )?;
+    let warnings_json = install_args.warnings_json.clone();
+    let deny_warnings = install_args.deny_warnings;
+    report_deprecations(install_args.forbid_deprecated)?;
+    let result = toolchain_install(install_args).await;
+    let report = report_warnings(warnings_json.as_deref(), deny_warnings);
+    result?;
+    report?;
+
+    println!("{key}");
+    if let Some(output_file) = get_output_file() {
+        write_outputs(
+            &output_file,
+            &[
+                ("cache-key", key),
+                ("cache-hit", cache_hit.to_string()),
+                ("cache-path", rustup_toolchains.display().to_string()),
+                ("toolchain-path", install_path.display().to_string()),
+                ("export-file", export_file.display().to_string()),
+            ],
+        )?;
+    }
+    Ok(())
+}
+
 /// Installs the Rust for ESP chips environment
-async fn install(args: InstallOpts) -> Result<()> {
+async fn install(mut args: InstallOpts) -> Result<()> {
     initialize_logger(&args.log_level);
-    check_for_update(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    if args.ci {
+        args.apply_ci_preset();
+    } else if !args.offline && !args.frozen && !args.dry_run && args.from_bundle.is_none() {
+        check_for_update(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    }
+    apply_project(&mut args)?;
 
+    set_tracing(args.trace_http);
+    set_output(&args.output);
+    emit(Event::Started {
+        command: "install".to_string(),
+    });
+    if !args.dry_run {
+        check_integrity();
+    }
     info!("{} Installing the Espressif Rust ecosystem", emoji::DISC);
-    toolchain_install(args).await?;
-    info!("{} Installation successfully completed!", emoji::CHECK);
+    let warnings_json = args.warnings_json.clone();
+    let deny_warnings = args.deny_warnings;
+    let print_install_script = args.print_install_script;
+    let only_print_urls = args.only_print_urls;
+    let dry_run = args.dry_run;
+    let result = match report_deprecations(args.forbid_deprecated) {
+        Ok(()) => toolchain_install(args).await,
+        Err(e) => Err(e.into()),
+    };
+    let report = report_warnings(warnings_json.as_deref(), deny_warnings);
+    let result = result.and(report);
+    emit_outcome("install", &result);
+    result?;
+    if print_install_script {
+        info!("{} Install script successfully generated!", emoji::CHECK);
+    } else if only_print_urls {
+        info!("{} Artifact URLs successfully resolved!", emoji::CHECK);
+    } else if dry_run {
+        info!("{} Dry run successfully completed!", emoji::CHECK);
+    } else {
+        info!("{} Installation successfully completed!", emoji::CHECK);
+    }
+    Ok(())
+}
+
+/// Lists the toolchains installed by espup
+async fn list(args: ListOpts) -> Result<()> {
+    initialize_logger(&args.log_level);
+
+    let toolchains_dir = get_rustup_home().join("toolchains");
+    let installed = installed_toolchains(&toolchains_dir);
+    if installed.is_empty() {
+        info!(
+            "{} No toolchain installed by espup in '{}'",
+            emoji::INFO,
+            toolchains_dir.display()
+        );
+        return Ok(());
+    }
+    for toolchain in installed {
+        info!(
+            "{} {} (Xtensa Rust {}): '{}'",
+            emoji::CHECK,
+            toolchain.name,
+            toolchain
+                .xtensa_rust_version
+                .as_deref()
+                .unwrap_or("not installed"),
+            toolchain.path.display()
+        );
+        for version in &toolchain.llvm_versions {
+            info!("{}   LLVM {}", emoji::INFO, version);
+        }
+        for (name, release) in &toolchain.gcc {
+            info!("{}   GCC {} {}", emoji::INFO, name, release);
+        }
+        if !toolchain.has_manifest {
+            warn!(
+                "{}   No installation manifest, run 'espup install --name {}' to repair it",
+                emoji::WARN,
+                toolchain.name
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Manages the download mirrors
+async fn mirror(args: MirrorOpts) -> Result<()> {
+    match args.subcommand {
+        MirrorSubCommand::Bench(args) => {
+            initialize_logger(&args.log_level);
+            check_for_update(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+            set_tracing(args.trace_http);
+
+            let config = Config::load()?;
+            let mut mirrors = vec![DEFAULT_MIRROR.to_string()];
+            for mirror in config.mirrors.iter().chain(args.mirrors.iter()) {
+                if !mirrors.contains(mirror) {
+                    mirrors.push(mirror.clone());
+                }
+            }
+
+            let results = benchmark_mirrors(&mirrors).await;
+            let fastest = results.first().ok_or(Error::NoMirrorAvailable)?;
+            for result in &results {
+                info!(
+                    "{} {}: latency {} ms, throughput {:.2} MiB/s",
+                    emoji::INFO,
+                    result.mirror,
+                    result.latency.as_millis(),
+                    result.throughput / (1024.0 * 1024.0)
+                );
+            }
+
+            if args.no_save {
+                info!("{} Fastest mirror: '{}'", emoji::CHECK, fastest.mirror);
+            } else {
+                // Only the configuration of the user is saved, without the system-wide values
+                let mut config = Config::load_user()?;
+                config.preferred_mirror = Some(fastest.mirror.clone());
+                config.save()?;
+                info!(
+                    "{} Preferred mirror set to '{}' in '{}'",
+                    emoji::CHECK,
+                    fastest.mirror,
+                    Config::path().display()
+                );
+            }
+            Ok(())
+        }
+        MirrorSubCommand::Push(args) => {
+            initialize_logger(&args.log_level);
+            check_for_update(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+            set_tracing(args.trace_http);
+            args.network.apply(&Config::load()?);
+
+            let host_triples = args
+                .hosts
+                .iter()
+                .map(|host| get_host_triple(Some(host.clone())))
+                .collect::<Result<Vec<_>, _>>()?;
+            let toolchain_version = XtensaRust::parse_version(&args.toolchain_version)?;
+            let lockfile =
+                mirror_push(&args.destination, &toolchain_version, &host_triples).await?;
+            info!(
+                "{} Mirrored {} artifacts to '{}'. Install them with `espup install --mirror {}`",
+                emoji::CHECK,
+                lockfile.artifacts.len(),
+                args.destination,
+                args.destination
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Shows the default component versions
+async fn pins(args: PinsOpts) -> Result<()> {
+    match args.subcommand {
+        PinsSubCommand::Show(args) => {
+            initialize_logger(&args.log_level);
+            let path = PinTable::path();
+            if args.bundled || !path.exists() {
+                print!("{BUNDLED_PINS}");
+            } else {
+                let pins = PinTable::load()?;
+                info!(
+                    "{} Bundled versions overridden by '{}'",
+                    emoji::INFO,
+                    path.display()
+                );
+                print!(
+                    "{}",
+                    toml::to_string(&pins).map_err(|e| Error::InvalidPinsFile(
+                        path.display().to_string(),
+                        e.to_string()
+                    ))?
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Removes the unused versions, orphaned temporary files and cached artifacts
+async fn prune(args: PruneOpts) -> Result<()> {
+    initialize_logger(&args.log_level);
+
+    let config = Config::load()?.cache;
+    let cache_dir = get_cache_dir();
+    let items = reclaimable(
+        &get_rustup_home().join("toolchains"),
+        &cache_dir,
+        &configured_staging_dir(&config),
+        &config,
+        !args.keep_cache,
+        SystemTime::now(),
+    )?;
+    if items.is_empty() {
+        info!("{} Nothing to prune", emoji::CHECK);
+        return Ok(());
+    }
+    let to_mib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+    for item in &items {
+        info!(
+            "{}   {} '{}' ({:.2} MiB)",
+            emoji::INFO,
+            item.kind,
+            item.path.display(),
+            to_mib(item.size)
+        );
+    }
+    let total: u64 = items.iter().map(|item| item.size).sum();
+    info!(
+        "{} {} items can be removed, reclaiming {:.2} MiB",
+        emoji::INFO,
+        items.len(),
+        to_mib(total)
+    );
+    if args.dry_run {
+        return Ok(());
+    }
+    if !confirm("Remove them?")? {
+        warn!(
+            "{} Nothing removed, run with '--yes' to remove them without confirmation",
+            emoji::WARN
+        );
+        return Ok(());
+    }
+    for item in &items {
+        item.remove()?;
+    }
+    info!("{} Reclaimed {:.2} MiB", emoji::CHECK, to_mib(total));
+    Ok(())
+}
+
+/// Rolls back to a previous installation
+async fn rollback(args: RollbackOpts) -> Result<()> {
+    let mut install_args = args.install;
+    initialize_logger(&install_args.log_level);
+
+    let install_path = get_rustup_home()
+        .join("toolchains")
+        .join(&install_args.name);
+    let manifest = InstallManifest::load(&install_path)?;
+    if args.list {
+        if manifest.history.is_empty() {
+            info!(
+                "{} No previous installation of '{}' is recorded",
+                emoji::INFO,
+                install_args.name
+            );
+        }
+        for (steps, record) in manifest.history.iter().enumerate() {
+            info!(
+                "{} {}: Xtensa Rust {}, LLVM {}, GCC {}, nightly '{}', targets: {}",
+                emoji::INFO,
+                steps + 1,
+                record.xtensa_rust_version.as_deref().unwrap_or("-"),
+                record.llvm_version,
+                record.gcc_release.as_deref().unwrap_or("-"),
+                record.nightly_version,
+                record.targets.join(", ")
+            );
+        }
+        return Ok(());
+    }
+    let record = args
+        .steps
+        .checked_sub(1)
+        .and_then(|index| manifest.history.get(index))
+        .ok_or_else(|| Error::NoPreviousInstallation(install_args.name.clone()))?;
+
+    if install_args.ci {
+        install_args.apply_ci_preset();
+    }
+    set_tracing(install_args.trace_http);
+    set_output(&install_args.output);
+    emit(Event::Started {
+        command: "rollback".to_string(),
+    });
+    check_integrity();
+    info!(
+        "{} Rolling back to Xtensa Rust {}, LLVM {}",
+        emoji::DISC,
+        record.xtensa_rust_version.as_deref().unwrap_or("-"),
+        record.llvm_version
+    );
+    let warnings_json = install_args.warnings_json.clone();
+    let deny_warnings = install_args.deny_warnings;
+    let result = toolchain_rollback(install_args, record).await;
+    let report = report_warnings(warnings_json.as_deref(), deny_warnings);
+    let result = result.and(report);
+    emit_outcome("rollback", &result);
+    result?;
+    info!("{} Rollback successfully completed!", emoji::CHECK);
+    Ok(())
+}
+
+/// Prints the JSON schema of the machine-readable output
+async fn schema(args: SchemaOpts) -> Result<()> {
+    initialize_logger(&args.log_level);
+    print!("{SCHEMA}");
+    Ok(())
+}
+
+/// Manages the espup installation itself
+async fn self_command(args: SelfOpts) -> Result<()> {
+    match args.subcommand {
+        SelfSubCommand::Test(args) => {
+            initialize_logger(&args.log_level);
+
+            let install_path = get_rustup_home().join("toolchains").join(&args.name);
+            let manifest = InstallManifest::load(&install_path)?;
+            let host_triple = get_host_triple(Some(manifest.host_triple.clone()))?;
+            let export_file = get_export_file(args.export_file, &args.name, ExportShell::Native)?;
+            let targets = match args.targets {
+                Some(targets) => targets,
+                None => parse_targets(&manifest.targets.join(","))?,
+            };
+            let mut targets: Vec<_> = targets.into_iter().collect();
+            targets.sort_by_key(|target| target.to_string());
+            let toolchain = |target: &Target| {
+                if target.is_xtensa() {
+                    args.name.clone()
+                } else {
+                    manifest.nightly_version.clone()
+                }
+            };
+
+            info!(
+                "{} Testing the installation in '{}'",
+                emoji::DISC,
+                install_path.display()
+            );
+            let mut toolchains: Vec<String> = targets.iter().map(toolchain).collect();
+            toolchains.sort();
+            toolchains.dedup();
+            let mut cases: Vec<TestCase> = toolchains
+                .iter()
+                .map(|name| TestCase::run("resolve", name, || resolve_toolchain(name)))
+                .collect();
+            cases.extend(targets.iter().map(|target| {
+                TestCase::from_report("files", &verify_target(target, &host_triple, &install_path))
+            }));
+            cases.extend(targets.iter().map(|target| {
+                TestCase::run("compile", target.to_string(), || {
+                    compile_smoke_test(&toolchain(target), target)
+                })
+            }));
+            let platform = host_triple.platform();
+            cases.extend(
+                [
+                    diagnose_homes(
+                        &manifest,
+                        env::var_os("CARGO_HOME").map(PathBuf::from),
+                        env::var_os("RUSTUP_HOME").map(PathBuf::from),
+                        &home_dir().unwrap_or_default(),
+                        platform,
+                    ),
+                    diagnose_export_file(&export_file, &manifest.exports, platform),
+                    diagnose_libclang(
+                        &manifest.exports,
+                        env::var("LIBCLANG_PATH").ok(),
+                        &export_file,
+                    ),
+                    diagnose_path(
+                        &manifest.exports,
+                        env::var_os("PATH").as_deref(),
+                        &export_file,
+                    ),
+                ]
+                .iter()
+                .map(|diagnosis| TestCase::from_diagnosis("exports", diagnosis)),
+            );
+
+            for case in &cases {
+                match &case.failure {
+                    None => info!("{} {} {}", emoji::CHECK, case.suite, case.name),
+                    Some(failure) => warn!(
+                        "{} {} {}: {}",
+                        emoji::ERROR,
+                        case.suite,
+                        case.name,
+                        failure.lines().next().unwrap_or_default()
+                    ),
+                }
+            }
+            let report = junit_report(&cases);
+            match &args.report {
+                Some(path) => std::fs::write(path, report).map_err(Error::from)?,
+                None => print!("{report}"),
+            }
+            let failed = cases.iter().filter(|case| case.failure.is_some()).count();
+            if failed > 0 {
+                return Err(Error::SelfTestFailed(failed).into());
+            }
+            info!("{} Self-test successfully completed!", emoji::CHECK);
+            Ok(())
+        }
+        SelfSubCommand::Update(args) => {
+            initialize_logger(&args.log_level);
+
+            let current_exe = env::current_exe().map_err(Error::from)?;
+            // Windows can only remove the binary replaced by the previous update once it exits
+            let _ = std::fs::remove_file(old_binary_path(&current_exe));
+            let host_triple = get_host_triple(None)?;
+            let release = tokio::task::spawn_blocking(move || SelfRelease::latest(&host_triple))
+                .await
+                .map_err(|_| Error::GithubQuery)??;
+            let current = env!("CARGO_PKG_VERSION");
+            if !is_newer(&release.version, current) {
+                info!("{} espup {} is up to date", emoji::CHECK, current);
+                return Ok(());
+            }
+            if args.check {
+                info!(
+                    "{} 'espup self update' would update espup {} to {}",
+                    emoji::INFO,
+                    current,
+                    release.version
+                );
+                return Ok(());
+            }
+
+            info!(
+                "{} Updating espup {} to {}",
+                emoji::DOWNLOAD,
+                current,
+                release.version
+            );
+            let bytes = release.download().await?;
+            replace_binary(&current_exe, &release, &bytes)?;
+            info!(
+                "{} espup successfully updated to {}!",
+                emoji::CHECK,
+                release.version
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Queries the chip targets supported by the Xtensa Rust releases
+async fn targets(args: TargetsOpts) -> Result<()> {
+    match args.subcommand {
+        TargetsSubCommand::Supported(args) => {
+            initialize_logger(&args.log_level);
+            check_for_update(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+            set_tracing(args.trace_http);
+
+            info!(
+                "{} Targets supported by Xtensa Rust {}",
+                emoji::INFO,
+                args.toolchain_version
+            );
+            for target in Target::iter() {
+                if target.is_supported_by(&args.toolchain_version) {
+                    info!("{} {}", emoji::CHECK, target);
+                } else {
+                    warn!(
+                        "{} {} requires Xtensa Rust {} or newer",
+                        emoji::WARN,
+                        target,
+                        target.first_supported_release()
+                    );
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Shows a dashboard of the installed toolchains
+#[cfg(feature = "tui")]
+async fn tui(args: TuiOpts) -> Result<()> {
+    if espup::prompt::is_non_interactive() {
+        return Err(Error::NonInteractive("espup tui".to_string()).into());
+    }
+    let host_triple = get_host_triple(args.default_host)?;
+    // The latest version is only shown, the prefetched metadata is enough when GitHub is unreachable
+    let latest = match prefetch(host_triple.clone()).await {
+        Ok(plan) => plan.latest().map(str::to_string),
+        Err(_) => UpdatePlan::load(&host_triple).and_then(|plan| plan.latest().map(str::to_string)),
+    };
+    run_dashboard(&get_rustup_home().join("toolchains"), latest, &args.name)?;
     Ok(())
 }
 
 /// Uninstalls the Rust for ESP chips environment
 async fn uninstall(args: UninstallOpts) -> Result<()> {
     initialize_logger(&args.log_level);
-    check_for_update(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    if !args.dry_run {
+        check_for_update(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    }
+    set_output(&args.output);
+    emit(Event::Started {
+        command: "uninstall".to_string(),
+    });
+    if !args.dry_run {
+        check_integrity();
+    }
 
     info!("{} Uninstalling the Espressif Rust ecosystem", emoji::DISC);
 
-    let install_path = get_rustup_home().join("toolchains").join(args.name);
+    let install_path = get_rustup_home().join("toolchains").join(&args.name);
 
-    Llvm::uninstall(&install_path)?;
+    let manifest = InstallManifest::load(&install_path).ok();
+    // The environment of the user is the one of this host, whatever the toolchain was built for
+    let platform = get_host_triple(None)?.platform();
+    if args.dry_run {
+        info!("{} Dry run, nothing will be uninstalled", emoji::INFO);
+        if let Some(manifest) = &manifest {
+            let artifacts = &manifest.rustup_artifacts;
+            if artifacts.toolchain {
+                info!(
+                    "{} Rustup toolchain: '{}'",
+                    emoji::INFO,
+                    artifacts.nightly_version
+                );
+            }
+            for artifact in artifacts.targets.iter().chain(&artifacts.components) {
+                info!(
+                    "{} Rustup artifact: '{}' of '{}'",
+                    emoji::INFO,
+                    artifact,
+                    artifacts.nightly_version
+                );
+            }
+            for path in &manifest.overrides {
+                info!("{} Toolchain override: '{}'", emoji::INFO, path.display());
+            }
+            if platform.applies_exports() {
+                for export in &manifest.exports {
+                    info!("{} Environment variable: {:?}", emoji::INFO, export);
+                }
+            }
+        }
+        info!(
+            "{} Toolchain directory: '{}'",
+            emoji::INFO,
+            install_path.display()
+        );
+        for export_file in generated_export_files(&args.name, manifest.as_ref()) {
+            info!("{} Export file: '{}'", emoji::INFO, export_file.display());
+        }
+        for cleanup in profile_cleanups(&args.name, manifest.as_ref()) {
+            for line in &cleanup.removed {
+                info!(
+                    "{} Line of '{}': '{}'",
+                    emoji::INFO,
+                    cleanup.path.display(),
+                    line
+                );
+            }
+        }
+        emit_outcome("uninstall", &Ok(()));
+        info!("{} Dry run successfully completed!", emoji::CHECK);
+        return Ok(());
+    }
+    let result = remove_installation(&args.name, &install_path, manifest, platform);
+    emit_outcome("uninstall", &result);
+    result?;
 
-    uninstall_gcc_toolchains(&install_path)?;
+    info!("{} Uninstallation successfully completed!", emoji::CHECK);
+    Ok(())
+}
+
+/// Returns the export files of the toolchain, the default ones for every shell and the ones
+/// recorded in its manifest, whether they exist or not.
+fn export_files(name: &str, manifest: Option<&InstallManifest>) -> Vec<PathBuf> {
+    let mut export_files: Vec<PathBuf> = [ExportShell::Native, ExportShell::Cmd]
+        .into_iter()
+        .filter_map(|shell| get_export_file(None, name, shell).ok())
+        .collect();
+    if let Some(manifest) = manifest {
+        export_files.extend(manifest.export_files.iter().cloned());
+    }
+    export_files.sort();
+    export_files.dedup();
+    export_files
+}
+
+/// Returns the export files that espup generated for the toolchain.
+fn generated_export_files(name: &str, manifest: Option<&InstallManifest>) -> Vec<PathBuf> {
+    export_files(name, manifest)
+        .into_iter()
+        .filter(|export_file| {
+            [ExportShell::Native, ExportShell::Cmd]
+                .into_iter()
+                .any(|shell| is_generated_export_file(export_file, name, shell))
+        })
+        .collect()
+}
+
+/// Returns the cleanups of the lines that espup appended to shell profiles and environment
+/// files for the toolchain, and of the lines sourcing its export files.
+fn profile_cleanups(name: &str, manifest: Option<&InstallManifest>) -> Vec<ProfileCleanup> {
+    let edits = manifest
+        .map(|manifest| manifest.profile_edits.as_slice())
+        .unwrap_or_default();
+    cleanups(
+        &home_dir().unwrap_or_default(),
+        edits,
+        &export_files(name, manifest),
+    )
+}
+
+/// Removes the installation of the toolchain and the rustup artifacts added along with it.
+fn remove_installation(
+    name: &str,
+    install_path: &Path,
+    manifest: Option<InstallManifest>,
+    platform: &dyn Platform,
+) -> Result<()> {
+    let generated = generated_export_files(name, manifest.as_ref());
+    let profile_changes = profile_cleanups(name, manifest.as_ref());
+    if let Some(manifest) = &manifest {
+        // The registry is left untouched if the installation did not change it
+        set_persist_environment(!manifest.export_file_only);
+        clear_exports(&manifest.exports, platform)?;
+        RiscVTarget::uninstall(&manifest.rustup_artifacts)?;
+        if !manifest.rustup_artifacts.is_empty() {
+            emit(Event::Removed {
+                name: "RISC-V Rust target".to_string(),
+                path: None,
+            });
+        }
+        for path in &manifest.overrides {
+            if let Err(e) = unset_override(path) {
+                warn!("{} {}", emoji::WARN, e);
+            }
+        }
+    }
+    if get_default_toolchain().ok().flatten().as_deref() == Some(name) {
+        match manifest.and_then(|manifest| manifest.previous_default) {
+            Some(previous_default) => set_default_toolchain(&previous_default)?,
+            None => warn!(
+                "{} '{}' is the default toolchain, please set a new one with 'rustup default <toolchain>'",
+                emoji::WARN,
+                name
+            ),
+        }
+    }
+
+    if Llvm::uninstall(install_path, platform)? {
+        emit(Event::Removed {
+            name: "LLVM".to_string(),
+            path: Some(install_path.join(CLANG_NAME)),
+        });
+    }
+
+    for gcc_path in uninstall_gcc_toolchains(install_path, platform)? {
+        emit(Event::Removed {
+            name: "GCC".to_string(),
+            path: Some(gcc_path),
+        });
+    }
 
     info!(
         "{} Deleting the Xtensa Rust toolchain located in '{}'",
         emoji::DISC,
         &install_path.display()
     );
-    remove_dir_all(&install_path)
+    remove_dir_all(install_path)
         .map_err(|_| Error::RemoveDirectory(install_path.display().to_string()))?;
+    emit(Event::Removed {
+        name: "Xtensa Rust".to_string(),
+        path: Some(install_path.to_path_buf()),
+    });
 
-    #[cfg(windows)]
-    set_environment_variable("PATH", &env::var("PATH").unwrap())?;
+    for export_file in generated {
+        info!(
+            "{} Deleting the export file '{}'",
+            emoji::DISC,
+            export_file.display()
+        );
+        remove_file(&export_file).map_err(Error::IoError)?;
+    }
+    for cleanup in profile_changes {
+        info!(
+            "{} Removing {} line(s) from '{}'",
+            emoji::WRENCH,
+            cleanup.removed.len(),
+            cleanup.path.display()
+        );
+        cleanup.apply()?;
+    }
 
-    info!("{} Uninstallation successfully completed!", emoji::CHECK);
     Ok(())
 }
 
 /// Updates Xtensa Rust toolchain.
-async fn update(args: InstallOpts) -> Result<()> {
+async fn update(mut args: InstallOpts) -> Result<()> {
     initialize_logger(&args.log_level);
-    check_for_update(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    if args.ci {
+        args.apply_ci_preset();
+    } else if !args.offline && !args.frozen && !args.dry_run && args.from_bundle.is_none() {
+        check_for_update(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    }
+    apply_project(&mut args)?;
 
+    set_tracing(args.trace_http);
+    set_output(&args.output);
+    emit(Event::Started {
+        command: "update".to_string(),
+    });
+    if !args.dry_run {
+        check_integrity();
+    }
     info!("{} Updating Espressif Rust ecosystem", emoji::DISC);
-    toolchain_install(args).await?;
-    info!("{} Update successfully completed!", emoji::CHECK);
+    let warnings_json = args.warnings_json.clone();
+    let deny_warnings = args.deny_warnings;
+    let dry_run = args.dry_run;
+    let result = match report_deprecations(args.forbid_deprecated) {
+        Ok(()) => toolchain_install(args).await,
+        Err(e) => Err(e.into()),
+    };
+    let report = report_warnings(warnings_json.as_deref(), deny_warnings);
+    let result = result.and(report);
+    emit_outcome("update", &result);
+    result?;
+    if dry_run {
+        info!("{} Dry run successfully completed!", emoji::CHECK);
+    } else {
+        info!("{} Update successfully completed!", emoji::CHECK);
+    }
+    Ok(())
+}
+
+/// Verifies the GCC toolchains installed for each chip
+async fn verify(args: VerifyOpts) -> Result<()> {
+    initialize_logger(&args.log_level);
+    check_for_update(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+
+    let host_triple = get_host_triple(args.default_host)?;
+    set_pins(Config::load()?.pins)?;
+    let install_path = get_rustup_home().join("toolchains").join(&args.name);
+    let targets = match args.targets {
+        Some(targets) => targets,
+        None => {
+            let installed = InstallManifest::load(&install_path)
+                .map(|manifest| manifest.targets.join(","))
+                .unwrap_or_default();
+            parse_targets(if installed.is_empty() {
+                "all"
+            } else {
+                &installed
+            })?
+        }
+    };
+    let mut targets: Vec<_> = targets.into_iter().collect();
+    targets.sort_by_key(|target| target.to_string());
+
+    info!(
+        "{} Verifying the GCC toolchains installed in '{}'",
+        emoji::DISC,
+        install_path.display()
+    );
+    let mut incomplete = 0;
+    for target in &targets {
+        let report = verify_target(target, &host_triple, &install_path);
+        if report.missing.is_empty() {
+            info!("{} {} is fully supported", emoji::CHECK, report.target);
+        } else {
+            incomplete += 1;
+            warn!(
+                "{} {} is missing: {}",
+                emoji::WARN,
+                report.target,
+                report.missing.join(", ")
+            );
+        }
+    }
+    if incomplete > 0 {
+        return Err(Error::IncompleteInstallation(incomplete).into());
+    }
+    info!("{} Verification successfully completed!", emoji::CHECK);
+    Ok(())
+}
+
+/// Emits the event of the outcome of a command.
+fn emit_outcome(command: &str, result: &Result<()>) {
+    match result {
+        Ok(()) => emit(Event::Completed {
+            command: command.to_string(),
+        }),
+        Err(e) => emit(Event::error(e)),
+    }
+}
+
+/// Prints the summary of the warnings found and, optionally, writes them as JSON.
+///
+/// If `deny_warnings` is set, any warning makes it fail.
+fn report_warnings(json_file: Option<&Path>, deny_warnings: bool) -> Result<()> {
+    warnings::print_summary();
+    if let Some(json_file) = json_file {
+        warnings::write_json(json_file)?;
+    }
+    let count = warnings::collected().len();
+    if deny_warnings && count > 0 {
+        return Err(Error::DeniedWarnings(count).into());
+    }
     Ok(())
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    match Cli::parse().subcommand {
+    let args = migrate_args(env::args_os());
+    // The defaults of the configuration file are overridden by the command line
+    let mut defaults: Vec<(&'static str, String)> = PinTable::load()
+        .map(|pins| vec![("nightly_version", pins.nightly)])
+        .unwrap_or_default();
+    defaults.extend(
+        Config::load()
+            .map(|config| config.defaults.arguments())
+            .unwrap_or_default(),
+    );
+    let matches = with_defaults(Cli::command(), &defaults).get_matches_from(args);
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    set_homes(cli.cargo_home.as_deref(), cli.rustup_home.as_deref());
+    set_non_interactive(cli.non_interactive, cli.yes);
+    // Only the commands reading or writing the installations need the homes of cargo and rustup
+    if !matches!(
+        cli.subcommand,
+        SubCommand::Completions(_)
+            | SubCommand::Explain(_)
+            | SubCommand::Schema(_)
+            | SubCommand::SelfCommand(_)
+    ) {
+        check_homes()?;
+    }
+    // The `--ci` preset does not override the options given by the user
+    let extended_llvm_given = matches.subcommand().is_some_and(|(_, subcommand)| {
+        subcommand.try_get_raw("extended_llvm").is_ok_and(|_| {
+            subcommand.value_source("extended_llvm") > Some(ValueSource::DefaultValue)
+        })
+    });
+    match cli.subcommand {
+        SubCommand::Auth(args) => auth(args).await,
+        SubCommand::Bundle(args) => bundle(args).await,
+        SubCommand::Cache(args) => cache(args).await,
+        SubCommand::Check(args) => check(args).await,
         SubCommand::Completions(args) => completions(args).await,
-        SubCommand::Install(args) => install(*args).await,
-        SubCommand::Update(args) => update(*args).await,
+        SubCommand::Doctor(args) => doctor(args).await,
+        SubCommand::Explain(args) => explain(args).await,
+        SubCommand::GhaInstall(mut args) => {
+            args.install.extended_llvm_given = extended_llvm_given;
+            gha_install(*args).await
+        }
+        SubCommand::Install(mut args) => {
+            args.extended_llvm_given = extended_llvm_given;
+            install(*args).await
+        }
+        SubCommand::List(args) => list(args).await,
+        SubCommand::Mirror(args) => mirror(args).await,
+        SubCommand::Pins(args) => pins(args).await,
+        SubCommand::Prune(args) => prune(args).await,
+        SubCommand::Rollback(mut args) => {
+            args.install.extended_llvm_given = extended_llvm_given;
+            rollback(*args).await
+        }
+        SubCommand::Schema(args) => schema(args).await,
+        SubCommand::SelfCommand(args) => self_command(args).await,
+        SubCommand::Targets(args) => targets(args).await,
+        #[cfg(feature = "tui")]
+        SubCommand::Tui(args) => tui(args).await,
+        SubCommand::Update(mut args) => {
+            // Explicit targets only refresh the given ones, keeping the other installed targets
+            args.partial_targets = matches
+                .subcommand_matches("update")
+                .and_then(|update| update.value_source("targets"))
+                == Some(ValueSource::CommandLine);
+            args.extended_llvm_given = extended_llvm_given;
+            update(*args).await
+        }
         SubCommand::Uninstall(args) => uninstall(args).await,
+        SubCommand::Verify(args) => verify(args).await,
     }
 }
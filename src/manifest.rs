@@ -0,0 +1,350 @@
+//! Manifest describing an existing installation.
+
+use crate::{
+    env::ExportItem, error::Error, profile::ProfileEdit, targets::Target,
+    toolchain::rust::RustupArtifacts,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs::{read_to_string, write},
+    path::{Path, PathBuf},
+};
+
+/// Name of the manifest file, stored inside the toolchain directory.
+pub const MANIFEST_FILE: &str = "espup-manifest.json";
+/// Number of previous installations recorded in the manifest.
+pub const HISTORY_SIZE: usize = 5;
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct InstallManifest {
+    /// Host triple.
+    pub host_triple: String,
+    /// Installed LLVM version.
+    pub llvm_version: String,
+    /// Nightly Rust toolchain version used for RISC-V targets.
+    pub nightly_version: String,
+    /// Installed targets.
+    pub targets: Vec<String>,
+    /// Xtensa Rust toolchain version, if installed.
+    pub xtensa_rust_version: Option<String>,
+    /// Environment exports required by the installation.
+    pub exports: Vec<ExportItem>,
+    /// Rustup artifacts added by the installation.
+    #[serde(default)]
+    pub rustup_artifacts: RustupArtifacts,
+    /// Default toolchain before espup set the installed one as default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_default: Option<String>,
+    /// Directories where the installed toolchain was set as override.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overrides: Vec<PathBuf>,
+    /// Installed GCC release, `None` if GCC is not installed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gcc_release: Option<String>,
+    /// Whether the extended LLVM tools are installed.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub extended_llvm: bool,
+    /// Whether the installation of LLVM was skipped.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skip_llvm: bool,
+    /// Whether the installation of the `rust-src` component was skipped.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skip_rust_src: bool,
+    /// Previous installations of the toolchain, the most recent first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<InstallRecord>,
+    /// Cargo home used by the installation, `None` for the ones of older espup versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cargo_home: Option<PathBuf>,
+    /// Rustup home used by the installation, `None` for the ones of older espup versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rustup_home: Option<PathBuf>,
+    /// Whether the environment variables of the user were left untouched on Windows hosts.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub export_file_only: bool,
+    /// Export files written for the installation.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub export_files: Vec<PathBuf>,
+    /// Lines appended to shell profiles and environment files.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub profile_edits: Vec<ProfileEdit>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct InstallRecord {
+    /// Xtensa Rust toolchain version, if installed.
+    pub xtensa_rust_version: Option<String>,
+    /// Installed LLVM version.
+    pub llvm_version: String,
+    /// Installed GCC release, `None` if GCC is not installed.
+    pub gcc_release: Option<String>,
+    /// Nightly Rust toolchain version used for RISC-V targets.
+    pub nightly_version: String,
+    /// Installed targets.
+    pub targets: Vec<String>,
+}
+
+impl InstallManifest {
+    /// Returns the path of the manifest of the toolchain installed in the given path.
+    pub fn path(install_path: &Path) -> PathBuf {
+        install_path.join(MANIFEST_FILE)
+    }
+
+    /// Loads the manifest of the toolchain installed in the given path.
+    pub fn load(install_path: &Path) -> Result<Self, Error> {
+        let path = Self::path(install_path);
+        let contents = read_to_string(&path)
+            .map_err(|_| Error::MissingManifest(path.display().to_string()))?;
+        serde_json::from_str(&contents)
+            .map_err(|_| Error::MissingManifest(path.display().to_string()))
+    }
+
+    /// Returns the record of the installed versions.
+    pub fn record(&self) -> InstallRecord {
+        InstallRecord {
+            xtensa_rust_version: self.xtensa_rust_version.clone(),
+            llvm_version: self.llvm_version.clone(),
+            gcc_release: self.gcc_release.clone(),
+            nightly_version: self.nightly_version.clone(),
+            targets: self.targets.clone(),
+        }
+    }
+
+    /// Records the previous installation in the history, keeping the last `HISTORY_SIZE` ones
+    /// that differ from the current installation.
+    pub fn record_previous(&mut self, previous: &InstallManifest) {
+        let current = self.record();
+        let mut history = vec![previous.record()];
+        history.extend(previous.history.iter().cloned());
+        history.retain(|record| *record != current);
+        history.dedup();
+        history.truncate(HISTORY_SIZE);
+        self.history = history;
+    }
+
+    /// Keeps the components of a previous installation that a partial update of some targets
+    /// does not touch: the other targets, the Xtensa Rust toolchain and its LLVM when no Xtensa
+    /// target is updated, the RISC-V targets when no RISC-V target is updated, and their exports.
+    pub fn keep_untouched(&mut self, previous: &InstallManifest, targets: &HashSet<Target>) {
+        self.targets.extend(previous.targets.iter().cloned());
+        self.targets.sort();
+        self.targets.dedup();
+        if !targets.iter().any(|target| target.is_xtensa())
+            && previous.xtensa_rust_version.is_some()
+        {
+            self.xtensa_rust_version = previous.xtensa_rust_version.clone();
+            self.llvm_version = previous.llvm_version.clone();
+        }
+        if !targets.iter().any(|target| target.is_riscv()) {
+            self.nightly_version = previous.nightly_version.clone();
+            self.rustup_artifacts = previous.rustup_artifacts.clone();
+        }
+        // The GCC release installed by the update is recorded, the previous one is only kept when
+        // the update installs no GCC toolchain
+        self.gcc_release = self
+            .gcc_release
+            .take()
+            .or_else(|| previous.gcc_release.clone());
+        // The exports of the components replaced by the update, e.g. the `PATH` entry of a GCC
+        // toolchain of the previous release, are stale
+        let kept: Vec<ExportItem> = previous
+            .exports
+            .iter()
+            .filter(|export| !self.exports.iter().any(|item| replaces(item, export)))
+            .cloned()
+            .collect();
+        self.exports.extend(kept);
+    }
+
+    /// Records an export file written for the installation.
+    pub fn record_export_file(&mut self, export_file: &Path) {
+        if !self.export_files.iter().any(|path| path == export_file) {
+            self.export_files.push(export_file.to_path_buf());
+        }
+    }
+
+    /// Records the lines appended to a shell profile or environment file.
+    pub fn record_edit(&mut self, path: &Path, lines: Vec<String>) {
+        match self.profile_edits.iter_mut().find(|edit| edit.path == path) {
+            Some(edit) => edit.lines.extend(lines),
+            None => self.profile_edits.push(ProfileEdit {
+                path: path.to_path_buf(),
+                lines,
+            }),
+        }
+    }
+
+    /// Keeps track of the files written for a previous installation that still exist, so
+    /// uninstalling cleans them up as well.
+    pub fn keep_written_files(&mut self, previous: &InstallManifest) {
+        for export_file in previous.export_files.iter().filter(|path| path.is_file()) {
+            self.record_export_file(export_file);
+        }
+        for edit in previous
+            .profile_edits
+            .iter()
+            .filter(|edit| edit.path.is_file())
+        {
+            self.record_edit(&edit.path, edit.lines.clone());
+        }
+    }
+
+    /// Saves the manifest in the toolchain installed in the given path.
+    pub fn save(&self, install_path: &Path) -> Result<(), Error> {
+        let contents = serde_json::to_string_pretty(self).map_err(|_| Error::SerializeJson)?;
+        write(Self::path(install_path), contents)?;
+        Ok(())
+    }
+}
+
+/// Returns true if an export sets the same variable, or adds the `bin` directory of the same
+/// component to the `PATH`, as a previous one.
+fn replaces(item: &ExportItem, previous: &ExportItem) -> bool {
+    match (item, previous) {
+        (
+            ExportItem::EnvSet { key, .. },
+            ExportItem::EnvSet {
+                key: previous_key, ..
+            },
+        ) => key == previous_key,
+        (ExportItem::PathAdd(dir), ExportItem::PathAdd(previous_dir)) => {
+            component_dir(dir) == component_dir(previous_dir)
+        }
+        _ => item == previous,
+    }
+}
+
+/// Returns the last two components of a directory, e.g. `riscv32-esp-elf/bin` for the `bin`
+/// directory of a GCC toolchain, whatever the release it is installed for.
+fn component_dir(dir: &str) -> Vec<&str> {
+    let mut components: Vec<&str> = dir
+        .rsplit(['/', '\\'])
+        .filter(|component| !component.is_empty())
+        .take(2)
+        .collect();
+    components.reverse();
+    components
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        env::ExportItem,
+        manifest::{InstallManifest, HISTORY_SIZE},
+        targets::Target,
+    };
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_manifest_load_and_save() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        assert!(InstallManifest::load(temp_dir.path()).is_err());
+
+        let manifest = InstallManifest {
+            host_triple: "x86_64-unknown-linux-gnu".to_string(),
+            llvm_version: "esp-16.0.0-20230516".to_string(),
+            nightly_version: "nightly".to_string(),
+            targets: vec!["esp32".to_string()],
+            xtensa_rust_version: Some("1.72.0.0".to_string()),
+            exports: vec![ExportItem::EnvSet {
+                key: "LIBCLANG_PATH".to_string(),
+                value: "/path".to_string(),
+            }],
+            ..Default::default()
+        };
+        manifest.save(temp_dir.path()).unwrap();
+        assert_eq!(InstallManifest::load(temp_dir.path()).unwrap(), manifest);
+    }
+
+    #[test]
+    fn test_record_previous() {
+        let installation = |version: &str| InstallManifest {
+            llvm_version: "esp-17.0.1_20240112".to_string(),
+            xtensa_rust_version: Some(version.to_string()),
+            ..Default::default()
+        };
+        let mut manifest = installation("1.76.0.1");
+        for version in ["1.77.0.0", "1.78.0.0", "1.76.0.1", "1.79.0.0"] {
+            let mut next = installation(version);
+            next.record_previous(&manifest);
+            manifest = next;
+        }
+        let versions: Vec<_> = manifest
+            .history
+            .iter()
+            .map(|record| record.xtensa_rust_version.as_deref().unwrap())
+            .collect();
+        // Reinstalling a previous version moves it to the front of the history
+        assert_eq!(versions, vec!["1.76.0.1", "1.78.0.0", "1.77.0.0"]);
+
+        let mut manifest = installation("1.80.0.0");
+        for minor in 81..81 + 2 * HISTORY_SIZE {
+            let mut next = installation(&format!("1.{minor}.0.0"));
+            next.record_previous(&manifest);
+            manifest = next;
+        }
+        assert_eq!(manifest.history.len(), HISTORY_SIZE);
+    }
+
+    #[test]
+    fn test_keep_untouched() {
+        let previous = InstallManifest {
+            llvm_version: "esp-16.0.0-20230516".to_string(),
+            nightly_version: "nightly-2024-01-01".to_string(),
+            targets: vec!["esp32".to_string(), "esp32c3".to_string()],
+            xtensa_rust_version: Some("1.77.0.0".to_string()),
+            gcc_release: Some("12.2.0_20230208".to_string()),
+            exports: vec![
+                ExportItem::PathAdd("/esp/xtensa-esp-elf/bin".to_string()),
+                ExportItem::PathAdd(
+                    "/esp/riscv32-esp-elf/esp-12.2.0_20230208/riscv32-esp-elf/bin".to_string(),
+                ),
+                ExportItem::EnvSet {
+                    key: "LIBCLANG_PATH".to_string(),
+                    value: "/esp/esp-clang/lib".to_string(),
+                },
+            ],
+            ..Default::default()
+        };
+        // Only the RISC-V GCC toolchain is installed for the updated chip
+        let mut manifest = InstallManifest {
+            llvm_version: "esp-17.0.1_20240419".to_string(),
+            nightly_version: "nightly".to_string(),
+            targets: vec!["esp32c6".to_string()],
+            gcc_release: Some("13.2.0_20230928".to_string()),
+            exports: vec![
+                ExportItem::PathAdd(
+                    "/esp/riscv32-esp-elf/esp-13.2.0_20230928/riscv32-esp-elf/bin".to_string(),
+                ),
+                ExportItem::EnvSet {
+                    key: "LIBCLANG_PATH".to_string(),
+                    value: "/esp/esp-clang/lib".to_string(),
+                },
+            ],
+            ..Default::default()
+        };
+        manifest.keep_untouched(&previous, &HashSet::from([Target::ESP32C6]));
+        assert_eq!(manifest.targets, vec!["esp32", "esp32c3", "esp32c6"]);
+        assert_eq!(manifest.xtensa_rust_version.as_deref(), Some("1.77.0.0"));
+        assert_eq!(manifest.llvm_version, "esp-16.0.0-20230516");
+        assert_eq!(manifest.nightly_version, "nightly");
+        assert_eq!(manifest.gcc_release.as_deref(), Some("13.2.0_20230928"));
+        // The RISC-V GCC toolchain of the previous release is no longer in the `PATH`
+        assert_eq!(manifest.exports.len(), 3);
+        assert!(manifest
+            .exports
+            .contains(&ExportItem::PathAdd("/esp/xtensa-esp-elf/bin".to_string())));
+        assert!(!manifest.exports.contains(&ExportItem::PathAdd(
+            "/esp/riscv32-esp-elf/esp-12.2.0_20230208/riscv32-esp-elf/bin".to_string()
+        )));
+
+        // Without GCC in the update, the previous release is still the installed one
+        let mut manifest = InstallManifest {
+            targets: vec!["esp32c6".to_string()],
+            ..Default::default()
+        };
+        manifest.keep_untouched(&previous, &HashSet::from([Target::ESP32C6]));
+        assert_eq!(manifest.gcc_release.as_deref(), Some("12.2.0_20230208"));
+    }
+}
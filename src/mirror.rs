@@ -0,0 +1,535 @@
+//! Mirrors of the GitHub releases and benchmarking tools.
+
+use crate::{
+    cache::stage,
+    checksum::{sha256, Checksum},
+    credentials::authorize,
+    emoji,
+    error::Error,
+    host_triple::HostTriple,
+    http::{trace_request, trace_response},
+    lockfile::{LockedArtifact, Lockfile, DEFAULT_LOCKFILE},
+    oci::OCI_SCHEME,
+    proxy::client,
+    targets::Target,
+    timeout::read,
+    toolchain::{gcc::Gcc, llvm::Llvm, rust::XtensaRust, verify_checksum},
+    warnings::{self, WarningKind},
+};
+use log::{debug, info, warn};
+use reqwest::header;
+use std::{
+    fs::write,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Default source of the release artifacts.
+pub const DEFAULT_MIRROR: &str = "https://github.com";
+/// Base URL of the GitHub API.
+pub const GITHUB_API: &str = "https://api.github.com";
+/// Path of the GitHub API endpoints in a distribution server.
+const DIST_SERVER_API_PATH: &str = "api";
+/// Artifact used to probe the mirrors, relative to the mirror URL.
+const PROBE_ARTIFACT: &str = "espressif/crosstool-NG/releases/download/esp-12.2.0_20230208/riscv32-esp-elf-12.2.0_20230208-x86_64-linux-gnu.tar.xz";
+/// Number of bytes downloaded from the probe artifact.
+const PROBE_SIZE: u64 = 1024 * 1024;
+/// Scheme of the mirrors hosted in S3 buckets.
+const S3_SCHEME: &str = "s3://";
+
+static MIRROR: Mutex<Option<String>> = Mutex::new(None);
+static API_SERVER: Mutex<Option<String>> = Mutex::new(None);
+/// Sources not tried yet, by priority.
+static FALLBACK_MIRRORS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone)]
+pub struct MirrorBenchmark {
+    /// Mirror URL.
+    pub mirror: String,
+    /// Time until the response headers were received.
+    pub latency: Duration,
+    /// Download throughput, in bytes per second.
+    pub throughput: f64,
+}
+
+/// Replaces the default source of a release URL with the given mirror.
+pub fn apply_mirror(url: &str, mirror: &str) -> String {
+    match url.strip_prefix(DEFAULT_MIRROR) {
+        Some(path) => format!("{}{}", mirror.trim_end_matches('/'), path),
+        None => url.to_string(),
+    }
+}
+
+/// Returns the HTTPS endpoint of a `s3://bucket/prefix` mirror, or the mirror unchanged.
+pub fn resolve_mirror(mirror: &str) -> String {
+    match mirror.strip_prefix(S3_SCHEME) {
+        Some(path) => {
+            let (bucket, prefix) = path.split_once('/').unwrap_or((path, ""));
+            let prefix = prefix.trim_matches('/');
+            if prefix.is_empty() {
+                format!("https://{bucket}.s3.amazonaws.com")
+            } else {
+                format!("https://{bucket}.s3.amazonaws.com/{prefix}")
+            }
+        }
+        None => mirror.trim_end_matches('/').to_string(),
+    }
+}
+
+/// Sets the mirror used to download the release artifacts.
+pub fn set_mirror(mirror: Option<String>) {
+    let mirror = mirror
+        .map(|mirror| resolve_mirror(&mirror))
+        .filter(|mirror| mirror != DEFAULT_MIRROR);
+    if let Some(mirror) = &mirror {
+        info!("{} Using mirror '{}'", emoji::INFO, mirror);
+    }
+    *MIRROR.lock().unwrap() = mirror;
+}
+
+/// Sets the distribution server, which serves the release artifacts under the same paths as
+/// GitHub and the GitHub API endpoints under `<server>/api`.
+pub fn set_dist_server(dist_server: Option<String>) {
+    *API_SERVER.lock().unwrap() = dist_server.as_deref().map(dist_server_api);
+    if dist_server.is_some() {
+        set_mirror(dist_server);
+    }
+}
+
+/// Returns the URL the distribution server serves the GitHub API endpoints under.
+fn dist_server_api(dist_server: &str) -> String {
+    format!(
+        "{}/{DIST_SERVER_API_PATH}",
+        dist_server.trim_end_matches('/')
+    )
+}
+
+/// Returns the sources to fall back to, by priority, when the primary one is unreachable.
+///
+/// GitHub comes first when a mirror is the primary source. OCI registries are skipped, as they
+/// do not serve the release metadata.
+fn fallback_order(primary: Option<&str>, mirrors: &[String]) -> Vec<String> {
+    let primary = primary.unwrap_or(DEFAULT_MIRROR);
+    let mut order: Vec<String> = Vec::new();
+    for mirror in [DEFAULT_MIRROR.to_string()]
+        .into_iter()
+        .chain(mirrors.iter().map(|mirror| resolve_mirror(mirror)))
+    {
+        if mirror != primary && !mirror.starts_with(OCI_SCHEME) && !order.contains(&mirror) {
+            order.push(mirror);
+        }
+    }
+    order
+}
+
+/// Sets the mirrors to fall back to, by priority, when the mirror in use is unreachable.
+pub fn set_fallback_mirrors(mirrors: &[String]) {
+    let order = fallback_order(current_mirror().as_deref(), mirrors);
+    debug!("{} Fallback mirrors: {:?}", emoji::DEBUG, order);
+    *FALLBACK_MIRRORS.lock().unwrap() = order;
+}
+
+/// Returns the source in use, either GitHub or a mirror.
+pub fn current_source() -> String {
+    current_mirror().unwrap_or_else(|| DEFAULT_MIRROR.to_string())
+}
+
+/// Returns true if an error means that the source could not be reached, or failed to serve the
+/// request, so the next source may succeed.
+pub fn is_unreachable(error: &Error) -> bool {
+    match error {
+        Error::Timeout(..) => true,
+        Error::RewquestError(e) => {
+            e.is_connect() || e.is_timeout() || e.status().is_some_and(|s| s.is_server_error())
+        }
+        _ => false,
+    }
+}
+
+/// Switches to the next fallback mirror after `failed` was unreachable, serving both the release
+/// artifacts and, under `<mirror>/api`, the GitHub API endpoints.
+///
+/// Returns false when there is no source left. If another request already switched away from
+/// `failed`, the source in use is kept, so concurrent failures do not skip mirrors.
+pub fn fall_back(failed: &str, error: &Error) -> bool {
+    if current_source() != failed {
+        return true;
+    }
+    let mut fallbacks = FALLBACK_MIRRORS.lock().unwrap();
+    if fallbacks.is_empty() {
+        return false;
+    }
+    let next = fallbacks.remove(0);
+    warnings::push(
+        WarningKind::Fallback,
+        format!("'{failed}' is unreachable ({error}), falling back to mirror '{next}'"),
+    );
+    if next == DEFAULT_MIRROR {
+        *API_SERVER.lock().unwrap() = None;
+        *MIRROR.lock().unwrap() = None;
+    } else {
+        *API_SERVER.lock().unwrap() = Some(format!("{next}/{DIST_SERVER_API_PATH}"));
+        *MIRROR.lock().unwrap() = Some(next);
+    }
+    true
+}
+
+/// Returns the URL of a GitHub API endpoint in the distribution server in use, if any.
+pub fn api_url(url: &str) -> String {
+    match API_SERVER.lock().unwrap().as_deref() {
+        Some(api_server) => apply_api_server(url, api_server),
+        None => url.to_string(),
+    }
+}
+
+/// Returns the URL of a GitHub API endpoint in an API server.
+fn apply_api_server(url: &str, api_server: &str) -> String {
+    match url.strip_prefix(GITHUB_API) {
+        Some(path) => format!("{api_server}{path}"),
+        None => url.to_string(),
+    }
+}
+
+/// Returns the mirror in use, if any.
+pub fn current_mirror() -> Option<String> {
+    MIRROR.lock().unwrap().clone()
+}
+
+/// Returns the URL of an artifact in the mirror in use, if any.
+///
+/// OCI mirrors are not addressed by URL, see [`crate::oci::OciReference`].
+pub fn mirrored_url(url: &str) -> String {
+    match MIRROR.lock().unwrap().as_deref() {
+        Some(mirror) if !mirror.starts_with(OCI_SCHEME) => apply_mirror(url, mirror),
+        _ => url.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReleaseArtifact {
+    /// Name of the component.
+    pub name: String,
+    /// Upstream URL of the artifact.
+    pub url: String,
+    /// URL of the checksums published with the artifact.
+    pub checksums_url: String,
+}
+
+impl ReleaseArtifact {
+    /// Returns the path of the artifact, relative to the mirror.
+    pub fn path(&self) -> &str {
+        self.url.strip_prefix(DEFAULT_MIRROR).unwrap_or(&self.url)
+    }
+
+    /// Returns the file name of the artifact.
+    pub fn file_name(&self) -> String {
+        self.url.rsplit('/').next().unwrap_or_default().to_string()
+    }
+}
+
+/// Returns every artifact required to install a version in the given hosts.
+pub fn release_artifacts(
+    toolchain_version: &str,
+    host_triples: &[HostTriple],
+) -> Result<Vec<ReleaseArtifact>, Error> {
+    let mut artifacts: Vec<ReleaseArtifact> = Vec::new();
+    let mut push = |name: &str, url: String, checksums_url: String| {
+        if !artifacts.iter().any(|known| known.url == url) {
+            artifacts.push(ReleaseArtifact {
+                name: name.to_string(),
+                url,
+                checksums_url,
+            });
+        }
+    };
+    for host_triple in host_triples {
+        let checksums_url =
+            XtensaRust::new(toolchain_version, host_triple, Path::new("")).checksums_url;
+        for url in XtensaRust::release_urls(toolchain_version, host_triple) {
+            push("Xtensa Rust", url, checksums_url.clone());
+        }
+        for extended in [false, true] {
+            let llvm = Llvm::new(Path::new(""), host_triple, extended, toolchain_version)?;
+            push("LLVM", llvm.repository_url, llvm.checksums_url);
+        }
+        for target in [Target::ESP32, Target::ESP32S2, Target::ESP32S3] {
+            let gcc = Gcc::new(&target, host_triple, Path::new(""));
+            push(&gcc.name, gcc.dist_url(), gcc.checksums_url());
+        }
+        let gcc = Gcc::new_riscv(host_triple, Path::new(""));
+        push(&gcc.name, gcc.dist_url(), gcc.checksums_url());
+    }
+    Ok(artifacts)
+}
+
+/// Downloads a release artifact from its upstream URL.
+pub(crate) async fn download(client: &reqwest::Client, url: &str) -> Result<Vec<u8>, Error> {
+    info!("{} Downloading '{}'", emoji::DOWNLOAD, url);
+    let request = authorize(client.get(url), url).build()?;
+    trace_request(request.method(), url, request.headers());
+    let start = Instant::now();
+    let resp = read(url, client.execute(request)).await?;
+    trace_response(url, resp.status(), resp.headers(), start.elapsed());
+    Ok(read(url, resp.error_for_status()?.bytes()).await?.to_vec())
+}
+
+/// Downloads a release artifact and verifies it against its published checksum, returning its
+/// contents and digest.
+pub(crate) async fn download_verified(
+    client: &reqwest::Client,
+    artifact: &ReleaseArtifact,
+) -> Result<(Vec<u8>, String), Error> {
+    let bytes = download(client, &artifact.url).await?;
+    verify_checksum(
+        &Checksum::Published(artifact.checksums_url.clone()),
+        &artifact.url,
+        &bytes,
+    )
+    .await?;
+    let digest = sha256(&bytes);
+    Ok((bytes, digest))
+}
+
+/// Uploads a file to an S3 destination using the AWS CLI.
+fn upload_to_s3(file: &Path, destination: &str) -> Result<(), Error> {
+    debug!(
+        "{} Uploading '{}' to '{}'",
+        emoji::DEBUG,
+        file.display(),
+        destination
+    );
+    let status = Command::new("aws")
+        .args(["s3", "cp", "--only-show-errors"])
+        .arg(file)
+        .arg(destination)
+        .status()
+        .map_err(|_| Error::MirrorUpload(destination.to_string()))?;
+    if !status.success() {
+        return Err(Error::MirrorUpload(destination.to_string()));
+    }
+    Ok(())
+}
+
+/// Downloads the artifacts of a version for the given hosts, verified against their published
+/// checksums, and uploads them to a `s3://bucket/prefix` destination, along with their checksums
+/// and a lockfile.
+pub async fn push(
+    destination: &str,
+    toolchain_version: &str,
+    host_triples: &[HostTriple],
+) -> Result<Lockfile, Error> {
+    if !destination.starts_with(S3_SCHEME) {
+        return Err(Error::MirrorUpload(destination.to_string()));
+    }
+    let destination = destination.trim_end_matches('/');
+    let staging_dir = stage()?;
+    let client = client()?;
+    let mut lockfile = Lockfile::default();
+    // Verified digests of the artifacts, by URL of the checksums they are published in
+    let mut checksums: Vec<(String, String)> = Vec::new();
+    for artifact in release_artifacts(toolchain_version, host_triples)? {
+        let (bytes, sha256) = download_verified(&client, &artifact).await?;
+        let file_name = artifact.file_name();
+        let file: PathBuf = staging_dir.path().join(&file_name);
+        write(&file, &bytes)?;
+        info!("{} Uploading '{}'", emoji::WRENCH, file_name);
+        upload_to_s3(&file, &format!("{destination}{}", artifact.path()))?;
+        std::fs::remove_file(&file)?;
+        let line = format!("{sha256}  {file_name}\n");
+        match checksums
+            .iter_mut()
+            .find(|(url, _)| *url == artifact.checksums_url)
+        {
+            Some((_, contents)) => contents.push_str(&line),
+            None => checksums.push((artifact.checksums_url.clone(), line)),
+        }
+        lockfile.artifacts.push(LockedArtifact {
+            name: artifact.name,
+            url: artifact.url,
+            file_name,
+            sha256,
+        });
+    }
+
+    // Installations from the mirror look the digests up in the checksums next to the artifacts
+    for (url, contents) in checksums {
+        let path = url.strip_prefix(DEFAULT_MIRROR).unwrap_or(&url);
+        let file = staging_dir
+            .path()
+            .join(path.rsplit('/').next().unwrap_or_default());
+        write(&file, contents)?;
+        upload_to_s3(&file, &format!("{destination}{path}"))?;
+        std::fs::remove_file(&file)?;
+    }
+
+    let lockfile_path = staging_dir.path().join(DEFAULT_LOCKFILE);
+    lockfile.save(&lockfile_path)?;
+    upload_to_s3(&lockfile_path, &format!("{destination}/{DEFAULT_LOCKFILE}"))?;
+    Ok(lockfile)
+}
+
+/// Downloads the probe artifact from a mirror and measures its latency and throughput.
+pub async fn benchmark(mirror: &str) -> Result<MirrorBenchmark, Error> {
+    let url = format!("{}/{}", resolve_mirror(mirror), PROBE_ARTIFACT);
+    debug!("{} Probing mirror with '{}'", emoji::DEBUG, url);
+    let client = client()?;
+    let request = authorize(client.get(&url), &url)
+        .header(header::RANGE, format!("bytes=0-{}", PROBE_SIZE - 1))
+        .build()?;
+    trace_request(request.method(), &url, request.headers());
+    let start = Instant::now();
+    let resp = client.execute(request).await?;
+    let latency = start.elapsed();
+    trace_response(&url, resp.status(), resp.headers(), latency);
+    let resp = resp.error_for_status()?;
+    let bytes = resp.bytes().await?;
+    let elapsed = start.elapsed().as_secs_f64();
+
+    Ok(MirrorBenchmark {
+        mirror: mirror.to_string(),
+        latency,
+        throughput: bytes.len() as f64 / elapsed,
+    })
+}
+
+/// Benchmarks every mirror and returns the results sorted from fastest to slowest.
+pub async fn benchmark_mirrors(mirrors: &[String]) -> Vec<MirrorBenchmark> {
+    let mut results = Vec::new();
+    for mirror in mirrors {
+        info!("{} Benchmarking mirror '{}'", emoji::DOWNLOAD, mirror);
+        match benchmark(mirror).await {
+            Ok(result) => results.push(result),
+            Err(e) => warn!(
+                "{} Mirror '{}' could not be benchmarked: {}",
+                emoji::WARN,
+                mirror,
+                e
+            ),
+        }
+    }
+    results.sort_by(|a, b| b.throughput.total_cmp(&a.throughput));
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::Error,
+        host_triple::HostTriple,
+        mirror::{
+            apply_api_server, apply_mirror, dist_server_api, fallback_order, is_unreachable,
+            release_artifacts, resolve_mirror, DEFAULT_MIRROR,
+        },
+    };
+
+    #[test]
+    fn test_apply_mirror() {
+        let url = format!("{DEFAULT_MIRROR}/esp-rs/rust-build/releases/download/v1.0.0/rust.zip");
+        assert_eq!(
+            apply_mirror(&url, "https://mirror.example.com/"),
+            "https://mirror.example.com/esp-rs/rust-build/releases/download/v1.0.0/rust.zip"
+        );
+        assert_eq!(apply_mirror(&url, DEFAULT_MIRROR), url);
+        assert_eq!(
+            apply_mirror("https://example.com/file.zip", "https://mirror.example.com"),
+            "https://example.com/file.zip"
+        );
+    }
+
+    #[test]
+    fn test_resolve_mirror() {
+        assert_eq!(
+            resolve_mirror("s3://espup-mirror/toolchains/"),
+            "https://espup-mirror.s3.amazonaws.com/toolchains"
+        );
+        assert_eq!(
+            resolve_mirror("s3://espup-mirror"),
+            "https://espup-mirror.s3.amazonaws.com"
+        );
+        assert_eq!(
+            resolve_mirror("https://mirror.example.com/"),
+            "https://mirror.example.com"
+        );
+    }
+
+    #[test]
+    fn test_fallback_order() {
+        let mirrors = vec![
+            "https://mirror.example.com/".to_string(),
+            "oci://ghcr.io/esp-rs".to_string(),
+            "s3://espup-mirror".to_string(),
+            "https://mirror.example.com".to_string(),
+        ];
+        assert_eq!(
+            fallback_order(None, &mirrors),
+            vec![
+                "https://mirror.example.com".to_string(),
+                "https://espup-mirror.s3.amazonaws.com".to_string(),
+            ]
+        );
+        assert_eq!(
+            fallback_order(Some("https://mirror.example.com"), &mirrors),
+            vec![
+                DEFAULT_MIRROR.to_string(),
+                "https://espup-mirror.s3.amazonaws.com".to_string(),
+            ]
+        );
+        assert!(is_unreachable(&Error::Timeout(
+            DEFAULT_MIRROR.to_string(),
+            60
+        )));
+        assert!(!is_unreachable(&Error::GithubToken));
+    }
+
+    #[test]
+    fn test_release_artifacts() {
+        let artifacts = release_artifacts(
+            "1.73.0.1",
+            &[
+                HostTriple::X86_64UnknownLinuxGnu,
+                HostTriple::X86_64PcWindowsMsvc,
+            ],
+        )
+        .unwrap();
+        let urls: Vec<&str> = artifacts
+            .iter()
+            .map(|artifact| artifact.url.as_str())
+            .collect();
+        assert!(urls.contains(
+            &"https://github.com/esp-rs/rust-build/releases/download/v1.73.0.1/rust-1.73.0.1-x86_64-unknown-linux-gnu.tar.xz"
+        ));
+        assert!(urls.contains(
+            &"https://github.com/esp-rs/rust-build/releases/download/v1.73.0.1/rust-1.73.0.1-x86_64-pc-windows-msvc.zip"
+        ));
+        // The rust-src artifact is shared between hosts and only listed once
+        assert_eq!(
+            urls.iter()
+                .filter(|url| url.ends_with("rust-src-1.73.0.1.tar.xz"))
+                .count(),
+            1
+        );
+        // Xtensa Rust, rust-src, 2 LLVM and 4 GCC for Linux; Xtensa Rust, 2 LLVM and 4 GCC for Windows
+        assert_eq!(artifacts.len(), 15);
+    }
+
+    #[test]
+    fn test_dist_server() {
+        let api = "https://api.github.com/repos/esp-rs/rust-build/releases/latest";
+        let release = "https://github.com/esp-rs/rust-build/releases/download/v1.0.0/rust.zip";
+        let dist_server = "https://dist.example.com/";
+        assert_eq!(
+            apply_api_server(api, &dist_server_api(dist_server)),
+            "https://dist.example.com/api/repos/esp-rs/rust-build/releases/latest"
+        );
+        assert_eq!(
+            apply_api_server("https://example.com/api", &dist_server_api(dist_server)),
+            "https://example.com/api"
+        );
+        assert_eq!(
+            apply_mirror(release, &resolve_mirror(dist_server)),
+            "https://dist.example.com/esp-rs/rust-build/releases/download/v1.0.0/rust.zip"
+        );
+    }
+}
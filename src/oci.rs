@@ -0,0 +1,248 @@
+//! OCI registries as sources of the release artifacts, following the ORAS conventions.
+
+use crate::{
+    credentials::authorize,
+    emoji,
+    error::Error,
+    http::{trace_request, trace_response},
+    mirror::DEFAULT_MIRROR,
+};
+use log::debug;
+use reqwest::{header, Client, Response, StatusCode};
+use std::{env, time::Instant};
+
+/// Scheme of the mirrors hosted in OCI registries.
+pub const OCI_SCHEME: &str = "oci://";
+/// Media type of the OCI manifests.
+const MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+/// Annotation holding the file name of a layer.
+const TITLE_ANNOTATION: &str = "org.opencontainers.image.title";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OciReference {
+    /// Registry host.
+    pub registry: String,
+    /// Repository in the registry.
+    pub repository: String,
+    /// Tag of the artifact.
+    pub tag: String,
+    /// File name of the layer.
+    pub file_name: String,
+}
+
+impl OciReference {
+    /// Maps a release URL to its location in an `oci://registry/namespace` mirror.
+    ///
+    /// `https://github.com/<owner>/<repo>/releases/download/<tag>/<file>` is stored as the
+    /// `<file>` layer of `<namespace>/<owner>/<repo>:<tag>`.
+    pub fn from_release_url(mirror: &str, url: &str) -> Option<Self> {
+        let location = mirror.strip_prefix(OCI_SCHEME)?.trim_end_matches('/');
+        let (registry, namespace) = location.split_once('/').unwrap_or((location, ""));
+        let path = url.strip_prefix(DEFAULT_MIRROR)?.trim_start_matches('/');
+        let parts: Vec<&str> = path.split('/').collect();
+        match parts.as_slice() {
+            [owner, repo, "releases", "download", tag, file_name] => {
+                let repository = [namespace, owner, repo]
+                    .iter()
+                    .filter(|part| !part.is_empty())
+                    .map(|part| part.to_lowercase())
+                    .collect::<Vec<String>>()
+                    .join("/");
+                Some(Self {
+                    registry: registry.to_string(),
+                    repository,
+                    tag: tag.to_string(),
+                    file_name: file_name.to_string(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the URL of the manifest.
+    pub fn manifest_url(&self) -> String {
+        format!(
+            "https://{}/v2/{}/manifests/{}",
+            self.registry, self.repository, self.tag
+        )
+    }
+
+    /// Returns the URL of a blob.
+    pub fn blob_url(&self, digest: &str) -> String {
+        format!(
+            "https://{}/v2/{}/blobs/{}",
+            self.registry, self.repository, digest
+        )
+    }
+}
+
+/// Parses the `realm`, `service` and `scope` of a `WWW-Authenticate: Bearer ...` header.
+pub fn parse_bearer_challenge(challenge: &str) -> Option<(String, Option<String>, Option<String>)> {
+    let params = challenge.strip_prefix("Bearer ")?;
+    let mut realm = None;
+    let mut service = None;
+    let mut scope = None;
+    let mut rest = params.trim();
+    while let Some((key, value)) = rest.split_once("=\"") {
+        let (value, remaining) = value.split_once('"')?;
+        match key.trim_start_matches(',').trim() {
+            "realm" => realm = Some(value.to_string()),
+            "service" => service = Some(value.to_string()),
+            "scope" => scope = Some(value.to_string()),
+            _ => {}
+        }
+        rest = remaining;
+    }
+    Some((realm?, service, scope))
+}
+
+/// Sends a GET request, with a bearer token if one is provided.
+async fn get(
+    client: &Client,
+    url: &str,
+    token: Option<&str>,
+    accept: Option<&str>,
+) -> Result<Response, Error> {
+    let mut request = client.get(url);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    if let Some(accept) = accept {
+        request = request.header(header::ACCEPT, accept);
+    }
+    let request = request.build()?;
+    trace_request(request.method(), url, request.headers());
+    let start = Instant::now();
+    let resp = client.execute(request).await?;
+    trace_response(url, resp.status(), resp.headers(), start.elapsed());
+    Ok(resp)
+}
+
+/// Requests a token to the registry, using the `ESPUP_OCI_USERNAME` and `ESPUP_OCI_PASSWORD`
+/// credentials when set, or the credentials stored with `espup auth login`.
+async fn authenticate(client: &Client, challenge: &str) -> Result<String, Error> {
+    let (realm, service, scope) = parse_bearer_challenge(challenge)
+        .ok_or_else(|| Error::OciRegistry(format!("unsupported challenge '{challenge}'")))?;
+    let mut query = Vec::new();
+    if let Some(service) = service {
+        query.push(("service", service));
+    }
+    if let Some(scope) = scope {
+        query.push(("scope", scope));
+    }
+    let mut request = client.get(&realm).query(&query);
+    if let Ok(username) = env::var("ESPUP_OCI_USERNAME") {
+        request = request.basic_auth(username, env::var("ESPUP_OCI_PASSWORD").ok());
+    } else {
+        request = authorize(request, &realm);
+    }
+    let body = request.send().await?.error_for_status()?.text().await?;
+    let json: serde_json::Value = serde_json::from_str(&body)
+        .map_err(|_| Error::OciRegistry(format!("invalid token response from '{realm}'")))?;
+    json["token"]
+        .as_str()
+        .or_else(|| json["access_token"].as_str())
+        .map(str::to_string)
+        .ok_or_else(|| Error::OciRegistry(format!("no token returned by '{realm}'")))
+}
+
+/// Pulls the layer of an artifact, returning `None` if the artifact is not in the registry.
+pub async fn pull(client: &Client, reference: &OciReference) -> Result<Option<Response>, Error> {
+    let manifest_url = reference.manifest_url();
+    let mut token = None;
+    let mut resp = get(client, &manifest_url, None, Some(MANIFEST_MEDIA_TYPE)).await?;
+    if resp.status() == StatusCode::UNAUTHORIZED {
+        let challenge = resp
+            .headers()
+            .get(header::WWW_AUTHENTICATE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        token = Some(authenticate(client, &challenge).await?);
+        resp = get(
+            client,
+            &manifest_url,
+            token.as_deref(),
+            Some(MANIFEST_MEDIA_TYPE),
+        )
+        .await?;
+    }
+    if resp.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let body = resp.error_for_status()?.text().await?;
+    let manifest: serde_json::Value = serde_json::from_str(&body)
+        .map_err(|_| Error::OciRegistry(format!("invalid manifest '{manifest_url}'")))?;
+    let digest = manifest["layers"].as_array().and_then(|layers| {
+        layers.iter().find_map(|layer| {
+            (layer["annotations"][TITLE_ANNOTATION].as_str() == Some(&reference.file_name))
+                .then(|| layer["digest"].as_str())
+                .flatten()
+        })
+    });
+    let Some(digest) = digest else {
+        debug!(
+            "{} '{}' is not a layer of '{}'",
+            emoji::DEBUG,
+            reference.file_name,
+            manifest_url
+        );
+        return Ok(None);
+    };
+    let resp = get(client, &reference.blob_url(digest), token.as_deref(), None).await?;
+    Ok(Some(resp))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::oci::{parse_bearer_challenge, OciReference};
+
+    #[test]
+    fn test_oci_reference_from_release_url() {
+        let url = "https://github.com/espressif/crosstool-NG/releases/download/esp-12.2.0_20230208/riscv32-esp-elf-12.2.0_20230208-x86_64-linux-gnu.tar.xz";
+        let reference = OciReference::from_release_url("oci://ghcr.io/acme/espup/", url).unwrap();
+        assert_eq!(
+            reference,
+            OciReference {
+                registry: "ghcr.io".to_string(),
+                repository: "acme/espup/espressif/crosstool-ng".to_string(),
+                tag: "esp-12.2.0_20230208".to_string(),
+                file_name: "riscv32-esp-elf-12.2.0_20230208-x86_64-linux-gnu.tar.xz".to_string(),
+            }
+        );
+        assert_eq!(
+            reference.manifest_url(),
+            "https://ghcr.io/v2/acme/espup/espressif/crosstool-ng/manifests/esp-12.2.0_20230208"
+        );
+        assert_eq!(
+            OciReference::from_release_url("oci://registry.example.com", url)
+                .unwrap()
+                .repository,
+            "espressif/crosstool-ng"
+        );
+        assert!(OciReference::from_release_url("https://mirror.example.com", url).is_none());
+        assert!(
+            OciReference::from_release_url("oci://ghcr.io", "https://example.com/file.zip")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_parse_bearer_challenge() {
+        assert_eq!(
+            parse_bearer_challenge(
+                r#"Bearer realm="https://ghcr.io/token",service="ghcr.io",scope="repository:acme/espup:pull""#
+            ),
+            Some((
+                "https://ghcr.io/token".to_string(),
+                Some("ghcr.io".to_string()),
+                Some("repository:acme/espup:pull".to_string())
+            ))
+        );
+        assert_eq!(
+            parse_bearer_challenge(r#"Bearer realm="https://auth.example.com/token""#),
+            Some(("https://auth.example.com/token".to_string(), None, None))
+        );
+        assert_eq!(parse_bearer_challenge(r#"Basic realm="registry""#), None);
+    }
+}
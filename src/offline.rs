@@ -0,0 +1,177 @@
+//! Offline installations from a local artifact directory.
+
+use crate::{emoji, error::Error, host_triple::HostTriple};
+use log::{debug, info};
+use std::{
+    fs::{read, read_dir},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+static ARTIFACT_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Sets the directory the artifacts are resolved from instead of downloading them, `None`
+/// restores the downloads.
+pub fn set_artifact_dir(artifact_dir: Option<PathBuf>) {
+    if let Some(artifact_dir) = &artifact_dir {
+        info!(
+            "{} Resolving artifacts from '{}'",
+            emoji::INFO,
+            artifact_dir.display()
+        );
+    }
+    *ARTIFACT_DIR.lock().unwrap() = artifact_dir;
+}
+
+/// Returns true if the artifacts are resolved from a local directory.
+pub fn is_offline() -> bool {
+    ARTIFACT_DIR.lock().unwrap().is_some()
+}
+
+/// Returns the path of an artifact in the artifact directory, matched by the file name of its URL.
+fn artifact_path(url: &str) -> Option<PathBuf> {
+    ARTIFACT_DIR
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|artifact_dir| artifact_in(artifact_dir, url))
+}
+
+/// Returns the path of an artifact in a directory, matched by the file name of its URL.
+fn artifact_in(artifact_dir: &Path, url: &str) -> PathBuf {
+    artifact_dir.join(url.rsplit('/').next().unwrap_or(url))
+}
+
+/// Reads an artifact from the artifact directory, returning `None` if it does not exist.
+pub fn read_artifact(url: &str) -> Result<Option<Vec<u8>>, Error> {
+    match artifact_path(url) {
+        Some(path) => read_artifact_file(&path),
+        None => Ok(None),
+    }
+}
+
+/// Reads an artifact file, returning `None` if it does not exist.
+fn read_artifact_file(path: &Path) -> Result<Option<Vec<u8>>, Error> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    debug!("{} Reading artifact '{}'", emoji::DEBUG, path.display());
+    Ok(Some(read(path)?))
+}
+
+/// Returns the size of an artifact in the artifact directory, `None` if it does not exist.
+pub fn artifact_size(url: &str) -> Option<u64> {
+    Some(artifact_path(url)?.metadata().ok()?.len())
+}
+
+/// Finds the latest Xtensa Rust version with an artifact for the host in the artifact directory,
+/// among the ones matching the requested version, if any, e.g. `1.73.0` or `1.73.0.1`.
+pub fn find_toolchain_version(
+    host_triple: &HostTriple,
+    requested: Option<&str>,
+) -> Result<String, Error> {
+    let artifact_dir = ARTIFACT_DIR.lock().unwrap().clone().unwrap_or_default();
+    find_toolchain_version_in(&artifact_dir, host_triple, requested)
+}
+
+/// Finds the latest Xtensa Rust version with an artifact for the host in a directory.
+fn find_toolchain_version_in(
+    artifact_dir: &Path,
+    host_triple: &HostTriple,
+    requested: Option<&str>,
+) -> Result<String, Error> {
+    let entries = read_dir(artifact_dir)
+        .map_err(|_| Error::InvalidBundle(artifact_dir.display().to_string()))?;
+    let suffix = format!(
+        "-{host_triple}.{}",
+        host_triple.platform().archive_extension()
+    );
+    entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter_map(|name| {
+            let version = name.strip_prefix("rust-")?.strip_suffix(&suffix)?;
+            if let Some(requested) = requested {
+                if version != requested && !version.starts_with(&format!("{requested}.")) {
+                    return None;
+                }
+            }
+            let parts = version
+                .split('.')
+                .map(|part| part.parse::<u64>().ok())
+                .collect::<Option<Vec<u64>>>()?;
+            Some((parts, version.to_string()))
+        })
+        .max()
+        .map(|(_, version)| version)
+        .ok_or_else(|| match requested {
+            Some(requested) => Error::InvalidVersion(requested.to_string()),
+            None => Error::MissingOfflineToolchain(artifact_dir.display().to_string()),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        host_triple::HostTriple,
+        offline::{artifact_in, find_toolchain_version_in, read_artifact_file},
+    };
+    use std::fs::write;
+
+    #[test]
+    fn test_offline_artifacts() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        for name in [
+            "rust-1.72.0.0-x86_64-unknown-linux-gnu.tar.xz",
+            "rust-1.73.0.1-x86_64-unknown-linux-gnu.tar.xz",
+            "rust-1.74.0.0-aarch64-apple-darwin.tar.xz",
+            "rust-src-1.73.0.1.tar.xz",
+        ] {
+            write(temp_dir.path().join(name), name).unwrap();
+        }
+        let artifact_dir = temp_dir.path();
+        let read_artifact = |url: &str| read_artifact_file(&artifact_in(artifact_dir, url));
+
+        assert_eq!(
+            find_toolchain_version_in(artifact_dir, &HostTriple::X86_64UnknownLinuxGnu, None)
+                .unwrap(),
+            "1.73.0.1"
+        );
+        assert_eq!(
+            find_toolchain_version_in(
+                artifact_dir,
+                &HostTriple::X86_64UnknownLinuxGnu,
+                Some("1.72.0")
+            )
+            .unwrap(),
+            "1.72.0.0"
+        );
+        assert_eq!(
+            find_toolchain_version_in(
+                artifact_dir,
+                &HostTriple::X86_64UnknownLinuxGnu,
+                Some("1.73.0.1")
+            )
+            .unwrap(),
+            "1.73.0.1"
+        );
+        assert!(find_toolchain_version_in(
+            artifact_dir,
+            &HostTriple::X86_64UnknownLinuxGnu,
+            Some("1.74.0")
+        )
+        .is_err());
+        assert!(
+            find_toolchain_version_in(artifact_dir, &HostTriple::X86_64PcWindowsMsvc, None)
+                .is_err()
+        );
+        assert_eq!(
+            read_artifact("https://github.com/esp-rs/rust-build/releases/download/v1.73.0.1/rust-src-1.73.0.1.tar.xz")
+                .unwrap(),
+            Some(b"rust-src-1.73.0.1.tar.xz".to_vec())
+        );
+        assert_eq!(
+            read_artifact("https://example.com/missing.tar.xz").unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,184 @@
+//! Machine-readable output, emitting the progress of a command as JSON events on stdout.
+//!
+//! The events follow the JSON schema of [`SCHEMA_VERSION`], published in `docs/schemas`. Its
+//! version is only increased by changes breaking the existing fields.
+
+use crate::{emoji, env::ExportItem, error::Error, warnings::WarningKind};
+use serde::Serialize;
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Version of the schema of the events, sent with each of them.
+pub const SCHEMA_VERSION: u32 = 1;
+/// JSON schema of the events.
+pub const SCHEMA: &str = include_str!("../docs/schemas/events-v1.json");
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Event of a command, emitted as a JSON line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event {
+    /// The command started.
+    Started { command: String },
+    /// A component was installed.
+    Component {
+        name: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        version: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        path: Option<PathBuf>,
+    },
+    /// An environment change was written to the export file.
+    Export { export: ExportItem },
+    /// A component, or a directory, was removed.
+    Removed { name: String, path: Option<PathBuf> },
+    /// A non-fatal issue was found.
+    Warning { kind: WarningKind, message: String },
+    /// The installed and latest Xtensa Rust versions were checked.
+    Check {
+        name: String,
+        installed: Option<String>,
+        latest: Option<String>,
+    },
+    /// The command failed.
+    Error {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        code: Option<String>,
+        message: String,
+    },
+    /// The command completed successfully.
+    Completed { command: String },
+}
+
+impl Event {
+    /// Returns the event of a failed command.
+    pub fn error(report: &miette::Report) -> Self {
+        let message = report.to_string();
+        Event::Error {
+            code: report.code().map(|code| code.to_string()),
+            // The emoji prefixing the messages is only meant for the terminal
+            message: message
+                .trim_start_matches(&emoji::ERROR.to_string())
+                .to_string(),
+        }
+    }
+}
+
+/// Sets the output format, `text` or `json`.
+pub fn set_output(format: &str) {
+    JSON.store(format == "json", Ordering::Relaxed);
+}
+
+/// Returns true if the events are emitted as JSON.
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// Event along with the version of its schema.
+#[derive(Serialize)]
+struct Envelope<'a> {
+    schema_version: u32,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// Serializes an event as a JSON line.
+fn to_line(event: &Event) -> Result<String, Error> {
+    serde_json::to_string(&Envelope {
+        schema_version: SCHEMA_VERSION,
+        event,
+    })
+    .map_err(|_| Error::SerializeJson)
+}
+
+/// Prints the event on stdout, if the output format is JSON.
+///
+/// The logs are written to stderr, so stdout only contains the events.
+pub fn emit(event: Event) {
+    if !is_json() {
+        return;
+    }
+    if let Ok(line) = to_line(&event) {
+        println!("{line}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        env::ExportItem,
+        output::{to_line, Event, SCHEMA, SCHEMA_VERSION},
+        warnings::WarningKind,
+    };
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_to_line() {
+        assert_eq!(
+            to_line(&Event::Component {
+                name: "LLVM".to_string(),
+                version: Some("17".to_string()),
+                path: Some(PathBuf::from("/esp/xtensa-esp32-elf-clang")),
+            })
+            .unwrap(),
+            r#"{"schema_version":1,"event":"component","name":"LLVM","version":"17","path":"/esp/xtensa-esp32-elf-clang"}"#
+        );
+        assert_eq!(
+            to_line(&Event::Component {
+                name: "RISC-V Rust target".to_string(),
+                version: None,
+                path: None,
+            })
+            .unwrap(),
+            r#"{"schema_version":1,"event":"component","name":"RISC-V Rust target"}"#
+        );
+        assert!(to_line(&Event::Export {
+            export: ExportItem::PathAdd("/esp/bin".to_string())
+        })
+        .unwrap()
+        .starts_with(r#"{"schema_version":1,"event":"export","export":"#));
+        assert_eq!(
+            to_line(&Event::Warning {
+                kind: WarningKind::Skipped,
+                message: "GCC installation skipped".to_string(),
+            })
+            .unwrap(),
+            r#"{"schema_version":1,"event":"warning","kind":"skipped","message":"GCC installation skipped"}"#
+        );
+
+        // Every event is declared in the published schema
+        let schema: serde_json::Value = serde_json::from_str(SCHEMA).unwrap();
+        assert_eq!(
+            schema["properties"]["schema_version"]["const"],
+            SCHEMA_VERSION
+        );
+        let declared = schema["properties"]["event"]["enum"].as_array().unwrap();
+        for event in [
+            Event::Started {
+                command: "install".to_string(),
+            },
+            Event::Removed {
+                name: "LLVM".to_string(),
+                path: None,
+            },
+            Event::Check {
+                name: "esp".to_string(),
+                installed: None,
+                latest: Some("1.76.0.1".to_string()),
+            },
+            Event::Error {
+                code: None,
+                message: "Failed".to_string(),
+            },
+            Event::Completed {
+                command: "install".to_string(),
+            },
+        ] {
+            let line: serde_json::Value = serde_json::from_str(&to_line(&event).unwrap()).unwrap();
+            assert!(declared.contains(&line["event"]));
+        }
+    }
+}
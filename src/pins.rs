@@ -0,0 +1,224 @@
+//! Component version pins, overriding the versions bundled with espup.
+//!
+//! The bundled versions are an embedded TOML table, which a local `pins.toml` file overrides.
+
+use crate::{config::Config, emoji, error::Error, preflight::compare_versions};
+use log::{debug, info};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, fs::read_to_string, path::PathBuf, sync::Mutex};
+use toml::{Table, Value};
+
+/// Default component versions of this espup release.
+pub const BUNDLED_PINS: &str = include_str!("pins.toml");
+/// Name of the file overriding the bundled versions, next to the configuration file.
+const PINS_FILE: &str = "pins.toml";
+
+/// Format of the LLVM versions, e.g. `esp-16.0.0-20230516` or `esp-17.0.1_20231207`.
+const RE_LLVM_VERSION: &str = r"^esp-\d+\.\d+\.\d+([-_]\d{8})?$";
+/// Format of the GCC releases, e.g. `13.2.0_20230928`.
+const RE_GCC_RELEASE: &str = r"^\d+\.\d+\.\d+_\d{8}$";
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Pins {
+    /// LLVM version installed instead of the one matching the Xtensa Rust version.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub llvm: Option<String>,
+    /// GCC release installed instead of the default one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gcc: Option<String>,
+}
+
+impl Pins {
+    /// Checks the format of the pinned versions.
+    pub fn validate(&self) -> Result<(), Error> {
+        for (component, version, format) in [
+            ("llvm", &self.llvm, RE_LLVM_VERSION),
+            ("gcc", &self.gcc, RE_GCC_RELEASE),
+        ] {
+            if let Some(version) = version {
+                if !Regex::new(format).unwrap().is_match(version) {
+                    return Err(Error::InvalidPin(component.to_string(), version.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct LlvmPin {
+    /// Last Xtensa Rust version the LLVM version is installed for, every version if `None`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_rust: Option<String>,
+    /// LLVM version.
+    pub version: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct PinTable {
+    /// Default nightly Rust toolchain.
+    pub nightly: String,
+    /// Default GCC release.
+    pub gcc: String,
+    /// Default LLVM versions, by the last Xtensa Rust version they are installed for.
+    pub llvm: Vec<LlvmPin>,
+}
+
+impl PinTable {
+    /// Returns the path of the file overriding the bundled versions.
+    pub fn path() -> PathBuf {
+        Config::path().with_file_name(PINS_FILE)
+    }
+
+    /// Parses the bundled versions with the keys of an overriding file.
+    fn parse(overrides: Option<&str>) -> Result<Self, String> {
+        let mut table = BUNDLED_PINS.parse::<Table>().map_err(|e| e.to_string())?;
+        if let Some(overrides) = overrides {
+            let overrides = overrides.parse::<Table>().map_err(|e| e.to_string())?;
+            table.extend(overrides);
+        }
+        let pins: Self = Value::Table(table)
+            .try_into()
+            .map_err(|e: toml::de::Error| e.to_string())?;
+        if !Regex::new(RE_GCC_RELEASE).unwrap().is_match(&pins.gcc) {
+            return Err(format!("invalid GCC release '{}'", pins.gcc));
+        }
+        if let Some(llvm) = pins
+            .llvm
+            .iter()
+            .find(|llvm| !Regex::new(RE_LLVM_VERSION).unwrap().is_match(&llvm.version))
+        {
+            return Err(format!("invalid LLVM version '{}'", llvm.version));
+        }
+        if !pins.llvm.iter().any(|llvm| llvm.max_rust.is_none()) {
+            return Err("the last `llvm` entry must not have a `max_rust`".to_string());
+        }
+        Ok(pins)
+    }
+
+    /// Loads the bundled versions, overridden by the local file if it exists.
+    pub fn load() -> Result<Self, Error> {
+        let path = Self::path();
+        let overrides = read_to_string(&path).ok();
+        if overrides.is_some() {
+            debug!(
+                "{} Overriding the bundled versions with '{}'",
+                emoji::DEBUG,
+                path.display()
+            );
+        }
+        Self::parse(overrides.as_deref())
+            .map_err(|e| Error::InvalidPinsFile(path.display().to_string(), e))
+    }
+
+    /// Returns the bundled versions.
+    pub fn bundled() -> Self {
+        Self::parse(None).expect("the bundled pins are valid")
+    }
+
+    /// Returns the LLVM version installed for an Xtensa Rust version.
+    pub fn llvm_version(&self, xtensa_rust_version: &str) -> String {
+        self.llvm
+            .iter()
+            .find(|llvm| {
+                llvm.max_rust.as_deref().map_or(true, |max_rust| {
+                    compare_versions(xtensa_rust_version, max_rust) != Ordering::Greater
+                })
+            })
+            .map(|llvm| llvm.version.clone())
+            .unwrap_or_default()
+    }
+}
+
+static PINS: Mutex<Pins> = Mutex::new(Pins {
+    llvm: None,
+    gcc: None,
+});
+static PIN_TABLE: Mutex<Option<PinTable>> = Mutex::new(None);
+
+/// Sets the pinned versions used by every installation, loading the default versions.
+pub fn set_pins(pins: Pins) -> Result<(), Error> {
+    pins.validate()?;
+    *PIN_TABLE.lock().unwrap() = Some(PinTable::load()?);
+    for (component, version) in [("LLVM", &pins.llvm), ("GCC", &pins.gcc)] {
+        if let Some(version) = version {
+            info!("{} Using pinned {} '{}'", emoji::INFO, component, version);
+        }
+    }
+    *PINS.lock().unwrap() = pins;
+    Ok(())
+}
+
+/// Returns the pinned LLVM version, if any.
+pub fn pinned_llvm() -> Option<String> {
+    PINS.lock().unwrap().llvm.clone()
+}
+
+/// Returns the pinned GCC release, if any.
+pub fn pinned_gcc() -> Option<String> {
+    PINS.lock().unwrap().gcc.clone()
+}
+
+/// Returns the default versions, the bundled ones if they were not loaded.
+pub fn pin_table() -> PinTable {
+    PIN_TABLE
+        .lock()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(PinTable::bundled)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pins::{PinTable, Pins};
+
+    #[test]
+    fn test_validate_pins() {
+        assert!(Pins::default().validate().is_ok());
+        let pins = Pins {
+            llvm: Some("esp-17.0.1_20231207".to_string()),
+            gcc: Some("13.2.0_20230928".to_string()),
+        };
+        assert!(pins.validate().is_ok());
+        let pins = Pins {
+            llvm: Some("esp-16.0.0-20230516".to_string()),
+            gcc: None,
+        };
+        assert!(pins.validate().is_ok());
+        let pins = Pins {
+            llvm: Some("17.0.1".to_string()),
+            gcc: None,
+        };
+        assert!(pins.validate().is_err());
+        let pins = Pins {
+            llvm: None,
+            gcc: Some("esp-13.2.0_20230928".to_string()),
+        };
+        assert!(pins.validate().is_err());
+    }
+
+    #[test]
+    fn test_pin_table() {
+        let bundled = PinTable::bundled();
+        assert_eq!(bundled.nightly, "nightly");
+        assert_eq!(bundled.llvm_version("1.69.0.0"), "esp-15.0.0-20221201");
+        assert_eq!(bundled.llvm_version("1.68.0.1"), "esp-15.0.0-20221201");
+        assert_eq!(bundled.llvm_version("1.69.0.1"), "esp-16.0.0-20230516");
+
+        // The overriding keys replace the bundled ones
+        let pins = PinTable::parse(Some(
+            "gcc = \"13.2.0_20230928\"\n[[llvm]]\nversion = \"esp-17.0.1_20231207\"\n",
+        ))
+        .unwrap();
+        assert_eq!(pins.nightly, "nightly");
+        assert_eq!(pins.gcc, "13.2.0_20230928");
+        assert_eq!(pins.llvm_version("1.69.0.0"), "esp-17.0.1_20231207");
+
+        assert!(PinTable::parse(Some("gcc = \"13.2.0\"")).is_err());
+        assert!(PinTable::parse(Some(
+            "[[llvm]]\nmax_rust = \"1.70.0.0\"\nversion = \"esp-16.0.0-20230516\"\n"
+        ))
+        .is_err());
+    }
+}
@@ -0,0 +1,446 @@
+//! Host platform capabilities consumed by the installers.
+//!
+//! Everything that depends on the host (artifact naming, archive format, how environment variables
+//! are exported and path conventions) is answered by a [`Platform`], so supporting a new host only
+//! requires a new implementation and its mapping in [`HostTriple::platform`].
+
+use crate::host_triple::HostTriple;
+
+/// CPU architecture of a host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    X86_64,
+    Aarch64,
+}
+
+/// Capabilities of a host platform.
+pub trait Platform: Send + Sync {
+    /// Host name used in the GCC release artifacts.
+    fn gcc_arch(&self) -> &'static str;
+
+    /// Host name used in the LLVM release artifacts.
+    fn llvm_arch(&self) -> &'static str;
+
+    /// Extension of the Xtensa Rust and GCC release artifacts.
+    fn archive_extension(&self) -> &'static str {
+        "tar.xz"
+    }
+
+    /// Extension of the Xtensa Rust release artifacts preferred over `archive_extension`, when
+    /// they are published, as they decompress faster.
+    fn preferred_archive_extension(&self) -> Option<&'static str> {
+        Some("tar.zst")
+    }
+
+    /// Whether the Xtensa Rust release ships `rust-src` as a separate artifact.
+    fn ships_rust_src(&self) -> bool {
+        true
+    }
+
+    /// Directory, relative to the LLVM installation, containing `libclang`.
+    fn libclang_dir(&self) -> &'static str {
+        "esp-clang/lib"
+    }
+
+    /// Path, relative to the LLVM installation, of the `clang` executable.
+    fn clang_path(&self) -> &'static str {
+        "esp-clang/bin/clang"
+    }
+
+    /// Value of `LIBCLANG_PATH` for the given `libclang` directory.
+    fn libclang_path(&self, libclang_dir: &str) -> String {
+        libclang_dir.to_string()
+    }
+
+    /// Whether the `libclang` directory needs to be in the `PATH` to load the library.
+    fn libclang_in_path(&self) -> bool {
+        false
+    }
+
+    /// Separator of the directories in the `PATH`.
+    fn path_separator(&self) -> &'static str {
+        ":"
+    }
+
+    /// Suffix of the executables.
+    fn exe_suffix(&self) -> &'static str {
+        ""
+    }
+
+    /// Rewrites the separators of a path, or of the paths in a line, to the native ones.
+    fn native_path(&self, path: &str) -> String {
+        path.to_string()
+    }
+
+    /// Whether the exports are applied to the environment of the user when installing, instead of
+    /// only being written to the export file.
+    fn applies_exports(&self) -> bool {
+        false
+    }
+
+    /// Export that sets an environment variable.
+    fn export_var(&self, key: &str, value: &str) -> String {
+        format!("export {key}=\"{value}\"")
+    }
+
+    /// Export that prepends a directory to the `PATH`.
+    fn export_path(&self, dir: &str) -> String {
+        format!("export PATH=\"{dir}:$PATH\"")
+    }
+
+    /// Extension of the activation scripts.
+    fn activation_extension(&self) -> &'static str {
+        "sh"
+    }
+
+    /// Activation line saving the value of a variable into `backup`, or clearing `backup` if the
+    /// variable is not set.
+    fn save_var(&self, key: &str, backup: &str) -> String {
+        format!(
+            "if [ -n \"${{{key}+x}}\" ]; then export {backup}=\"${key}\"; else unset {backup}; fi"
+        )
+    }
+
+    /// Deactivation line restoring the value of a variable saved by [`Platform::save_var`], or
+    /// unsetting the variable if it was not set.
+    fn restore_var(&self, key: &str, backup: &str) -> String {
+        format!("if [ -n \"${{{backup}+x}}\" ]; then export {key}=\"${backup}\"; unset {backup}; else unset {key}; fi")
+    }
+
+    /// Line sourcing a script if a variable is set.
+    fn source_if_set(&self, key: &str, script: &str) -> String {
+        format!(
+            "if [ -n \"${{{key}+x}}\" ]; then . {}; fi",
+            sh_quote(script)
+        )
+    }
+
+    /// First lines of the install scripts, which run in a new working directory.
+    fn script_header(&self) -> String {
+        "#!/usr/bin/env bash\nset -euo pipefail\ncd \"$(mktemp -d)\"".to_string()
+    }
+
+    /// Script command downloading a file and checking its SHA256 digest, when it is known.
+    fn script_fetch(&self, url: &str, sha256: Option<&str>, file: &str) -> String {
+        let download = format!("curl -fsSL -o {} {}", sh_quote(file), sh_quote(url));
+        match sha256 {
+            Some(sha256) => format!(
+                "{download}\necho {} | sha256sum -c -",
+                sh_quote(&format!("{sha256}  {file}"))
+            ),
+            None => format!("# The SHA256 digest of '{file}' is not published\n{download}"),
+        }
+    }
+
+    /// Script command extracting an archive into a directory, optionally stripping its top
+    /// directory.
+    fn script_extract(&self, file: &str, dir: &str, strip: bool) -> String {
+        format!(
+            "mkdir -p {dir}\ntar -xf {} -C {dir}{}",
+            sh_quote(file),
+            if strip { " --strip-components=1" } else { "" },
+            dir = sh_quote(dir)
+        )
+    }
+
+    /// Script command running a program.
+    fn script_run(&self, args: &[String]) -> String {
+        args.iter()
+            .map(|arg| {
+                if arg
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "_-./=:+@,".contains(c))
+                {
+                    arg.clone()
+                } else {
+                    sh_quote(arg)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Script command writing a file.
+    fn script_write_file(&self, path: &str, contents: &str) -> String {
+        format!("cat > {} <<'EOF'\n{contents}\nEOF", sh_quote(path))
+    }
+}
+
+/// Quotes a shell word.
+fn sh_quote(word: &str) -> String {
+    format!("'{}'", word.replace('\'', r"'\''"))
+}
+
+/// Quotes a PowerShell string, without expanding its variables.
+fn ps_quote(word: &str) -> String {
+    format!("'{}'", word.replace('\'', "''"))
+}
+
+/// Linux hosts.
+#[derive(Debug, Clone, Copy)]
+pub struct Linux(pub Arch);
+
+impl Platform for Linux {
+    fn gcc_arch(&self) -> &'static str {
+        match self.0 {
+            Arch::X86_64 => "x86_64-linux-gnu",
+            Arch::Aarch64 => "aarch64-linux-gnu",
+        }
+    }
+
+    fn llvm_arch(&self) -> &'static str {
+        match self.0 {
+            Arch::X86_64 => "linux-amd64",
+            Arch::Aarch64 => "linux-arm64",
+        }
+    }
+}
+
+/// macOS hosts.
+#[derive(Debug, Clone, Copy)]
+pub struct MacOs(pub Arch);
+
+impl Platform for MacOs {
+    fn gcc_arch(&self) -> &'static str {
+        match self.0 {
+            Arch::X86_64 => "x86_64-apple-darwin",
+            Arch::Aarch64 => "aarch64-apple-darwin",
+        }
+    }
+
+    fn llvm_arch(&self) -> &'static str {
+        match self.0 {
+            Arch::X86_64 => "macos",
+            Arch::Aarch64 => "macos-arm64",
+        }
+    }
+
+    // `sha256sum` is not available in macOS
+    fn script_fetch(&self, url: &str, sha256: Option<&str>, file: &str) -> String {
+        let download = format!("curl -fsSL -o {} {}", sh_quote(file), sh_quote(url));
+        match sha256 {
+            Some(sha256) => format!(
+                "{download}\necho {} | shasum -a 256 -c -",
+                sh_quote(&format!("{sha256}  {file}"))
+            ),
+            None => format!("# The SHA256 digest of '{file}' is not published\n{download}"),
+        }
+    }
+}
+
+/// Windows hosts, both MSVC and MinGW.
+#[derive(Debug, Clone, Copy)]
+pub struct Windows;
+
+impl Platform for Windows {
+    fn gcc_arch(&self) -> &'static str {
+        "x86_64-w64-mingw32"
+    }
+
+    fn llvm_arch(&self) -> &'static str {
+        "win64"
+    }
+
+    fn archive_extension(&self) -> &'static str {
+        "zip"
+    }
+
+    fn preferred_archive_extension(&self) -> Option<&'static str> {
+        None
+    }
+
+    // Windows releases bundle rust and rust-src, as the install script is not available
+    fn ships_rust_src(&self) -> bool {
+        false
+    }
+
+    fn libclang_dir(&self) -> &'static str {
+        "esp-clang/bin"
+    }
+
+    fn clang_path(&self) -> &'static str {
+        "esp-clang/bin/clang.exe"
+    }
+
+    fn libclang_path(&self, libclang_dir: &str) -> String {
+        format!("{libclang_dir}/libclang.dll")
+    }
+
+    fn libclang_in_path(&self) -> bool {
+        true
+    }
+
+    fn path_separator(&self) -> &'static str {
+        ";"
+    }
+
+    fn exe_suffix(&self) -> &'static str {
+        ".exe"
+    }
+
+    fn native_path(&self, path: &str) -> String {
+        path.replace('/', r"\")
+    }
+
+    // The variables are written to the registry, unless only the export file is requested
+    fn applies_exports(&self) -> bool {
+        true
+    }
+
+    fn export_var(&self, key: &str, value: &str) -> String {
+        format!("$Env:{key} = \"{value}\"")
+    }
+
+    fn export_path(&self, dir: &str) -> String {
+        format!("$Env:PATH = \"{dir};\" + $Env:PATH")
+    }
+
+    fn activation_extension(&self) -> &'static str {
+        "ps1"
+    }
+
+    fn save_var(&self, key: &str, backup: &str) -> String {
+        format!("if (Test-Path Env:{key}) {{ $Env:{backup} = $Env:{key} }} else {{ Remove-Item Env:{backup} -ErrorAction SilentlyContinue }}")
+    }
+
+    fn restore_var(&self, key: &str, backup: &str) -> String {
+        format!("if (Test-Path Env:{backup}) {{ $Env:{key} = $Env:{backup}; Remove-Item Env:{backup} }} else {{ Remove-Item Env:{key} -ErrorAction SilentlyContinue }}")
+    }
+
+    fn source_if_set(&self, key: &str, script: &str) -> String {
+        format!("if (Test-Path Env:{key}) {{ . {} }}", ps_quote(script))
+    }
+
+    fn script_header(&self) -> String {
+        "$ErrorActionPreference = 'Stop'\n$ProgressPreference = 'SilentlyContinue'\nSet-Location (New-Item -ItemType Directory -Path (Join-Path $Env:TEMP (New-Guid)))".to_string()
+    }
+
+    fn script_fetch(&self, url: &str, sha256: Option<&str>, file: &str) -> String {
+        let download = format!(
+            "Invoke-WebRequest -Uri {} -OutFile {}",
+            ps_quote(url),
+            ps_quote(file)
+        );
+        match sha256 {
+            Some(sha256) => format!(
+                "{download}\nif ((Get-FileHash -Algorithm SHA256 {file}).Hash -ne {sha256}) {{ throw \"Checksum mismatch of \" + {file} }}",
+                file = ps_quote(file),
+                sha256 = ps_quote(sha256)
+            ),
+            None => format!("# The SHA256 digest of '{file}' is not published\n{download}"),
+        }
+    }
+
+    // Tarballs are extracted with the `tar` shipped with Windows, zip archives are extracted next
+    // to the destination, to move the contents of their `esp` directory
+    fn script_extract(&self, file: &str, dir: &str, strip: bool) -> String {
+        if !file.ends_with(".zip") {
+            return format!(
+                "New-Item -ItemType Directory -Force -Path {dir} | Out-Null\ntar -xf {} -C {dir}{}",
+                ps_quote(file),
+                if strip { " --strip-components=1" } else { "" },
+                dir = ps_quote(dir)
+            );
+        }
+        if !strip {
+            return format!(
+                "Expand-Archive -Path {} -DestinationPath {} -Force",
+                ps_quote(file),
+                ps_quote(dir)
+            );
+        }
+        let extracted = ps_quote(&format!("{dir}.extracted"));
+        format!(
+            "Expand-Archive -Path {} -DestinationPath {extracted} -Force\nNew-Item -ItemType Directory -Force -Path {dir} | Out-Null\nMove-Item -Path (Join-Path {extracted} 'esp/*') -Destination {dir} -Force\nRemove-Item -Recurse -Force {extracted}",
+            ps_quote(file),
+            dir = ps_quote(dir)
+        )
+    }
+
+    fn script_run(&self, args: &[String]) -> String {
+        let args: Vec<String> = args.iter().map(|arg| ps_quote(arg)).collect();
+        format!("& {}", args.join(" "))
+    }
+
+    fn script_write_file(&self, path: &str, contents: &str) -> String {
+        format!(
+            "Set-Content -Path {} -Value @'\n{contents}\n'@",
+            ps_quote(path)
+        )
+    }
+}
+
+impl HostTriple {
+    /// Gets the capabilities of the host.
+    pub fn platform(&self) -> &'static dyn Platform {
+        match self {
+            HostTriple::X86_64UnknownLinuxGnu => &Linux(Arch::X86_64),
+            HostTriple::Aarch64UnknownLinuxGnu => &Linux(Arch::Aarch64),
+            HostTriple::X86_64PcWindowsMsvc | HostTriple::X86_64PcWindowsGnu => &Windows,
+            HostTriple::X86_64AppleDarwin => &MacOs(Arch::X86_64),
+            HostTriple::Aarch64AppleDarwin => &MacOs(Arch::Aarch64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::host_triple::HostTriple;
+
+    #[test]
+    fn test_platform() {
+        let linux = HostTriple::Aarch64UnknownLinuxGnu.platform();
+        assert_eq!(linux.gcc_arch(), "aarch64-linux-gnu");
+        assert_eq!(linux.llvm_arch(), "linux-arm64");
+        assert_eq!(linux.archive_extension(), "tar.xz");
+        assert_eq!(linux.preferred_archive_extension(), Some("tar.zst"));
+        assert!(linux.ships_rust_src());
+        assert!(!linux.applies_exports());
+        assert_eq!(linux.native_path("/esp/bin"), "/esp/bin");
+        assert_eq!(linux.libclang_path("/esp/lib"), "/esp/lib");
+        assert_eq!(
+            linux.export_path("/esp/bin"),
+            "export PATH=\"/esp/bin:$PATH\""
+        );
+        assert_eq!(
+            linux.save_var("PATH", "_ESPUP_OLD_PATH"),
+            "if [ -n \"${PATH+x}\" ]; then export _ESPUP_OLD_PATH=\"$PATH\"; else unset _ESPUP_OLD_PATH; fi"
+        );
+
+        let macos = HostTriple::X86_64AppleDarwin.platform();
+        assert_eq!(macos.gcc_arch(), "x86_64-apple-darwin");
+        assert_eq!(macos.llvm_arch(), "macos");
+
+        let windows = HostTriple::X86_64PcWindowsGnu.platform();
+        assert_eq!(windows.gcc_arch(), "x86_64-w64-mingw32");
+        assert_eq!(windows.archive_extension(), "zip");
+        assert_eq!(windows.preferred_archive_extension(), None);
+        assert!(!windows.ships_rust_src());
+        assert!(windows.applies_exports());
+        assert_eq!(windows.exe_suffix(), ".exe");
+        assert_eq!(windows.native_path("C:/esp/bin"), r"C:\esp\bin");
+        assert_eq!(
+            windows.libclang_path("C:/esp/bin"),
+            "C:/esp/bin/libclang.dll"
+        );
+        assert_eq!(
+            windows.export_var("CLANG_PATH", "C:/esp/clang.exe"),
+            "$Env:CLANG_PATH = \"C:/esp/clang.exe\""
+        );
+        assert_eq!(
+            windows.export_path("C:/esp/bin"),
+            "$Env:PATH = \"C:/esp/bin;\" + $Env:PATH"
+        );
+        assert_eq!(
+            windows.restore_var("LIBCLANG_PATH", "_ESPUP_OLD_LIBCLANG_PATH"),
+            "if (Test-Path Env:_ESPUP_OLD_LIBCLANG_PATH) { $Env:LIBCLANG_PATH = $Env:_ESPUP_OLD_LIBCLANG_PATH; Remove-Item Env:_ESPUP_OLD_LIBCLANG_PATH } else { Remove-Item Env:LIBCLANG_PATH -ErrorAction SilentlyContinue }"
+        );
+        assert_eq!(
+            windows.script_extract("llvm.tar.xz", "C:/esp", true),
+            "New-Item -ItemType Directory -Force -Path 'C:/esp' | Out-Null\ntar -xf 'llvm.tar.xz' -C 'C:/esp' --strip-components=1"
+        );
+        assert!(windows
+            .script_extract("rust.zip", "C:/esp", false)
+            .starts_with("Expand-Archive"));
+    }
+}
@@ -0,0 +1,156 @@
+//! Prefetched metadata of the newest Xtensa Rust releases, used to plan updates when GitHub is
+//! unreachable.
+
+use crate::{
+    cache::get_cache_dir,
+    emoji,
+    error::Error,
+    host_triple::HostTriple,
+    toolchain::{github_query, rust::XTENSA_RUST_API_URL},
+};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{create_dir_all, read_to_string, write},
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Name of the file storing the prefetched metadata, inside the cache directory.
+const UPDATE_PLAN_FILE: &str = "update-plan.json";
+/// Number of releases whose metadata is kept.
+const PREFETCHED_VERSIONS: usize = 5;
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct UpdatePlan {
+    /// Host triple the releases were filtered for.
+    pub host_triple: String,
+    /// Time the metadata was fetched, in seconds since the Unix epoch.
+    pub fetched_at: u64,
+    /// Xtensa Rust versions with artifacts for the host, newest first.
+    pub versions: Vec<String>,
+}
+
+impl UpdatePlan {
+    /// Returns the path of the prefetched metadata.
+    pub fn path() -> PathBuf {
+        get_cache_dir().join(UPDATE_PLAN_FILE)
+    }
+
+    /// Loads the prefetched metadata for a host, if there is any.
+    pub fn load(host_triple: &HostTriple) -> Option<Self> {
+        let contents = read_to_string(Self::path()).ok()?;
+        serde_json::from_str::<Self>(&contents)
+            .ok()
+            .filter(|plan| plan.host_triple == host_triple.to_string())
+    }
+
+    /// Saves the prefetched metadata.
+    pub fn save(&self) -> Result<(), Error> {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)
+                .map_err(|_| Error::CreateDirectory(parent.display().to_string()))?;
+        }
+        let contents = serde_json::to_string_pretty(self).map_err(|_| Error::SerializeJson)?;
+        write(path, contents)?;
+        Ok(())
+    }
+
+    /// Builds the plan from the releases listed by the GitHub API, keeping the newest ones that
+    /// publish an artifact for the host.
+    pub fn from_releases(releases: &serde_json::Value, host_triple: &HostTriple) -> Self {
+        let extension = host_triple.platform().archive_extension();
+        let mut versions: Vec<(Vec<u64>, String)> = releases
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|release| {
+                let version = release["tag_name"].as_str()?.trim_start_matches('v');
+                let artifact = format!("rust-{version}-{host_triple}.{extension}");
+                let has_artifact = release["assets"]
+                    .as_array()?
+                    .iter()
+                    .any(|asset| asset["name"].as_str() == Some(artifact.as_str()));
+                let key = version
+                    .split('.')
+                    .map(|part| part.parse::<u64>().ok())
+                    .collect::<Option<Vec<u64>>>()?;
+                has_artifact.then(|| (key, version.to_string()))
+            })
+            .collect();
+        versions.sort_by(|a, b| b.0.cmp(&a.0));
+        versions.truncate(PREFETCHED_VERSIONS);
+        Self {
+            host_triple: host_triple.to_string(),
+            fetched_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            versions: versions.into_iter().map(|(_, version)| version).collect(),
+        }
+    }
+
+    /// Returns the newest version available for the host.
+    pub fn latest(&self) -> Option<&str> {
+        self.versions.first().map(String::as_str)
+    }
+
+    /// Returns the time elapsed since the metadata was fetched.
+    pub fn age(&self) -> Duration {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        Duration::from_secs(now.saturating_sub(self.fetched_at))
+    }
+}
+
+/// Fetches the metadata of the newest Xtensa Rust releases for a host and stores it.
+pub async fn prefetch(host_triple: HostTriple) -> Result<UpdatePlan, Error> {
+    let releases = tokio::task::spawn_blocking(|| github_query(XTENSA_RUST_API_URL))
+        .await
+        .map_err(|_| Error::GithubQuery)??;
+    let plan = UpdatePlan::from_releases(&releases, &host_triple);
+    plan.save()?;
+    debug!(
+        "{} Prefetched Xtensa Rust versions: {:?}",
+        emoji::DEBUG,
+        plan.versions
+    );
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{host_triple::HostTriple, prefetch::UpdatePlan};
+    use serde_json::json;
+
+    #[test]
+    fn test_update_plan_from_releases() {
+        let release = |version: &str, hosts: &[&str]| {
+            let assets: Vec<_> = hosts
+                .iter()
+                .map(|host| json!({ "name": format!("rust-{version}-{host}.tar.xz") }))
+                .collect();
+            json!({ "tag_name": format!("v{version}"), "assets": assets })
+        };
+        let releases = json!([
+            release("1.73.0.1", &["x86_64-unknown-linux-gnu"]),
+            release("1.74.0.0", &["aarch64-apple-darwin"]),
+            release(
+                "1.72.0.0",
+                &["x86_64-unknown-linux-gnu", "aarch64-apple-darwin"]
+            ),
+            release("1.9.0.0", &["x86_64-unknown-linux-gnu"]),
+        ]);
+
+        let plan = UpdatePlan::from_releases(&releases, &HostTriple::X86_64UnknownLinuxGnu);
+        assert_eq!(plan.host_triple, "x86_64-unknown-linux-gnu");
+        assert_eq!(plan.versions, vec!["1.73.0.1", "1.72.0.0", "1.9.0.0"]);
+        assert_eq!(plan.latest(), Some("1.73.0.1"));
+
+        let plan = UpdatePlan::from_releases(&releases, &HostTriple::X86_64PcWindowsMsvc);
+        assert_eq!(plan.latest(), None);
+    }
+}
@@ -0,0 +1,541 @@
+//! Host requirements checks for the downloaded binaries.
+
+use crate::{
+    emoji,
+    error::Error,
+    footprint::format_size,
+    locale::{command, decode_output},
+    warnings::{self, WarningKind},
+};
+use log::debug;
+use std::{
+    cmp::Ordering,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+use strum::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum HostLibrary {
+    /// GNU C library, on Linux hosts.
+    #[strum(serialize = "glibc")]
+    Glibc,
+    /// macOS version.
+    #[strum(serialize = "macOS")]
+    MacOs,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HostRequirement {
+    /// Name of the component.
+    pub component: &'static str,
+    /// Prefix of the component versions the requirement applies to.
+    pub version_prefix: &'static str,
+    /// Host library or OS the requirement applies to.
+    pub library: HostLibrary,
+    /// Minimum version of the host library or OS.
+    pub minimum: &'static str,
+}
+
+/// Known minimum host versions of the released artifacts.
+pub const HOST_REQUIREMENTS: &[HostRequirement] = &[
+    HostRequirement {
+        component: "Xtensa Rust",
+        version_prefix: "",
+        library: HostLibrary::Glibc,
+        minimum: "2.17",
+    },
+    HostRequirement {
+        component: "Xtensa Rust",
+        version_prefix: "",
+        library: HostLibrary::MacOs,
+        minimum: "10.12",
+    },
+    HostRequirement {
+        component: "LLVM",
+        version_prefix: "esp-16",
+        library: HostLibrary::Glibc,
+        minimum: "2.17",
+    },
+    HostRequirement {
+        component: "GCC",
+        version_prefix: "12.2.0",
+        library: HostLibrary::Glibc,
+        minimum: "2.17",
+    },
+];
+
+/// Detects the host library or OS version the artifacts depend on.
+pub fn detect_host_version() -> Option<(HostLibrary, String)> {
+    if cfg!(target_os = "linux") {
+        detect_glibc_version().map(|version| (HostLibrary::Glibc, version))
+    } else if cfg!(target_os = "macos") {
+        let output = command("sw_vers")
+            .arg("-productVersion")
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        let version = decode_output(&output.stdout).trim().to_string();
+        (!version.is_empty()).then_some((HostLibrary::MacOs, version))
+    } else {
+        None
+    }
+}
+
+/// Detects the glibc version of the host.
+fn detect_glibc_version() -> Option<String> {
+    // `getconf GNU_LIBC_VERSION` prints `glibc <version>`
+    if let Ok(output) = command("getconf")
+        .arg("GNU_LIBC_VERSION")
+        .stderr(Stdio::null())
+        .output()
+    {
+        let stdout = decode_output(&output.stdout);
+        if let Some(version) = stdout.trim().strip_prefix("glibc ") {
+            return Some(version.to_string());
+        }
+    }
+    // `ldd --version` first line ends with the version
+    let output = command("ldd")
+        .arg("--version")
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let stdout = decode_output(&output.stdout);
+    let first_line = stdout.lines().next()?;
+    if !first_line.contains("GLIBC") && !first_line.contains("GNU libc") {
+        return None;
+    }
+    first_line.split_whitespace().last().map(str::to_string)
+}
+
+/// Compares two dot separated versions numerically.
+pub(crate) fn compare_versions(a: &str, b: &str) -> Ordering {
+    let parse = |version: &str| -> Vec<u32> {
+        version
+            .split('.')
+            .map(|n| {
+                n.chars()
+                    .take_while(char::is_ascii_digit)
+                    .collect::<String>()
+                    .parse()
+                    .unwrap_or(0)
+            })
+            .collect()
+    };
+    parse(a).cmp(&parse(b))
+}
+
+/// Checks the components against the requirements for the detected host version.
+fn check(
+    requirements: &[HostRequirement],
+    components: &[(&str, &str)],
+    library: HostLibrary,
+    detected: &str,
+) -> Result<(), Error> {
+    for (component, version) in components {
+        for requirement in requirements.iter().filter(|r| {
+            r.component == *component
+                && r.library == library
+                && version.starts_with(r.version_prefix)
+        }) {
+            if compare_versions(detected, requirement.minimum) == Ordering::Less {
+                return Err(Error::HostRequirement(
+                    format!("{component} {version}"),
+                    library.to_string(),
+                    requirement.minimum.to_string(),
+                    detected.to_string(),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Verifies that the host meets the minimum requirements of the given components and versions.
+pub fn check_host_requirements(components: &[(&str, &str)]) -> Result<(), Error> {
+    match detect_host_version() {
+        Some((library, detected)) => {
+            debug!("{} Detected {} {}", emoji::DEBUG, library, detected);
+            check(HOST_REQUIREMENTS, components, library, &detected)
+        }
+        None => {
+            if cfg!(unix) {
+                warnings::push(
+                    WarningKind::Skipped,
+                    "Unable to detect the host version, skipping host requirements check",
+                );
+            }
+            Ok(())
+        }
+    }
+}
+
+/// File listing the mounted filesystems, on Linux hosts.
+const MOUNTS_FILE: &str = "/proc/self/mounts";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mount {
+    /// Directory the filesystem is mounted on.
+    pub mount_point: PathBuf,
+    /// Mount options.
+    pub options: Vec<String>,
+}
+
+impl Mount {
+    /// Returns true if binaries in the filesystem can not be executed.
+    pub fn is_noexec(&self) -> bool {
+        self.options.iter().any(|option| option == "noexec")
+    }
+}
+
+/// Parses the contents of a `/proc/mounts` file.
+pub fn parse_mounts(contents: &str) -> Vec<Mount> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let _device = fields.next()?;
+            // Spaces in mount points are escaped as `\040`
+            let mount_point = fields.next()?.replace("\\040", " ");
+            let _fs_type = fields.next()?;
+            let options = fields.next()?.split(',').map(str::to_string).collect();
+            Some(Mount {
+                mount_point: PathBuf::from(mount_point),
+                options,
+            })
+        })
+        .collect()
+}
+
+/// Finds the mount a path belongs to, the one with the longest matching mount point.
+pub fn find_mount<'a>(mounts: &'a [Mount], path: &Path) -> Option<&'a Mount> {
+    mounts
+        .iter()
+        .filter(|mount| path.starts_with(&mount.mount_point))
+        .max_by_key(|mount| mount.mount_point.components().count())
+}
+
+/// Resolves a path that may not exist yet through its closest existing ancestor.
+fn resolve_path(path: &Path) -> PathBuf {
+    let mut existing = path;
+    let mut missing = Vec::new();
+    while !existing.exists() {
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name.to_os_string());
+                existing = parent;
+            }
+            _ => return path.to_path_buf(),
+        }
+    }
+    let mut resolved = existing
+        .canonicalize()
+        .unwrap_or_else(|_| existing.to_path_buf());
+    resolved.extend(missing.iter().rev());
+    resolved
+}
+
+/// Checks the given directories against the mounts, returning an error for the first one mounted
+/// with `noexec`. Each directory comes with a hint on how to use a different one.
+fn check_mounts(mounts: &[Mount], directories: &[(&Path, &str)]) -> Result<(), Error> {
+    for (directory, hint) in directories {
+        let resolved = resolve_path(directory);
+        if let Some(mount) = find_mount(mounts, &resolved) {
+            debug!(
+                "{} '{}' is mounted on '{}' with '{}'",
+                emoji::DEBUG,
+                resolved.display(),
+                mount.mount_point.display(),
+                mount.options.join(",")
+            );
+            if mount.is_noexec() {
+                return Err(Error::NoExecMount(
+                    directory.display().to_string(),
+                    mount.mount_point.display().to_string(),
+                    hint.to_string(),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Verifies that binaries can be executed from the given directories, which would otherwise
+/// break running `install.sh` and the installed toolchains.
+pub fn check_executable_directories(directories: &[(&Path, &str)]) -> Result<(), Error> {
+    match read_to_string(MOUNTS_FILE) {
+        Ok(contents) => check_mounts(&parse_mounts(&contents), directories),
+        // Not a Linux host
+        Err(_) => Ok(()),
+    }
+}
+
+/// Parses the space available, in bytes, from the output of `df -Pk`.
+#[cfg(any(unix, test))]
+fn parse_df(stdout: &str) -> Option<u64> {
+    // The POSIX format prints a header and a single line per filesystem
+    let available: u64 = stdout
+        .lines()
+        .nth(1)?
+        .split_whitespace()
+        .nth(3)?
+        .parse()
+        .ok()?;
+    Some(available * 1024)
+}
+
+/// Returns the space available in the filesystem of a directory, which may not exist yet, `None`
+/// if it can not be determined.
+pub fn available_space(directory: &Path) -> Option<u64> {
+    let existing = directory.ancestors().find(|dir| dir.exists())?;
+    #[cfg(unix)]
+    {
+        let output = command("df")
+            .arg("-Pk")
+            .arg(existing)
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        parse_df(&decode_output(&output.stdout))
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+        use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+        let path: Vec<u16> = existing.as_os_str().encode_wide().chain(Some(0)).collect();
+        let mut available = 0u64;
+        // SAFETY: `path` is a null-terminated wide string and the other outputs are optional
+        let result = unsafe {
+            GetDiskFreeSpaceExW(
+                path.as_ptr(),
+                &mut available,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+        };
+        (result != 0).then_some(available)
+    }
+}
+
+/// Returns an identifier of the filesystem of a directory, which may not exist yet.
+fn filesystem_id(directory: &Path) -> Option<String> {
+    let existing = directory.ancestors().find(|dir| dir.exists())?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        existing
+            .metadata()
+            .ok()
+            .map(|metadata| metadata.dev().to_string())
+    }
+    #[cfg(windows)]
+    {
+        // The drive or share of the path
+        existing
+            .canonicalize()
+            .ok()?
+            .components()
+            .next()
+            .map(|prefix| prefix.as_os_str().to_string_lossy().to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpaceRequirement {
+    /// Directory written by the installation.
+    pub directory: PathBuf,
+    /// Space required in the directory, in bytes.
+    pub required: u64,
+    /// Hint on how to use a different directory.
+    pub hint: String,
+    /// Identifier of the filesystem of the directory, if it can be determined.
+    pub filesystem: Option<String>,
+    /// Space available in the filesystem, in bytes, if it can be determined.
+    pub available: Option<u64>,
+}
+
+/// Returns the requirements that the space of their filesystem can not satisfy, with the space
+/// required by all the directories sharing the filesystem.
+fn space_shortages(requirements: &[SpaceRequirement]) -> Vec<SpaceRequirement> {
+    let mut shortages: Vec<SpaceRequirement> = Vec::new();
+    for (index, requirement) in requirements.iter().enumerate() {
+        let Some(available) = requirement.available else {
+            continue;
+        };
+        let shares_filesystem = |other: &SpaceRequirement| {
+            requirement.filesystem.is_some() && other.filesystem == requirement.filesystem
+        };
+        // The filesystem is only reported once, for its first directory
+        if requirements[..index].iter().any(shares_filesystem) {
+            continue;
+        }
+        let required = requirements[index + 1..]
+            .iter()
+            .filter(|other| shares_filesystem(other))
+            .map(|other| other.required)
+            .sum::<u64>()
+            + requirement.required;
+        if required > available {
+            shortages.push(SpaceRequirement {
+                required,
+                ..requirement.clone()
+            });
+        }
+    }
+    shortages
+}
+
+/// Checks that the filesystems of the given directories have the space required by the
+/// installation. Each directory comes with the space it requires and a hint on how to use a
+/// different one.
+///
+/// With `force`, a lack of space is only reported as a warning.
+pub fn check_disk_space(directories: &[(&Path, u64, &str)], force: bool) -> Result<(), Error> {
+    let requirements: Vec<SpaceRequirement> = directories
+        .iter()
+        .map(|(directory, required, hint)| SpaceRequirement {
+            directory: directory.to_path_buf(),
+            required: *required,
+            hint: hint.to_string(),
+            filesystem: filesystem_id(directory),
+            available: available_space(directory),
+        })
+        .collect();
+    for requirement in &requirements {
+        debug!(
+            "{} '{}' requires {}, {} available",
+            emoji::DEBUG,
+            requirement.directory.display(),
+            format_size(requirement.required),
+            requirement
+                .available
+                .map_or("unknown".to_string(), format_size)
+        );
+    }
+    for shortage in space_shortages(&requirements) {
+        let directory = shortage.directory.display().to_string();
+        let required = format_size(shortage.required);
+        let available = format_size(shortage.available.unwrap_or_default());
+        if !force {
+            return Err(Error::InsufficientDiskSpace(
+                directory,
+                required,
+                available,
+                shortage.hint,
+            ));
+        }
+        warnings::push(
+            WarningKind::LowDiskSpace,
+            format!("'{directory}' may not have enough space: about {required} required, {available} available"),
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::preflight::{
+        check, check_mounts, compare_versions, find_mount, parse_df, parse_mounts, space_shortages,
+        HostLibrary, HostRequirement, SpaceRequirement,
+    };
+    use std::{cmp::Ordering, path::Path};
+
+    #[test]
+    fn test_compare_versions() {
+        assert_eq!(compare_versions("2.27", "2.31"), Ordering::Less);
+        assert_eq!(compare_versions("2.31", "2.31"), Ordering::Equal);
+        assert_eq!(compare_versions("2.9", "2.17"), Ordering::Less);
+        assert_eq!(compare_versions("13.4.1", "10.12"), Ordering::Greater);
+        assert_eq!(compare_versions("2.35-0ubuntu3", "2.35"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_check() {
+        let requirements = [HostRequirement {
+            component: "LLVM",
+            version_prefix: "esp-17",
+            library: HostLibrary::Glibc,
+            minimum: "2.31",
+        }];
+        let components = [("LLVM", "esp-17.0.1_20240419")];
+        assert!(check(&requirements, &components, HostLibrary::Glibc, "2.27").is_err());
+        assert!(check(&requirements, &components, HostLibrary::Glibc, "2.31").is_ok());
+        assert!(check(&requirements, &components, HostLibrary::MacOs, "10.0").is_ok());
+        let components = [("LLVM", "esp-16.0.0-20230516")];
+        assert!(check(&requirements, &components, HostLibrary::Glibc, "2.27").is_ok());
+    }
+
+    #[test]
+    fn test_noexec_mounts() {
+        let mounts = parse_mounts(
+            "\
+/dev/sda1 / ext4 rw,relatime 0 0
+tmpfs /tmp tmpfs rw,nosuid,nodev,noexec 0 0
+/dev/sdb1 /home ext4 rw,relatime 0 0
+/dev/sdc1 /mnt/my\\040disk ext4 rw,noexec 0 0
+",
+        );
+        assert_eq!(mounts.len(), 4);
+        assert_eq!(mounts[3].mount_point, Path::new("/mnt/my disk"));
+        assert_eq!(
+            find_mount(&mounts, Path::new("/tmp/espup"))
+                .unwrap()
+                .mount_point,
+            Path::new("/tmp")
+        );
+        assert_eq!(
+            find_mount(&mounts, Path::new("/tmpfiles"))
+                .unwrap()
+                .mount_point,
+            Path::new("/")
+        );
+        assert!(check_mounts(&mounts, &[(Path::new("/home/user/.rustup"), "")]).is_ok());
+        assert!(check_mounts(&mounts, &[(Path::new("/tmp/espup/tmp"), "")]).is_err());
+    }
+
+    #[test]
+    fn test_space_shortages() {
+        assert_eq!(
+            parse_df(
+                "Filesystem     1024-blocks      Used Available Capacity Mounted on\n/dev/sda1        102400000  81920000  20480000      80% /\n"
+            ),
+            Some(20480000 * 1024)
+        );
+        assert_eq!(
+            parse_df("df: /nonexistent: No such file or directory\n"),
+            None
+        );
+
+        let requirement =
+            |directory: &str, required: u64, filesystem: &str, available: u64| SpaceRequirement {
+                directory: Path::new(directory).to_path_buf(),
+                required,
+                hint: String::new(),
+                filesystem: Some(filesystem.to_string()),
+                available: Some(available),
+            };
+        // The staging and toolchain directories fit separately, but not on the same filesystem
+        let staging = requirement("/tmp/espup", 300, "1", 1000);
+        let toolchains = requirement("/home/esp/.rustup", 800, "1", 1000);
+        assert!(space_shortages(std::slice::from_ref(&staging)).is_empty());
+        let shortages = space_shortages(&[staging.clone(), toolchains.clone()]);
+        assert_eq!(shortages.len(), 1);
+        assert_eq!(shortages[0].directory, Path::new("/tmp/espup"));
+        assert_eq!(shortages[0].required, 1100);
+
+        let toolchains = SpaceRequirement {
+            filesystem: Some("2".to_string()),
+            ..toolchains
+        };
+        assert!(space_shortages(&[staging.clone(), toolchains]).is_empty());
+        // Unknown available space is not reported
+        let unknown = SpaceRequirement {
+            available: None,
+            ..requirement("/opt", 5000, "3", 0)
+        };
+        assert!(space_shortages(&[staging, unknown]).is_empty());
+    }
+}
@@ -0,0 +1,204 @@
+//! Lines written by espup to shell profiles and environment files, tracked in the installation
+//! manifest so uninstalling the toolchain removes them, along with the lines sourcing its export
+//! file.
+
+use crate::{doctor::USER_PROFILES, error::Error};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{read_to_string, write},
+    path::{Path, PathBuf},
+};
+
+/// Commands sourcing a file in the supported shells.
+const SOURCE_COMMANDS: [&str; 3] = [".", "source", "call"];
+/// References to the home directory in the sourced paths, in the supported shells.
+const HOME_REFERENCES: [&str; 5] = ["~", "$HOME", "${HOME}", "%USERPROFILE%", "$env:USERPROFILE"];
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ProfileEdit {
+    /// Modified file.
+    pub path: PathBuf,
+    /// Lines appended to the file.
+    pub lines: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileCleanup {
+    /// Cleaned up file.
+    pub path: PathBuf,
+    /// Contents of the file without the removed lines.
+    pub contents: String,
+    /// Removed lines.
+    pub removed: Vec<String>,
+}
+
+impl ProfileCleanup {
+    /// Writes the cleaned up file.
+    pub fn apply(&self) -> Result<(), Error> {
+        write(&self.path, &self.contents)?;
+        Ok(())
+    }
+}
+
+/// Returns true if a line only sources one of the export files, with its path expanded from the
+/// home directory if it refers to it.
+pub fn sources_export_file(line: &str, home_dir: &Path, export_files: &[PathBuf]) -> bool {
+    let Some((command, file)) = line.trim().split_once(char::is_whitespace) else {
+        return false;
+    };
+    if !SOURCE_COMMANDS.contains(&command) {
+        return false;
+    }
+    let file = file.trim().trim_matches(['"', '\'']);
+    let file = HOME_REFERENCES
+        .iter()
+        .find_map(|home| {
+            let rest = file.strip_prefix(home)?;
+            let rest = rest
+                .strip_prefix(['/', '\\'])
+                .or(rest.is_empty().then_some(rest))?;
+            Some(home_dir.join(rest))
+        })
+        .unwrap_or_else(|| PathBuf::from(file));
+    export_files.contains(&file)
+}
+
+/// Removes the tracked lines, each one once, and the lines sourcing the export files from the
+/// contents of a file, keeping the line endings of the other lines. Returns `None` if there is
+/// nothing to remove.
+fn cleanup(
+    path: &Path,
+    contents: &str,
+    tracked: &[&str],
+    home_dir: &Path,
+    export_files: &[PathBuf],
+) -> Option<ProfileCleanup> {
+    let mut tracked = tracked.to_vec();
+    let mut kept = String::new();
+    let mut removed = Vec::new();
+    for raw_line in contents.split_inclusive('\n') {
+        let line = raw_line.trim_end_matches(['\r', '\n']);
+        if let Some(index) = tracked.iter().position(|tracked| *tracked == line) {
+            tracked.remove(index);
+            removed.push(line.to_string());
+        } else if sources_export_file(line, home_dir, export_files) {
+            removed.push(line.to_string());
+        } else {
+            kept.push_str(raw_line);
+        }
+    }
+    if removed.is_empty() {
+        return None;
+    }
+    Some(ProfileCleanup {
+        path: path.to_path_buf(),
+        contents: kept,
+        removed,
+    })
+}
+
+/// Returns the cleanups of the files edited by espup and of the shell profiles of the user
+/// sourcing the export files.
+pub fn cleanups(
+    home_dir: &Path,
+    edits: &[ProfileEdit],
+    export_files: &[PathBuf],
+) -> Vec<ProfileCleanup> {
+    let mut paths: Vec<PathBuf> = edits.iter().map(|edit| edit.path.clone()).collect();
+    paths.extend(USER_PROFILES.iter().map(|profile| home_dir.join(profile)));
+    paths.sort();
+    paths.dedup();
+    paths
+        .iter()
+        .filter_map(|path| {
+            let contents = read_to_string(path).ok()?;
+            let tracked: Vec<&str> = edits
+                .iter()
+                .filter(|edit| edit.path == *path)
+                .flat_map(|edit| edit.lines.iter().map(String::as_str))
+                .collect();
+            cleanup(path, &contents, &tracked, home_dir, export_files)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::profile::{cleanups, sources_export_file, ProfileEdit};
+    use std::{
+        fs::{read_to_string, write},
+        path::{Path, PathBuf},
+    };
+
+    #[test]
+    fn test_cleanups() {
+        let home = Path::new("/home/user");
+        let export_files = vec![PathBuf::from("/home/user/export-esp.sh")];
+        assert!(sources_export_file(
+            ". $HOME/export-esp.sh",
+            home,
+            &export_files
+        ));
+        assert!(sources_export_file(
+            "source \"~/export-esp.sh\"",
+            home,
+            &export_files
+        ));
+        assert!(sources_export_file(
+            ". /home/user/export-esp.sh",
+            home,
+            &export_files
+        ));
+        assert!(!sources_export_file(
+            "# . $HOME/export-esp.sh",
+            home,
+            &export_files
+        ));
+        assert!(!sources_export_file(
+            ". $HOME/export-other.sh",
+            home,
+            &export_files
+        ));
+        // Export files of the same name in other directories are not the ones of espup
+        assert!(!sources_export_file(
+            ". /opt/project/export-esp.sh",
+            home,
+            &export_files
+        ));
+
+        let home_dir = tempfile::TempDir::new().unwrap();
+        let export_files = vec![home_dir.path().join("export-esp.sh")];
+        let bashrc = home_dir.path().join(".bashrc");
+        write(
+            &bashrc,
+            "alias ll='ls -l'\r\n. $HOME/export-esp.sh\r\nexport A=1",
+        )
+        .unwrap();
+        let env_file = home_dir.path().join("esp.env");
+        write(
+            &env_file,
+            "A=1\nLIBCLANG_PATH=/llvm/lib\nLIBCLANG_PATH=/llvm/lib\n",
+        )
+        .unwrap();
+        let edits = vec![ProfileEdit {
+            path: env_file.clone(),
+            lines: vec!["LIBCLANG_PATH=/llvm/lib".to_string()],
+        }];
+
+        let cleanups = cleanups(home_dir.path(), &edits, &export_files);
+        assert_eq!(cleanups.len(), 2);
+        for cleanup in &cleanups {
+            cleanup.apply().unwrap();
+        }
+        // Only the tracked lines are removed, each one once
+        assert_eq!(
+            read_to_string(&env_file).unwrap(),
+            "A=1\nLIBCLANG_PATH=/llvm/lib\n"
+        );
+        // The line endings of the kept lines are left untouched
+        assert_eq!(
+            read_to_string(&bashrc).unwrap(),
+            "alias ll='ls -l'\r\nexport A=1"
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! Download progress, redrawn in place on a terminal and logged as percentage milestones when
+//! the output is piped, e.g. to the log of a CI job.
+
+use crate::emoji;
+use log::info;
+use std::{
+    io::{stderr, Write},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+/// Percentage between two logged milestones.
+const MILESTONE_STEP: u64 = 25;
+/// Minimum time between two redraws of the progress line.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Whether the progress is redrawn in place, only when the logs are written to a terminal.
+static INTERACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether the progress is redrawn in place or logged as milestones.
+pub fn set_interactive(interactive: bool) {
+    INTERACTIVE.store(interactive, Ordering::Relaxed);
+}
+
+/// Returns true if the progress is redrawn in place.
+pub fn is_interactive() -> bool {
+    INTERACTIVE.load(Ordering::Relaxed)
+}
+
+/// Returns the milestone reached at a percentage, `None` if it was already logged.
+fn next_milestone(logged: u64, percent: u64) -> Option<u64> {
+    let milestone = percent / MILESTONE_STEP * MILESTONE_STEP;
+    (milestone > logged).then_some(milestone)
+}
+
+#[derive(Debug)]
+pub struct Progress {
+    /// Name of the downloaded artifact.
+    name: String,
+    /// Size of the artifact, in bytes, `None` if the server did not send it.
+    total: Option<u64>,
+    /// Bytes received so far.
+    received: u64,
+    /// Last milestone logged.
+    milestone: u64,
+    /// Time of the last redraw, `None` if it was not drawn yet.
+    drawn_at: Option<Instant>,
+}
+
+impl Progress {
+    /// Starts the progress of a download, from the bytes already received by a previous attempt.
+    pub fn new(name: &str, total: Option<u64>, received: u64) -> Self {
+        Self {
+            name: name.to_string(),
+            total,
+            received,
+            milestone: 0,
+            drawn_at: None,
+        }
+    }
+
+    /// Returns the percentage of the artifact received, `None` if its size is unknown.
+    fn percent(&self) -> Option<u64> {
+        self.total
+            .filter(|total| *total > 0)
+            .map(|total| (self.received * 100 / total).min(100))
+    }
+
+    /// Records a received chunk of bytes.
+    pub fn advance(&mut self, bytes: usize) {
+        self.received += bytes as u64;
+        let Some(percent) = self.percent() else {
+            return;
+        };
+        if is_interactive() {
+            if self
+                .drawn_at
+                .map_or(true, |drawn_at| drawn_at.elapsed() >= REDRAW_INTERVAL)
+            {
+                eprint!("\r{} {}: {percent}%", emoji::DOWNLOAD, self.name);
+                let _ = stderr().flush();
+                self.drawn_at = Some(Instant::now());
+            }
+        } else if let Some(milestone) = next_milestone(self.milestone, percent) {
+            self.milestone = milestone;
+            info!(
+                "{} Downloaded {}% of '{}'",
+                emoji::DOWNLOAD,
+                milestone,
+                self.name
+            );
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        // Clears the progress line before the next log line
+        if self.drawn_at.is_some() {
+            eprint!("\r\x1b[2K");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::progress::{next_milestone, Progress};
+
+    #[test]
+    fn test_milestones() {
+        assert_eq!(next_milestone(0, 10), None);
+        assert_eq!(next_milestone(0, 30), Some(25));
+        assert_eq!(next_milestone(25, 49), None);
+        assert_eq!(next_milestone(25, 100), Some(100));
+
+        // Resumed downloads start from the bytes already received
+        let mut progress = Progress::new("llvm.tar.xz", Some(200), 100);
+        assert_eq!(progress.percent(), Some(50));
+        progress.received += 150;
+        assert_eq!(progress.percent(), Some(100));
+        assert_eq!(Progress::new("gcc.tar.xz", None, 0).percent(), None);
+    }
+}
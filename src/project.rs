@@ -0,0 +1,241 @@
+//! Per-project toolchains declared in the `rust-toolchain.toml` file read by rustup and cargo.
+
+use crate::{cli::InstallOpts, emoji, error::Error, targets::Target};
+use log::debug;
+use std::{
+    collections::HashSet,
+    fs::{read_to_string, write},
+    path::Path,
+    str::FromStr,
+};
+use toml::{Table, Value};
+
+/// Toolchain file of a project.
+pub const TOOLCHAIN_FILE: &str = "rust-toolchain.toml";
+/// Legacy toolchain file, either in the TOML format or only naming the channel.
+const LEGACY_TOOLCHAIN_FILE: &str = "rust-toolchain";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProjectToolchain {
+    /// Channel of the toolchain, either the name of the Xtensa Rust toolchain or a nightly one.
+    pub channel: Option<String>,
+    /// Rust target triples.
+    pub targets: Vec<String>,
+}
+
+impl ProjectToolchain {
+    /// Loads the toolchain declared by a project, empty if it does not declare any.
+    pub fn load(project_dir: &Path) -> Result<Self, Error> {
+        for file in [TOOLCHAIN_FILE, LEGACY_TOOLCHAIN_FILE] {
+            let path = project_dir.join(file);
+            if let Ok(contents) = read_to_string(&path) {
+                debug!(
+                    "{} Reading toolchain file '{}'",
+                    emoji::DEBUG,
+                    path.display()
+                );
+                return Self::parse(&contents, file == LEGACY_TOOLCHAIN_FILE)
+                    .ok_or_else(|| Error::ReadToolchainFile(path.display().to_string()));
+            }
+        }
+        Ok(Self::default())
+    }
+
+    /// Parses the contents of a toolchain file.
+    fn parse(contents: &str, legacy: bool) -> Option<Self> {
+        let Ok(table) = contents.parse::<Table>() else {
+            // Legacy files may only contain the name of the channel
+            let channel = contents.trim();
+            let is_channel = !channel.is_empty() && !channel.contains(char::is_whitespace);
+            return (legacy && is_channel).then(|| Self {
+                channel: Some(channel.to_string()),
+                targets: Vec::new(),
+            });
+        };
+        let toolchain = table.get("toolchain")?.as_table()?;
+        let channel = match toolchain.get("channel") {
+            Some(channel) => Some(channel.as_str()?.to_string()),
+            None => None,
+        };
+        let targets = match toolchain.get("targets") {
+            Some(targets) => targets
+                .as_array()?
+                .iter()
+                .map(|target| target.as_str().map(str::to_string))
+                .collect::<Option<Vec<String>>>()?,
+            None => Vec::new(),
+        };
+        Some(Self { channel, targets })
+    }
+
+    /// Returns the chips of the declared Rust targets, ignoring the ones of other devices.
+    pub fn chips(&self) -> HashSet<Target> {
+        self.targets
+            .iter()
+            .filter_map(|triple| {
+                if triple.starts_with("riscv32imc-") {
+                    Some(Target::ESP32C3)
+                } else if triple.starts_with("riscv32imac-") {
+                    Some(Target::ESP32C6)
+                } else {
+                    let chip = triple.strip_prefix("xtensa-")?.split('-').next()?;
+                    Target::from_str(chip).ok()
+                }
+            })
+            .collect()
+    }
+
+    /// Applies the declared toolchain to the installation options.
+    ///
+    /// A nightly channel sets the nightly version and any other channel the name of the Xtensa
+    /// Rust toolchain, while the targets default to the ones of the options.
+    pub fn apply(&self, args: &mut InstallOpts) {
+        match self.channel.as_deref() {
+            Some(channel) if channel.starts_with("nightly") => {
+                args.nightly_version = channel.to_string();
+            }
+            Some(channel) if !is_release_channel(channel) => args.name = channel.to_string(),
+            _ => {}
+        }
+        let chips = self.chips();
+        if !chips.is_empty() {
+            args.targets = chips;
+        }
+    }
+
+    /// Returns the toolchain installed for the targets.
+    ///
+    /// Xtensa chips use the Xtensa Rust toolchain, which is a custom toolchain declaring no
+    /// targets, while RISC-V chips use the nightly toolchain with their targets.
+    pub fn for_installation(name: &str, nightly_version: &str, targets: &HashSet<Target>) -> Self {
+        if targets.iter().any(|target| target.is_xtensa()) {
+            return Self {
+                channel: Some(name.to_string()),
+                targets: Vec::new(),
+            };
+        }
+        let mut triples: Vec<String> = targets
+            .iter()
+            .map(|target| target.rust_target().to_string())
+            .collect();
+        triples.sort();
+        triples.dedup();
+        Self {
+            channel: Some(nightly_version.to_string()),
+            targets: triples,
+        }
+    }
+
+    /// Writes the toolchain to the `rust-toolchain.toml` of a project, keeping its other settings.
+    pub fn save(&self, project_dir: &Path) -> Result<(), Error> {
+        let path = project_dir.join(TOOLCHAIN_FILE);
+        let invalid = || Error::WriteToolchainFile(path.display().to_string());
+        let mut table = match read_to_string(&path) {
+            Ok(contents) => contents.parse::<Table>().map_err(|_| invalid())?,
+            Err(_) => Table::new(),
+        };
+        let toolchain = table
+            .entry("toolchain")
+            .or_insert_with(|| Value::Table(Table::new()))
+            .as_table_mut()
+            .ok_or_else(invalid)?;
+        if let Some(channel) = &self.channel {
+            toolchain.insert("channel".to_string(), Value::String(channel.clone()));
+        }
+        if self.targets.is_empty() {
+            toolchain.remove("targets");
+        } else {
+            toolchain.insert(
+                "targets".to_string(),
+                Value::Array(self.targets.iter().cloned().map(Value::String).collect()),
+            );
+            toolchain
+                .entry("components")
+                .or_insert_with(|| Value::Array(vec![Value::String("rust-src".to_string())]));
+        }
+        write(&path, table.to_string()).map_err(|_| invalid())
+    }
+}
+
+/// Returns true if the channel is a stable or beta release, which espup does not install.
+fn is_release_channel(channel: &str) -> bool {
+    channel == "stable"
+        || channel.starts_with("beta")
+        || channel.starts_with(|c: char| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cli::InstallOpts,
+        project::{ProjectToolchain, TOOLCHAIN_FILE},
+        targets::Target,
+    };
+    use clap::Parser;
+    use std::{
+        collections::HashSet,
+        fs::{read_to_string, write},
+    };
+
+    #[test]
+    fn test_project_toolchain() {
+        let project_dir = tempfile::TempDir::new().unwrap();
+        assert_eq!(
+            ProjectToolchain::load(project_dir.path()).unwrap(),
+            ProjectToolchain::default()
+        );
+
+        write(project_dir.path().join("rust-toolchain"), "esp\n").unwrap();
+        let mut args = InstallOpts::parse_from(["install", "-t", "esp32s3"]);
+        ProjectToolchain::load(project_dir.path())
+            .unwrap()
+            .apply(&mut args);
+        assert_eq!(args.name, "esp");
+        assert_eq!(args.targets, HashSet::from([Target::ESP32S3]));
+
+        write(
+            project_dir.path().join(TOOLCHAIN_FILE),
+            "[toolchain]\nchannel = \"nightly-2024-01-15\"\ncomponents = [\"rust-src\"]\ntargets = [\"riscv32imac-unknown-none-elf\", \"x86_64-unknown-linux-gnu\"]\n",
+        )
+        .unwrap();
+        let project = ProjectToolchain::load(project_dir.path()).unwrap();
+        project.apply(&mut args);
+        assert_eq!(args.nightly_version, "nightly-2024-01-15");
+        assert_eq!(args.targets, HashSet::from([Target::ESP32C6]));
+
+        write(project_dir.path().join(TOOLCHAIN_FILE), "[toolchain\n").unwrap();
+        assert!(ProjectToolchain::load(project_dir.path()).is_err());
+
+        // The other settings of the project are kept
+        write(
+            project_dir.path().join(TOOLCHAIN_FILE),
+            "[toolchain]\nchannel = \"nightly\"\nprofile = \"minimal\"\n",
+        )
+        .unwrap();
+        let toolchain = ProjectToolchain::for_installation(
+            "esp",
+            "nightly",
+            &HashSet::from([Target::ESP32C2, Target::ESP32C3]),
+        );
+        assert_eq!(toolchain.targets, vec!["riscv32imc-unknown-none-elf"]);
+        toolchain.save(project_dir.path()).unwrap();
+        let contents = read_to_string(project_dir.path().join(TOOLCHAIN_FILE)).unwrap();
+        assert!(contents.contains("profile = \"minimal\""));
+        assert!(contents.contains("components = [\"rust-src\"]"));
+        assert_eq!(
+            ProjectToolchain::load(project_dir.path()).unwrap(),
+            toolchain
+        );
+
+        let toolchain = ProjectToolchain::for_installation(
+            "esp",
+            "nightly",
+            &HashSet::from([Target::ESP32, Target::ESP32C3]),
+        );
+        toolchain.save(project_dir.path()).unwrap();
+        assert_eq!(
+            ProjectToolchain::load(project_dir.path()).unwrap(),
+            toolchain
+        );
+    }
+}
@@ -0,0 +1,102 @@
+//! Prompts for confirmations and secrets, disabled in the non-interactive mode used for unattended
+//! provisioning, e.g. with Ansible or cloud-init, where waiting for input would hang forever.
+
+use crate::error::Error;
+use console::Term;
+use std::{
+    io::{stdin, IsTerminal},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Variable enabling the non-interactive mode, as `--non-interactive` does.
+pub const NON_INTERACTIVE_VAR: &str = "ESPUP_NON_INTERACTIVE";
+
+static NON_INTERACTIVE: AtomicBool = AtomicBool::new(false);
+static ASSUME_YES: AtomicBool = AtomicBool::new(false);
+
+/// Enables or disables the non-interactive mode, and whether the confirmations are answered
+/// with yes instead of failing in it.
+pub fn set_non_interactive(non_interactive: bool, assume_yes: bool) {
+    NON_INTERACTIVE.store(non_interactive || assume_yes, Ordering::Relaxed);
+    ASSUME_YES.store(assume_yes, Ordering::Relaxed);
+}
+
+/// Returns true if espup never prompts.
+pub fn is_non_interactive() -> bool {
+    NON_INTERACTIVE.load(Ordering::Relaxed)
+}
+
+/// Returns the answer to a confirmation that is not asked, `None` if it has to be asked.
+fn unattended_answer(
+    prompt: &str,
+    non_interactive: bool,
+    assume_yes: bool,
+) -> Option<Result<bool, Error>> {
+    if assume_yes {
+        return Some(Ok(true));
+    }
+    if non_interactive {
+        return Some(Err(Error::NonInteractive(prompt.to_string())));
+    }
+    None
+}
+
+/// Asks for confirmation, `false` if the standard input is not a terminal.
+///
+/// In the non-interactive mode, the confirmation is answered by `--yes` or fails.
+pub fn confirm(prompt: &str) -> Result<bool, Error> {
+    if let Some(answer) = unattended_answer(
+        prompt,
+        is_non_interactive(),
+        ASSUME_YES.load(Ordering::Relaxed),
+    ) {
+        return answer;
+    }
+    if !stdin().is_terminal() {
+        return Ok(false);
+    }
+    let term = Term::stderr();
+    term.write_str(&format!("{prompt} [y/N] "))?;
+    let answer = term.read_line()?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Reads a secret without echoing it, or from the standard input when it is not a terminal.
+///
+/// In the non-interactive mode, the secret must be piped to the standard input.
+pub fn read_secret(prompt: &str) -> Result<String, Error> {
+    let stdin = stdin();
+    if !stdin.is_terminal() {
+        let mut secret = String::new();
+        stdin.read_line(&mut secret)?;
+        return Ok(secret.trim().to_string());
+    }
+    if is_non_interactive() {
+        return Err(Error::NonInteractive(prompt.trim().to_string()));
+    }
+    let term = Term::stderr();
+    term.write_str(prompt)?;
+    Ok(term.read_secure_line()?.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{error::Error, prompt::unattended_answer};
+
+    #[test]
+    fn test_unattended_answer() {
+        assert!(matches!(
+            unattended_answer("Remove them?", false, true),
+            Some(Ok(true))
+        ));
+        assert!(matches!(
+            unattended_answer("Remove them?", true, true),
+            Some(Ok(true))
+        ));
+        assert!(matches!(
+            unattended_answer("Remove them?", true, false),
+            Some(Err(Error::NonInteractive(prompt))) if prompt == "Remove them?"
+        ));
+        assert!(unattended_answer("Remove them?", false, false).is_none());
+    }
+}
@@ -0,0 +1,162 @@
+//! HTTP, HTTPS and SOCKS5 proxies support.
+
+use crate::{
+    emoji,
+    error::Error,
+    timeout::{connect_timeout, read_timeout},
+    tls::{root_certificates, tls_settings},
+};
+use log::debug;
+use reqwest::{NoProxy, Proxy};
+use std::{env, sync::Mutex, time::Duration};
+
+/// Supported proxy URL schemes.
+const PROXY_SCHEMES: &[&str] = &["http", "https", "socks5", "socks5h"];
+/// Environment variables the proxy is read from, by priority.
+const PROXY_ENV_VARS: &[&str] = &["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"];
+/// Time an idle connection is kept open to be reused by the next request.
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+/// Interval of the TCP keep-alive probes of the open connections.
+const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProxySettings {
+    /// Proxy URL, overriding the environment variables.
+    pub url: Option<String>,
+    /// Proxy credentials, as `user[:password]`.
+    pub credentials: Option<String>,
+}
+
+static PROXY: Mutex<Option<ProxySettings>> = Mutex::new(None);
+/// Async client shared by every request, so connections are reused across downloads.
+static CLIENT: Mutex<Option<reqwest::Client>> = Mutex::new(None);
+
+/// Sets the proxy settings used by the HTTP clients.
+pub fn set_proxy(settings: ProxySettings) {
+    *PROXY.lock().unwrap() = Some(settings);
+    // The shared client is rebuilt with the new proxy on its next use
+    reset_client();
+}
+
+/// Drops the shared client, so the next request builds one with the current settings.
+pub(crate) fn reset_client() {
+    *CLIENT.lock().unwrap() = None;
+}
+
+/// Returns the proxy URL from the settings, or from the environment variables.
+fn resolve_url(
+    settings: &ProxySettings,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Option<String> {
+    settings.url.clone().or_else(|| {
+        PROXY_ENV_VARS
+            .iter()
+            .filter_map(|var| lookup(var))
+            .find(|value| !value.is_empty())
+    })
+}
+
+/// Builds the proxy from its URL and credentials.
+fn build_proxy(url: &str, credentials: Option<&str>) -> Result<Proxy, Error> {
+    let scheme = url.split_once("://").map(|(scheme, _)| scheme);
+    if !scheme.is_some_and(|scheme| PROXY_SCHEMES.contains(&scheme)) {
+        return Err(Error::InvalidProxy(url.to_string()));
+    }
+    let mut proxy = Proxy::all(url).map_err(|_| Error::InvalidProxy(url.to_string()))?;
+    if let Some(credentials) = credentials {
+        let (username, password) = credentials.split_once(':').unwrap_or((credentials, ""));
+        proxy = proxy.basic_auth(username, password);
+    }
+    Ok(proxy.no_proxy(NoProxy::from_env()))
+}
+
+/// Returns the configured proxy, if any. Without one, the clients keep using the system proxies.
+fn configured_proxy() -> Result<Option<Proxy>, Error> {
+    let settings = PROXY.lock().unwrap().clone().unwrap_or_default();
+    if settings.url.is_none() && settings.credentials.is_none() {
+        return Ok(None);
+    }
+    match resolve_url(&settings, |var| env::var(var).ok()) {
+        Some(url) => {
+            debug!("{} Using proxy '{}'", emoji::DEBUG, url);
+            build_proxy(&url, settings.credentials.as_deref()).map(Some)
+        }
+        None => Ok(None),
+    }
+}
+
+/// Returns the async HTTP client using the configured proxy and certificate authorities.
+///
+/// The client is shared, so its pooled connections, negotiated over HTTP/2 when the server
+/// supports it, are kept alive and reused instead of paying a TLS handshake per artifact.
+pub fn client() -> Result<reqwest::Client, Error> {
+    let mut shared = CLIENT.lock().unwrap();
+    if let Some(client) = shared.as_ref() {
+        return Ok(client.clone());
+    }
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(connect_timeout())
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .tcp_keepalive(TCP_KEEPALIVE)
+        .http2_adaptive_window(true);
+    if let Some(proxy) = configured_proxy()? {
+        builder = builder.proxy(proxy);
+    }
+    let tls = tls_settings();
+    builder = builder.tls_built_in_root_certs(tls.uses_native_certs());
+    for certificate in root_certificates(&tls)? {
+        builder = builder.add_root_certificate(certificate);
+    }
+    let client = builder.build()?;
+    *shared = Some(client.clone());
+    Ok(client)
+}
+
+/// Returns a blocking HTTP client using the configured proxy and certificate authorities.
+///
+/// Its requests are only used for API queries, so the read timeout bounds the whole request.
+pub fn blocking_client() -> Result<reqwest::blocking::Client, Error> {
+    let mut builder = reqwest::blocking::Client::builder()
+        .connect_timeout(connect_timeout())
+        .timeout(read_timeout());
+    if let Some(proxy) = configured_proxy()? {
+        builder = builder.proxy(proxy);
+    }
+    let tls = tls_settings();
+    builder = builder.tls_built_in_root_certs(tls.uses_native_certs());
+    for certificate in root_certificates(&tls)? {
+        builder = builder.add_root_certificate(certificate);
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proxy::{build_proxy, resolve_url, ProxySettings};
+
+    #[test]
+    fn test_resolve_url() {
+        let lookup = |var: &str| (var == "ALL_PROXY").then(|| "socks5://proxy:1080".to_string());
+        assert_eq!(
+            resolve_url(&ProxySettings::default(), lookup),
+            Some("socks5://proxy:1080".to_string())
+        );
+        let settings = ProxySettings {
+            url: Some("http://proxy:3128".to_string()),
+            credentials: None,
+        };
+        assert_eq!(
+            resolve_url(&settings, lookup),
+            Some("http://proxy:3128".to_string())
+        );
+        assert_eq!(resolve_url(&ProxySettings::default(), |_| None), None);
+    }
+
+    #[test]
+    fn test_build_proxy() {
+        assert!(build_proxy("http://proxy:3128", Some("user:secret")).is_ok());
+        assert!(build_proxy("socks5h://127.0.0.1:1080", None).is_ok());
+        assert!(build_proxy("ftp://proxy:21", None).is_err());
+        assert!(build_proxy("proxy:3128", None).is_err());
+    }
+}
@@ -0,0 +1,261 @@
+//! Garbage collection of the unused LLVM and GCC versions, orphaned temporary files and cached
+//! artifacts.
+
+use crate::{
+    cache::{entries as cache_entries, CacheConfig},
+    env::ExportItem,
+    error::Error,
+    list::{subdirectories, GCC_NAMES},
+    manifest::InstallManifest,
+    selfcheck::{find_issues, Issue},
+    toolchain::{llvm::CLANG_NAME, rust::QUARANTINE_DIR},
+};
+use std::{
+    ffi::OsStr,
+    fs::{read_dir, remove_dir_all, remove_file},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use strum::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum ReclaimableKind {
+    /// LLVM or GCC version no longer exported by its installation.
+    #[strum(serialize = "unused component")]
+    UnusedComponent,
+    /// Corrupted toolchain moved aside before installing it again.
+    #[strum(serialize = "quarantined toolchain")]
+    Quarantined,
+    /// Temporary file or directory left behind by an interrupted run.
+    #[strum(serialize = "orphaned temporary file")]
+    Orphan,
+    /// Artifact of the download cache.
+    #[strum(serialize = "cached artifact")]
+    CachedArtifact,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reclaimable {
+    /// Kind of the item.
+    pub kind: ReclaimableKind,
+    /// Path of the file or directory.
+    pub path: PathBuf,
+    /// Size, in bytes.
+    pub size: u64,
+}
+
+impl Reclaimable {
+    fn new(kind: ReclaimableKind, path: PathBuf) -> Self {
+        let size = disk_usage(&path);
+        Self { kind, path, size }
+    }
+
+    /// Removes the file or directory.
+    pub fn remove(&self) -> Result<(), Error> {
+        if self.path.is_dir() {
+            remove_dir_all(&self.path)
+                .map_err(|_| Error::RemoveDirectory(self.path.display().to_string()))
+        } else {
+            Ok(remove_file(&self.path)?)
+        }
+    }
+}
+
+/// Returns the size of a file, or of the contents of a directory, in bytes.
+pub(crate) fn disk_usage(path: &Path) -> u64 {
+    let Ok(metadata) = path.symlink_metadata() else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| disk_usage(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Returns the LLVM and GCC versions of an installation that none of its exports refer to.
+fn unused_components(install_path: &Path, manifest: &InstallManifest) -> Vec<PathBuf> {
+    let exported: Vec<PathBuf> = manifest
+        .exports
+        .iter()
+        .filter_map(|export| match export {
+            ExportItem::PathAdd(dir) => Some(PathBuf::from(dir)),
+            ExportItem::EnvSet { value, .. } => Some(PathBuf::from(value)),
+            ExportItem::SourceLine(_) => None,
+        })
+        .collect();
+    let mut components: Vec<PathBuf> = Vec::new();
+    for name in GCC_NAMES.iter().chain(&[CLANG_NAME]) {
+        let dir = install_path.join(name);
+        components.extend(
+            subdirectories(&dir)
+                .into_iter()
+                .map(|version| dir.join(version)),
+        );
+    }
+    components
+        .into_iter()
+        .filter(|component| {
+            // The LLVM libraries may be installed without being exported, e.g. for CI
+            let is_llvm = component.parent() == Some(install_path.join(CLANG_NAME).as_path());
+            let is_current_llvm =
+                is_llvm && component.file_name() == Some(OsStr::new(&manifest.llvm_version));
+            !is_current_llvm && !exported.iter().any(|path| path.starts_with(component))
+        })
+        .collect()
+}
+
+/// Finds the reclaimable items: the LLVM and GCC versions no longer used by the installations
+/// with a manifest, the quarantined toolchains, the orphaned temporary files and, if `include_cache` is `true`, the cached
+/// artifacts not pinned in the cache configuration.
+pub fn reclaimable(
+    toolchains_dir: &Path,
+    cache_dir: &Path,
+    staging_dir: &Path,
+    cache_config: &CacheConfig,
+    include_cache: bool,
+    now: SystemTime,
+) -> Result<Vec<Reclaimable>, Error> {
+    let mut items = Vec::new();
+    for name in subdirectories(toolchains_dir) {
+        let install_path = toolchains_dir.join(name);
+        // Without a manifest, there is no way to tell which versions are used
+        let Ok(manifest) = InstallManifest::load(&install_path) else {
+            continue;
+        };
+        items.extend(
+            unused_components(&install_path, &manifest)
+                .into_iter()
+                .map(|path| Reclaimable::new(ReclaimableKind::UnusedComponent, path)),
+        );
+    }
+    if let Some(rustup_home) = toolchains_dir.parent() {
+        let quarantine_dir = rustup_home.join(QUARANTINE_DIR);
+        items.extend(
+            subdirectories(&quarantine_dir).into_iter().map(|name| {
+                Reclaimable::new(ReclaimableKind::Quarantined, quarantine_dir.join(name))
+            }),
+        );
+    }
+    for issue in find_issues(cache_dir, staging_dir, toolchains_dir, now) {
+        if let Issue::Orphan(path) = issue {
+            items.push(Reclaimable::new(ReclaimableKind::Orphan, path));
+        }
+    }
+    if include_cache {
+        for entry in cache_entries(cache_dir)? {
+            let name = entry.name();
+            // Temporary files of the downloads in progress are left to the self-check
+            if !name.starts_with('.') && !cache_config.is_pinned(&name) {
+                items.push(Reclaimable {
+                    kind: ReclaimableKind::CachedArtifact,
+                    path: entry.path,
+                    size: entry.size,
+                });
+            }
+        }
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cache::CacheConfig,
+        env::ExportItem,
+        manifest::InstallManifest,
+        prune::{reclaimable, ReclaimableKind},
+        toolchain::{gcc::RISCV_GCC, llvm::CLANG_NAME},
+    };
+    use std::{
+        fs::{create_dir_all, write},
+        time::SystemTime,
+    };
+
+    #[test]
+    fn test_reclaimable() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let toolchains_dir = temp_dir.path().join("toolchains");
+        let cache_dir = temp_dir.path().join("cache");
+        let esp = toolchains_dir.join("esp");
+        let old_gcc = esp.join(RISCV_GCC).join("esp-12.2.0_20230208");
+        let gcc = esp.join(RISCV_GCC).join("esp-13.2.0_20230928");
+        let old_llvm = esp.join(CLANG_NAME).join("esp-16.0.4-20231113");
+        let llvm = esp.join(CLANG_NAME).join("esp-17.0.1_20240112");
+        for dir in [&old_gcc, &gcc, &old_llvm, &llvm] {
+            create_dir_all(dir).unwrap();
+        }
+        write(old_gcc.join("gcc"), "12345").unwrap();
+        InstallManifest {
+            llvm_version: "esp-17.0.1_20240112".to_string(),
+            exports: vec![ExportItem::PathAdd(
+                gcc.join(RISCV_GCC).join("bin").display().to_string(),
+            )],
+            ..Default::default()
+        }
+        .save(&esp)
+        .unwrap();
+        let quarantined = temp_dir
+            .path()
+            .join("espup-quarantine")
+            .join("esp-1700000000");
+        create_dir_all(&quarantined).unwrap();
+        // Versions of an installation without manifest are kept
+        create_dir_all(toolchains_dir.join("other").join(CLANG_NAME).join("esp-15")).unwrap();
+        create_dir_all(&cache_dir).unwrap();
+        write(
+            cache_dir.join("0123456789abcdef-llvm-esp-16.tar.xz"),
+            "llvm",
+        )
+        .unwrap();
+        write(cache_dir.join("0123456789abcdef-gcc-13.2.0.tar.xz"), "gcc").unwrap();
+        let cache_config = CacheConfig {
+            pinned: vec!["13.2.0".to_string()],
+            ..Default::default()
+        };
+
+        let now = SystemTime::now();
+        let items = reclaimable(
+            &toolchains_dir,
+            &cache_dir,
+            &cache_dir.join("tmp"),
+            &cache_config,
+            true,
+            now,
+        )
+        .unwrap();
+        let paths: Vec<_> = items.iter().map(|item| item.path.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                old_gcc.clone(),
+                old_llvm,
+                quarantined,
+                cache_dir.join("0123456789abcdef-llvm-esp-16.tar.xz")
+            ]
+        );
+        assert_eq!(items[0].kind, ReclaimableKind::UnusedComponent);
+        assert_eq!(items[0].size, 5);
+        assert_eq!(items[2].kind, ReclaimableKind::Quarantined);
+        assert_eq!(items[3].kind, ReclaimableKind::CachedArtifact);
+
+        let items = reclaimable(
+            &toolchains_dir,
+            &cache_dir,
+            &cache_dir.join("tmp"),
+            &cache_config,
+            false,
+            now,
+        )
+        .unwrap();
+        assert_eq!(items.len(), 3);
+        items[0].remove().unwrap();
+        assert!(!old_gcc.exists());
+    }
+}
@@ -0,0 +1,131 @@
+//! Step state of an installation in progress, recording the installed components so an
+//! interrupted installation, e.g. by Ctrl-C or a CI timeout, continues where it left off.
+
+use crate::{env::ExportItem, error::Error, manifest::InstallRecord};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{read_to_string, remove_file, write},
+    path::{Path, PathBuf},
+};
+
+/// Name of the step state file, stored inside the toolchain directory until the installation
+/// completes.
+pub const STATE_FILE: &str = "espup-state.json";
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct InstallState {
+    /// Versions being installed, a state recorded for other versions is discarded.
+    pub plan: InstallRecord,
+    /// Installed components, with the exports they returned.
+    pub completed: Vec<CompletedStep>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct CompletedStep {
+    /// Name of the component.
+    pub name: String,
+    /// Environment exports required by the component.
+    pub exports: Vec<ExportItem>,
+}
+
+impl InstallState {
+    /// Returns the path of the state of the toolchain installed in the given path.
+    pub fn path(install_path: &Path) -> PathBuf {
+        install_path.join(STATE_FILE)
+    }
+
+    /// Loads the state left by an interrupted installation, `None` if there is none.
+    pub fn load(install_path: &Path) -> Option<Self> {
+        let contents = read_to_string(Self::path(install_path)).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    /// Returns the state to resume the installation of a plan from.
+    ///
+    /// The steps completed by an interrupted installation are kept only if it installed the
+    /// same versions.
+    pub fn resume(install_path: &Path, plan: InstallRecord) -> Self {
+        match Self::load(install_path) {
+            Some(state) if state.plan == plan => state,
+            _ => Self {
+                plan,
+                completed: Vec::new(),
+            },
+        }
+    }
+
+    /// Returns the exports of a component completed by a previous attempt.
+    pub fn completed(&self, name: &str) -> Option<&[ExportItem]> {
+        self.completed
+            .iter()
+            .find(|step| step.name == name)
+            .map(|step| step.exports.as_slice())
+    }
+
+    /// Records an installed component.
+    pub fn complete(&mut self, name: String, exports: Vec<ExportItem>) {
+        self.completed.retain(|step| step.name != name);
+        self.completed.push(CompletedStep { name, exports });
+    }
+
+    /// Saves the state in the toolchain installed in the given path.
+    pub fn save(&self, install_path: &Path) -> Result<(), Error> {
+        let contents = serde_json::to_string_pretty(self).map_err(|_| Error::SerializeJson)?;
+        write(Self::path(install_path), contents)?;
+        Ok(())
+    }
+
+    /// Removes the state once the installation completes.
+    pub fn clear(install_path: &Path) -> Result<(), Error> {
+        let path = Self::path(install_path);
+        if path.exists() {
+            remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        env::ExportItem,
+        manifest::InstallRecord,
+        resume::{InstallState, STATE_FILE},
+    };
+
+    #[test]
+    fn test_resume() {
+        let install_path = tempfile::TempDir::new().unwrap();
+        let plan = InstallRecord {
+            xtensa_rust_version: Some("1.77.0.0".to_string()),
+            llvm_version: "esp-16.0.0-20230516".to_string(),
+            ..Default::default()
+        };
+        assert!(InstallState::resume(install_path.path(), plan.clone())
+            .completed
+            .is_empty());
+
+        let mut state = InstallState::resume(install_path.path(), plan.clone());
+        let exports = vec![ExportItem::PathAdd("/llvm/lib".to_string())];
+        state.complete("LLVM".to_string(), exports.clone());
+        state.save(install_path.path()).unwrap();
+        assert!(install_path.path().join(STATE_FILE).exists());
+
+        let resumed = InstallState::resume(install_path.path(), plan.clone());
+        assert_eq!(resumed.completed("LLVM"), Some(exports.as_slice()));
+        assert_eq!(resumed.completed("GCC"), None);
+
+        // The steps of other versions are not reused
+        let other = InstallRecord {
+            llvm_version: "esp-17.0.1_20240419".to_string(),
+            ..plan
+        };
+        assert_eq!(
+            InstallState::resume(install_path.path(), other).completed("LLVM"),
+            None
+        );
+
+        InstallState::clear(install_path.path()).unwrap();
+        assert!(InstallState::load(install_path.path()).is_none());
+    }
+}
@@ -0,0 +1,201 @@
+//! Standalone install scripts, replaying the resolved steps of an installation.
+
+use crate::{env::ExportItem, platform::Platform};
+use std::path::{Path, PathBuf};
+
+/// Step of an installation, rendered as a command of the script of the host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptStep {
+    /// Comment explaining the following steps.
+    Comment(String),
+    /// Downloads an artifact into the working directory and checks its SHA256 digest, when it is
+    /// published.
+    Fetch {
+        url: String,
+        sha256: Option<String>,
+        file: String,
+    },
+    /// Extracts a downloaded archive into a directory, optionally stripping its `esp/` prefix.
+    Extract {
+        file: String,
+        destination: PathBuf,
+        strip: bool,
+    },
+    /// Runs a command.
+    Run(Vec<String>),
+    /// Environment change written to the export file.
+    Export(ExportItem),
+}
+
+/// Renders the steps as a script for the platform, which writes the exports to the export file.
+pub fn render(steps: &[ScriptStep], platform: &dyn Platform, export_file: &Path) -> String {
+    let mut lines = vec![platform.script_header()];
+    let mut exports = Vec::new();
+    for step in steps {
+        match step {
+            ScriptStep::Comment(comment) => lines.push(format!("\n# {comment}")),
+            ScriptStep::Fetch { url, sha256, file } => {
+                lines.push(platform.script_fetch(url, sha256.as_deref(), file))
+            }
+            ScriptStep::Extract {
+                file,
+                destination,
+                strip,
+            } => lines.push(platform.script_extract(
+                file,
+                &destination.display().to_string(),
+                *strip,
+            )),
+            ScriptStep::Run(args) => lines.push(platform.script_run(args)),
+            ScriptStep::Export(export) => exports.push(export.render(platform)),
+        }
+    }
+    lines.push("\n# Export file".to_string());
+    lines.push(platform.script_write_file(&export_file.display().to_string(), &exports.join("\n")));
+    lines.join("\n") + "\n"
+}
+
+/// Describes the steps, one line each, for the platform.
+pub fn describe(steps: &[ScriptStep], platform: &dyn Platform) -> Vec<String> {
+    steps
+        .iter()
+        .map(|step| match step {
+            ScriptStep::Comment(comment) => format!("{comment}:"),
+            ScriptStep::Fetch { url, sha256, .. } => {
+                format!(
+                    "  Download '{url}' (SHA256: {})",
+                    sha256.as_deref().unwrap_or("unknown")
+                )
+            }
+            ScriptStep::Extract {
+                file, destination, ..
+            } => format!("  Extract '{file}' into '{}'", destination.display()),
+            ScriptStep::Run(args) => format!("  Run '{}'", args.join(" ")),
+            ScriptStep::Export(export) => format!("  Export '{}'", export.render(platform)),
+        })
+        .collect()
+}
+
+/// Returns the URL and SHA256 digest, if published, of every artifact fetched by the steps, once
+/// each.
+pub fn artifacts(steps: &[ScriptStep]) -> Vec<(&str, Option<&str>)> {
+    let mut artifacts: Vec<(&str, Option<&str>)> = Vec::new();
+    for step in steps {
+        if let ScriptStep::Fetch { url, sha256, .. } = step {
+            if !artifacts.iter().any(|(known, _)| known == url) {
+                artifacts.push((url, sha256.as_deref()));
+            }
+        }
+    }
+    artifacts
+}
+
+/// Formats an artifact as a `<url> <sha256> <size>` line, with `-` when the digest or the size
+/// is not known.
+pub fn artifact_line(url: &str, sha256: Option<&str>, size: Option<u64>) -> String {
+    let size = size.map_or_else(|| "-".to_string(), |size| size.to_string());
+    format!("{url} {} {size}", sha256.unwrap_or("-"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        env::ExportItem,
+        host_triple::HostTriple,
+        script::{artifact_line, artifacts, describe, render, ScriptStep},
+    };
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_render() {
+        let steps = vec![
+            ScriptStep::Comment("GCC (riscv32-esp-elf)".to_string()),
+            ScriptStep::Fetch {
+                url: "https://example.com/gcc.tar.xz".to_string(),
+                sha256: Some("ab".repeat(32)),
+                file: "gcc.tar.xz".to_string(),
+            },
+            ScriptStep::Extract {
+                file: "gcc.tar.xz".to_string(),
+                destination: PathBuf::from("/esp/gcc"),
+                strip: false,
+            },
+            ScriptStep::Run(vec!["rustup".to_string(), "--prefix=''".to_string()]),
+            ScriptStep::Export(ExportItem::PathAdd("/esp/gcc/bin".to_string())),
+        ];
+        let script = render(
+            &steps,
+            HostTriple::X86_64UnknownLinuxGnu.platform(),
+            Path::new("/home/esp/export-esp.sh"),
+        );
+        assert!(script.starts_with("#!/usr/bin/env bash\n"));
+        assert!(script.contains("\n# GCC (riscv32-esp-elf)\n"));
+        assert!(script.contains("curl -fsSL -o 'gcc.tar.xz' 'https://example.com/gcc.tar.xz'\n"));
+        assert!(script.contains(&format!(
+            "echo '{}  gcc.tar.xz' | sha256sum -c -\n",
+            "ab".repeat(32)
+        )));
+        assert!(script.contains("mkdir -p '/esp/gcc'\ntar -xf 'gcc.tar.xz' -C '/esp/gcc'\n"));
+        assert!(script.contains("rustup '--prefix='\\'''\\'''\n"));
+        assert!(script.ends_with(
+            "cat > '/home/esp/export-esp.sh' <<'EOF'\nexport PATH=\"/esp/gcc/bin:$PATH\"\nEOF\n"
+        ));
+
+        let script = render(
+            &steps,
+            HostTriple::X86_64PcWindowsMsvc.platform(),
+            Path::new("C:/esp/export-esp.ps1"),
+        );
+        assert!(script.contains("(Get-FileHash -Algorithm SHA256 'gcc.tar.xz').Hash"));
+        assert!(script.contains(
+            "New-Item -ItemType Directory -Force -Path '/esp/gcc' | Out-Null\ntar -xf 'gcc.tar.xz' -C '/esp/gcc'\n"
+        ));
+        assert!(script.contains("$Env:PATH = \"/esp/gcc/bin;\" + $Env:PATH"));
+
+        let description = describe(&steps, HostTriple::X86_64UnknownLinuxGnu.platform());
+        assert_eq!(description[0], "GCC (riscv32-esp-elf):");
+        assert_eq!(
+            description[1],
+            format!(
+                "  Download 'https://example.com/gcc.tar.xz' (SHA256: {})",
+                "ab".repeat(32)
+            )
+        );
+        assert_eq!(description[2], "  Extract 'gcc.tar.xz' into '/esp/gcc'");
+        assert_eq!(
+            description[4],
+            "  Export 'export PATH=\"/esp/gcc/bin:$PATH\"'"
+        );
+
+        let mut steps = steps;
+        steps.push(steps[1].clone());
+        let artifacts = artifacts(&steps);
+        assert_eq!(artifacts.len(), 1);
+        assert_eq!(
+            artifact_line(artifacts[0].0, artifacts[0].1, Some(1024)),
+            format!("https://example.com/gcc.tar.xz {} 1024", "ab".repeat(32))
+        );
+        assert_eq!(
+            artifact_line("https://example.com/rust.zip", Some("cd"), None),
+            "https://example.com/rust.zip cd -"
+        );
+        assert_eq!(
+            artifact_line("https://example.com/rust.zip", None, Some(1024)),
+            "https://example.com/rust.zip - 1024"
+        );
+
+        let steps = vec![ScriptStep::Fetch {
+            url: "https://example.com/rust.zip".to_string(),
+            sha256: None,
+            file: "rust.zip".to_string(),
+        }];
+        let platform = HostTriple::X86_64UnknownLinuxGnu.platform();
+        assert_eq!(
+            describe(&steps, platform),
+            vec!["  Download 'https://example.com/rust.zip' (SHA256: unknown)"]
+        );
+        let script = render(&steps, platform, Path::new("/home/esp/export-esp.sh"));
+        assert!(script.contains("# The SHA256 digest of 'rust.zip' is not published\n"));
+        assert!(!script.contains("sha256sum"));
+    }
+}
@@ -0,0 +1,165 @@
+//! Segmented downloads, fetching ranges of large artifacts over parallel connections.
+
+use crate::{
+    credentials::authorize,
+    emoji,
+    error::Error,
+    http::{trace_request, trace_response},
+    mirror::mirrored_url,
+    throttle::throttle,
+    timeout::read,
+};
+use log::debug;
+use reqwest::{header, StatusCode};
+use std::{
+    collections::VecDeque,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+/// Artifacts smaller than this are downloaded over a single connection.
+const MIN_SEGMENTED_SIZE: u64 = 32 * 1024 * 1024;
+
+static SEGMENTS: AtomicUsize = AtomicUsize::new(1);
+
+/// Sets the number of connections used to download each large artifact, `1` disables the
+/// segmented downloads.
+pub fn set_segments(segments: usize) {
+    SEGMENTS.store(segments.max(1), Ordering::Relaxed);
+}
+
+/// Returns true if the large artifacts are downloaded over parallel connections.
+pub fn is_enabled() -> bool {
+    SEGMENTS.load(Ordering::Relaxed) > 1
+}
+
+/// Splits `len` bytes in up to `segments` contiguous, inclusive, byte ranges.
+fn segment_ranges(len: u64, segments: usize) -> Vec<(u64, u64)> {
+    if len == 0 {
+        return Vec::new();
+    }
+    let segments = (segments as u64).clamp(1, len);
+    let size = (len + segments - 1) / segments;
+    (0..segments)
+        .map(|i| (i * size, ((i + 1) * size).min(len) - 1))
+        .filter(|(start, end)| start <= end)
+        .collect()
+}
+
+/// Returns the size of an artifact, if the server supports range requests.
+async fn ranged_size(client: &reqwest::Client, url: &str) -> Result<Option<u64>, Error> {
+    let request = authorize(client.head(url), url).build()?;
+    trace_request(request.method(), url, request.headers());
+    let start = Instant::now();
+    let resp = read(url, client.execute(request)).await?;
+    trace_response(url, resp.status(), resp.headers(), start.elapsed());
+    let accepts_ranges = resp
+        .headers()
+        .get(header::ACCEPT_RANGES)
+        .is_some_and(|value| value == "bytes");
+    if !resp.status().is_success() || !accepts_ranges {
+        return Ok(None);
+    }
+    Ok(resp
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse().ok()))
+}
+
+/// Downloads an inclusive byte range of an artifact.
+async fn fetch_range(
+    client: reqwest::Client,
+    url: String,
+    start: u64,
+    end: u64,
+) -> Result<Vec<u8>, Error> {
+    let request = authorize(client.get(&url), &url)
+        .header(header::RANGE, format!("bytes={start}-{end}"))
+        .build()?;
+    trace_request(request.method(), &url, request.headers());
+    let started = Instant::now();
+    let mut resp = read(&url, client.execute(request)).await?;
+    trace_response(&url, resp.status(), resp.headers(), started.elapsed());
+    let expected = end - start + 1;
+    if resp.status() != StatusCode::PARTIAL_CONTENT {
+        return Err(Error::IncompleteDownload(url, 0, expected));
+    }
+    let mut bytes = Vec::with_capacity(expected as usize);
+    while let Some(chunk) = read(&url, resp.chunk()).await? {
+        throttle(chunk.len()).await;
+        bytes.extend_from_slice(&chunk);
+    }
+    if bytes.len() as u64 != expected {
+        return Err(Error::IncompleteDownload(url, bytes.len() as u64, expected));
+    }
+    Ok(bytes)
+}
+
+/// Downloads a large artifact over parallel connections, each fetching a range of it.
+///
+/// Returns `None` when segmented downloads are disabled, the artifact is small or the server does
+/// not support range requests, so it is downloaded over a single connection instead.
+pub async fn download(client: &reqwest::Client, url: &str) -> Result<Option<Vec<u8>>, Error> {
+    let segments = SEGMENTS.load(Ordering::Relaxed);
+    if segments <= 1 {
+        return Ok(None);
+    }
+    let source_url = mirrored_url(url);
+    let Some(len) = ranged_size(client, &source_url).await? else {
+        return Ok(None);
+    };
+    if len < MIN_SEGMENTED_SIZE {
+        return Ok(None);
+    }
+    debug!(
+        "{} Downloading '{}' in {} segments",
+        emoji::DEBUG,
+        source_url,
+        segments
+    );
+    let mut tasks: VecDeque<_> = segment_ranges(len, segments)
+        .into_iter()
+        .map(|(start, end)| {
+            tokio::spawn(fetch_range(client.clone(), source_url.clone(), start, end))
+        })
+        .collect();
+    let mut bytes = Vec::with_capacity(len as usize);
+    while let Some(task) = tasks.pop_front() {
+        let result = task.await.unwrap_or_else(|_| {
+            Err(Error::IncompleteDownload(
+                source_url.clone(),
+                bytes.len() as u64,
+                len,
+            ))
+        });
+        match result {
+            Ok(segment) => bytes.extend_from_slice(&segment),
+            Err(e) => {
+                // The artifact is downloaded again over a single connection
+                tasks.iter().for_each(|task| task.abort());
+                return Err(e);
+            }
+        }
+    }
+    if bytes.len() as u64 != len {
+        return Err(Error::IncompleteDownload(
+            source_url,
+            bytes.len() as u64,
+            len,
+        ));
+    }
+    Ok(Some(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::segmented::segment_ranges;
+
+    #[test]
+    fn test_segment_ranges() {
+        assert_eq!(segment_ranges(10, 3), vec![(0, 3), (4, 7), (8, 9)]);
+        assert_eq!(segment_ranges(4, 8), vec![(0, 0), (1, 1), (2, 2), (3, 3)]);
+        assert_eq!(segment_ranges(100, 1), vec![(0, 99)]);
+        assert_eq!(segment_ranges(0, 4), vec![]);
+    }
+}
@@ -0,0 +1,209 @@
+//! Integrity self-check of the state of espup, repairing what interrupted runs left behind before
+//! it compounds across runs.
+
+use crate::{
+    github::{CachedResponse, API_CACHE_DIR},
+    manifest::{InstallManifest, MANIFEST_FILE},
+};
+use std::{
+    fmt,
+    fs::{read_dir, read_to_string, remove_dir_all, remove_file},
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// Age after which a temporary file or directory is considered orphaned, as a newer one may still
+/// be used by a concurrent run.
+const ORPHAN_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+/// Prefixes of the temporary files and directories created while downloading and installing.
+const TEMP_PREFIXES: [&str; 2] = [".tmp", ".xtensa-rust-"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    /// Temporary file or directory left behind by an interrupted run.
+    Orphan(PathBuf),
+    /// Empty artifact in the download cache, left behind by an interrupted download.
+    EmptyArtifact(PathBuf),
+    /// Cached GitHub API response that can not be parsed.
+    CorruptResponse(PathBuf),
+    /// Installation manifest that can not be parsed, only repaired by reinstalling the toolchain.
+    CorruptManifest(PathBuf),
+}
+
+impl Issue {
+    /// Returns true if the issue is repaired automatically.
+    pub fn is_repairable(&self) -> bool {
+        !matches!(self, Self::CorruptManifest(_))
+    }
+
+    /// Repairs the issue, removing the offending file or directory.
+    pub fn repair(&self) -> io::Result<()> {
+        match self {
+            Self::Orphan(path) if path.is_dir() => remove_dir_all(path),
+            Self::Orphan(path) | Self::EmptyArtifact(path) | Self::CorruptResponse(path) => {
+                remove_file(path)
+            }
+            Self::CorruptManifest(_) => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Orphan(path) => write!(f, "orphaned temporary file '{}'", path.display()),
+            Self::EmptyArtifact(path) => write!(f, "empty cached artifact '{}'", path.display()),
+            Self::CorruptResponse(path) => {
+                write!(f, "corrupted GitHub API response '{}'", path.display())
+            }
+            Self::CorruptManifest(path) => write!(
+                f,
+                "corrupted installation manifest '{}', reinstall the toolchain to repair it",
+                path.display()
+            ),
+        }
+    }
+}
+
+/// Returns true if the entry was last modified before `ORPHAN_AGE`.
+fn is_stale(path: &Path, now: SystemTime) -> bool {
+    path.symlink_metadata()
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|modified| {
+            now.duration_since(modified)
+                .is_ok_and(|age| age >= ORPHAN_AGE)
+        })
+}
+
+/// Returns the entries of a directory, empty if it does not exist.
+fn entries(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Returns true if the entry is a temporary file or directory.
+fn is_temporary(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| TEMP_PREFIXES.iter().any(|prefix| name.starts_with(prefix)))
+}
+
+/// Finds the issues of the download cache, the staging directory and the installed toolchains.
+pub fn find_issues(
+    cache_dir: &Path,
+    staging_dir: &Path,
+    toolchains_dir: &Path,
+    now: SystemTime,
+) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let api_dir = cache_dir.join(API_CACHE_DIR);
+    let mut dirs = vec![cache_dir, staging_dir, api_dir.as_path(), toolchains_dir];
+    // The staging directory may be configured as the cache directory itself
+    dirs.dedup();
+    for dir in dirs {
+        issues.extend(
+            entries(dir)
+                .into_iter()
+                .filter(|path| is_temporary(path) && is_stale(path, now))
+                .map(Issue::Orphan),
+        );
+    }
+    issues.extend(
+        entries(cache_dir)
+            .into_iter()
+            .filter(|path| !is_temporary(path))
+            .filter(|path| path.metadata().is_ok_and(|m| m.is_file() && m.len() == 0))
+            .map(Issue::EmptyArtifact),
+    );
+    issues.extend(
+        entries(&api_dir)
+            .into_iter()
+            .filter(|path| !is_temporary(path) && path.is_file())
+            .filter(|path| {
+                read_to_string(path).map_or(true, |contents| {
+                    serde_json::from_str::<CachedResponse>(&contents).is_err()
+                })
+            })
+            .map(Issue::CorruptResponse),
+    );
+    issues.extend(
+        entries(toolchains_dir)
+            .into_iter()
+            .filter(|path| {
+                path.join(MANIFEST_FILE).is_file() && InstallManifest::load(path).is_err()
+            })
+            .map(|path| Issue::CorruptManifest(path.join(MANIFEST_FILE))),
+    );
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        github::API_CACHE_DIR,
+        manifest::MANIFEST_FILE,
+        selfcheck::{find_issues, Issue, ORPHAN_AGE},
+    };
+    use std::{
+        fs::{create_dir_all, write},
+        time::SystemTime,
+    };
+
+    #[test]
+    fn test_find_issues() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let cache_dir = temp_dir.path().join("cache");
+        let staging_dir = cache_dir.join("tmp");
+        let toolchains_dir = temp_dir.path().join("toolchains");
+        create_dir_all(staging_dir.join(".tmpAbC123")).unwrap();
+        create_dir_all(cache_dir.join(API_CACHE_DIR)).unwrap();
+        create_dir_all(toolchains_dir.join(".xtensa-rust-XyZ")).unwrap();
+        create_dir_all(toolchains_dir.join("esp")).unwrap();
+        write(cache_dir.join("0123456789abcdef-llvm.tar.xz"), "").unwrap();
+        write(cache_dir.join("0123456789abcdef-gcc.tar.xz"), "gcc").unwrap();
+        write(
+            cache_dir.join(API_CACHE_DIR).join("truncated.json"),
+            "{\"etag\":",
+        )
+        .unwrap();
+        write(
+            cache_dir.join(API_CACHE_DIR).join("valid.json"),
+            r#"{"etag":"\"abc\"","body":"[]"}"#,
+        )
+        .unwrap();
+        write(toolchains_dir.join("esp").join(MANIFEST_FILE), "{").unwrap();
+
+        // Temporary entries are only orphaned once old enough
+        let now = SystemTime::now();
+        assert_eq!(
+            find_issues(&cache_dir, &staging_dir, &toolchains_dir, now),
+            vec![
+                Issue::EmptyArtifact(cache_dir.join("0123456789abcdef-llvm.tar.xz")),
+                Issue::CorruptResponse(cache_dir.join(API_CACHE_DIR).join("truncated.json")),
+                Issue::CorruptManifest(toolchains_dir.join("esp").join(MANIFEST_FILE)),
+            ]
+        );
+        let issues = find_issues(&cache_dir, &staging_dir, &toolchains_dir, now + ORPHAN_AGE);
+        assert_eq!(issues[0], Issue::Orphan(staging_dir.join(".tmpAbC123")));
+        assert_eq!(
+            issues[1],
+            Issue::Orphan(toolchains_dir.join(".xtensa-rust-XyZ"))
+        );
+        for issue in issues.iter().filter(|issue| issue.is_repairable()) {
+            issue.repair().unwrap();
+        }
+        assert_eq!(
+            find_issues(&cache_dir, &staging_dir, &toolchains_dir, now + ORPHAN_AGE),
+            vec![Issue::CorruptManifest(
+                toolchains_dir.join("esp").join(MANIFEST_FILE)
+            )]
+        );
+    }
+}
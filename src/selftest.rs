@@ -0,0 +1,218 @@
+//! End-to-end self-test of an installation, reported in the JUnit XML format so CI images can be
+//! validated right after they are built.
+
+use crate::{
+    doctor::Diagnosis,
+    locale::{command, decode_output},
+    targets::Target,
+    verify::ChipReport,
+};
+use std::{
+    fmt::Write,
+    fs::write,
+    time::{Duration, Instant},
+};
+use tempfile::TempDir;
+
+/// Manifest of the crate compiled by the smoke tests.
+const SMOKE_TEST_MANIFEST: &str = r#"[package]
+name = "espup-smoke-test"
+version = "0.0.0"
+edition = "2021"
+
+[lib]
+path = "lib.rs"
+"#;
+/// Source of the crate compiled by the smoke tests.
+const SMOKE_TEST_SOURCE: &str =
+    "#![no_std]\n\npub fn add(a: u32, b: u32) -> u32 {\n    a.wrapping_add(b)\n}\n";
+/// Number of lines of the compiler output kept in a failure.
+const FAILURE_LINES: usize = 20;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestCase {
+    /// Suite of the test case: `resolve`, `files`, `compile` or `exports`.
+    pub suite: &'static str,
+    /// Name of the test case.
+    pub name: String,
+    /// Failure message, `None` if the test case passed.
+    pub failure: Option<String>,
+    /// Time taken by the test case.
+    pub duration: Duration,
+}
+
+impl TestCase {
+    /// Runs a test case, timing it.
+    pub fn run(
+        suite: &'static str,
+        name: impl Into<String>,
+        test: impl FnOnce() -> Result<(), String>,
+    ) -> Self {
+        let start = Instant::now();
+        let failure = test().err();
+        Self {
+            suite,
+            name: name.into(),
+            failure,
+            duration: start.elapsed(),
+        }
+    }
+
+    /// Returns the test case of a diagnostic check.
+    pub fn from_diagnosis(suite: &'static str, diagnosis: &Diagnosis) -> Self {
+        Self {
+            suite,
+            name: diagnosis.check.to_string(),
+            failure: (!diagnosis.passed).then(|| match &diagnosis.fix {
+                Some(fix) => format!("{}. {fix}", diagnosis.detail),
+                None => diagnosis.detail.clone(),
+            }),
+            duration: Duration::ZERO,
+        }
+    }
+
+    /// Returns the test case of the verification of the files installed for a chip.
+    pub fn from_report(suite: &'static str, report: &ChipReport) -> Self {
+        Self {
+            suite,
+            name: report.target.to_string(),
+            failure: (!report.missing.is_empty())
+                .then(|| format!("Missing: {}", report.missing.join(", "))),
+            duration: Duration::ZERO,
+        }
+    }
+}
+
+/// Returns the last lines of a command output.
+fn tail(output: &str) -> String {
+    let lines: Vec<&str> = output.lines().collect();
+    lines[lines.len().saturating_sub(FAILURE_LINES)..].join("\n")
+}
+
+/// Checks that rustup resolves a toolchain.
+pub fn resolve_toolchain(toolchain: &str) -> Result<(), String> {
+    let output = command("rustc")
+        .arg(format!("+{toolchain}"))
+        .arg("--version")
+        .output()
+        .map_err(|e| format!("Failed to run rustc: {e}"))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(tail(&decode_output(&output.stderr)))
+    }
+}
+
+/// Compiles a `no_std` crate for a chip, building `core` from source for the Xtensa targets.
+pub fn compile_smoke_test(toolchain: &str, target: &Target) -> Result<(), String> {
+    let project_dir = TempDir::new().map_err(|e| e.to_string())?;
+    write(project_dir.path().join("Cargo.toml"), SMOKE_TEST_MANIFEST)
+        .and_then(|_| write(project_dir.path().join("lib.rs"), SMOKE_TEST_SOURCE))
+        .map_err(|e| e.to_string())?;
+    let mut cargo = command("cargo");
+    cargo
+        .arg(format!("+{toolchain}"))
+        .args(["build", "--release", "--target", target.rust_target()])
+        .current_dir(project_dir.path())
+        .env("CARGO_TARGET_DIR", project_dir.path().join("target"));
+    if target.is_xtensa() {
+        cargo.arg("-Zbuild-std=core");
+    }
+    let output = cargo
+        .output()
+        .map_err(|e| format!("Failed to run cargo: {e}"))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(tail(&decode_output(&output.stderr)))
+    }
+}
+
+/// Escapes the special characters of XML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Renders the test cases as a JUnit XML report, with a test suite per suite of test cases.
+pub fn junit_report(cases: &[TestCase]) -> String {
+    let mut suites: Vec<&str> = cases.iter().map(|case| case.suite).collect();
+    suites.dedup();
+    let failures = cases.iter().filter(|case| case.failure.is_some()).count();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<testsuites name=\"espup\" tests=\"{}\" failures=\"{failures}\">",
+        cases.len()
+    );
+    for suite in suites {
+        let suite_cases: Vec<&TestCase> = cases.iter().filter(|case| case.suite == suite).collect();
+        let _ = writeln!(
+            xml,
+            "  <testsuite name=\"{suite}\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">",
+            suite_cases.len(),
+            suite_cases
+                .iter()
+                .filter(|case| case.failure.is_some())
+                .count(),
+            suite_cases
+                .iter()
+                .map(|case| case.duration.as_secs_f64())
+                .sum::<f64>()
+        );
+        for case in suite_cases {
+            let _ = write!(
+                xml,
+                "    <testcase classname=\"espup.{suite}\" name=\"{}\" time=\"{:.3}\"",
+                escape(&case.name),
+                case.duration.as_secs_f64()
+            );
+            match &case.failure {
+                Some(failure) => {
+                    let _ = writeln!(
+                        xml,
+                        ">\n      <failure message=\"{}\">{}</failure>\n    </testcase>",
+                        escape(failure.lines().next().unwrap_or_default()),
+                        escape(failure)
+                    );
+                }
+                None => xml.push_str("/>\n"),
+            }
+        }
+        xml.push_str("  </testsuite>\n");
+    }
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::selftest::{junit_report, TestCase};
+    use std::time::Duration;
+
+    #[test]
+    fn test_junit_report() {
+        let cases = vec![
+            TestCase::run("resolve", "esp", || Ok(())),
+            TestCase {
+                suite: "compile",
+                name: "esp32".to_string(),
+                failure: Some("error: <core> not found\nhelp: install rust-src".to_string()),
+                duration: Duration::from_millis(1500),
+            },
+            TestCase::run("compile", "esp32c3", || Ok(())),
+        ];
+        let report = junit_report(&cases);
+        assert!(report.starts_with("<?xml"));
+        assert!(report.contains("<testsuites name=\"espup\" tests=\"3\" failures=\"1\">"));
+        assert!(report.contains("<testsuite name=\"compile\" tests=\"2\" failures=\"1\""));
+        assert!(report.contains("<testcase classname=\"espup.resolve\" name=\"esp\""));
+        assert!(report.contains(
+            "<failure message=\"error: &lt;core&gt; not found\">error: &lt;core&gt; not found\nhelp: install rust-src</failure>"
+        ));
+        assert!(report.contains("name=\"esp32\" time=\"1.500\""));
+        assert!(report.ends_with("</testsuites>\n"));
+    }
+}
@@ -0,0 +1,244 @@
+//! Self-update of espup from the binaries published with its GitHub releases.
+
+use crate::{
+    checksum::sha256,
+    emoji,
+    error::Error,
+    host_triple::HostTriple,
+    locale::{command, decode_output},
+    proxy::client,
+    toolchain::{download_artifact, github_query},
+};
+use log::debug;
+use std::{
+    env::consts::EXE_SUFFIX,
+    fs::{remove_file, rename},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// GitHub API URL of the latest espup release.
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/esp-rs/espup/releases/latest";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelfRelease {
+    /// Version of the release, without the `v` prefix of its tag.
+    pub version: String,
+    /// Download URL of the binary for the host.
+    pub url: String,
+    /// SHA256 digest of the binary, if GitHub published it.
+    pub sha256: Option<String>,
+}
+
+impl SelfRelease {
+    /// Parses a GitHub release, `None` if it does not publish a binary for the host.
+    pub fn parse(json: &serde_json::Value, host_triple: &HostTriple) -> Option<Self> {
+        let version = json["tag_name"]
+            .as_str()?
+            .trim_start_matches('v')
+            .to_string();
+        let binary = format!("espup-{host_triple}{EXE_SUFFIX}");
+        let asset = json["assets"]
+            .as_array()?
+            .iter()
+            .find(|asset| asset["name"].as_str() == Some(binary.as_str()))?;
+        Some(Self {
+            version,
+            url: asset["browser_download_url"].as_str()?.to_string(),
+            sha256: asset["digest"]
+                .as_str()
+                .and_then(|digest| digest.strip_prefix("sha256:"))
+                .map(str::to_string),
+        })
+    }
+
+    /// Queries the latest release for the host.
+    pub fn latest(host_triple: &HostTriple) -> Result<Self, Error> {
+        let json = github_query(LATEST_RELEASE_URL)?;
+        Self::parse(&json, host_triple).ok_or_else(|| {
+            Error::SelfUpdate(format!(
+                "the latest release has no binary for '{host_triple}'"
+            ))
+        })
+    }
+
+    /// Downloads the binary, verifying it against its published digest.
+    pub async fn download(&self) -> Result<Vec<u8>, Error> {
+        // Fail before downloading anything if the binary can not be verified
+        self.expected_digest()?;
+        let bytes = download_artifact(&client()?, &self.url)
+            .await?
+            .ok_or_else(|| Error::ArtifactNotFound(self.url.clone()))?;
+        self.verify(&bytes)?;
+        Ok(bytes)
+    }
+
+    /// Returns the published digest of the binary, an error if GitHub did not publish it.
+    fn expected_digest(&self) -> Result<&str, Error> {
+        self.sha256
+            .as_deref()
+            .ok_or_else(|| Error::MissingChecksum(self.binary_name().to_string()))
+    }
+
+    /// Verifies some bytes against the published digest of the binary.
+    pub fn verify(&self, bytes: &[u8]) -> Result<(), Error> {
+        let expected = self.expected_digest()?;
+        let actual = sha256(bytes);
+        if actual != expected {
+            return Err(Error::ChecksumMismatch(
+                self.binary_name().to_string(),
+                expected.to_string(),
+                actual,
+            ));
+        }
+        debug!("{} Verified checksum of '{}'", emoji::DEBUG, self.url);
+        Ok(())
+    }
+
+    /// Returns the file name of the binary.
+    fn binary_name(&self) -> &str {
+        self.url.rsplit('/').next().unwrap_or(&self.url)
+    }
+}
+
+/// Returns true if a version is newer than the current one, comparing their numeric components.
+pub fn is_newer(version: &str, current: &str) -> bool {
+    let parse = |version: &str| {
+        version
+            .split('.')
+            .map(|component| component.parse::<u64>().ok())
+            .collect::<Option<Vec<u64>>>()
+    };
+    match (parse(version), parse(current)) {
+        (Some(version), Some(current)) => version > current,
+        _ => false,
+    }
+}
+
+/// Returns the path where the replaced binary is moved on Windows, which can not overwrite a
+/// running executable.
+pub fn old_binary_path(current_exe: &Path) -> PathBuf {
+    current_exe.with_extension("old")
+}
+
+/// Checks that a downloaded binary runs and reports the expected version.
+fn verify_binary(path: &Path, version: &str) -> Result<(), Error> {
+    let output = command(path)
+        .arg("--version")
+        .output()
+        .map_err(|e| Error::SelfUpdate(format!("the downloaded binary does not run: {e}")))?;
+    let stdout = decode_output(&output.stdout);
+    if !output.status.success() || !stdout.split_whitespace().any(|word| word == version) {
+        return Err(Error::SelfUpdate(format!(
+            "the downloaded binary does not report version '{version}'"
+        )));
+    }
+    debug!("{} Verified downloaded binary '{}'", emoji::DEBUG, version);
+    Ok(())
+}
+
+/// Replaces the binary of espup with a downloaded one, after verifying it against the published
+/// digest of the release and checking that it reports the expected version.
+///
+/// The binary is written next to the current one and renamed over it, so an interrupted update
+/// leaves the current binary untouched.
+pub fn replace_binary(
+    current_exe: &Path,
+    release: &SelfRelease,
+    bytes: &[u8],
+) -> Result<(), Error> {
+    // Nothing is executed before it is verified
+    release.verify(bytes)?;
+    let dir = current_exe
+        .parent()
+        .ok_or_else(|| Error::SelfUpdate(format!("'{}' has no parent", current_exe.display())))?;
+    let mut file = tempfile::Builder::new()
+        .prefix(".espup-")
+        .suffix(EXE_SUFFIX)
+        .tempfile_in(dir)?;
+    file.write_all(bytes)?;
+    #[cfg(unix)]
+    {
+        use std::{fs::Permissions, os::unix::fs::PermissionsExt};
+        file.as_file()
+            .set_permissions(Permissions::from_mode(0o755))?;
+    }
+    // The file must be closed before running it
+    let path = file.into_temp_path();
+    verify_binary(&path, &release.version)?;
+    let old_binary = cfg!(windows).then(|| old_binary_path(current_exe));
+    if let Some(old_binary) = &old_binary {
+        let _ = remove_file(old_binary);
+        rename(current_exe, old_binary)?;
+    }
+    if let Err(e) = path.persist(current_exe) {
+        // The current binary is put back, so a failed update leaves a working installation
+        if let Some(old_binary) = &old_binary {
+            rename(old_binary, current_exe)?;
+        }
+        return Err(Error::SelfUpdate(e.to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        checksum::sha256,
+        host_triple::HostTriple,
+        selfupdate::{is_newer, replace_binary, SelfRelease},
+    };
+    use serde_json::json;
+    use std::env::consts::EXE_SUFFIX;
+
+    #[test]
+    fn test_self_release() {
+        let binary = format!("espup-x86_64-unknown-linux-gnu{EXE_SUFFIX}");
+        let json = json!({
+            "tag_name": "v0.12.0",
+            "assets": [
+                {
+                    "name": "espup-x86_64-unknown-linux-gnu.zip",
+                    "browser_download_url": "https://example.com/espup.zip",
+                },
+                {
+                    "name": binary,
+                    "browser_download_url": "https://example.com/espup",
+                    "digest": "sha256:0123456789abcdef",
+                },
+            ],
+        });
+        assert_eq!(
+            SelfRelease::parse(&json, &HostTriple::X86_64UnknownLinuxGnu),
+            Some(SelfRelease {
+                version: "0.12.0".to_string(),
+                url: "https://example.com/espup".to_string(),
+                sha256: Some("0123456789abcdef".to_string()),
+            })
+        );
+        assert_eq!(
+            SelfRelease::parse(&json, &HostTriple::Aarch64AppleDarwin),
+            None
+        );
+
+        let mut release = SelfRelease {
+            version: "0.12.0".to_string(),
+            url: "https://example.com/espup".to_string(),
+            sha256: Some(sha256(b"espup")),
+        };
+        assert!(release.verify(b"espup").is_ok());
+        assert!(release.verify(b"tampered").is_err());
+        // Binaries without a published digest are never installed
+        release.sha256 = None;
+        assert!(release.verify(b"espup").is_err());
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let current_exe = temp_dir.path().join("espup");
+        assert!(replace_binary(&current_exe, &release, b"espup").is_err());
+        assert!(!current_exe.exists());
+
+        assert!(is_newer("0.12.0", "0.11.0"));
+        assert!(is_newer("0.10.0", "0.9.1"));
+        assert!(!is_newer("0.11.0", "0.11.0"));
+        assert!(!is_newer("0.12.0-rc.1", "0.11.0"));
+    }
+}
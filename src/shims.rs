@@ -0,0 +1,198 @@
+//! Detection of the shim-based version managers, asdf and mise, intercepting `cargo` and `rustc`.
+//!
+//! Their shims run the Rust version selected by the version manager, which may not defer to
+//! rustup, so `+toolchain` overrides such as `cargo +esp` fail after the installation.
+
+use crate::{home::home_dir, locale};
+use std::{
+    env::{self, consts::EXE_SUFFIX},
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+use strum::Display;
+
+/// Tools resolving the `+toolchain` overrides through rustup.
+const TOOLS: [&str; 2] = ["cargo", "rustc"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum VersionManager {
+    #[strum(serialize = "asdf")]
+    Asdf,
+    #[strum(serialize = "mise")]
+    Mise,
+}
+
+impl VersionManager {
+    /// Returns the version manager owning a directory, either its shims directory or the one of
+    /// an installed version, given the data directory of each version manager.
+    fn owning(dir: &Path, data_dirs: &[(Self, PathBuf)]) -> Option<Self> {
+        data_dirs
+            .iter()
+            .find(|(_, data_dir)| {
+                dir == data_dir.join("shims") || dir.starts_with(data_dir.join("installs"))
+            })
+            .map(|(manager, _)| *manager)
+    }
+
+    /// Returns the fix making the shims of the version manager defer to rustup.
+    pub fn fix(&self) -> &'static str {
+        match self {
+            Self::Asdf => "Run 'asdf global rust system', or put the rustup binaries before the asdf shims in the PATH",
+            Self::Mise => "Run 'mise use --global rust@system', or put the rustup binaries before the mise shims in the PATH",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shim {
+    /// Intercepted tool.
+    pub tool: &'static str,
+    /// Version manager providing the shim.
+    pub manager: VersionManager,
+    /// Path of the shim.
+    pub path: PathBuf,
+}
+
+impl Shim {
+    /// Describes the shim failing to resolve a toolchain.
+    pub fn unresolved(&self, toolchain: &str) -> String {
+        format!(
+            "'{}' is intercepted by the {} shim '{}', which does not resolve '+{toolchain}'",
+            self.tool,
+            self.manager,
+            self.path.display()
+        )
+    }
+}
+
+/// Returns the data directories of the version managers, holding their shims and installations.
+pub fn data_dirs() -> Vec<(VersionManager, PathBuf)> {
+    let home = home_dir().unwrap_or_default();
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join(".local").join("share"));
+    let data_dir = |var: &str, default: PathBuf| env::var_os(var).map_or(default, PathBuf::from);
+    vec![
+        (
+            VersionManager::Asdf,
+            data_dir("ASDF_DATA_DIR", home.join(".asdf")),
+        ),
+        (
+            VersionManager::Mise,
+            data_dir("MISE_DATA_DIR", data_home.join("mise")),
+        ),
+        // mise was formerly named rtx
+        (
+            VersionManager::Mise,
+            data_dir("RTX_DATA_DIR", data_home.join("rtx")),
+        ),
+    ]
+}
+
+/// Returns the first executable of a tool in the `PATH`.
+fn find_in_path(path: &OsStr, tool: &str) -> Option<PathBuf> {
+    env::split_paths(path)
+        .map(|dir| dir.join(format!("{tool}{EXE_SUFFIX}")))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Finds the shims of version managers intercepting `cargo` and `rustc` in the `PATH`.
+pub fn find_shims(path: Option<&OsStr>, data_dirs: &[(VersionManager, PathBuf)]) -> Vec<Shim> {
+    let Some(path) = path else {
+        return Vec::new();
+    };
+    TOOLS
+        .iter()
+        .filter_map(|tool| {
+            let found = find_in_path(path, tool)?;
+            let manager = VersionManager::owning(found.parent()?, data_dirs)?;
+            Some(Shim {
+                tool,
+                manager,
+                path: found,
+            })
+        })
+        .collect()
+}
+
+/// Returns true if the tool in the `PATH` resolves a rustup toolchain with `+<toolchain>`.
+pub fn resolves_toolchain(tool: &str, toolchain: &str) -> bool {
+    locale::command(tool)
+        .arg(format!("+{toolchain}"))
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shims::{find_shims, VersionManager};
+    use std::{
+        env::{consts::EXE_SUFFIX, join_paths},
+        fs::{create_dir_all, write},
+    };
+
+    #[test]
+    fn test_find_shims() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let data_dirs = [
+            (VersionManager::Asdf, temp_dir.path().join(".asdf")),
+            (
+                VersionManager::Mise,
+                temp_dir.path().join(".local/share/mise"),
+            ),
+        ];
+        let asdf_shims = temp_dir.path().join(".asdf").join("shims");
+        let mise_shims = temp_dir.path().join(".local/share/mise/shims");
+        let mise_install = temp_dir
+            .path()
+            .join(".local/share/mise/installs/rust/1.76.0/bin");
+        let project_bin = temp_dir.path().join("asdf").join("bin");
+        let cargo_bin = temp_dir.path().join(".cargo").join("bin");
+        for dir in [
+            &asdf_shims,
+            &mise_shims,
+            &mise_install,
+            &project_bin,
+            &cargo_bin,
+        ] {
+            create_dir_all(dir).unwrap();
+        }
+        write(asdf_shims.join(format!("cargo{EXE_SUFFIX}")), "").unwrap();
+        write(mise_shims.join(format!("rustc{EXE_SUFFIX}")), "").unwrap();
+        write(mise_install.join(format!("cargo{EXE_SUFFIX}")), "").unwrap();
+        write(project_bin.join(format!("cargo{EXE_SUFFIX}")), "").unwrap();
+        for tool in ["cargo", "rustc"] {
+            write(cargo_bin.join(format!("{tool}{EXE_SUFFIX}")), "").unwrap();
+        }
+
+        let path = join_paths([&asdf_shims, &mise_shims, &cargo_bin]).unwrap();
+        let shims = find_shims(Some(&path), &data_dirs);
+        assert_eq!(shims.len(), 2);
+        assert_eq!(shims[0].tool, "cargo");
+        assert_eq!(shims[0].manager, VersionManager::Asdf);
+        assert_eq!(shims[1].tool, "rustc");
+        assert_eq!(shims[1].manager, VersionManager::Mise);
+        assert!(shims[0]
+            .unresolved("esp")
+            .ends_with("which does not resolve '+esp'"));
+
+        // The versions installed by mise are also put in the PATH
+        let path = join_paths([&mise_install, &cargo_bin]).unwrap();
+        assert_eq!(
+            find_shims(Some(&path), &data_dirs)[0].manager,
+            VersionManager::Mise
+        );
+        // Directories merely named after a version manager are not theirs
+        let path = join_paths([&project_bin, &cargo_bin]).unwrap();
+        assert!(find_shims(Some(&path), &data_dirs).is_empty());
+
+        // The rustup binaries take precedence over the shims
+        let path = join_paths([&cargo_bin, &asdf_shims]).unwrap();
+        assert!(find_shims(Some(&path), &data_dirs).is_empty());
+        assert!(find_shims(None, &data_dirs).is_empty());
+    }
+}
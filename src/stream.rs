@@ -0,0 +1,231 @@
+//! Streaming extraction, decompressing `tar` archives while they are downloaded.
+
+use crate::{
+    checksum::Sha256Digest, emoji, error::Error, progress::Progress, throttle::throttle,
+    timeout::read,
+};
+use flate2::read::GzDecoder;
+use log::{debug, info};
+use std::{
+    fs::{create_dir_all, read_dir, remove_file, rename},
+    io::{self, Read, Write},
+    path::Path,
+};
+use tar::Archive;
+use tempfile::TempDir;
+use tokio::sync::mpsc::{channel, Receiver};
+use xz2::read::XzDecoder;
+use zstd::stream::read::Decoder as ZstdDecoder;
+
+/// Maximum number of downloaded chunks waiting to be decompressed.
+const CHUNKS_CAPACITY: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// `tar.gz` archive.
+    Gzip,
+    /// `tar.xz` archive.
+    Xz,
+    /// `tar.zst` archive.
+    Zstd,
+}
+
+impl Compression {
+    /// Returns the compression of an archive from its file extension, `None` if it can not be
+    /// extracted while it is downloaded.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "gz" => Some(Self::Gzip),
+            "xz" => Some(Self::Xz),
+            "zst" => Some(Self::Zstd),
+            _ => None,
+        }
+    }
+}
+
+/// Reader over the chunks of a download, received from the async task downloading it.
+struct ChunkReader {
+    receiver: Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.chunk.len() {
+            match self.receiver.blocking_recv() {
+                Some(chunk) => {
+                    self.chunk = chunk;
+                    self.position = 0;
+                }
+                // The download is over
+                None => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.chunk.len() - self.position);
+        buf[..len].copy_from_slice(&self.chunk[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+/// Extracts the archive received as chunks into a directory.
+fn unpack_chunks(
+    receiver: Receiver<Vec<u8>>,
+    compression: Compression,
+    destination: &Path,
+) -> io::Result<()> {
+    let reader = ChunkReader {
+        receiver,
+        chunk: Vec::new(),
+        position: 0,
+    };
+    match compression {
+        Compression::Gzip => Archive::new(GzDecoder::new(reader)).unpack(destination),
+        Compression::Xz => Archive::new(XzDecoder::new(reader)).unpack(destination),
+        Compression::Zstd => Archive::new(ZstdDecoder::new(reader)?).unpack(destination),
+    }
+}
+
+/// Moves the contents of a directory into another one, merging the directories present in both.
+fn move_into(source: &Path, destination: &Path) -> io::Result<()> {
+    create_dir_all(destination)?;
+    for entry in read_dir(source)? {
+        let entry = entry?;
+        let target = destination.join(entry.file_name());
+        match target.symlink_metadata() {
+            Ok(metadata) if metadata.is_dir() && entry.file_type()?.is_dir() => {
+                move_into(&entry.path(), &target)?;
+                continue;
+            }
+            // Renaming over an existing file fails on Windows
+            Ok(metadata) if !metadata.is_dir() => remove_file(&target)?,
+            _ => {}
+        }
+        rename(entry.path(), &target)?;
+    }
+    Ok(())
+}
+
+/// Extracts an archive into a directory while it is downloaded, returning its SHA256 digest.
+///
+/// The archive is extracted into a temporary directory inside the destination and only moved into
+/// it once its digest matches `expected`, if any, so a corrupted download never leaves files
+/// behind. The downloaded bytes are also written to `copy`, e.g. a file of the download cache.
+pub async fn extract(
+    mut resp: reqwest::Response,
+    url: &str,
+    compression: Compression,
+    destination: &Path,
+    expected: Option<&str>,
+    mut copy: Option<&mut (dyn Write + Send)>,
+) -> Result<String, Error> {
+    info!(
+        "{} Downloading and extracting '{}' to '{}'",
+        emoji::DOWNLOAD,
+        url,
+        destination.display()
+    );
+    create_dir_all(destination)
+        .map_err(|_| Error::CreateDirectory(destination.display().to_string()))?;
+    let staging = TempDir::new_in(destination)?;
+    let (sender, receiver) = channel(CHUNKS_CAPACITY);
+    let staging_path = staging.path().to_path_buf();
+    let unpack =
+        tokio::task::spawn_blocking(move || unpack_chunks(receiver, compression, &staging_path));
+
+    let mut digest = Sha256Digest::default();
+    let mut sender = Some(sender);
+    let artifact = url.rsplit('/').next().unwrap_or(url);
+    let mut progress = Progress::new(artifact, resp.content_length(), 0);
+    while let Some(chunk) = read(url, resp.chunk()).await? {
+        throttle(chunk.len()).await;
+        digest.update(&chunk);
+        progress.advance(chunk.len());
+        if let Some(copy) = copy.as_mut() {
+            copy.write_all(&chunk)?;
+        }
+        // The archive may end before the body, e.g. with padding, the rest is only hashed
+        if let Some(active) = &sender {
+            if active.send(chunk.to_vec()).await.is_err() {
+                sender = None;
+            }
+        }
+    }
+    drop(sender);
+    drop(progress);
+    unpack
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))??;
+
+    let actual = digest.finish();
+    if let Some(expected) = expected {
+        if actual != expected {
+            return Err(Error::ChecksumMismatch(
+                artifact.to_string(),
+                expected.to_string(),
+                actual,
+            ));
+        }
+        debug!("{} Verified checksum of '{}'", emoji::DEBUG, url);
+    }
+    move_into(staging.path(), destination)?;
+    Ok(actual)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stream::{move_into, unpack_chunks, Compression};
+    use flate2::{write::GzEncoder, Compression as Level};
+    use std::{fs, io::Write};
+
+    #[test]
+    fn test_unpack_chunks() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let contents = b"#!/bin/sh\n";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "gcc/bin/gcc", &contents[..])
+            .unwrap();
+        let tar = builder.into_inner().unwrap();
+        let mut gzip = GzEncoder::new(Vec::new(), Level::default());
+        gzip.write_all(&tar).unwrap();
+        let archives = [
+            (Compression::Gzip, gzip.finish().unwrap()),
+            (
+                Compression::Zstd,
+                zstd::encode_all(tar.as_slice(), 0).unwrap(),
+            ),
+        ];
+
+        let staging = tempfile::TempDir::new().unwrap();
+        for (compression, archive) in archives {
+            let (sender, receiver) = tokio::sync::mpsc::channel(archive.len());
+            for chunk in archive.chunks(7) {
+                sender.blocking_send(chunk.to_vec()).unwrap();
+            }
+            drop(sender);
+            unpack_chunks(receiver, compression, staging.path()).unwrap();
+            assert_eq!(
+                fs::read(staging.path().join("gcc/bin/gcc")).unwrap(),
+                contents
+            );
+        }
+
+        // Existing directories are merged and existing files replaced
+        let destination = tempfile::TempDir::new().unwrap();
+        fs::create_dir_all(destination.path().join("gcc/bin")).unwrap();
+        fs::create_dir_all(destination.path().join("gcc/lib")).unwrap();
+        fs::write(destination.path().join("gcc/bin/gcc"), "stale").unwrap();
+        move_into(staging.path(), destination.path()).unwrap();
+        assert_eq!(
+            fs::read(destination.path().join("gcc/bin/gcc")).unwrap(),
+            contents
+        );
+        assert!(destination.path().join("gcc/lib").is_dir());
+        assert_eq!(Compression::from_extension("zip"), None);
+    }
+}
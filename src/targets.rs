@@ -1,9 +1,9 @@
 //! ESP32 chip variants support.
 
-use crate::{emoji, error::Error};
+use crate::{emoji, error::Error, preflight::compare_versions};
 use log::debug;
 use miette::Result;
-use std::{collections::HashSet, str::FromStr};
+use std::{cmp::Ordering, collections::HashSet, str::FromStr};
 use strum::{Display, EnumIter, EnumString, IntoEnumIterator};
 
 #[derive(Clone, Copy, EnumIter, EnumString, PartialEq, Hash, Eq, Debug, Display)]
@@ -35,6 +35,74 @@ impl Target {
     pub fn is_xtensa(&self) -> bool {
         matches!(self, Target::ESP32 | Target::ESP32S2 | Target::ESP32S3)
     }
+
+    /// Returns the Rust target triple of the chip.
+    pub fn rust_target(&self) -> &'static str {
+        match self {
+            Target::ESP32 => "xtensa-esp32-none-elf",
+            Target::ESP32S2 => "xtensa-esp32s2-none-elf",
+            Target::ESP32S3 => "xtensa-esp32s3-none-elf",
+            Target::ESP32C2 | Target::ESP32C3 => "riscv32imc-unknown-none-elf",
+            Target::ESP32C6 | Target::ESP32H2 => "riscv32imac-unknown-none-elf",
+        }
+    }
+
+    /// Returns the first Xtensa Rust release supporting the target.
+    pub fn first_supported_release(&self) -> &'static str {
+        match self {
+            Target::ESP32 | Target::ESP32C3 | Target::ESP32S2 | Target::ESP32S3 => "1.56.0.1",
+            Target::ESP32C2 => "1.64.0.0",
+            Target::ESP32C6 => "1.67.0.0",
+            Target::ESP32H2 => "1.69.0.0",
+        }
+    }
+
+    /// Returns true if the Xtensa Rust release supports the target, a `major.minor.patch` version
+    /// standing for the latest release of that Rust version.
+    pub fn is_supported_by(&self, toolchain_version: &str) -> bool {
+        let first_release = self.first_supported_release();
+        let first_release = match toolchain_version.split('.').count() {
+            3 => first_release
+                .rsplit_once('.')
+                .map_or(first_release, |(version, _)| version),
+            _ => first_release,
+        };
+        compare_versions(toolchain_version, first_release) != Ordering::Less
+    }
+}
+
+/// Returns the Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Returns the closest supported target to an unsupported one, if any is close enough.
+pub fn suggest_target(target: &str) -> Option<Target> {
+    Target::iter()
+        .map(|t| (edit_distance(target, &t.to_string()), t))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, t)| t)
+}
+
+/// Builds the hint shown when an unsupported target is used.
+fn unsupported_target_hint(target: &str) -> String {
+    let valid_targets: Vec<String> = Target::iter().map(|t| t.to_string()).collect();
+    let valid_targets = format!("Valid targets: {},all", valid_targets.join(","));
+    match suggest_target(target) {
+        Some(suggestion) => format!("Did you mean '{suggestion}'? {valid_targets}"),
+        None => valid_targets,
+    }
 }
 
 /// Returns a vector of Chips from a comma or space separated string.
@@ -49,9 +117,9 @@ pub fn parse_targets(targets_str: &str) -> Result<HashSet<Target>, Error> {
     } else {
         let mut targets = HashSet::new();
         for target in targets_str.split([',', ' ']) {
-            targets.insert(
-                Target::from_str(target).map_err(|_| Error::UnsupportedTarget(target.into()))?,
-            );
+            targets.insert(Target::from_str(target).map_err(|_| {
+                Error::UnsupportedTarget(target.into(), unsupported_target_hint(target))
+            })?);
         }
 
         targets
@@ -63,8 +131,9 @@ pub fn parse_targets(targets_str: &str) -> Result<HashSet<Target>, Error> {
 
 #[cfg(test)]
 mod tests {
-    use crate::targets::{parse_targets, Target};
+    use crate::targets::{parse_targets, suggest_target, Target};
     use std::collections::HashSet;
+    use strum::IntoEnumIterator;
 
     #[test]
     #[allow(unused_variables)]
@@ -95,4 +164,24 @@ mod tests {
         .collect();
         assert!(matches!(parse_targets("all"), Ok(targets)));
     }
+
+    #[test]
+    fn test_is_supported_by() {
+        assert!(Target::ESP32.is_supported_by("1.56.0.1"));
+        assert!(Target::ESP32C6.is_supported_by("1.76.0.1"));
+        assert!(!Target::ESP32C6.is_supported_by("1.66.0.0"));
+        assert!(!Target::ESP32H2.is_supported_by("1.68.0.1"));
+        assert!(Target::iter().all(|target| target.is_supported_by("1.69.0.0")));
+        assert!(Target::ESP32.is_supported_by("1.56.0"));
+        assert!(!Target::ESP32C6.is_supported_by("1.66.0"));
+    }
+
+    #[test]
+    fn test_suggest_target() {
+        assert_eq!(suggest_target("esp32-s3"), Some(Target::ESP32S3));
+        assert_eq!(suggest_target("esp32c33"), Some(Target::ESP32C3));
+        assert_eq!(suggest_target("esp23"), Some(Target::ESP32));
+        assert_eq!(suggest_target("stm32f4"), None);
+        assert!(parse_targets("esp32,esp32sc3").is_err());
+    }
 }
@@ -0,0 +1,112 @@
+//! Bandwidth limiting of the downloads.
+
+use crate::{emoji, error::Error};
+use log::info;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+use tokio::time::{sleep_until, Instant};
+
+/// Maximum download rate in bytes per second, `0` when unlimited.
+static RATE_LIMIT: AtomicU64 = AtomicU64::new(0);
+/// Time at which the bytes read so far are allowed, shared by every concurrent download.
+static NEXT_SLOT: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Parses a rate in bytes per second, with an optional `K`, `M` or `G` suffix, as `curl
+/// --limit-rate` does.
+pub fn parse_rate(rate: &str) -> Result<u64, Error> {
+    let invalid = || Error::InvalidRate(rate.to_string());
+    let trimmed = rate.trim();
+    let (number, multiplier) = match trimmed.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&trimmed[..trimmed.len() - 1], 1024),
+        Some('M') => (&trimmed[..trimmed.len() - 1], 1024 * 1024),
+        Some('G') => (&trimmed[..trimmed.len() - 1], 1024 * 1024 * 1024),
+        _ => (trimmed, 1),
+    };
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    let bytes = (number * multiplier as f64) as u64;
+    if !number.is_finite() || bytes == 0 {
+        return Err(invalid());
+    }
+    Ok(bytes)
+}
+
+/// Sets the maximum download rate in bytes per second, `None` removes the limit.
+pub fn set_rate_limit(rate: Option<u64>) {
+    if let Some(rate) = rate {
+        info!(
+            "{} Limiting downloads to {:.2} KiB/s",
+            emoji::INFO,
+            rate as f64 / 1024.0
+        );
+    }
+    RATE_LIMIT.store(rate.unwrap_or(0), Ordering::Relaxed);
+    *NEXT_SLOT.lock().unwrap() = None;
+}
+
+/// Returns the time a chunk of bytes read now has to wait for, keeping the overall throughput
+/// under the rate limit.
+fn reserve(bytes: usize, now: Instant) -> Option<Instant> {
+    let rate = RATE_LIMIT.load(Ordering::Relaxed);
+    reserve_slot(&mut NEXT_SLOT.lock().unwrap(), rate, bytes, now)
+}
+
+/// Returns the time a chunk of bytes read now has to wait for under a rate limit, `0` when
+/// unlimited, given the time at which the bytes read so far are allowed.
+fn reserve_slot(
+    next_slot: &mut Option<Instant>,
+    rate: u64,
+    bytes: usize,
+    now: Instant,
+) -> Option<Instant> {
+    if rate == 0 {
+        return None;
+    }
+    let start = next_slot.filter(|slot| *slot > now).unwrap_or(now);
+    let end = start + Duration::from_secs_f64(bytes as f64 / rate as f64);
+    *next_slot = Some(end);
+    Some(end)
+}
+
+/// Waits until a chunk of downloaded bytes fits in the rate limit.
+pub async fn throttle(bytes: usize) {
+    if let Some(deadline) = reserve(bytes, Instant::now()) {
+        sleep_until(deadline).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::throttle::{parse_rate, reserve_slot};
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    #[test]
+    fn test_rate_limit() {
+        assert_eq!(parse_rate("2048").unwrap(), 2048);
+        assert_eq!(parse_rate("500K").unwrap(), 500 * 1024);
+        assert_eq!(parse_rate("1.5m").unwrap(), 1536 * 1024);
+        assert_eq!(parse_rate("1G").unwrap(), 1024 * 1024 * 1024);
+        assert!(parse_rate("0").is_err());
+        assert!(parse_rate("fast").is_err());
+        assert!(parse_rate("K").is_err());
+
+        let now = Instant::now();
+        let mut next_slot = None;
+        assert_eq!(reserve_slot(&mut next_slot, 0, 1024, now), None);
+
+        assert_eq!(
+            reserve_slot(&mut next_slot, 1024, 1024, now),
+            Some(now + Duration::from_secs(1))
+        );
+        // Concurrent downloads share the same budget
+        assert_eq!(
+            reserve_slot(&mut next_slot, 1024, 512, now),
+            Some(now + Duration::from_millis(1500))
+        );
+    }
+}
@@ -0,0 +1,114 @@
+//! Network timeouts, so a stalled server fails the installation instead of hanging it.
+
+use crate::{emoji, error::Error, proxy::reset_client};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::{
+    future::Future,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Seconds to wait for a connection to be established, by default.
+pub const DEFAULT_CONNECT_TIMEOUT: u64 = 30;
+/// Seconds to wait for a response or the next chunk of a download, by default.
+pub const DEFAULT_READ_TIMEOUT: u64 = 60;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct TimeoutConfig {
+    /// Seconds to wait for a connection to be established.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect: Option<u64>,
+    /// Seconds to wait for a response or the next chunk of a download.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read: Option<u64>,
+}
+
+static CONNECT_TIMEOUT: AtomicU64 = AtomicU64::new(DEFAULT_CONNECT_TIMEOUT);
+static READ_TIMEOUT: AtomicU64 = AtomicU64::new(DEFAULT_READ_TIMEOUT);
+
+/// Sets the timeouts used by the HTTP clients, the defaults are used for the missing ones.
+pub fn set_timeouts(timeouts: TimeoutConfig) {
+    let connect = timeouts.connect.unwrap_or(DEFAULT_CONNECT_TIMEOUT).max(1);
+    let read = timeouts.read.unwrap_or(DEFAULT_READ_TIMEOUT).max(1);
+    debug!(
+        "{} Using a {}s connect timeout and a {}s read timeout",
+        emoji::DEBUG,
+        connect,
+        read
+    );
+    CONNECT_TIMEOUT.store(connect, Ordering::Relaxed);
+    READ_TIMEOUT.store(read, Ordering::Relaxed);
+    // The shared client is rebuilt with the new timeouts on its next use
+    reset_client();
+}
+
+/// Returns the time to wait for a connection to be established.
+pub fn connect_timeout() -> Duration {
+    Duration::from_secs(CONNECT_TIMEOUT.load(Ordering::Relaxed))
+}
+
+/// Returns the time to wait for a response or the next chunk of a download.
+pub fn read_timeout() -> Duration {
+    Duration::from_secs(READ_TIMEOUT.load(Ordering::Relaxed))
+}
+
+/// Converts a request error, reporting the timeouts with the URL and the time waited.
+pub fn request_error(url: &str, error: reqwest::Error) -> Error {
+    if !error.is_timeout() {
+        return error.into();
+    }
+    let waited = if error.is_connect() {
+        connect_timeout()
+    } else {
+        read_timeout()
+    };
+    Error::Timeout(url.to_string(), waited.as_secs())
+}
+
+/// Awaits a request, or a chunk of its response, failing if nothing is received before the read
+/// timeout.
+pub async fn read<T>(
+    url: &str,
+    future: impl Future<Output = Result<T, reqwest::Error>>,
+) -> Result<T, Error> {
+    read_within(url, future, read_timeout()).await
+}
+
+/// Awaits a request, or a chunk of its response, failing if nothing is received in time.
+async fn read_within<T>(
+    url: &str,
+    future: impl Future<Output = Result<T, reqwest::Error>>,
+    timeout: Duration,
+) -> Result<T, Error> {
+    match tokio::time::timeout(timeout, future).await {
+        Ok(result) => result.map_err(|e| request_error(url, e)),
+        Err(_) => Err(Error::Timeout(url.to_string(), timeout.as_secs())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{error::Error, timeout::read_within};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_read_timeout() {
+        let timeout = Duration::from_secs(1);
+        let stalled = async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok::<_, reqwest::Error>(())
+        };
+        assert!(matches!(
+            read_within("https://example.com", stalled, timeout).await,
+            Err(Error::Timeout(url, 1)) if url == "https://example.com"
+        ));
+        let ready = async { Ok::<_, reqwest::Error>(42) };
+        assert_eq!(
+            read_within("https://example.com", ready, timeout)
+                .await
+                .unwrap(),
+            42
+        );
+    }
+}
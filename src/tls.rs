@@ -0,0 +1,115 @@
+//! Custom certificate authorities, for networks re-signing TLS traffic.
+
+use crate::{emoji, error::Error, proxy::reset_client};
+use log::debug;
+use reqwest::Certificate;
+use std::{fs::read_to_string, path::PathBuf, sync::Mutex};
+
+/// Marker starting each certificate of a PEM bundle.
+const PEM_BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+/// Marker ending each certificate of a PEM bundle.
+const PEM_END: &str = "-----END CERTIFICATE-----";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsSettings {
+    /// PEM bundle of the certificate authorities to trust, instead of the native certificate
+    /// store.
+    pub ca_cert: Option<PathBuf>,
+    /// Keep trusting the native certificate store along with the custom bundle.
+    pub native_certs: bool,
+}
+
+impl TlsSettings {
+    /// Returns true if the native certificate store is trusted.
+    pub fn uses_native_certs(&self) -> bool {
+        self.ca_cert.is_none() || self.native_certs
+    }
+}
+
+static TLS: Mutex<TlsSettings> = Mutex::new(TlsSettings {
+    ca_cert: None,
+    native_certs: false,
+});
+
+/// Sets the certificate authorities trusted by the HTTP clients.
+pub fn set_tls(settings: TlsSettings) {
+    *TLS.lock().unwrap() = settings;
+    // The shared client is rebuilt with the new certificates on its next use
+    reset_client();
+}
+
+/// Returns the certificate authorities trusted by the HTTP clients.
+pub fn tls_settings() -> TlsSettings {
+    TLS.lock().unwrap().clone()
+}
+
+/// Splits a PEM bundle in its certificates, ignoring any text around them.
+fn split_pem(bundle: &str) -> Vec<String> {
+    let mut certificates = Vec::new();
+    let mut rest = bundle;
+    while let Some(start) = rest.find(PEM_BEGIN) {
+        let Some(len) = rest[start..].find(PEM_END) else {
+            break;
+        };
+        let end = start + len + PEM_END.len();
+        certificates.push(rest[start..end].to_string());
+        rest = &rest[end..];
+    }
+    certificates
+}
+
+/// Returns the custom certificate authorities to add to the HTTP clients.
+pub fn root_certificates(settings: &TlsSettings) -> Result<Vec<Certificate>, Error> {
+    let Some(path) = &settings.ca_cert else {
+        return Ok(Vec::new());
+    };
+    let invalid = || Error::InvalidCaCert(path.display().to_string());
+    let bundle = read_to_string(path).map_err(|_| invalid())?;
+    let certificates = split_pem(&bundle)
+        .iter()
+        .map(|pem| Certificate::from_pem(pem.as_bytes()).map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, _>>()?;
+    if certificates.is_empty() {
+        return Err(invalid());
+    }
+    debug!(
+        "{} Trusting {} certificates from '{}'",
+        emoji::DEBUG,
+        certificates.len(),
+        path.display()
+    );
+    Ok(certificates)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tls::{root_certificates, split_pem, TlsSettings};
+    use std::io::Write;
+
+    #[test]
+    fn test_split_pem() {
+        let bundle = "# Corporate CA\n-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n\
+            -----BEGIN CERTIFICATE-----\nBBBB\n-----END CERTIFICATE-----\n-----BEGIN CERTIFICATE-----\n";
+        assert_eq!(
+            split_pem(bundle),
+            vec![
+                "-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----".to_string(),
+                "-----BEGIN CERTIFICATE-----\nBBBB\n-----END CERTIFICATE-----".to_string(),
+            ]
+        );
+        assert!(split_pem("not a certificate").is_empty());
+
+        assert!(TlsSettings::default().uses_native_certs());
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"not a certificate").unwrap();
+        let settings = TlsSettings {
+            ca_cert: Some(file.path().to_path_buf()),
+            native_certs: false,
+        };
+        assert!(!settings.uses_native_certs());
+        assert!(root_certificates(&settings).is_err());
+        assert!(root_certificates(&TlsSettings::default())
+            .unwrap()
+            .is_empty());
+    }
+}
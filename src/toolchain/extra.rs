@@ -0,0 +1,363 @@
+//! Extra crates installed along with the toolchains, from prebuilt binaries when available.
+
+use crate::{
+    cache::stage,
+    checksum::Checksum,
+    emoji,
+    env::ExportItem,
+    error::Error,
+    frozen::ensure_network,
+    host_triple::HostTriple,
+    locale::command,
+    offline::is_offline,
+    proxy::client,
+    toolchain::{download_file, github_query, rust::get_cargo_home, Installable},
+    warnings::{self, WarningKind},
+};
+use async_trait::async_trait;
+use log::{debug, info};
+use reqwest::header;
+use std::{
+    fs::{copy, create_dir_all, read_dir},
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+/// crates.io API endpoint of the crates.
+const CRATES_IO_API_URL: &str = "https://crates.io/api/v1/crates";
+/// Archive formats of the prebuilt binaries, by preference.
+const BINARY_EXTENSIONS: [&str; 3] = ["tar.xz", "tar.gz", "zip"];
+/// Prefix of the GitHub repositories.
+const GITHUB_PREFIX: &str = "https://github.com/";
+/// GitHub API endpoint of the repositories.
+const GITHUB_REPOS_API_URL: &str = "https://api.github.com/repos";
+
+#[derive(Debug, Clone)]
+pub struct ExtraCrate {
+    /// Crate name.
+    pub name: String,
+    /// Crate version, the latest stable version if not provided.
+    pub version: Option<String>,
+    /// Host triple.
+    pub host_triple: HostTriple,
+    /// Path to the cargo home directory.
+    pub cargo_home: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PrebuiltBinary {
+    /// URL of the archive.
+    url: String,
+    /// File name of the archive.
+    file_name: String,
+    /// Digest of the archive published by GitHub or with the release, if any.
+    checksum: Option<Checksum>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ReleaseFile {
+    /// File name of the asset.
+    name: String,
+    /// Download URL of the asset.
+    url: String,
+    /// SHA256 digest of the asset computed by GitHub, not available for older releases.
+    sha256: Option<String>,
+}
+
+impl ExtraCrate {
+    /// Create a new instance from a `name[@version]` specification.
+    pub fn new(spec: &str, host_triple: &HostTriple) -> Self {
+        let (name, version) = match spec.split_once('@') {
+            Some((name, version)) => (name, Some(version.to_string())),
+            None => (spec, None),
+        };
+        Self {
+            name: name.to_string(),
+            version,
+            host_triple: host_triple.clone(),
+            cargo_home: get_cargo_home(),
+        }
+    }
+
+    /// Queries crates.io for the version to install and the `owner/repo` GitHub repository of the
+    /// crate.
+    async fn resolve(&self) -> Result<(String, Option<String>), Error> {
+        let url = format!("{CRATES_IO_API_URL}/{}", self.name);
+        debug!("{} Querying crates.io: '{}'", emoji::DEBUG, url);
+        let body = client()?
+            .get(&url)
+            .header(header::USER_AGENT, "espup")
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let json: serde_json::Value =
+            serde_json::from_str(&body).map_err(|_| Error::InstallExtraCrate(self.name.clone()))?;
+        let version = match &self.version {
+            Some(version) => version.clone(),
+            None => json["crate"]["max_stable_version"]
+                .as_str()
+                .ok_or_else(|| Error::InstallExtraCrate(self.name.clone()))?
+                .to_string(),
+        };
+        let repository = json["crate"]["repository"]
+            .as_str()
+            .and_then(|repository| repository.strip_prefix(GITHUB_PREFIX))
+            .map(|repository| repository.trim_end_matches('/').trim_end_matches(".git"))
+            .map(str::to_string);
+        Ok((version, repository))
+    }
+
+    /// Looks for a prebuilt binary of the host in the GitHub release of the version.
+    async fn find_prebuilt(&self, version: &str, repository: &str) -> Option<PrebuiltBinary> {
+        for tag in [format!("v{version}"), format!("{}-v{version}", self.name)] {
+            let api_url = format!("{GITHUB_REPOS_API_URL}/{repository}/releases/tags/{tag}");
+            let Ok(Ok(json)) = tokio::task::spawn_blocking(move || github_query(&api_url)).await
+            else {
+                continue;
+            };
+            let assets: Vec<ReleaseFile> = json["assets"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|asset| {
+                    Some(ReleaseFile {
+                        name: asset["name"].as_str()?.to_string(),
+                        url: asset["browser_download_url"].as_str()?.to_string(),
+                        sha256: asset["digest"]
+                            .as_str()
+                            .and_then(|digest| digest.strip_prefix("sha256:"))
+                            .map(str::to_string),
+                    })
+                })
+                .collect();
+            if let Some(binary) = prebuilt_binary(&assets, &self.name, &self.host_triple) {
+                return Some(binary);
+            }
+        }
+        None
+    }
+
+    /// Installs a prebuilt binary, verifying it against its published digest.
+    async fn install_prebuilt(&self, binary: PrebuiltBinary) -> Result<(), Error> {
+        let checksum = binary
+            .checksum
+            .ok_or_else(|| Error::MissingChecksum(binary.file_name.clone()))?;
+        let temp_dir = stage()?;
+        download_file(
+            binary.url,
+            &binary.file_name,
+            &temp_dir.path().display().to_string(),
+            true,
+            false,
+            checksum,
+        )
+        .await?;
+        let bin_dir = self.cargo_home.join("bin");
+        create_dir_all(&bin_dir)
+            .map_err(|_| Error::CreateDirectory(bin_dir.display().to_string()))?;
+        let mut executables = Vec::new();
+        collect_executables(temp_dir.path(), &mut executables)?;
+        if executables.is_empty() {
+            return Err(Error::InstallExtraCrate(self.name.clone()));
+        }
+        for executable in executables {
+            let destination = bin_dir.join(executable.file_name().unwrap());
+            debug!("{} Installing '{}'", emoji::DEBUG, destination.display());
+            copy(&executable, &destination)?;
+        }
+        Ok(())
+    }
+
+    /// Builds and installs the crate with `cargo install`.
+    fn cargo_install(&self, version: Option<&str>) -> Result<(), Error> {
+        let mut args = vec!["install", "--locked", self.name.as_str()];
+        if let Some(version) = version {
+            args.extend(["--version", version]);
+        }
+        debug!("{} Running 'cargo {}'", emoji::DEBUG, args.join(" "));
+        if !command("cargo")
+            .args(&args)
+            .stdout(Stdio::null())
+            .status()?
+            .success()
+        {
+            return Err(Error::InstallExtraCrate(self.name.clone()));
+        }
+        Ok(())
+    }
+}
+
+/// Finds the prebuilt binary of the host among the assets of a release, and its digest.
+fn prebuilt_binary(
+    assets: &[ReleaseFile],
+    name: &str,
+    host_triple: &HostTriple,
+) -> Option<PrebuiltBinary> {
+    let host = host_triple.to_string();
+    let binary = BINARY_EXTENSIONS.iter().find_map(|extension| {
+        assets.iter().find(|asset| {
+            asset.name.starts_with(name)
+                && asset.name.contains(&host)
+                && asset.name.ends_with(&format!(".{extension}"))
+        })
+    })?;
+    // The digest computed by GitHub, or either a sidecar file or a list of checksums of the
+    // release
+    let checksum = binary.sha256.clone().map(Checksum::Digest).or_else(|| {
+        assets
+            .iter()
+            .find(|asset| asset.name == format!("{}.sha256", binary.name))
+            .or_else(|| {
+                assets.iter().find(|asset| {
+                    let checksums = asset.name.to_lowercase();
+                    checksums.contains("checksum") || checksums.contains("sha256sums")
+                })
+            })
+            .map(|checksums| Checksum::Published(checksums.url.clone()))
+    });
+    Some(PrebuiltBinary {
+        url: binary.url.clone(),
+        file_name: binary.name.clone(),
+        checksum,
+    })
+}
+
+/// Collects the executables extracted from a prebuilt binary archive.
+fn collect_executables(dir: &Path, executables: &mut Vec<PathBuf>) -> Result<(), Error> {
+    for entry in read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_executables(&path, executables)?;
+        } else if is_executable(&path) {
+            executables.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|metadata| metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "exe")
+}
+
+#[async_trait]
+impl Installable for ExtraCrate {
+    async fn install(&self) -> Result<Vec<ExportItem>, Error> {
+        if is_offline() {
+            warnings::push(
+                WarningKind::Skipped,
+                format!("'{}' is not installed in offline installations", self.name),
+            );
+            return Ok(Vec::new());
+        }
+        // Extra crates are resolved from crates.io, which is never cached
+        ensure_network(&format!("{CRATES_IO_API_URL}/{}", self.name))?;
+        info!("{} Installing '{}'", emoji::WRENCH, self.name);
+        let resolved = self.resolve().await;
+        let prebuilt = match &resolved {
+            Ok((version, Some(repository))) => self.find_prebuilt(version, repository).await,
+            _ => None,
+        };
+        // Prebuilt binaries that cannot be verified are never installed, the crate is built instead
+        let fallback = match prebuilt {
+            Some(binary) => match self.install_prebuilt(binary).await {
+                Ok(()) => None,
+                Err(e) => Some(format!(
+                    "The prebuilt binary of '{}' could not be installed ({e}), building it with 'cargo install'",
+                    self.name
+                )),
+            },
+            None => Some(format!(
+                "No prebuilt binary of '{}' for '{}', building it with 'cargo install'",
+                self.name, self.host_triple
+            )),
+        };
+        if let Some(reason) = fallback {
+            warnings::push(WarningKind::Fallback, reason);
+            let version = resolved.ok().map(|(version, _)| version);
+            self.cargo_install(version.as_deref().or(self.version.as_deref()))?;
+        }
+        // Cargo's bin directory is already in the PATH
+        Ok(Vec::new())
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    // Prebuilt binaries and `cargo install` may need the Rust toolchains installed by espup
+    fn dependencies(&self) -> Vec<String> {
+        vec!["Xtensa Rust".to_string(), "RISC-V Rust target".to_string()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        checksum::Checksum,
+        error::Error,
+        host_triple::HostTriple,
+        toolchain::extra::{prebuilt_binary, ExtraCrate, PrebuiltBinary, ReleaseFile},
+    };
+
+    #[tokio::test]
+    async fn test_extra_crate() {
+        let host_triple = HostTriple::X86_64UnknownLinuxGnu;
+        let extra = ExtraCrate::new("espflash@2.1.0", &host_triple);
+        assert_eq!(extra.name, "espflash");
+        assert_eq!(extra.version.as_deref(), Some("2.1.0"));
+        assert_eq!(ExtraCrate::new("ldproxy", &host_triple).version, None);
+
+        let asset = |name: &str| ReleaseFile {
+            name: name.to_string(),
+            url: format!("https://example.com/{name}"),
+            sha256: None,
+        };
+        let mut assets = vec![
+            asset("espflash-x86_64-pc-windows-msvc.zip"),
+            asset("espflash-x86_64-unknown-linux-gnu.zip"),
+            asset("espflash-x86_64-unknown-linux-gnu.tar.gz"),
+            asset("espflash-x86_64-unknown-linux-gnu.tar.gz.sha256"),
+            asset("cargo-espflash-x86_64-unknown-linux-gnu.tar.xz"),
+        ];
+        assert_eq!(
+            prebuilt_binary(&assets, "espflash", &host_triple),
+            Some(PrebuiltBinary {
+                url: "https://example.com/espflash-x86_64-unknown-linux-gnu.tar.gz".to_string(),
+                file_name: "espflash-x86_64-unknown-linux-gnu.tar.gz".to_string(),
+                checksum: Some(Checksum::Published(
+                    "https://example.com/espflash-x86_64-unknown-linux-gnu.tar.gz.sha256"
+                        .to_string()
+                )),
+            })
+        );
+        assert_eq!(
+            prebuilt_binary(&assets, "espflash", &HostTriple::Aarch64AppleDarwin),
+            None
+        );
+        // Without any digest, the binary is rejected and the crate is built with `cargo install`
+        let binary = prebuilt_binary(&assets, "cargo-espflash", &host_triple).unwrap();
+        assert_eq!(binary.checksum, None);
+        assert!(matches!(
+            extra.install_prebuilt(binary).await,
+            Err(Error::MissingChecksum(file_name)) if file_name == "cargo-espflash-x86_64-unknown-linux-gnu.tar.xz"
+        ));
+        // The digest computed by GitHub is preferred
+        assets[4].sha256 = Some("ab".repeat(32));
+        assert_eq!(
+            prebuilt_binary(&assets, "cargo-espflash", &host_triple)
+                .unwrap()
+                .checksum,
+            Some(Checksum::Digest("ab".repeat(32)))
+        );
+    }
+}
@@ -1,22 +1,28 @@
 //! GCC Toolchain source and installation tools.
 
 use crate::{
+    checksum::Checksum,
     emoji,
+    env::{apply_exports, clear_exports, exports_within, ExportItem},
     error::Error,
     host_triple::HostTriple,
+    pins::{pin_table, pinned_gcc},
+    platform::Platform,
+    script::ScriptStep,
     targets::Target,
-    toolchain::{download_file, Installable},
+    toolchain::{download_file, published_digest, Installable},
+    warnings::{self, WarningKind},
 };
 use async_trait::async_trait;
-use log::{debug, info, warn};
+use log::{debug, info};
 use miette::Result;
 use std::{
+    collections::HashSet,
     fs::remove_dir_all,
     path::{Path, PathBuf},
 };
 
 const DEFAULT_GCC_REPOSITORY: &str = "https://github.com/espressif/crosstool-NG/releases/download";
-const DEFAULT_GCC_RELEASE: &str = "12.2.0_20230208";
 pub const ESP32_GCC: &str = "xtensa-esp32-elf";
 pub const ESP32S2_GCC: &str = "xtensa-esp32s2-elf";
 pub const ESP32S3_GCC: &str = "xtensa-esp32s3-elf";
@@ -38,12 +44,31 @@ impl Gcc {
         format!("{}/{}/bin", &self.path.to_str().unwrap(), &self.name)
     }
 
+    /// Gets the URL of the GCC release artifact.
+    pub fn dist_url(&self) -> String {
+        let release = gcc_release();
+        let gcc_file = format!(
+            "{}-{}-{}.{}",
+            self.name,
+            release,
+            self.host_triple.platform().gcc_arch(),
+            self.host_triple.platform().archive_extension()
+        );
+        format!("{DEFAULT_GCC_REPOSITORY}/esp-{release}/{gcc_file}")
+    }
+
+    /// Gets the URL of the checksums published with the GCC release.
+    pub fn checksums_url(&self) -> String {
+        let release = gcc_release();
+        format!("{DEFAULT_GCC_REPOSITORY}/esp-{release}/crosstool-NG-esp-{release}-checksum.sha256")
+    }
+
     /// Create a new instance with default values and proper toolchain name.
     pub fn new(target: &Target, host_triple: &HostTriple, toolchain_path: &Path) -> Self {
         let name = get_gcc_name(target);
         let path = toolchain_path
             .join(&name)
-            .join(format!("esp-{DEFAULT_GCC_RELEASE}"));
+            .join(format!("esp-{}", gcc_release()));
 
         Self {
             host_triple: host_triple.clone(),
@@ -57,7 +82,7 @@ impl Gcc {
         let name = RISCV_GCC.to_string();
         let path = toolchain_path
             .join(&name)
-            .join(format!("esp-{DEFAULT_GCC_RELEASE}"));
+            .join(format!("esp-{}", gcc_release()));
 
         Self {
             host_triple: host_triple.clone(),
@@ -69,79 +94,72 @@ impl Gcc {
 
 #[async_trait]
 impl Installable for Gcc {
-    async fn install(&self) -> Result<Vec<String>, Error> {
-        let extension = get_artifact_extension(&self.host_triple);
+    async fn install(&self) -> Result<Vec<ExportItem>, Error> {
+        let platform = self.host_triple.platform();
         debug!("{} GCC path: {}", emoji::DEBUG, self.path.display());
         if self.path.exists() {
-            warn!(
-                "{} Previous installation of GCC exists in: '{}'. Reusing this installation.",
-                emoji::WARN,
-                &self.path.display()
+            warnings::push(
+                WarningKind::ReusedInstallation,
+                format!(
+                    "Previous installation of GCC exists in: '{}'. Reusing this installation.",
+                    &self.path.display()
+                ),
             );
         } else {
-            let gcc_file = format!(
-                "{}-{}-{}.{}",
-                self.name,
-                DEFAULT_GCC_RELEASE,
-                get_arch(&self.host_triple).unwrap(),
-                extension
-            );
-            let gcc_dist_url = format!(
-                "{}/esp-{}/{}",
-                DEFAULT_GCC_REPOSITORY, DEFAULT_GCC_RELEASE, gcc_file
-            );
             download_file(
-                gcc_dist_url,
-                &format!("{}.{}", &self.name, extension),
+                self.dist_url(),
+                &format!("{}.{}", &self.name, platform.archive_extension()),
                 &self.path.display().to_string(),
                 true,
                 false,
+                Checksum::Published(self.checksums_url()),
             )
             .await?;
         }
-        let mut exports: Vec<String> = Vec::new();
-
-        #[cfg(windows)]
-        if cfg!(windows) {
-            exports.push(format!(
-                "$Env:PATH = \"{};\" + $Env:PATH",
-                &self.get_bin_path()
-            ));
-            std::env::set_var(
-                "PATH",
-                self.get_bin_path().replace('/', "\\") + ";" + &std::env::var("PATH").unwrap(),
-            );
-        }
-        #[cfg(unix)]
-        exports.push(format!("export PATH=\"{}:$PATH\"", &self.get_bin_path()));
+        let exports = vec![ExportItem::PathAdd(self.get_bin_path())];
+        apply_exports(&exports, platform)?;
 
         Ok(exports)
     }
 
+    fn has_script(&self) -> bool {
+        true
+    }
+
+    async fn script(&self) -> Result<Vec<ScriptStep>, Error> {
+        let file = format!(
+            "{}.{}",
+            &self.name,
+            self.host_triple.platform().archive_extension()
+        );
+        let url = self.dist_url();
+        Ok(vec![
+            ScriptStep::Fetch {
+                sha256: published_digest(&url, &self.checksums_url()).await?,
+                url,
+                file: file.clone(),
+            },
+            ScriptStep::Extract {
+                file,
+                destination: self.path.clone(),
+                strip: false,
+            },
+            ScriptStep::Export(ExportItem::PathAdd(self.get_bin_path())),
+        ])
+    }
+
     fn name(&self) -> String {
         format!("GCC ({})", self.name)
     }
-}
 
-/// Gets the name of the GCC arch based on the host triple.
-fn get_arch(host_triple: &HostTriple) -> Result<&str> {
-    match host_triple {
-        HostTriple::X86_64AppleDarwin => Ok("x86_64-apple-darwin"),
-        HostTriple::Aarch64AppleDarwin => Ok("aarch64-apple-darwin"),
-        HostTriple::X86_64UnknownLinuxGnu => Ok("x86_64-linux-gnu"),
-        HostTriple::Aarch64UnknownLinuxGnu => Ok("aarch64-linux-gnu"),
-        HostTriple::X86_64PcWindowsMsvc | HostTriple::X86_64PcWindowsGnu => {
-            Ok("x86_64-w64-mingw32")
-        }
+    fn install_dir(&self) -> Option<PathBuf> {
+        Some(self.path.clone())
     }
 }
 
-/// Gets the artifact extension based on the host triple.
-fn get_artifact_extension(host_triple: &HostTriple) -> &str {
-    match host_triple {
-        HostTriple::X86_64PcWindowsMsvc | HostTriple::X86_64PcWindowsGnu => "zip",
-        _ => "tar.xz",
-    }
+/// Gets the GCC release to install, either the pinned one or the default one.
+pub fn gcc_release() -> String {
+    pinned_gcc().unwrap_or_else(|| pin_table().gcc)
 }
 
 /// Gets the toolchain name based on the Target
@@ -155,33 +173,94 @@ pub fn get_gcc_name(target: &Target) -> String {
     toolchain.to_string()
 }
 
+/// Returns the GCC toolchains required by the targets, sorted by name.
+///
+/// Targets resolving to the same artifact share a single toolchain, so it is only downloaded and
+/// installed once, while the different toolchains are installed in parallel.
+pub fn gcc_toolchains(
+    targets: &HashSet<Target>,
+    host_triple: &HostTriple,
+    toolchain_path: &Path,
+) -> Vec<Gcc> {
+    let mut toolchains: Vec<Gcc> = Vec::new();
+    for target in targets {
+        let mut required = vec![Gcc::new(target, host_triple, toolchain_path)];
+        // ESP32S2 and ESP32S3 also require the RISC-V toolchain for their ULP coprocessor
+        if matches!(target, Target::ESP32S2 | Target::ESP32S3) {
+            required.push(Gcc::new_riscv(host_triple, toolchain_path));
+        }
+        for gcc in required {
+            if !toolchains
+                .iter()
+                .any(|known| known.dist_url() == gcc.dist_url())
+            {
+                toolchains.push(gcc);
+            }
+        }
+    }
+    toolchains.sort_by(|a, b| a.name.cmp(&b.name));
+    toolchains
+}
+
 /// Checks if the toolchain is pressent, if present uninstalls it.
-pub fn uninstall_gcc_toolchains(toolchain_path: &Path) -> Result<(), Error> {
+///
+/// Returns the directories of the removed toolchains.
+pub fn uninstall_gcc_toolchains(
+    toolchain_path: &Path,
+    platform: &dyn Platform,
+) -> Result<Vec<PathBuf>, Error> {
     info!("{} Uninstalling GCC toolchain", emoji::WRENCH);
 
     let gcc_toolchains = vec![ESP32_GCC, ESP32S2_GCC, ESP32S3_GCC, RISCV_GCC];
+    let mut removed = Vec::new();
 
     for toolchain in gcc_toolchains {
         let gcc_path = toolchain_path.join(toolchain);
         if gcc_path.exists() {
-            #[cfg(windows)]
-            if cfg!(windows) {
-                let gcc_path = format!(
-                    "{}\\esp-{}\\{}\\bin",
-                    gcc_path.display(),
-                    DEFAULT_GCC_RELEASE,
-                    toolchain
-                );
-                std::env::set_var(
-                    "PATH",
-                    std::env::var("PATH")
-                        .unwrap()
-                        .replace(&format!("{gcc_path};"), ""),
-                );
-            }
-            remove_dir_all(gcc_path)?;
+            let exports = exports_within(&gcc_path, &[], |key| std::env::var(key).ok());
+            clear_exports(&exports, platform)?;
+            remove_dir_all(&gcc_path)?;
+            removed.push(gcc_path);
         }
     }
 
-    Ok(())
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        host_triple::HostTriple,
+        targets::Target,
+        toolchain::gcc::{gcc_toolchains, ESP32S3_GCC, ESP32_GCC, RISCV_GCC},
+    };
+    use std::{collections::HashSet, path::Path};
+
+    #[test]
+    fn test_gcc_toolchains() {
+        let host_triple = HostTriple::X86_64UnknownLinuxGnu;
+        let names = |targets: &[Target]| -> Vec<String> {
+            let targets: HashSet<Target> = targets.iter().copied().collect();
+            gcc_toolchains(&targets, &host_triple, Path::new("/esp"))
+                .into_iter()
+                .map(|gcc| gcc.name)
+                .collect()
+        };
+
+        // The RISC-V chips share a single toolchain
+        assert_eq!(
+            names(&[
+                Target::ESP32C2,
+                Target::ESP32C3,
+                Target::ESP32C6,
+                Target::ESP32H2
+            ]),
+            vec![RISCV_GCC]
+        );
+        assert_eq!(names(&[Target::ESP32]), vec![ESP32_GCC]);
+        assert_eq!(
+            names(&[Target::ESP32, Target::ESP32S3, Target::ESP32C3]),
+            vec![RISCV_GCC, ESP32_GCC, ESP32S3_GCC]
+        );
+    }
 }
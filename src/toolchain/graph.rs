@@ -0,0 +1,141 @@
+//! Dependency graph of the installable components.
+
+use crate::error::Error;
+use std::collections::HashMap;
+
+/// Sorts the components in waves, where every component only depends on components of
+/// previous waves. Components of the same wave can be installed in parallel.
+///
+/// Each component is given as its name and the names of its dependencies. Dependencies
+/// that are not part of the graph are assumed to be already installed.
+pub fn install_waves(components: &[(String, Vec<String>)]) -> Result<Vec<Vec<usize>>, Error> {
+    let indices: HashMap<&str, usize> = components
+        .iter()
+        .enumerate()
+        .map(|(i, (name, _))| (name.as_str(), i))
+        .collect();
+    // Edges from every component to the components depending on it, each one once
+    let mut dependents: Vec<Vec<usize>> = vec![Vec::new(); components.len()];
+    let mut pending = vec![0; components.len()];
+    for (j, (_, dependencies)) in components.iter().enumerate() {
+        let mut dependencies: Vec<usize> = dependencies
+            .iter()
+            .filter_map(|dependency| indices.get(dependency.as_str()).copied())
+            .collect();
+        dependencies.sort_unstable();
+        dependencies.dedup();
+        pending[j] = dependencies.len();
+        for i in dependencies {
+            dependents[i].push(j);
+        }
+    }
+    let mut installed = vec![false; components.len()];
+    let mut waves = Vec::new();
+    let cycle = |installed: &[bool]| {
+        let cycle: Vec<&str> = (0..components.len())
+            .filter(|&i| !installed[i])
+            .map(|i| components[i].0.as_str())
+            .collect();
+        Error::DependencyCycle(cycle.join(", "))
+    };
+
+    while installed.iter().any(|installed| !installed) {
+        let wave: Vec<usize> = (0..components.len())
+            .filter(|&i| !installed[i] && pending[i] == 0)
+            .collect();
+        if wave.is_empty() {
+            return Err(cycle(&installed));
+        }
+        for &i in &wave {
+            installed[i] = true;
+        }
+        for &i in &wave {
+            for &j in &dependents[i] {
+                pending[j] = pending[j].checked_sub(1).ok_or_else(|| cycle(&installed))?;
+            }
+        }
+        waves.push(wave);
+    }
+
+    Ok(waves)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        host_triple::HostTriple,
+        toolchain::{
+            extra::ExtraCrate,
+            graph::install_waves,
+            plugin::{Plugin, PluginConfig},
+            rust::XtensaRust,
+            Installable,
+        },
+    };
+    use std::path::Path;
+
+    fn component(name: &str, dependencies: &[&str]) -> (String, Vec<String>) {
+        (
+            name.to_string(),
+            dependencies.iter().map(|d| d.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn test_install_waves() {
+        let components = vec![
+            component("Extras", &["Xtensa Rust"]),
+            component("Xtensa Rust", &[]),
+            component("LLVM", &[]),
+            component("GCC", &["Missing"]),
+        ];
+        assert_eq!(
+            install_waves(&components).unwrap(),
+            vec![vec![1, 2, 3], vec![0]]
+        );
+
+        let components = vec![component("A", &["B"]), component("B", &["A"])];
+        assert!(install_waves(&components).is_err());
+        let components = vec![component("A", &["A"])];
+        assert!(install_waves(&components).is_err());
+
+        // Repeated dependencies and components sharing a name are counted once
+        let components = vec![
+            component("A", &[]),
+            component("A", &[]),
+            component("B", &["A", "A"]),
+        ];
+        assert_eq!(
+            install_waves(&components).unwrap(),
+            vec![vec![0, 1], vec![2]]
+        );
+
+        assert!(install_waves(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_install_waves_of_components() {
+        let host_triple = HostTriple::X86_64UnknownLinuxGnu;
+        let toolchain_path = Path::new("/toolchains/esp");
+        let plugin = PluginConfig {
+            name: "flasher".to_string(),
+            url: "https://example.com/flasher.zip".to_string(),
+            dependencies: vec!["espflash".to_string()],
+            ..Default::default()
+        };
+        let components: Vec<Box<dyn Installable>> = vec![
+            Box::new(Plugin::new(&plugin, &host_triple, toolchain_path)),
+            Box::new(ExtraCrate::new("espflash", &host_triple)),
+            Box::new(XtensaRust::new("1.73.0.1", &host_triple, toolchain_path)),
+        ];
+        let graph: Vec<(String, Vec<String>)> = components
+            .iter()
+            .map(|component| (component.name(), component.dependencies()))
+            .collect();
+        // Extra crates are installed once the Rust toolchains are, plugins after their dependencies
+        assert_eq!(
+            install_waves(&graph).unwrap(),
+            vec![vec![2], vec![1], vec![0]]
+        );
+    }
+}
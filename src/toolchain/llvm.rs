@@ -1,31 +1,34 @@
 //! LLVM Toolchain source and installation tools.
 
-#[cfg(windows)]
-use crate::env::{delete_environment_variable, set_environment_variable};
 use crate::{
+    checksum::Checksum,
     emoji,
+    env::{apply_exports, clear_exports, exports_within, ExportItem},
     error::Error,
     host_triple::HostTriple,
-    toolchain::{download_file, rust::RE_EXTENDED_SEMANTIC_VERSION, Installable},
+    pins::{pin_table, pinned_llvm},
+    platform::Platform,
+    script::ScriptStep,
+    toolchain::{download_file, published_digest, rust::RE_EXTENDED_SEMANTIC_VERSION, Installable},
+    warnings::{self, WarningKind},
 };
 use async_trait::async_trait;
-use log::{info, warn};
+use log::info;
 use miette::Result;
 use regex::Regex;
 use std::{
     fs::remove_dir_all,
     path::{Path, PathBuf},
-    u8,
 };
 
 const DEFAULT_LLVM_REPOSITORY: &str = "https://github.com/espressif/llvm-project/releases/download";
-const DEFAULT_LLVM_15_VERSION: &str = "esp-15.0.0-20221201";
-const DEFAULT_LLVM_16_VERSION: &str = "esp-16.0.0-20230516";
 pub const CLANG_NAME: &str = "xtensa-esp32-elf-clang";
 
 #[derive(Debug, Clone, Default)]
 pub struct Llvm {
-    // /// If `true`, full LLVM, instead of only libraries, are installed.
+    /// URL of the checksums published with the LLVM release.
+    pub checksums_url: String,
+    /// If `true`, full LLVM, instead of only libraries, are installed.
     extended: bool,
     /// LLVM Toolchain file name.
     pub file_name: String,
@@ -40,33 +43,55 @@ pub struct Llvm {
 }
 
 impl Llvm {
-    /// Gets the name of the LLVM arch based on the host triple.
-    fn get_arch(host_triple: &HostTriple) -> Result<&str> {
-        match host_triple {
-            HostTriple::Aarch64AppleDarwin => Ok("macos-arm64"),
-            HostTriple::X86_64AppleDarwin => Ok("macos"),
-            HostTriple::X86_64UnknownLinuxGnu => Ok("linux-amd64"),
-            HostTriple::Aarch64UnknownLinuxGnu => Ok("linux-arm64"),
-            HostTriple::X86_64PcWindowsMsvc | HostTriple::X86_64PcWindowsGnu => Ok("win64"),
-        }
-    }
-
-    /// Gets the binary path.
+    /// Gets the path of the directory containing libclang.
     fn get_lib_path(&self) -> String {
-        #[cfg(windows)]
-        let llvm_path = format!("{}/esp-clang/bin", self.path.to_str().unwrap());
-        #[cfg(unix)]
-        let llvm_path = format!("{}/esp-clang/lib", self.path.to_str().unwrap());
-        llvm_path
+        format!(
+            "{}/{}",
+            self.path.to_str().unwrap(),
+            self.host_triple.platform().libclang_dir()
+        )
     }
 
     /// Gets the binary path of clang
     fn get_bin_path(&self) -> String {
-        #[cfg(windows)]
-        let llvm_path = format!("{}/esp-clang/bin/clang.exe", self.path.to_str().unwrap());
-        #[cfg(unix)]
-        let llvm_path = format!("{}/esp-clang/bin/clang", self.path.to_str().unwrap());
-        llvm_path
+        format!(
+            "{}/{}",
+            self.path.to_str().unwrap(),
+            self.host_triple.platform().clang_path()
+        )
+    }
+
+    /// Gets the path of the directory containing clang and the other LLVM tools.
+    fn get_bin_dir(&self) -> String {
+        let clang_path = Path::new(self.host_triple.platform().clang_path());
+        format!(
+            "{}/{}",
+            self.path.to_str().unwrap(),
+            clang_path.parent().unwrap().to_str().unwrap()
+        )
+    }
+
+    /// Returns the environment exports of the installation.
+    fn exports(&self) -> Vec<ExportItem> {
+        let platform = self.host_triple.platform();
+        let mut exports = vec![ExportItem::EnvSet {
+            key: "LIBCLANG_PATH".to_string(),
+            value: platform.libclang_path(&self.get_lib_path()),
+        }];
+        if platform.libclang_in_path() {
+            exports.push(ExportItem::PathAdd(self.get_lib_path()));
+        }
+        if self.extended {
+            exports.push(ExportItem::EnvSet {
+                key: "CLANG_PATH".to_string(),
+                value: self.get_bin_path(),
+            });
+            // On Windows, the tools are next to libclang, which is already in the PATH
+            if self.get_bin_dir() != self.get_lib_path() || !platform.libclang_in_path() {
+                exports.push(ExportItem::PathAdd(self.get_bin_dir()));
+            }
+        }
+        exports
     }
 
     /// Create a new instance with default values and proper toolchain version.
@@ -76,38 +101,30 @@ impl Llvm {
         extended: bool,
         xtensa_rust_version: &str,
     ) -> Result<Self, Error> {
-        let re_extended: Regex = Regex::new(RE_EXTENDED_SEMANTIC_VERSION).unwrap();
-        let (major, minor, patch, subpatch) = match re_extended.captures(xtensa_rust_version) {
-            Some(version) => (
-                version.get(1).unwrap().as_str().parse::<u8>().unwrap(),
-                version.get(2).unwrap().as_str().parse::<u8>().unwrap(),
-                version.get(3).unwrap().as_str().parse::<u8>().unwrap(),
-                version.get(4).unwrap().as_str().parse::<u8>().unwrap(),
-            ),
-            None => return Err(Error::InvalidVersion(xtensa_rust_version.to_string())),
-        };
-
-        // Use LLVM 15 for versions 1.69.0.0 and below
-        let version = if (major == 1 && minor == 69 && patch == 0 && subpatch == 0)
-            || (major == 1 && minor < 69)
+        if !Regex::new(RE_EXTENDED_SEMANTIC_VERSION)
+            .unwrap()
+            .is_match(xtensa_rust_version)
         {
-            DEFAULT_LLVM_15_VERSION.to_string()
-        } else {
-            DEFAULT_LLVM_16_VERSION.to_string()
-        };
+            return Err(Error::InvalidVersion(xtensa_rust_version.to_string()));
+        }
+        let version =
+            pinned_llvm().unwrap_or_else(|| pin_table().llvm_version(xtensa_rust_version));
 
         let mut file_name = format!(
             "llvm-{}-{}.tar.xz",
             version,
-            Self::get_arch(host_triple).unwrap()
+            host_triple.platform().llvm_arch()
         );
         if !extended {
             file_name = format!("libs_{file_name}");
         }
         let repository_url = format!("{DEFAULT_LLVM_REPOSITORY}/{version}/{file_name}");
+        let checksums_url =
+            format!("{DEFAULT_LLVM_REPOSITORY}/{version}/llvm-{version}-checksum.sha256");
         let path = toolchain_path.join(CLANG_NAME).join(&version);
 
         Ok(Self {
+            checksums_url,
             extended,
             file_name,
             host_triple: host_triple.clone(),
@@ -117,41 +134,42 @@ impl Llvm {
         })
     }
 
-    /// Uninstall LLVM toolchain.
-    pub fn uninstall(toolchain_path: &Path) -> Result<(), Error> {
+    /// Uninstall LLVM toolchain, returning whether it was installed.
+    pub fn uninstall(toolchain_path: &Path, platform: &dyn Platform) -> Result<bool, Error> {
         info!("{} Uninstalling Xtensa LLVM", emoji::WRENCH);
         let llvm_path = toolchain_path.join(CLANG_NAME);
         if llvm_path.exists() {
-            #[cfg(windows)]
-            if cfg!(windows) {
-                delete_environment_variable("LIBCLANG_PATH")?;
-                delete_environment_variable("CLANG_PATH")?;
-                let updated_path = std::env::var("PATH").unwrap().replace(
-                    &format!(
-                        "{}\\{}\\esp-clang\\bin;",
-                        llvm_path.display().to_string().replace('/', "\\"),
-                        DEFAULT_LLVM_15_VERSION,
-                    ),
-                    "",
-                );
-                set_environment_variable("PATH", &updated_path)?;
-            }
+            // The variables changed by the user since the installation are left untouched
+            let exports = exports_within(&llvm_path, &["LIBCLANG_PATH", "CLANG_PATH"], |key| {
+                std::env::var(key).ok()
+            });
+            clear_exports(&exports, platform)?;
             remove_dir_all(toolchain_path.join(CLANG_NAME))?;
+            return Ok(true);
         }
-        Ok(())
+        Ok(false)
     }
 }
 
 #[async_trait]
 impl Installable for Llvm {
-    async fn install(&self) -> Result<Vec<String>, Error> {
-        let mut exports: Vec<String> = Vec::new();
-
+    async fn install(&self) -> Result<Vec<ExportItem>, Error> {
+        // A previous installation of only the libraries does not contain the tools
+        if self.extended && self.path.exists() && !Path::new(&self.get_bin_path()).exists() {
+            info!(
+                "{} Replacing the LLVM libraries in '{}' with the full LLVM",
+                emoji::WRENCH,
+                self.path.display()
+            );
+            remove_dir_all(&self.path)?;
+        }
         if Path::new(&self.path).exists() {
-            warn!(
-                "{} Previous installation of LLVM exists in: '{}'. Reusing this installation.",
-                emoji::WARN,
-                self.path.to_str().unwrap()
+            warnings::push(
+                WarningKind::ReusedInstallation,
+                format!(
+                    "Previous installation of LLVM exists in: '{}'. Reusing this installation.",
+                    self.path.to_str().unwrap()
+                ),
             );
         } else {
             info!("{} Installing Xtensa elf Clang", emoji::WRENCH);
@@ -161,47 +179,72 @@ impl Installable for Llvm {
                 self.path.to_str().unwrap(),
                 true,
                 false,
+                Checksum::Published(self.checksums_url.clone()),
             )
             .await?;
         }
         // Set environment variables.
-        #[cfg(windows)]
-        if cfg!(windows) {
-            exports.push(format!(
-                "$Env:LIBCLANG_PATH = \"{}/libclang.dll\"",
-                self.get_lib_path()
-            ));
-            exports.push(format!(
-                "$Env:PATH = \"{};\" + $Env:PATH",
-                self.get_lib_path()
-            ));
-            set_environment_variable(
-                "LIBCLANG_PATH",
-                &format!("{}\\libclang.dll", self.get_lib_path().replace('/', "\\")),
-            )?;
-
-            std::env::set_var(
-                "PATH",
-                self.get_lib_path().replace('/', "\\") + ";" + &std::env::var("PATH").unwrap(),
-            );
-        }
-        #[cfg(unix)]
-        exports.push(format!("export LIBCLANG_PATH=\"{}\"", self.get_lib_path()));
+        apply_exports(&self.exports(), self.host_triple.platform())?;
 
-        if self.extended {
-            #[cfg(windows)]
-            if cfg!(windows) {
-                exports.push(format!("$Env:CLANG_PATH = \"{}\"", self.get_bin_path()));
-                set_environment_variable("CLANG_PATH", &self.get_bin_path().replace('/', "\\"))?;
-            }
-            #[cfg(unix)]
-            exports.push(format!("export CLANG_PATH=\"{}\"", self.get_bin_path()));
-        }
+        Ok(self.exports())
+    }
 
-        Ok(exports)
+    fn has_script(&self) -> bool {
+        true
+    }
+
+    async fn script(&self) -> Result<Vec<ScriptStep>, Error> {
+        let file = "idf_tool_xtensa_elf_clang.tar.xz".to_string();
+        let mut steps = vec![
+            ScriptStep::Fetch {
+                url: self.repository_url.clone(),
+                sha256: published_digest(&self.repository_url, &self.checksums_url).await?,
+                file: file.clone(),
+            },
+            ScriptStep::Extract {
+                file,
+                destination: self.path.clone(),
+                strip: false,
+            },
+        ];
+        steps.extend(self.exports().into_iter().map(ScriptStep::Export));
+        Ok(steps)
     }
 
     fn name(&self) -> String {
         "LLVM".to_string()
     }
+
+    fn install_dir(&self) -> Option<PathBuf> {
+        Some(self.path.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{env::ExportItem, host_triple::HostTriple, toolchain::llvm::Llvm};
+    use std::path::Path;
+
+    #[test]
+    fn test_extended_llvm() {
+        let host_triple = HostTriple::X86_64UnknownLinuxGnu;
+        let toolchain_path = Path::new("/esp");
+        let llvm = Llvm::new(toolchain_path, &host_triple, false, "1.75.0.0").unwrap();
+        assert!(llvm.file_name.starts_with("libs_llvm-"));
+        assert_eq!(llvm.exports().len(), 1);
+
+        let llvm = Llvm::new(toolchain_path, &host_triple, true, "1.75.0.0").unwrap();
+        assert!(llvm.file_name.starts_with("llvm-"));
+        let bin_dir = format!("{}/esp-clang/bin", llvm.path.display());
+        assert_eq!(
+            llvm.exports()[1..],
+            [
+                ExportItem::EnvSet {
+                    key: "CLANG_PATH".to_string(),
+                    value: format!("{bin_dir}/clang"),
+                },
+                ExportItem::PathAdd(bin_dir),
+            ]
+        );
+    }
 }
@@ -1,56 +1,161 @@
 //! Different toolchains source and installation tools.
 
 use crate::{
+    bundle::extract as extract_bundle,
+    cache::{
+        cache_file, enforce_limits, get_cache_dir, get_staging_dir, is_download_cache_enabled,
+        persist_cached, read_cached, set_download_cache, set_staging_dir, store_cached,
+    },
+    checksum::{find_digest, parse_checksums, sha256, Checksum},
     cli::InstallOpts,
+    config::Config,
+    credentials::authorize,
+    dedup::hardlink_duplicates,
     emoji,
-    env::{create_export_file, export_environment, get_export_file},
+    env::{
+        append_env_file, create_activation_scripts, create_export_file, export_environment,
+        get_export_file, set_persist_environment, ExportItem, ExportShell,
+    },
     error::Error,
-    host_triple::get_host_triple,
-    targets::Target,
+    footprint::{
+        format_size, installed_size, Footprint, GCC_SIZE, LLVM_EXTENDED_SIZE, LLVM_EXTENDED_TOOLS,
+        LLVM_SIZE, RISCV_TARGETS_SIZE, RUST_DOCS, RUST_DOCS_SIZE, RUST_SRC_SIZE, XTENSA_RUST_SIZE,
+    },
+    frozen::{ensure_network, is_frozen, set_frozen},
+    github::{
+        best_asset_match, format_duration, get_token, read_cached_response, set_token,
+        store_cached_response, CachedResponse, RateLimit, ReleaseAsset,
+    },
+    hooks::{hook_env, run_hooks},
+    host_triple::{get_host_triple, HostTriple},
+    http::{trace_request, trace_response},
+    lockfile::{
+        locked_artifacts, locked_digest, record_verified, set_locked, Lockfile, DEFAULT_LOCKFILE,
+    },
+    manifest::{InstallManifest, InstallRecord},
+    mirror::{
+        api_url, current_mirror, current_source, fall_back, is_unreachable, mirrored_url,
+        set_dist_server, set_fallback_mirrors, set_mirror,
+    },
+    oci::{pull, OciReference},
+    offline::{artifact_size, find_toolchain_version, is_offline, read_artifact, set_artifact_dir},
+    output::{emit, Event},
+    pins::{set_pins, Pins},
+    platform::Platform,
+    prefetch::{prefetch, UpdatePlan},
+    preflight::{check_disk_space, check_executable_directories, check_host_requirements},
+    progress::Progress,
+    project::ProjectToolchain,
+    proxy::{blocking_client, client, set_proxy, ProxySettings},
+    resume::InstallState,
+    script::{
+        artifact_line, artifacts as script_artifacts, describe as describe_script,
+        render as render_script, ScriptStep,
+    },
+    segmented,
+    shims::{data_dirs, find_shims, resolves_toolchain},
+    stream::{self, Compression},
+    targets::{parse_targets, Target},
+    throttle::{set_rate_limit, throttle},
+    timeout::{read, request_error, set_timeouts, TimeoutConfig},
+    tls::{set_tls, TlsSettings},
     toolchain::{
-        gcc::Gcc,
-        llvm::Llvm,
-        rust::{check_rust_installation, get_rustup_home, RiscVTarget, XtensaRust},
+        extra::ExtraCrate,
+        gcc::{gcc_release, gcc_toolchains, ESP32S2_GCC, ESP32S3_GCC, ESP32_GCC, RISCV_GCC},
+        graph::install_waves,
+        llvm::{Llvm, CLANG_NAME},
+        plugin::Plugin,
+        rust::{
+            check_rust_installation, get_cargo_home, get_default_toolchain, get_rustup_home,
+            set_default_toolchain, set_override, RiscVTarget, ToolchainState, XtensaRust,
+        },
     },
+    warnings::{self, WarningKind},
 };
 use async_trait::async_trait;
 use flate2::bufread::GzDecoder;
 use log::{debug, info, warn};
 use miette::Result;
-use reqwest::{blocking::Client, header};
-use retry::{delay::Fixed, retry};
+use reqwest::{header, StatusCode};
+use retry::{delay::Fixed, retry, OperationResult};
 use std::{
     env,
-    fs::{create_dir_all, remove_file, File},
+    fs::{
+        create_dir_all, metadata, read_to_string, remove_dir_all, remove_file, write, File,
+        OpenOptions,
+    },
     io::Write,
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Instant,
 };
 use tar::Archive;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, Semaphore};
 use tokio_retry::{strategy::FixedInterval, Retry};
 use xz2::read::XzDecoder;
 use zip::ZipArchive;
+use zstd::stream::read::Decoder as ZstdDecoder;
 
+pub mod extra;
 pub mod gcc;
+pub mod graph;
 pub mod llvm;
+pub mod plugin;
 pub mod rust;
 
+/// Extension of the files holding interrupted downloads.
+const PARTIAL_EXTENSION: &str = "partial";
+/// Extension of the files holding the ETag of the response an interrupted download resumes.
+const ETAG_EXTENSION: &str = "etag";
+/// Number of hex digits of the URL digest prefixed to the partial files.
+const URL_DIGEST_LEN: usize = 16;
+/// Number of attempts to complete a download.
+const DOWNLOAD_ATTEMPTS: usize = 5;
+
 #[async_trait]
 pub trait Installable {
     /// Install some application, returning a vector of any required exports
-    async fn install(&self) -> Result<Vec<String>, Error>;
+    async fn install(&self) -> Result<Vec<ExportItem>, Error>;
     /// Returns the name of the toolchain being installeds
     fn name(&self) -> String;
+    /// Returns the names of the toolchains that need to be installed before this one
+    fn dependencies(&self) -> Vec<String> {
+        Vec::new()
+    }
+    /// Returns the directory the component is extracted to, removed when an interrupted
+    /// installation left it incomplete
+    fn install_dir(&self) -> Option<PathBuf> {
+        None
+    }
+    /// Returns true if the installation is replayed by `script`, which resolves its artifacts
+    fn has_script(&self) -> bool {
+        false
+    }
+    /// Returns the steps replaying the installation in a standalone script
+    async fn script(&self) -> Result<Vec<ScriptStep>, Error> {
+        Ok(vec![ScriptStep::Comment(format!(
+            "{} is not supported by install scripts, install it separately",
+            self.name()
+        ))])
+    }
 }
 
 /// Downloads a file from a URL and uncompresses it, if necesary, to the output directory.
+///
+/// Every download is verified against its expected SHA256 digest before it is installed, the one
+/// recorded in the lockfile of the installation taking precedence over the published one.
 pub async fn download_file(
     url: String,
     file_name: &str,
     output_directory: &str,
     uncompress: bool,
     strip: bool,
+    checksum: Checksum,
 ) -> Result<String, Error> {
+    let checksum = locked_digest(&url).map_or(checksum, Checksum::Digest);
     let file_path = format!("{output_directory}/{file_name}");
     if Path::new(&file_path).exists() {
         warn!(
@@ -75,13 +180,74 @@ pub async fn download_file(
         &file_path,
         url
     );
-    let resp = reqwest::get(&url).await?;
-    let bytes = resp.bytes().await?;
+    let client = client()?;
+    let mut source_url = url.clone();
+    let use_cache = is_download_cache_enabled() && !is_offline();
+    let mut cached = None;
+    if use_cache {
+        // Only a cached copy matching the expected digest is reused
+        let expected = expected_checksum(&checksum, &url).await?;
+        if let Some(bytes) = read_cached(&get_cache_dir(), &url, Some(&expected))? {
+            info!("{} Using cached '{}'", emoji::INFO, url);
+            cached = Some(bytes);
+        }
+    }
+    let from_cache = cached.is_some();
+    let streamed = Path::new(file_name)
+        .extension()
+        .and_then(|extension| Compression::from_extension(extension.to_str()?))
+        .filter(|_| uncompress && !from_cache && can_stream());
+    if let Some(compression) = streamed {
+        match stream_artifact(
+            &client,
+            &url,
+            compression,
+            output_directory,
+            &checksum,
+            use_cache,
+        )
+        .await
+        {
+            Ok(true) => return Ok(file_path),
+            Ok(false) => {}
+            Err(e @ (Error::ChecksumMismatch(..) | Error::MissingChecksum(_))) => return Err(e),
+            Err(e) => warnings::push(
+                WarningKind::Fallback,
+                format!("Streaming extraction of '{url}' failed ({e}), downloading it before extracting it"),
+            ),
+        }
+    }
+    let mut bytes = if from_cache {
+        cached
+    } else if is_offline() {
+        read_artifact(&url)?
+    } else {
+        download_artifact(&client, &url).await?
+    };
+    if bytes.is_none() && !is_offline() {
+        if let Some(asset_url) = find_release_asset(&url).await? {
+            warnings::push(
+                WarningKind::Fallback,
+                format!("Artifact '{url}' not found, using '{asset_url}' instead"),
+            );
+            bytes = download_artifact(&client, &asset_url).await?;
+            source_url = asset_url;
+        }
+    }
+    let bytes = bytes.ok_or_else(|| Error::ArtifactNotFound(source_url.clone()))?;
+    if !from_cache {
+        verify_checksum(&checksum, &source_url, &bytes).await?;
+    }
+    if use_cache && !from_cache && source_url == url {
+        if let Err(e) = store_cached(&get_cache_dir(), &url, &bytes) {
+            debug!("{} Failed to cache '{}': {}", emoji::DEBUG, url, e);
+        }
+    }
     if uncompress {
         let extension = Path::new(file_name).extension().unwrap().to_str().unwrap();
         match extension {
             "zip" => {
-                let mut tmpfile = tempfile::tempfile()?;
+                let mut tmpfile = tempfile::tempfile_in(get_staging_dir()?)?;
                 tmpfile.write_all(&bytes)?;
                 let mut zipfile = ZipArchive::new(tmpfile).unwrap();
                 if strip {
@@ -130,6 +296,16 @@ pub async fn download_file(
                 let mut archive = Archive::new(tarfile);
                 archive.unpack(output_directory)?;
             }
+            "zst" => {
+                info!(
+                    "{} Uncompressing tar.zst file to '{}'",
+                    emoji::WRENCH,
+                    output_directory
+                );
+                let tarfile = ZstdDecoder::new(bytes.as_slice())?;
+                let mut archive = Archive::new(tarfile);
+                archive.unpack(output_directory)?;
+            }
             _ => {
                 return Err(Error::UnsuportedFileExtension(extension.to_string()));
             }
@@ -142,33 +318,695 @@ pub async fn download_file(
     Ok(format!("{output_directory}/{file_name}"))
 }
 
+/// Sends a GET request.
+async fn fetch(client: &reqwest::Client, url: &str) -> Result<reqwest::Response, Error> {
+    let request = authorize(client.get(url), url).build()?;
+    trace_request(request.method(), url, request.headers());
+    let start = Instant::now();
+    let resp = read(url, client.execute(request)).await?;
+    trace_response(url, resp.status(), resp.headers(), start.elapsed());
+    Ok(resp)
+}
+
+/// Fetches a text file published with the artifacts, returning `None` if it does not exist.
+///
+/// The files are also stored in the download cache, where frozen installations read them from.
+async fn fetch_text(client: &reqwest::Client, url: &str) -> Result<Option<String>, Error> {
+    if is_offline() {
+        return Ok(read_artifact(url)?.map(|bytes| String::from_utf8_lossy(&bytes).to_string()));
+    }
+    let use_cache = is_download_cache_enabled();
+    if use_cache && is_frozen() {
+        if let Some(bytes) = read_cached(&get_cache_dir(), url, None)? {
+            return Ok(Some(String::from_utf8_lossy(&bytes).to_string()));
+        }
+    }
+    let Some(resp) = fetch_artifact(client, url).await? else {
+        return Ok(None);
+    };
+    let text = read(url, resp.error_for_status()?.text()).await?;
+    if use_cache {
+        if let Err(e) = store_cached(&get_cache_dir(), url, text.as_bytes()) {
+            debug!("{} Failed to cache '{}': {}", emoji::DEBUG, url, e);
+        }
+    }
+    Ok(Some(text))
+}
+
+/// Returns the size of an artifact announced by its server, if any.
+async fn content_length(client: &reqwest::Client, url: &str) -> Result<Option<u64>, Error> {
+    ensure_network(url)?;
+    let request = authorize(client.head(url), url).build()?;
+    trace_request(request.method(), url, request.headers());
+    let start = Instant::now();
+    let resp = read(url, client.execute(request)).await?;
+    trace_response(url, resp.status(), resp.headers(), start.elapsed());
+    if !resp.status().is_success() {
+        return Ok(None);
+    }
+    Ok(resp
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse().ok()))
+}
+
+/// Requests an artifact, returning `None` if it does not exist.
+///
+/// Falls back to the next mirror while the source in use is unreachable.
+async fn fetch_artifact(
+    client: &reqwest::Client,
+    url: &str,
+) -> Result<Option<reqwest::Response>, Error> {
+    ensure_network(url)?;
+    loop {
+        let source = current_source();
+        match fetch_from_source(client, url).await {
+            Err(e) if is_unreachable(&e) && fall_back(&source, &e) => continue,
+            result => return result,
+        }
+    }
+}
+
+/// Requests an artifact from the source in use, returning `None` if it does not exist.
+async fn fetch_from_source(
+    client: &reqwest::Client,
+    url: &str,
+) -> Result<Option<reqwest::Response>, Error> {
+    if let Some(reference) =
+        current_mirror().and_then(|mirror| OciReference::from_release_url(&mirror, url))
+    {
+        return pull(client, &reference).await;
+    }
+    let resp = fetch(client, &mirrored_url(url)).await?;
+    if resp.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if resp.status().is_server_error() {
+        return Err(resp.error_for_status().unwrap_err().into());
+    }
+    Ok(Some(resp))
+}
+
+/// Downloads an artifact, returning `None` if it does not exist.
+///
+/// Falls back to the next mirror while the source in use is unreachable.
+pub(crate) async fn download_artifact(
+    client: &reqwest::Client,
+    url: &str,
+) -> Result<Option<Vec<u8>>, Error> {
+    ensure_network(url)?;
+    loop {
+        let source = current_source();
+        match download_from_source(client, url).await {
+            Err(e) if is_unreachable(&e) && fall_back(&source, &e) => continue,
+            result => return result,
+        }
+    }
+}
+
+/// Downloads an artifact from the source in use, returning `None` if it does not exist.
+///
+/// Large artifacts are downloaded over parallel connections when `--segments` is set. Interrupted
+/// downloads are resumed from a `.partial` file in the staging directory, named after a digest of
+/// the URL so artifacts with the same name from different releases never share it, which also
+/// allows resuming them in a later run.
+async fn download_from_source(
+    client: &reqwest::Client,
+    url: &str,
+) -> Result<Option<Vec<u8>>, Error> {
+    if let Some(reference) =
+        current_mirror().and_then(|mirror| OciReference::from_release_url(&mirror, url))
+    {
+        let Some(resp) = pull(client, &reference).await? else {
+            return Ok(None);
+        };
+        let mut resp = resp.error_for_status()?;
+        let mut bytes = Vec::new();
+        let file_name = url.rsplit('/').next().unwrap_or(url);
+        let mut progress = Progress::new(file_name, resp.content_length(), 0);
+        while let Some(chunk) = read(url, resp.chunk()).await? {
+            throttle(chunk.len()).await;
+            bytes.extend_from_slice(&chunk);
+            progress.advance(chunk.len());
+        }
+        return Ok(Some(bytes));
+    }
+    let file_name = url.rsplit('/').next().unwrap_or(url);
+    match segmented::download(client, url).await {
+        Ok(Some(bytes)) => return Ok(Some(bytes)),
+        Ok(None) => {}
+        Err(e) => warnings::push(
+            WarningKind::Fallback,
+            format!("Segmented download of '{file_name}' failed ({e}), downloading it over a single connection"),
+        ),
+    }
+    let url_digest = sha256(url.as_bytes());
+    let partial = get_staging_dir()?.join(format!(
+        "{}-{file_name}.{PARTIAL_EXTENSION}",
+        &url_digest[..URL_DIGEST_LEN]
+    ));
+    let mut attempt = 1;
+    loop {
+        match resume_download(client, url, &partial).await {
+            Err(e) if attempt < DOWNLOAD_ATTEMPTS => {
+                attempt += 1;
+                warnings::push(
+                    WarningKind::Retried,
+                    format!(
+                        "Download of '{file_name}' was interrupted ({e}), resuming it (attempt {attempt}/{DOWNLOAD_ATTEMPTS})"
+                    ),
+                );
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Downloads an artifact into a partial file, continuing from its last byte if it exists.
+///
+/// The download is only resumed with `If-Range`, so the server sends the whole artifact again
+/// when it changed since the partial file was written, and the partial file is then overwritten.
+async fn resume_download(
+    client: &reqwest::Client,
+    url: &str,
+    partial: &Path,
+) -> Result<Option<Vec<u8>>, Error> {
+    let etag_file = partial.with_extension(ETAG_EXTENSION);
+    let etag = read_to_string(&etag_file).ok();
+    let offset = metadata(partial).map(|m| m.len()).unwrap_or(0);
+    let source_url = mirrored_url(url);
+    let mut request = authorize(client.get(&source_url), &source_url);
+    // Without the ETag of the partial file, there is no way to tell if the artifact changed
+    if let Some(etag) = etag.filter(|_| offset > 0) {
+        debug!(
+            "{} Resuming download of '{}' from byte {}",
+            emoji::DEBUG,
+            source_url,
+            offset
+        );
+        request = request
+            .header(header::RANGE, format!("bytes={offset}-"))
+            .header(header::IF_RANGE, etag);
+    }
+    let request = request.build()?;
+    trace_request(request.method(), &source_url, request.headers());
+    let start = Instant::now();
+    let resp = read(&source_url, client.execute(request)).await?;
+    trace_response(&source_url, resp.status(), resp.headers(), start.elapsed());
+    match resp.status() {
+        StatusCode::NOT_FOUND => return Ok(None),
+        // The partial file is stale, start over on the next attempt
+        StatusCode::RANGE_NOT_SATISFIABLE => remove_file(partial)?,
+        _ => {}
+    }
+    let mut resp = resp.error_for_status()?;
+    // Any other successful response carries the whole artifact, which starts over
+    let resumed = resp.status() == StatusCode::PARTIAL_CONTENT;
+    let mut file = if resumed {
+        OpenOptions::new().append(true).open(partial)?
+    } else {
+        match resp
+            .headers()
+            .get(header::ETAG)
+            .and_then(|etag| etag.to_str().ok())
+        {
+            Some(etag) => write(&etag_file, etag)?,
+            None => {
+                let _ = remove_file(&etag_file);
+            }
+        }
+        File::create(partial)?
+    };
+    let received = if resumed { offset } else { 0 };
+    let file_name = url.rsplit('/').next().unwrap_or(url);
+    let mut progress = Progress::new(
+        file_name,
+        resp.content_length().map(|length| received + length),
+        received,
+    );
+    while let Some(chunk) = read(&source_url, resp.chunk()).await? {
+        throttle(chunk.len()).await;
+        file.write_all(&chunk)?;
+        progress.advance(chunk.len());
+    }
+    let bytes = std::fs::read(partial)?;
+    remove_file(partial)?;
+    let _ = remove_file(&etag_file);
+    Ok(Some(bytes))
+}
+
+/// Returns true if archives can be extracted while they are downloaded.
+///
+/// Offline and frozen installations do not download anything and segmented downloads fetch the ranges out of order.
+fn can_stream() -> bool {
+    !is_offline() && !is_frozen() && !segmented::is_enabled()
+}
+
+/// Downloads an archive and extracts it while it is received, verifying it against its published
+/// SHA256 digest. Returns false if the artifact does not exist.
+async fn stream_artifact(
+    client: &reqwest::Client,
+    url: &str,
+    compression: Compression,
+    output_directory: &str,
+    checksum: &Checksum,
+    use_cache: bool,
+) -> Result<bool, Error> {
+    let expected = expected_checksum(checksum, url).await?;
+    let Some(resp) = fetch_artifact(client, url).await? else {
+        return Ok(false);
+    };
+    let resp = resp.error_for_status()?;
+    let cache_dir = get_cache_dir();
+    let mut cached = if use_cache {
+        Some(cache_file(&cache_dir)?)
+    } else {
+        None
+    };
+    stream::extract(
+        resp,
+        url,
+        compression,
+        Path::new(output_directory),
+        Some(&expected),
+        cached.as_mut().map(|file| file as &mut (dyn Write + Send)),
+    )
+    .await?;
+    record_verified(url, &expected);
+    if let Some(file) = cached {
+        if let Err(e) = persist_cached(file, &cache_dir, url) {
+            debug!("{} Failed to cache '{}': {}", emoji::DEBUG, url, e);
+        }
+    }
+    Ok(true)
+}
+
+/// Returns the expected SHA256 digest of an artifact, failing when it is not published.
+async fn expected_checksum(checksum: &Checksum, url: &str) -> Result<String, Error> {
+    let artifact = url.rsplit('/').next().unwrap_or(url);
+    match checksum {
+        Checksum::Digest(digest) => Ok(digest.to_lowercase()),
+        Checksum::Published(checksums_url) => published_digest(url, checksums_url)
+            .await?
+            .ok_or_else(|| Error::MissingChecksum(artifact.to_string())),
+    }
+}
+
+/// Verifies the downloaded bytes of an artifact against its expected SHA256 digest.
+pub(crate) async fn verify_checksum(
+    checksum: &Checksum,
+    url: &str,
+    bytes: &[u8],
+) -> Result<(), Error> {
+    let artifact = url.rsplit('/').next().unwrap_or(url);
+    let expected = expected_checksum(checksum, url).await?;
+    let actual = sha256(bytes);
+    if actual != expected {
+        return Err(Error::ChecksumMismatch(
+            artifact.to_string(),
+            expected,
+            actual,
+        ));
+    }
+    debug!("{} Verified checksum of '{}'", emoji::DEBUG, artifact);
+    record_verified(url, &actual);
+    Ok(())
+}
+
+/// Returns the variant of an artifact in the preferred archive format of the host, e.g. `tar.zst`
+/// instead of `tar.xz`, if it is listed in the published checksums, or the artifact itself.
+pub(crate) async fn preferred_variant(
+    url: &str,
+    platform: &dyn Platform,
+    checksums_url: &str,
+) -> String {
+    let variant = platform
+        .preferred_archive_extension()
+        .and_then(|preferred| {
+            url.strip_suffix(platform.archive_extension())
+                .map(|base| format!("{base}{preferred}"))
+        });
+    // Only the artifacts already resolved are available without network access
+    let Some(variant) = variant.filter(|_| !is_offline() && !is_frozen()) else {
+        return url.to_string();
+    };
+    let artifact = variant.rsplit('/').next().unwrap_or(&variant);
+    let published = match client() {
+        Ok(client) => fetch_text(&client, checksums_url).await,
+        Err(e) => Err(e),
+    };
+    match published {
+        Ok(Some(checksums)) if parse_checksums(&checksums, artifact).is_some() => {
+            debug!("{} Using '{}' instead of '{}'", emoji::DEBUG, variant, url);
+            variant
+        }
+        _ => url.to_string(),
+    }
+}
+
+/// Returns the SHA256 digest of an artifact published in its checksums, without downloading the
+/// artifact itself.
+///
+/// When the checksums do not list it, the digest is looked up in the GitHub release of the
+/// artifact, whatever the name of the checksums published with it.
+pub(crate) async fn published_digest(
+    url: &str,
+    checksums_url: &str,
+) -> Result<Option<String>, Error> {
+    let client = client()?;
+    let artifact = url.rsplit('/').next().unwrap_or(url);
+    if let Some(checksums) = fetch_text(&client, checksums_url).await? {
+        if let Some(digest) = find_digest(checksums_url, &checksums, artifact) {
+            return Ok(Some(digest));
+        }
+    }
+    if is_offline() {
+        return Ok(None);
+    }
+    let Some(asset) = ReleaseAsset::from_url(url) else {
+        return Ok(None);
+    };
+    let api_url = asset.release_api_url();
+    let json = match tokio::task::spawn_blocking(move || github_query(&api_url)).await {
+        Ok(Ok(json)) => json,
+        _ => {
+            debug!("{} Failed to list the assets of '{}'", emoji::DEBUG, url);
+            return Ok(None);
+        }
+    };
+    let assets = json["assets"].as_array().cloned().unwrap_or_default();
+    // The digest GitHub computes for every asset, not available for older releases
+    let digest = assets
+        .iter()
+        .find(|asset| asset["name"].as_str() == Some(artifact))
+        .and_then(|asset| asset["digest"].as_str()?.strip_prefix("sha256:"))
+        .map(str::to_string);
+    if digest.is_some() {
+        return Ok(digest);
+    }
+    let sidecar = format!("{artifact}.sha256");
+    for checksums_url in assets
+        .iter()
+        .filter(|asset| {
+            let name = asset["name"].as_str().unwrap_or_default();
+            let lowercase = name.to_lowercase();
+            name == sidecar || lowercase.contains("checksum") || lowercase.contains("sha256sums")
+        })
+        .filter_map(|asset| asset["browser_download_url"].as_str())
+    {
+        if let Some(checksums) = fetch_text(&client, checksums_url).await? {
+            if let Some(digest) = find_digest(checksums_url, &checksums, artifact) {
+                return Ok(Some(digest));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Lists the assets of the release of a missing artifact and returns the URL of the one that
+/// best matches it, in case upstream renamed it.
+async fn find_release_asset(url: &str) -> Result<Option<String>, Error> {
+    let Some(asset) = ReleaseAsset::from_url(url) else {
+        return Ok(None);
+    };
+    info!(
+        "{} Artifact '{}' not found, looking for it in the release assets",
+        emoji::INFO,
+        asset.file_name
+    );
+    let api_url = asset.release_api_url();
+    let json = tokio::task::spawn_blocking(move || github_query(&api_url))
+        .await
+        .map_err(|_| Error::GithubQuery)??;
+    let assets: Vec<(String, String)> = json["assets"]
+        .as_array()
+        .map(|assets| {
+            assets
+                .iter()
+                .filter_map(|asset| {
+                    Some((
+                        asset["name"].as_str()?.to_string(),
+                        asset["browser_download_url"].as_str()?.to_string(),
+                    ))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let names: Vec<String> = assets.iter().map(|(name, _)| name.clone()).collect();
+    let name = best_asset_match(&asset.file_name, &names)?;
+    Ok(assets
+        .iter()
+        .find(|(asset_name, _)| asset_name == name)
+        .map(|(_, url)| url.clone()))
+}
+
 /// Installs or updates the Espressif Rust ecosystem.
 pub async fn install(args: InstallOpts) -> Result<()> {
-    let export_file = get_export_file(args.export_file)?;
-    let mut exports: Vec<String> = Vec::new();
-    let host_triple = get_host_triple(args.default_host)?;
-    let xtensa_rust_version = if let Some(toolchain_version) = &args.toolchain_version {
-        toolchain_version.clone()
+    install_pinned(args, None).await
+}
+
+/// Returns the size of an artifact, announced by its server or read from the artifact directory,
+/// or the given estimate if it is not known.
+async fn artifact_size_or(client: &reqwest::Client, url: &str, estimate: u64) -> u64 {
+    let size = if is_offline() {
+        artifact_size(url)
     } else {
-        XtensaRust::get_latest_version().await?
+        content_length(client, url).await.ok().flatten()
+    };
+    size.unwrap_or_else(|| {
+        debug!(
+            "{} Size of '{}' is unknown, estimating it to {}",
+            emoji::DEBUG,
+            url,
+            format_size(estimate)
+        );
+        estimate
+    })
+}
+
+/// Returns the footprint of the components installed with the given options, from the sizes of
+/// their artifacts.
+async fn install_footprint(
+    args: &InstallOpts,
+    host_triple: &HostTriple,
+    install_path: &Path,
+    xtensa_rust_version: &str,
+) -> Result<Footprint, Error> {
+    let client = client()?;
+    let mut footprint = Footprint::default();
+    if args.targets.iter().any(|target| target.is_xtensa()) {
+        let rust = XtensaRust::new(xtensa_rust_version, host_triple, install_path);
+        footprint.add_archive(
+            "Xtensa Rust",
+            artifact_size_or(&client, &rust.dist_url, XTENSA_RUST_SIZE).await,
+        );
+        let platform = host_triple.platform();
+        if !args.skip_rust_src && platform.ships_rust_src() {
+            footprint.add_archive(
+                "rust-src",
+                artifact_size_or(&client, &rust.src_dist_url, RUST_SRC_SIZE).await,
+            );
+        }
+        // The documentation is part of the Xtensa Rust artifact, only its installation is optional
+        if args.with_docs && platform.ships_rust_src() {
+            footprint.add_optional(RUST_DOCS, RUST_DOCS_SIZE, 0);
+        }
+    }
+    if !args.skip_llvm {
+        let llvm = Llvm::new(install_path, host_triple, false, xtensa_rust_version)?;
+        let llvm_size = artifact_size_or(&client, &llvm.repository_url, LLVM_SIZE).await;
+        footprint.add_archive("LLVM", llvm_size);
+        // The full LLVM artifact replaces the `libclang` one
+        if args.extended_llvm {
+            let extended = Llvm::new(install_path, host_triple, true, xtensa_rust_version)?;
+            let extended_size =
+                artifact_size_or(&client, &extended.repository_url, LLVM_EXTENDED_SIZE).await;
+            let extra = extended_size.saturating_sub(llvm_size);
+            footprint.add_optional(LLVM_EXTENDED_TOOLS, installed_size(extra), extra);
+        }
+    }
+    if args.targets.iter().any(|target| target.is_riscv()) {
+        // Installed by rustup, which downloads them into its own directory
+        footprint.add("RISC-V targets", RISCV_TARGETS_SIZE, 0);
+    }
+    if args.installs_gcc() {
+        for gcc in gcc_toolchains(&args.targets, host_triple, install_path) {
+            let size = artifact_size_or(&client, &gcc.dist_url(), GCC_SIZE).await;
+            footprint.add_archive(gcc.name, size);
+        }
+    }
+    Ok(footprint)
+}
+
+/// Installs the exact versions of a previous installation.
+pub async fn rollback(mut args: InstallOpts, record: &InstallRecord) -> Result<()> {
+    args.toolchain_version = record.xtensa_rust_version.clone();
+    args.nightly_version = record.nightly_version.clone();
+    args.skip_gcc = record.gcc_release.is_none();
+    if !record.targets.is_empty() {
+        args.targets = parse_targets(&record.targets.join(","))?;
+    }
+    let pins = Pins {
+        llvm: Some(record.llvm_version.clone()),
+        gcc: record.gcc_release.clone(),
     };
-    let install_path = get_rustup_home().join("toolchains").join(args.name);
+    install_pinned(args, Some(pins)).await
+}
+
+/// Installs the Espressif Rust ecosystem, with pinned versions overriding the configured ones.
+async fn install_pinned(args: InstallOpts, pins: Option<Pins>) -> Result<()> {
+    let shell = ExportShell::from_arg(args.shell.as_deref());
+    let export_file = get_export_file(args.export_file.clone(), &args.name, shell)?;
+    let install_path = get_rustup_home().join("toolchains").join(&args.name);
+    set_persist_environment(!args.export_file_only);
+    if args.rebuild_exports_only {
+        return rebuild_exports(
+            &install_path,
+            &export_file,
+            shell,
+            args.env_file.as_deref(),
+            args.activate_dir.as_deref(),
+        );
+    }
+    set_token(args.github_token.clone());
+    set_frozen(args.frozen);
+    let config = Config::load()?;
+    set_proxy(ProxySettings {
+        url: args.proxy.clone().or_else(|| config.proxy.clone()),
+        credentials: args.proxy_user.clone(),
+    });
+    set_tls(TlsSettings {
+        ca_cert: args.ca_cert.clone(),
+        native_certs: args.native_certs,
+    });
+    let mut exports: Vec<ExportItem> = Vec::new();
+    set_timeouts(TimeoutConfig {
+        connect: args.connect_timeout.or(config.timeouts.connect),
+        read: args.read_timeout.or(config.timeouts.read),
+    });
+    set_mirror(
+        args.mirror
+            .clone()
+            .or_else(|| config.preferred_mirror.clone()),
+    );
+    set_dist_server(args.dist_server.clone());
+    let mut fallback_mirrors = args.fallback_mirrors.clone();
+    fallback_mirrors.extend(config.mirrors.iter().cloned());
+    set_fallback_mirrors(&fallback_mirrors);
+    set_staging_dir(
+        args.temp_dir
+            .clone()
+            .or_else(|| config.cache.staging_dir.clone()),
+    );
+    // A dry run does not store the queried metadata in the cache either
+    set_download_cache(!args.no_cache && !args.dry_run);
+    set_pins(pins.unwrap_or_else(|| config.pins.clone()))?;
+    set_rate_limit(args.limit_rate);
+    segmented::set_segments(args.segments as usize);
+    // The extracted bundle is removed when dropped, at the end of the installation
+    let bundle = match &args.from_bundle {
+        Some(bundle) => Some(extract_bundle(bundle)?),
+        None => None,
+    };
+    let offline = args.offline || bundle.is_some();
+    set_artifact_dir(match &bundle {
+        Some((bundle_dir, _)) => Some(bundle_dir.path().to_path_buf()),
+        None => args.artifact_dir.clone(),
+    });
+    let host_triple = get_host_triple(args.default_host.clone())?;
+    let locked = if args.locked {
+        let lockfile_path = args
+            .lockfile
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_LOCKFILE));
+        Some(Lockfile::load(&lockfile_path)?)
+    } else {
+        None
+    };
+    // Bundles record the digests of their artifacts, which may not publish any
+    set_locked(
+        locked
+            .iter()
+            .flat_map(|locked| &locked.artifacts)
+            .chain(bundle.iter().flat_map(|(_, manifest)| &manifest.artifacts)),
+    );
+    let locked_version = locked
+        .as_ref()
+        .and_then(|locked| locked.toolchain_version.clone());
+    // Offline installations never query GitHub: the Xtensa Rust version is resolved from the
+    // bundle manifest or the artifact directory, and the other versions from the bundled pins
+    let xtensa_rust_version = if offline {
+        match (
+            args.toolchain_version.as_ref().or(locked_version.as_ref()),
+            &bundle,
+        ) {
+            (None, Some((_, bundle_manifest))) => bundle_manifest.toolchain_version.clone(),
+            (requested, _) => find_toolchain_version(&host_triple, requested.map(String::as_str))?,
+        }
+    } else if let Some(toolchain_version) = &args.toolchain_version {
+        XtensaRust::parse_version(toolchain_version)?
+    } else if let Some(version) = locked_version {
+        version
+    } else {
+        match XtensaRust::get_latest_version().await {
+            Ok(version) => version,
+            Err(e) => match UpdatePlan::load(&host_triple).filter(|plan| plan.latest().is_some()) {
+                Some(plan) => {
+                    warnings::push(
+                        WarningKind::Fallback,
+                        format!(
+                            "Failed to query the latest Xtensa Rust version, using the metadata prefetched {} ago",
+                            format_duration(plan.age())
+                        ),
+                    );
+                    plan.versions[0].clone()
+                }
+                None => return Err(e),
+            },
+        }
+    };
+    if let (Some(bundle), Some((_, bundle_manifest))) = (&args.from_bundle, &bundle) {
+        bundle_manifest.check(bundle, &host_triple, &args.targets, &xtensa_rust_version)?;
+    }
+    let prefetching =
+        (args.prefetch && !offline && !args.frozen && !args.dry_run && !args.only_print_urls)
+            .then(|| tokio::spawn(prefetch(host_triple.clone())));
+    let mut extended_llvm = args.extended_llvm;
+    let mut with_docs = args.with_docs;
+    // Install scripts and dry runs do not check the disk space, nor query the artifact sizes for it
+    let resolve_only = args.print_install_script || args.only_print_urls || args.dry_run;
+    let mut footprint = if args.max_install_size.is_some() || !resolve_only {
+        install_footprint(&args, &host_triple, &install_path, &xtensa_rust_version).await?
+    } else {
+        Footprint::default()
+    };
+    if let Some(max_install_size) = args.max_install_size {
+        let trimmed = footprint.fit(max_install_size)?;
+        extended_llvm &= !trimmed.iter().any(|name| name == LLVM_EXTENDED_TOOLS);
+        with_docs &= !trimmed.iter().any(|name| name == RUST_DOCS);
+        info!(
+            "{} Estimated installation size: {} of {}",
+            emoji::INFO,
+            format_size(footprint.total()),
+            format_size(max_install_size)
+        );
+    }
     let llvm: Llvm = Llvm::new(
         &install_path,
         &host_triple,
-        args.extended_llvm,
+        extended_llvm,
         &xtensa_rust_version,
     )?;
-    let targets = args.targets;
+    let targets = args.targets.clone();
     let xtensa_rust = if targets.contains(&Target::ESP32)
         || targets.contains(&Target::ESP32S2)
         || targets.contains(&Target::ESP32S3)
     {
-        Some(XtensaRust::new(
-            &xtensa_rust_version,
-            &host_triple,
-            &install_path,
-        ))
+        Some(XtensaRust {
+            skip_src: args.skip_rust_src,
+            with_docs,
+            ..XtensaRust::new(&xtensa_rust_version, &host_triple, &install_path)
+        })
     } else {
         None
     };
@@ -194,74 +1032,558 @@ pub async fn install(args: InstallOpts) -> Result<()> {
         args.toolchain_version,
     );
 
-    check_rust_installation().await?;
+    let gcc_release = gcc_release();
+    let mut components = Vec::new();
+    if !args.skip_llvm {
+        components.push(("LLVM", llvm.version.as_str()));
+    }
+    if xtensa_rust.is_some() {
+        components.push(("Xtensa Rust", xtensa_rust_version.as_str()));
+    }
+    if args.installs_gcc() {
+        components.push(("GCC", gcc_release.as_str()));
+    }
+    // Install scripts are run on other hosts, which are checked by the script itself
+    if !args.print_install_script && !args.only_print_urls && !args.dry_run {
+        check_host_requirements(&components)?;
+        let staging_dir = get_staging_dir()?;
+        let staging_hint =
+            "Set `--temp-dir`, or `cache.staging_dir` in the configuration file, to stage the artifacts elsewhere.";
+        let install_hint =
+            "Set the `RUSTUP_HOME` environment variable to install the toolchains elsewhere.";
+        check_executable_directories(&[
+            (&staging_dir, staging_hint),
+            (&install_path, install_hint),
+        ])?;
+        // Reused components do not take any space
+        if llvm.path.exists() {
+            footprint.remove("LLVM");
+            footprint.remove(LLVM_EXTENDED_TOOLS);
+        }
+        if let Some(xtensa_rust) = &xtensa_rust {
+            if xtensa_rust.state() == ToolchainState::Installed(Some(xtensa_rust.version.clone())) {
+                footprint.remove("Xtensa Rust");
+                footprint.remove("rust-src");
+                footprint.remove(RUST_DOCS);
+            }
+        }
+        for gcc in gcc_toolchains(&targets, &host_triple, &install_path) {
+            if gcc.path.exists() {
+                footprint.remove(&gcc.name);
+            }
+        }
+        check_disk_space(
+            &[
+                (&staging_dir, footprint.download_size(), staging_hint),
+                (&install_path, footprint.total(), install_hint),
+            ],
+            args.force,
+        )?;
+
+        check_rust_installation().await?;
+    }
+
+    let mut manifest = InstallManifest {
+        host_triple: host_triple.to_string(),
+        llvm_version: llvm.version.clone(),
+        nightly_version: args.nightly_version.clone(),
+        targets: targets.iter().map(|t| t.to_string()).collect(),
+        xtensa_rust_version: xtensa_rust.as_ref().map(|rust| rust.version.clone()),
+        exports: Vec::new(),
+        rustup_artifacts: Default::default(),
+        previous_default: None,
+        overrides: Vec::new(),
+        gcc_release: args.installs_gcc().then(|| gcc_release.clone()),
+        extended_llvm,
+        skip_llvm: args.skip_llvm,
+        skip_rust_src: args.skip_rust_src,
+        history: Vec::new(),
+        cargo_home: Some(get_cargo_home()),
+        rustup_home: Some(get_rustup_home()),
+        export_file_only: args.export_file_only,
+        export_files: Vec::new(),
+        profile_edits: Vec::new(),
+    };
+    manifest.targets.sort();
+    let previous = InstallManifest::load(&install_path).ok();
+    if let Some(previous) = &previous {
+        manifest.record_previous(previous);
+        manifest.keep_written_files(previous);
+    }
+    let partial = previous.as_ref().filter(|_| args.partial_targets);
 
     // Build up a vector of installable applications, all of which implement the
     // `Installable` async trait.
     let mut to_install = Vec::<Box<dyn Installable + Send + Sync>>::new();
+    // Events of the installed components, emitted once all of them are installed
+    let mut installed = Vec::new();
 
     if let Some(ref xtensa_rust) = xtensa_rust {
+        installed.push(Event::Component {
+            name: "Xtensa Rust".to_string(),
+            version: Some(xtensa_rust.version.clone()),
+            path: Some(xtensa_rust.toolchain_destination.clone()),
+        });
         to_install.push(Box::new(xtensa_rust.to_owned()));
     }
 
-    to_install.push(Box::new(llvm));
+    if args.skip_llvm {
+        warnings::push(
+            WarningKind::Skipped,
+            "LLVM installation skipped, bindings won't be able to be generated with bindgen",
+        );
+    } else if partial.is_some_and(|previous| previous.xtensa_rust_version.is_some())
+        && xtensa_rust.is_none()
+    {
+        // LLVM follows the version of the Xtensa Rust toolchain, which is not updated
+        debug!("{} Keeping the installed LLVM", emoji::DEBUG);
+    } else {
+        installed.push(Event::Component {
+            name: "LLVM".to_string(),
+            version: Some(llvm.version.clone()),
+            path: Some(llvm.path.clone()),
+        });
+        to_install.push(Box::new(llvm));
+    }
 
     if targets.iter().any(|t| t.is_riscv()) {
-        let riscv_target = RiscVTarget::new(&args.nightly_version);
+        let riscv_target = RiscVTarget {
+            skip_src: args.skip_rust_src,
+            ..RiscVTarget::new(&args.nightly_version)
+        };
+        // Artifacts added by a previous installation are still owned by espup
+        manifest.rustup_artifacts = match &previous {
+            Some(previous)
+                if previous.rustup_artifacts.nightly_version == args.nightly_version
+                    && !previous.rustup_artifacts.is_empty() =>
+            {
+                previous.rustup_artifacts.clone()
+            }
+            _ if args.print_install_script || args.only_print_urls || args.dry_run => {
+                Default::default()
+            }
+            _ => riscv_target.missing_artifacts()?,
+        };
+        installed.push(Event::Component {
+            name: riscv_target.name(),
+            version: Some(args.nightly_version.clone()),
+            path: None,
+        });
         to_install.push(Box::new(riscv_target));
     }
 
-    if !args.std {
-        targets.iter().for_each(|target| {
-            if target.is_xtensa() {
-                let gcc = Gcc::new(target, &host_triple, &install_path);
-                to_install.push(Box::new(gcc));
-            }
+    if args.installs_gcc() {
+        for gcc in gcc_toolchains(&targets, &host_triple, &install_path) {
+            installed.push(Event::Component {
+                name: gcc.name.clone(),
+                version: Some(gcc_release.clone()),
+                path: Some(gcc.path.clone()),
+            });
+            to_install.push(Box::new(gcc));
+        }
+    } else {
+        warnings::push(
+            WarningKind::Skipped,
+            "GCC installation skipped, no_std applications won't be able to be built",
+        );
+    }
+
+    for extra_crate in &args.extra_crates {
+        let extra_crate = ExtraCrate::new(extra_crate, &host_triple);
+        installed.push(Event::Component {
+            name: extra_crate.name.clone(),
+            version: extra_crate.version.clone(),
+            path: Some(extra_crate.cargo_home.join("bin")),
         });
-        // All RISC-V targets use the same GCC toolchain
-        // ESP32S2 and ESP32S3 also install the RISC-V toolchain for their ULP coprocessor
-        if targets.iter().any(|t| t != &Target::ESP32) {
-            let riscv_gcc = Gcc::new_riscv(&host_triple, &install_path);
-            to_install.push(Box::new(riscv_gcc));
-        }
-    }
-
-    // With a list of applications to install, install them all in parallel.
-    let installable_items = to_install.len();
-    let (tx, mut rx) = mpsc::channel::<Result<Vec<String>, Error>>(installable_items);
-    for app in to_install {
-        let tx = tx.clone();
-        let retry_strategy = FixedInterval::from_millis(50).take(3);
-        tokio::spawn(async move {
-            let res = Retry::spawn(retry_strategy, || async {
-                let res = app.install().await;
-                if res.is_err() {
-                    warn!(
-                        "{} Installation for '{}' failed, retrying",
-                        emoji::WARN,
-                        app.name()
+        to_install.push(Box::new(extra_crate));
+    }
+
+    for plugin in &config.plugins {
+        let plugin = Plugin::new(plugin, &host_triple, &install_path);
+        installed.push(Event::Component {
+            name: plugin.config.name.clone(),
+            version: None,
+            path: Some(plugin.path.clone()),
+        });
+        to_install.push(Box::new(plugin));
+    }
+
+    // With a list of applications to install, sort them by their dependencies and
+    // install every wave of independent applications in parallel.
+    let graph: Vec<(String, Vec<String>)> = to_install
+        .iter()
+        .map(|app| (app.name(), app.dependencies()))
+        .collect();
+    let waves = install_waves(&graph)?;
+    let mut steps = Vec::new();
+    // Lockfiles only record the artifacts of the components replayed by install scripts
+    for app in waves.iter().flatten().map(|&i| &to_install[i]) {
+        if resolve_only || app.has_script() {
+            steps.push(ScriptStep::Comment(app.name()));
+            steps.extend(app.script().await?);
+        }
+    }
+    let mut resolved = Lockfile {
+        toolchain_version: Some(xtensa_rust_version.clone()),
+        llvm_version: Some(manifest.llvm_version.clone()),
+        gcc_release: args.installs_gcc().then(|| gcc_release.clone()),
+        artifacts: locked_artifacts(&steps),
+    };
+    if let Some(locked) = &locked {
+        locked.check(&resolved)?;
+        // The artifacts without a published digest are verified against the locked one
+        if let Some((url, _)) = script_artifacts(&steps)
+            .into_iter()
+            .find(|(url, _)| !locked.artifacts.iter().any(|artifact| &artifact.url == url))
+        {
+            return Err(Error::UnlockedArtifact(url.to_string()).into());
+        }
+    }
+    if resolve_only {
+        if args.only_print_urls {
+            let client = client()?;
+            for (url, sha256) in script_artifacts(&steps) {
+                let size = content_length(&client, url).await?;
+                println!("{}", artifact_line(url, sha256, size));
+            }
+        } else if args.dry_run {
+            info!("{} Dry run, nothing will be installed", emoji::INFO);
+            info!("{} LLVM: {}", emoji::INFO, manifest.llvm_version);
+            if let Some(version) = &manifest.xtensa_rust_version {
+                info!("{} Xtensa Rust: {}", emoji::INFO, version);
+            }
+            if args.installs_gcc() {
+                info!("{} GCC: {}", emoji::INFO, gcc_release);
+            }
+            if targets.iter().any(|t| t.is_riscv()) {
+                info!(
+                    "{} Nightly toolchain: {}",
+                    emoji::INFO,
+                    args.nightly_version
+                );
+            }
+            info!(
+                "{} Toolchain directory: '{}'",
+                emoji::INFO,
+                install_path.display()
+            );
+            info!("{} Export file: '{}'", emoji::INFO, export_file.display());
+            if let Some(env_file) = &args.env_file {
+                info!("{} Environment file: '{}'", emoji::INFO, env_file.display());
+            }
+            if let Some(activate_dir) = &args.activate_dir {
+                info!(
+                    "{} Activation scripts: '{}'",
+                    emoji::INFO,
+                    activate_dir.display()
+                );
+            }
+            if args.set_default {
+                info!("{} Default toolchain: '{}'", emoji::INFO, args.name);
+            }
+            for line in describe_script(&steps, host_triple.platform()) {
+                info!("{} {}", emoji::INFO, line);
+            }
+        } else {
+            print!(
+                "{}",
+                render_script(&steps, host_triple.platform(), &export_file)
+            );
+        }
+        return Ok(());
+    }
+    let jobs = Arc::new(Semaphore::new(args.jobs as usize));
+    let mut to_install: Vec<Option<Box<dyn Installable + Send + Sync>>> =
+        to_install.into_iter().map(Some).collect();
+    // Continue the installation interrupted by a previous run, if any
+    let interrupted = InstallState::load(&install_path).is_some();
+    let mut state = InstallState::resume(&install_path, manifest.record());
+    if !state.completed.is_empty() {
+        info!(
+            "{} Resuming the interrupted installation, {} components already installed",
+            emoji::INFO,
+            state.completed.len()
+        );
+    }
+    create_dir_all(&install_path).map_err(Error::IoError)?;
+    state.save(&install_path)?;
+    for wave in waves {
+        let mut apps = Vec::new();
+        for app in wave.into_iter().filter_map(|i| to_install[i].take()) {
+            if let Some(completed) = state.completed(&app.name()) {
+                info!(
+                    "{} {} already installed, skipping",
+                    emoji::CHECK,
+                    app.name()
+                );
+                exports.extend(completed.iter().cloned());
+                continue;
+            }
+            if let Some(dir) = app.install_dir().filter(|dir| interrupted && dir.exists()) {
+                warn!(
+                    "{} Removing the incomplete installation of {} in '{}'",
+                    emoji::WARN,
+                    app.name(),
+                    dir.display()
+                );
+                remove_dir_all(&dir).map_err(Error::IoError)?;
+            }
+            apps.push(app);
+        }
+        let installable_items = apps.len();
+        let (tx, mut rx) =
+            mpsc::channel::<(String, Result<Vec<ExportItem>, Error>)>(installable_items.max(1));
+        for app in apps {
+            let tx = tx.clone();
+            let jobs = jobs.clone();
+            let retry_strategy = FixedInterval::from_millis(50).take(3);
+            tokio::spawn(async move {
+                let _permit = jobs.acquire_owned().await.unwrap();
+                let attempts = AtomicUsize::new(0);
+                let res = Retry::spawn(retry_strategy, || async {
+                    attempts.fetch_add(1, Ordering::Relaxed);
+                    app.install().await
+                })
+                .await;
+                // Only the installations recovered by a retry are reported, failures are errors
+                let attempts = attempts.into_inner();
+                if res.is_ok() && attempts > 1 {
+                    warnings::push(
+                        WarningKind::Retried,
+                        format!(
+                            "Installation for '{}' failed, succeeded after {attempts} attempts",
+                            app.name()
+                        ),
                     );
                 }
-                res
-            })
-            .await;
-            tx.send(res).await.unwrap();
+                tx.send((app.name(), res)).await.unwrap();
+            });
+        }
+
+        // Read the results of the install tasks as they complete, recording them in the state.
+        for _ in 0..installable_items {
+            let (name, res) = rx.recv().await.unwrap();
+            let names = res?;
+            state.complete(name, names.clone());
+            state.save(&install_path)?;
+            exports.extend(names);
+        }
+    }
+
+    manifest.exports = exports;
+    // A partial update keeps the components of the other installed targets
+    if let Some(previous) = partial {
+        manifest.keep_untouched(previous, &targets);
+    }
+    if !args.no_dedup {
+        deduplicate(&install_path)?;
+    }
+
+    create_export_file(
+        &export_file,
+        &args.name,
+        &manifest.exports,
+        host_triple.platform(),
+        shell,
+    )?;
+    manifest.record_export_file(&export_file);
+    if let Some(activate_dir) = &args.activate_dir {
+        create_activation_scripts(
+            activate_dir,
+            &args.name,
+            &manifest.exports,
+            host_triple.platform(),
+        )?;
+    }
+    installed.into_iter().for_each(emit);
+    for export in &manifest.exports {
+        emit(Event::Export {
+            export: export.clone(),
         });
     }
+    if args.set_default {
+        let current_default = get_default_toolchain()?;
+        // Keep the default recorded by a previous installation if it is already set
+        manifest.previous_default = if current_default.as_deref() == Some(args.name.as_str()) {
+            InstallManifest::load(&install_path)
+                .ok()
+                .and_then(|previous| previous.previous_default)
+        } else {
+            current_default
+        };
+        set_default_toolchain(&args.name)?;
+    }
+    manifest.overrides = InstallManifest::load(&install_path)
+        .map(|previous| previous.overrides)
+        .unwrap_or_default();
+    if args.set_override {
+        let current_dir = env::current_dir().map_err(Error::IoError)?;
+        set_override(&args.name, &current_dir)?;
+        if !manifest.overrides.contains(&current_dir) {
+            manifest.overrides.push(current_dir);
+        }
+    }
+    manifest.save(&install_path)?;
+    InstallState::clear(&install_path)?;
+    // The shims of version managers may run a Rust version that does not defer to rustup
+    if manifest.xtensa_rust_version.is_some() {
+        for shim in find_shims(env::var_os("PATH").as_deref(), &data_dirs()) {
+            if !resolves_toolchain(shim.tool, &args.name) {
+                warnings::push(
+                    WarningKind::ShadowedTool,
+                    format!("{}. {}", shim.unresolved(&args.name), shim.manager.fix()),
+                );
+            }
+        }
+    }
+    if let (Some(project), true) = (&args.project, args.write_toolchain_file) {
+        ProjectToolchain::for_installation(&args.name, &args.nightly_version, &targets)
+            .save(project)?;
+        info!(
+            "{} Toolchain file of '{}' updated",
+            emoji::CHECK,
+            project.display()
+        );
+    }
+    // Lockfiles are only written when requested, never next to an unrelated project
+    if let Some(lockfile_path) = args.lockfile.as_ref().filter(|_| !args.locked) {
+        let mut lockfile = if lockfile_path.exists() {
+            Lockfile::load(lockfile_path)?
+        } else {
+            Lockfile::default()
+        };
+        // The artifacts verified while installing are locked along with the published ones
+        resolved.artifacts = locked_artifacts(&steps);
+        lockfile.update(resolved);
+        lockfile.save(lockfile_path)?;
+        info!(
+            "{} Lockfile '{}' updated",
+            emoji::CHECK,
+            lockfile_path.display()
+        );
+    }
+    if let Some(prefetching) = prefetching {
+        match prefetching.await {
+            Ok(Ok(plan)) => info!(
+                "{} Prefetched the metadata of {} Xtensa Rust releases",
+                emoji::INFO,
+                plan.versions.len()
+            ),
+            Ok(Err(e)) => warn!("{} Failed to prefetch release metadata: {}", emoji::WARN, e),
+            Err(_) => warn!("{} Failed to prefetch release metadata", emoji::WARN),
+        }
+    }
+    if let Some(env_file) = &args.env_file {
+        let lines = append_env_file(env_file, &manifest.exports, host_triple.platform())?;
+        manifest.record_edit(env_file, lines);
+        manifest.save(&install_path)?;
+    }
+    if !args.no_hooks {
+        run_hooks(
+            &config.hooks.post_install,
+            &hook_env(&args.name, &install_path, &export_file, &manifest),
+        )?;
+    }
+    if args.ci {
+        print_ci_summary(&args.name, &install_path, &export_file, &manifest)?;
+    } else {
+        enforce_limits(&get_cache_dir(), &config.cache)?;
+        export_environment(&export_file, host_triple.platform())?;
+    }
+    Ok(())
+}
 
-    // Read the results of the install tasks as they complete.
-    for _ in 0..installable_items {
-        let names = rx.recv().await.unwrap()?;
-        exports.extend(names);
+/// Replaces the identical files shared by the installed GCC and LLVM toolchains with hardlinks.
+fn deduplicate(install_path: &Path) -> Result<(), Error> {
+    let dirs: Vec<PathBuf> = [ESP32_GCC, ESP32S2_GCC, ESP32S3_GCC, RISCV_GCC, CLANG_NAME]
+        .iter()
+        .map(|name| install_path.join(name))
+        .collect();
+    let report = hardlink_duplicates(&dirs)?;
+    if report.linked > 0 {
+        info!(
+            "{} Hardlinked {} duplicated files, saving {} MiB",
+            emoji::WRENCH,
+            report.linked,
+            report.saved / (1024 * 1024)
+        );
     }
+    Ok(())
+}
 
-    create_export_file(&export_file, &exports)?;
-    export_environment(&export_file)?;
+/// Prints a JSON summary of the installation to stdout.
+fn print_ci_summary(
+    name: &str,
+    install_path: &Path,
+    export_file: &Path,
+    manifest: &InstallManifest,
+) -> Result<(), Error> {
+    let summary = serde_json::json!({
+        "name": name,
+        "install_path": install_path,
+        "export_file": export_file,
+        "manifest": manifest,
+        "warnings": warnings::collected(),
+    });
+    println!(
+        "{}",
+        serde_json::to_string(&summary).map_err(|_| Error::SerializeJson)?
+    );
+    Ok(())
+}
+
+/// Regenerates the export file from the manifest of an existing installation.
+fn rebuild_exports(
+    install_path: &Path,
+    export_file: &PathBuf,
+    shell: ExportShell,
+    env_file: Option<&Path>,
+    activate_dir: Option<&Path>,
+) -> Result<()> {
+    info!(
+        "{} Rebuilding exports from the installation in '{}'",
+        emoji::WRENCH,
+        install_path.display()
+    );
+    let mut manifest = InstallManifest::load(install_path)?;
+    let platform = get_host_triple(Some(manifest.host_triple.clone()))?.platform();
+    let name = install_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    create_export_file(export_file, &name, &manifest.exports, platform, shell)?;
+    manifest.record_export_file(export_file);
+    if let Some(activate_dir) = activate_dir {
+        create_activation_scripts(activate_dir, &name, &manifest.exports, platform)?;
+    }
+    if let Some(env_file) = env_file {
+        let lines = append_env_file(env_file, &manifest.exports, platform)?;
+        manifest.record_edit(env_file, lines);
+    }
+    manifest.save(install_path)?;
+    export_environment(export_file, platform)?;
     Ok(())
 }
 
 /// Queries the GitHub API and returns the JSON response.
+///
+/// Falls back to the API endpoints of the next mirror while the source in use is unreachable.
 pub fn github_query(url: &str) -> Result<serde_json::Value, Error> {
+    loop {
+        let source = current_source();
+        match query_source(url) {
+            Err(e) if is_unreachable(&e) && fall_back(&source, &e) => continue,
+            result => return result,
+        }
+    }
+}
+
+/// Queries the GitHub API, or the distribution server in use, and returns the JSON response.
+///
+/// Responses are cached along with their `ETag` and revalidated with `If-None-Match`, so
+/// unchanged responses are not downloaded again and do not count against the rate limit.
+fn query_source(url: &str) -> Result<serde_json::Value, Error> {
+    let url = &api_url(url);
     info!("{} Querying GitHub API: '{}'", emoji::INFO, url);
     let mut headers = header::HeaderMap::new();
     headers.insert(header::USER_AGENT, "espup".parse().unwrap());
@@ -270,31 +1592,114 @@ pub fn github_query(url: &str) -> Result<serde_json::Value, Error> {
         "application/vnd.github+json".parse().unwrap(),
     );
     headers.insert("X-GitHub-Api-Version", "2022-11-28".parse().unwrap());
-    if let Some(token) = env::var_os("GITHUB_TOKEN") {
+    if let Some(token) = get_token() {
         debug!("{} Auth header added.", emoji::DEBUG);
-        headers.insert(
-            "Authorization",
-            format!("Bearer {}", token.to_string_lossy())
-                .parse()
-                .unwrap(),
-        );
+        headers.insert("Authorization", format!("Bearer {token}").parse().unwrap());
     }
-    let client = Client::new();
+    let cache_dir = get_cache_dir();
+    let cached = is_download_cache_enabled()
+        .then(|| read_cached_response(&cache_dir, url))
+        .flatten()
+        .and_then(|cached| {
+            let json = serde_json::from_str::<serde_json::Value>(&cached.body).ok()?;
+            Some((cached.etag, json))
+        });
+    if is_frozen() {
+        return match cached {
+            Some((_, json)) => {
+                debug!("{} Using cached response of '{}'", emoji::DEBUG, url);
+                Ok(json)
+            }
+            None => Err(Error::FrozenNetworkAccess(url.to_string())),
+        };
+    }
+    if let Some((etag, _)) = &cached {
+        if let Ok(etag) = etag.parse() {
+            headers.insert(header::IF_NONE_MATCH, etag);
+        }
+    }
+    let client = blocking_client()?;
     let json = retry(
         Fixed::from_millis(100).take(5),
-        || -> Result<serde_json::Value, Error> {
-            let res = client.get(url).headers(headers.clone()).send()?.text()?;
+        || -> OperationResult<serde_json::Value, Error> {
+            let request = match client.get(url).headers(headers.clone()).build() {
+                Ok(request) => request,
+                Err(e) => return OperationResult::Err(e.into()),
+            };
+            trace_request(request.method(), url, request.headers());
+            let start = Instant::now();
+            let resp = match client.execute(request) {
+                Ok(resp) => resp,
+                Err(e) => return OperationResult::Retry(request_error(url, e)),
+            };
+            trace_response(url, resp.status(), resp.headers(), start.elapsed());
+            if resp.status() == StatusCode::UNAUTHORIZED {
+                return OperationResult::Err(Error::GithubToken);
+            }
+            if let Some((_, json)) = cached.as_ref() {
+                if resp.status() == StatusCode::NOT_MODIFIED {
+                    debug!("{} Using cached response of '{}'", emoji::DEBUG, url);
+                    return OperationResult::Ok(json.clone());
+                }
+            }
+            let rate_limit = RateLimit::from_headers(resp.headers());
+            if let Some(rate_limit) = rate_limit {
+                debug!(
+                    "{} GitHub API rate limit: {} requests remaining",
+                    emoji::DEBUG,
+                    rate_limit.remaining
+                );
+                if rate_limit.is_exhausted() {
+                    if let Some((_, json)) = cached.as_ref() {
+                        warnings::push(
+                            WarningKind::Fallback,
+                            format!("GitHub API rate limit exceeded, using the cached response of '{url}'"),
+                        );
+                        return OperationResult::Ok(json.clone());
+                    }
+                    return OperationResult::Err(Error::GithubRateLimit(
+                        rate_limit.describe_limit(),
+                        rate_limit.describe_reset(),
+                    ));
+                }
+                if rate_limit.is_low() {
+                    warn!(
+                        "{} Only {} GitHub API requests remaining, the limit resets in {}",
+                        emoji::WARN,
+                        rate_limit.remaining,
+                        rate_limit.describe_reset()
+                    );
+                }
+            }
+            let etag = resp
+                .headers()
+                .get(header::ETAG)
+                .and_then(|etag| etag.to_str().ok())
+                .map(str::to_string);
+            let res = match resp.text() {
+                Ok(res) => res,
+                Err(e) => return OperationResult::Retry(request_error(url, e)),
+            };
             if res.contains(
                 "https://docs.github.com/rest/overview/resources-in-the-rest-api#rate-limiting",
             ) {
                 warn!("{} GitHub rate limit exceeded", emoji::WARN);
-                return Err(Error::GithubQuery);
+                return OperationResult::Retry(Error::GithubQuery);
+            }
+            match serde_json::from_str(&res) {
+                Ok(json) => {
+                    if let Some(etag) = etag.filter(|_| is_download_cache_enabled()) {
+                        let response = CachedResponse { etag, body: res };
+                        if let Err(e) = store_cached_response(&cache_dir, url, &response) {
+                            debug!("{} Failed to cache '{}': {}", emoji::DEBUG, url, e);
+                        }
+                    }
+                    OperationResult::Ok(json)
+                }
+                Err(_) => OperationResult::Retry(Error::SerializeJson),
             }
-            let json: serde_json::Value =
-                serde_json::from_str(&res).map_err(|_| Error::SerializeJson)?;
-            Ok(json)
         },
     )
-    .unwrap();
+    .map_err(|e| e.error)?;
     Ok(json)
 }
@@ -0,0 +1,179 @@
+//! Third-party components declared in the configuration file.
+
+use crate::{
+    checksum::Checksum,
+    emoji,
+    env::ExportItem,
+    error::Error,
+    host_triple::HostTriple,
+    toolchain::{download_file, Installable},
+    warnings::{self, WarningKind},
+};
+use async_trait::async_trait;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// Directory, inside the toolchain directory, where plugins are installed.
+pub const PLUGINS_DIR: &str = "plugins";
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct PluginConfig {
+    /// Name of the component.
+    pub name: String,
+    /// URL of the artifact. `{host_triple}` is replaced with the host triple.
+    pub url: String,
+    /// SHA256 digest of the artifact.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// URL of the checksums listing the artifact, used when no digest is declared.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksums_url: Option<String>,
+    /// Directory, relative to the component path, added to the `PATH`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bin_dir: Option<String>,
+    /// Environment variables to export. `{path}` is replaced with the component path.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Names of the components that need to be installed before this one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Plugin {
+    /// Plugin declaration.
+    pub config: PluginConfig,
+    /// Resolved URL of the artifact.
+    pub url: String,
+    /// Plugin installation path.
+    pub path: PathBuf,
+}
+
+impl Plugin {
+    /// Create a new instance from its declaration.
+    pub fn new(config: &PluginConfig, host_triple: &HostTriple, toolchain_path: &Path) -> Self {
+        let url = config
+            .url
+            .replace("{host_triple}", &host_triple.to_string());
+        let path = toolchain_path.join(PLUGINS_DIR).join(&config.name);
+        Self {
+            config: config.clone(),
+            url,
+            path,
+        }
+    }
+
+    /// Returns the exports of the plugin.
+    fn exports(&self) -> Vec<ExportItem> {
+        let path = self.path.display().to_string();
+        let mut exports = Vec::new();
+        if let Some(bin_dir) = &self.config.bin_dir {
+            exports.push(ExportItem::PathAdd(format!("{path}/{bin_dir}")));
+        }
+        for (key, value) in &self.config.env {
+            exports.push(ExportItem::EnvSet {
+                key: key.clone(),
+                value: value.replace("{path}", &path),
+            });
+        }
+        exports
+    }
+}
+
+#[async_trait]
+impl Installable for Plugin {
+    async fn install(&self) -> Result<Vec<ExportItem>, Error> {
+        if self.path.exists() {
+            warnings::push(
+                WarningKind::ReusedInstallation,
+                format!(
+                    "Previous installation of '{}' exists in: '{}'. Reusing this installation.",
+                    self.config.name,
+                    self.path.display()
+                ),
+            );
+        } else {
+            info!("{} Installing '{}'", emoji::WRENCH, self.config.name);
+            let checksum = match (&self.config.sha256, &self.config.checksums_url) {
+                (Some(sha256), _) => Checksum::Digest(sha256.clone()),
+                (None, Some(checksums_url)) => Checksum::Published(checksums_url.clone()),
+                (None, None) => return Err(Error::MissingChecksum(self.config.name.clone())),
+            };
+            let file_name = self
+                .url
+                .rsplit('/')
+                .next()
+                .filter(|name| !name.is_empty())
+                .ok_or_else(|| Error::InvalidPlugin(self.config.name.clone()))?;
+            let uncompress = [".zip", ".tar.gz", ".tar.xz"]
+                .iter()
+                .any(|extension| file_name.ends_with(extension));
+            download_file(
+                self.url.clone(),
+                file_name,
+                &self.path.display().to_string(),
+                uncompress,
+                false,
+                checksum,
+            )
+            .await?;
+        }
+
+        Ok(self.exports())
+    }
+
+    fn name(&self) -> String {
+        self.config.name.clone()
+    }
+
+    fn dependencies(&self) -> Vec<String> {
+        self.config.dependencies.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        env::ExportItem,
+        host_triple::HostTriple,
+        toolchain::plugin::{Plugin, PluginConfig},
+    };
+    use std::path::Path;
+
+    #[test]
+    fn test_plugin_new() {
+        let config = PluginConfig {
+            name: "flasher".to_string(),
+            url: "https://example.com/flasher-{host_triple}.zip".to_string(),
+            sha256: None,
+            checksums_url: Some("https://example.com/checksums.sha256".to_string()),
+            bin_dir: Some("bin".to_string()),
+            env: [("FLASHER_HOME".to_string(), "{path}/home".to_string())].into(),
+            dependencies: vec![],
+        };
+        let plugin = Plugin::new(
+            &config,
+            &HostTriple::X86_64UnknownLinuxGnu,
+            Path::new("/toolchains/esp"),
+        );
+        assert_eq!(
+            plugin.url,
+            "https://example.com/flasher-x86_64-unknown-linux-gnu.zip"
+        );
+        assert_eq!(plugin.path, Path::new("/toolchains/esp/plugins/flasher"));
+        assert_eq!(
+            plugin.exports(),
+            vec![
+                ExportItem::PathAdd("/toolchains/esp/plugins/flasher/bin".to_string()),
+                ExportItem::EnvSet {
+                    key: "FLASHER_HOME".to_string(),
+                    value: "/toolchains/esp/plugins/flasher/home".to_string(),
+                }
+            ]
+        );
+    }
+}
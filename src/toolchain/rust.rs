@@ -1,47 +1,89 @@
 //! Xtensa Rust Toolchain source and installation tools.
 
 use crate::{
+    cache::stage,
+    checksum::Checksum,
     emoji,
+    env::ExportItem,
     error::Error,
+    frozen::{ensure_network, is_frozen},
+    home::home_dir,
     host_triple::HostTriple,
+    locale::{command, decode_output, rustc_versions},
+    manifest::MANIFEST_FILE,
+    platform::Platform,
+    resume::STATE_FILE,
+    script::ScriptStep,
     toolchain::{
         download_file,
         gcc::{ESP32S2_GCC, ESP32S3_GCC, ESP32_GCC, RISCV_GCC},
         github_query,
         llvm::CLANG_NAME,
-        Installable,
+        plugin::PLUGINS_DIR,
+        preferred_variant, published_digest, Installable,
     },
+    warnings::{self, WarningKind},
 };
 use async_trait::async_trait;
-use directories::BaseDirs;
 use log::{debug, info, warn};
 use miette::Result;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::{
     env,
     fmt::Debug,
-    fs::{read_dir, remove_dir_all},
+    fs::{create_dir_all, read_dir, read_to_string, remove_dir_all, remove_file, rename, write},
+    io,
     path::{Path, PathBuf},
-    process::{Command, Stdio},
+    process::Stdio,
+    time::{SystemTime, UNIX_EPOCH},
 };
+use tempfile::TempDir;
 
 /// Xtensa Rust Toolchain repository
 const DEFAULT_XTENSA_RUST_REPOSITORY: &str =
     "https://github.com/esp-rs/rust-build/releases/download";
+/// File storing the version of the installed toolchain, relative to the toolchain directory. It is
+/// removed along with the toolchain, as it is stored in its `lib` directory.
+pub(crate) const VERSION_FILE: &str = "lib/rustlib/xtensa-rust-version";
+/// Directory of the rustup home where the corrupted toolchains are moved before reinstalling them.
+pub const QUARANTINE_DIR: &str = "espup-quarantine";
 /// Xtensa Rust Toolchain API URL
 const XTENSA_RUST_LATEST_API_URL: &str =
     "https://api.github.com/repos/esp-rs/rust-build/releases/latest";
-const XTENSA_RUST_API_URL: &str = "https://api.github.com/repos/esp-rs/rust-build/releases";
+pub const XTENSA_RUST_API_URL: &str = "https://api.github.com/repos/esp-rs/rust-build/releases";
+
+/// Targets installed in the nightly toolchain for RISC-V chips.
+const RISCV_TARGETS: [&str; 2] = [
+    "riscv32imc-unknown-none-elf",
+    "riscv32imac-unknown-none-elf",
+];
+/// Components installed in the nightly toolchain for RISC-V chips.
+const RISCV_COMPONENTS: [&str; 1] = ["rust-src"];
+/// Oldest rustup version installing a toolchain with its targets and components in one command.
+const MIN_RUSTUP_VERSION: (u32, u32, u32) = (1, 20, 0);
 
 /// Xtensa Rust Toolchain version regex.
 pub const RE_EXTENDED_SEMANTIC_VERSION: &str = r"^(?P<major>0|[1-9]\d*)\.(?P<minor>0|[1-9]\d*)\.(?P<patch>0|[1-9]\d*)\.(?P<subpatch>0|[1-9]\d*)?$";
 const RE_SEMANTIC_VERSION: &str =
     r"^(?P<major>0|[1-9]\d*)\.(?P<minor>0|[1-9]\d*)\.(?P<patch>0|[1-9]\d*)?$";
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToolchainState {
+    /// The toolchain directory contains no Xtensa Rust entries.
+    Missing,
+    /// The key binaries are installed, with the version of the toolchain if it can be read.
+    Installed(Option<String>),
+    /// Xtensa Rust entries without some of the key binaries, e.g. after an interrupted install.
+    Corrupted(Vec<PathBuf>),
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct XtensaRust {
     /// Path to the cargo home directory.
     pub cargo_home: PathBuf,
+    /// URL of the checksums published with the Xtensa Rust release.
+    pub checksums_url: String,
     /// Xtensa Rust toolchain file.
     pub dist_file: String,
     /// Xtensa Rust toolchain URL.
@@ -52,16 +94,18 @@ pub struct XtensaRust {
     pub path: PathBuf,
     /// Path to the rustup home directory.
     pub rustup_home: PathBuf,
-    #[cfg(unix)]
+    /// If `true`, the `rust-src` component is not installed.
+    pub skip_src: bool,
     /// Xtensa Src Rust toolchain file.
     pub src_dist_file: String,
-    #[cfg(unix)]
     /// Xtensa Src Rust toolchain URL.
     pub src_dist_url: String,
     /// Xtensa Rust toolchain destination path.
     pub toolchain_destination: PathBuf,
     /// Xtensa Rust Toolchain version.
     pub version: String,
+    /// If `true`, the `rust-docs` component is installed.
+    pub with_docs: bool,
 }
 
 impl XtensaRust {
@@ -78,16 +122,15 @@ impl XtensaRust {
 
     /// Create a new instance.
     pub fn new(toolchain_version: &str, host_triple: &HostTriple, toolchain_path: &Path) -> Self {
-        let artifact_extension = get_artifact_extension(host_triple);
+        let artifact_extension = host_triple.platform().archive_extension();
         let version = toolchain_version.to_string();
         let dist = format!("rust-{version}-{host_triple}");
         let dist_file = format!("{dist}.{artifact_extension}");
         let dist_url = format!("{DEFAULT_XTENSA_RUST_REPOSITORY}/v{version}/{dist_file}");
-        #[cfg(unix)]
+        let checksums_url =
+            format!("{DEFAULT_XTENSA_RUST_REPOSITORY}/v{version}/rust-{version}-checksum.sha256");
         let src_dist = format!("rust-src-{version}");
-        #[cfg(unix)]
         let src_dist_file = format!("{src_dist}.{artifact_extension}");
-        #[cfg(unix)]
         let src_dist_url = format!("{DEFAULT_XTENSA_RUST_REPOSITORY}/v{version}/{src_dist_file}");
         let cargo_home = get_cargo_home();
         let rustup_home = get_rustup_home();
@@ -95,17 +138,69 @@ impl XtensaRust {
 
         Self {
             cargo_home,
+            checksums_url,
             dist_file,
             dist_url,
             host_triple: host_triple.to_string(),
             path: toolchain_path.to_path_buf(),
             rustup_home,
-            #[cfg(unix)]
+            skip_src: false,
             src_dist_file,
-            #[cfg(unix)]
             src_dist_url,
             toolchain_destination,
             version,
+            with_docs: false,
+        }
+    }
+
+    /// Returns the capabilities of the host the toolchain is installed for.
+    fn platform(&self) -> Result<&'static dyn Platform, Error> {
+        Ok(self
+            .host_triple
+            .parse::<HostTriple>()
+            .map_err(|_| Error::UnsupportedHostTriple(self.host_triple.clone()))?
+            .platform())
+    }
+
+    /// Returns the suffix of the executables of the toolchain.
+    fn exe_suffix(&self) -> &'static str {
+        // Unsupported hosts are rejected before anything is installed
+        self.platform().map_or("", |platform| platform.exe_suffix())
+    }
+
+    /// Returns the `install.sh` argument leaving out the components that are not installed.
+    fn without_components(&self) -> &'static str {
+        if self.with_docs {
+            "--without=rust-docs-json-preview"
+        } else {
+            "--without=rust-docs-json-preview,rust-docs"
+        }
+    }
+
+    /// Gets the URLs of the release artifacts of a version for the given host.
+    pub fn release_urls(toolchain_version: &str, host_triple: &HostTriple) -> Vec<String> {
+        let artifact_extension = host_triple.platform().archive_extension();
+        let release_url = format!("{DEFAULT_XTENSA_RUST_REPOSITORY}/v{toolchain_version}");
+        let mut urls = vec![format!(
+            "{release_url}/rust-{toolchain_version}-{host_triple}.{artifact_extension}"
+        )];
+        if host_triple.platform().ships_rust_src() {
+            urls.push(format!(
+                "{release_url}/rust-src-{toolchain_version}.{artifact_extension}"
+            ));
+        }
+        urls
+    }
+
+    /// Checks the syntax of a version of the Xtensa toolchain, either `major.minor.patch` or
+    /// `major.minor.patch.subpatch`, without querying the releases.
+    pub fn parse_version_syntax(arg: &str) -> Result<String, Error> {
+        let re_extended = Regex::new(RE_EXTENDED_SEMANTIC_VERSION).unwrap();
+        let re_semver = Regex::new(RE_SEMANTIC_VERSION).unwrap();
+        if re_semver.is_match(arg) || re_extended.is_match(arg) {
+            Ok(arg.to_string())
+        } else {
+            Err(Error::InvalidVersion(arg.to_string()))
         }
     }
 
@@ -158,48 +253,153 @@ impl XtensaRust {
         info!("{} Uninstalling Xtensa Rust toolchain", emoji::WRENCH);
         let dir = read_dir(toolchain_path)?;
         for entry in dir {
-            let subdir_name = entry.unwrap().path().display().to_string();
-            if !subdir_name.contains(RISCV_GCC)
-                && !subdir_name.contains(ESP32_GCC)
-                && !subdir_name.contains(ESP32S2_GCC)
-                && !subdir_name.contains(ESP32S3_GCC)
-                && !subdir_name.contains(CLANG_NAME)
-            {
-                remove_dir_all(Path::new(&subdir_name)).unwrap();
+            let path = entry.unwrap().path();
+            if is_rust_entry(&path) {
+                remove_dir_all(&path).unwrap();
             }
         }
         Ok(())
     }
+
+    /// Creates the directory the toolchain is installed into before replacing the previous one.
+    ///
+    /// It is created next to the toolchain directory, so its entries can be renamed into it.
+    fn staging_dir(&self) -> Result<TempDir, Error> {
+        let parent = self
+            .toolchain_destination
+            .parent()
+            .unwrap_or(&self.toolchain_destination);
+        create_dir_all(parent).map_err(|_| Error::CreateDirectory(parent.display().to_string()))?;
+        Ok(tempfile::Builder::new()
+            .prefix(".xtensa-rust-")
+            .tempdir_in(parent)?)
+    }
+
+    /// Returns the version of the installed toolchain.
+    ///
+    /// The version is read from the version file written when installing it, falling back to
+    /// running the installed `rustc` directly, without going through the rustup proxies, for
+    /// toolchains installed by older espup versions.
+    fn installed_version(&self) -> Option<String> {
+        let rustc = self
+            .toolchain_destination
+            .join("bin")
+            .join(format!("rustc{}", self.exe_suffix()));
+        if !rustc.is_file() {
+            return None;
+        }
+        let version_file = self.toolchain_destination.join(VERSION_FILE);
+        if let Ok(version) = read_to_string(&version_file) {
+            debug!(
+                "{} Installed Xtensa Rust version read from '{}': {}",
+                emoji::DEBUG,
+                version_file.display(),
+                version.trim()
+            );
+            return Some(version.trim().to_string());
+        }
+        let output = command(&rustc)
+            .arg("--version")
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let versions = rustc_versions(&decode_output(&output.stdout));
+        // Xtensa Rust reports its extended version last, e.g. `(1.76.0.1)`
+        versions.last().cloned()
+    }
+
+    /// Returns the key binaries of the toolchain installed in `toolchain`, with the arguments
+    /// that check they can be executed.
+    fn key_binaries(&self, toolchain: &Path) -> [(PathBuf, &'static [&'static str]); 2] {
+        [
+            (
+                toolchain
+                    .join("bin")
+                    .join(format!("rustc{}", self.exe_suffix())),
+                &["--version"],
+            ),
+            (
+                toolchain
+                    .join("lib")
+                    .join("rustlib")
+                    .join(&self.host_triple)
+                    .join("bin")
+                    .join(format!("rust-lld{}", self.exe_suffix())),
+                &["-flavor", "gnu", "--version"],
+            ),
+        ]
+    }
+
+    /// Classifies the toolchain directory as missing, installed or corrupted.
+    pub fn state(&self) -> ToolchainState {
+        let has_entries = read_dir(&self.toolchain_destination)
+            .map(|dir| dir.flatten().any(|entry| is_rust_entry(&entry.path())))
+            .unwrap_or(false);
+        if !has_entries {
+            return ToolchainState::Missing;
+        }
+        let missing: Vec<PathBuf> = self
+            .key_binaries(&self.toolchain_destination)
+            .into_iter()
+            .map(|(binary, _)| binary)
+            .filter(|binary| !binary.is_file())
+            .collect();
+        if missing.is_empty() {
+            ToolchainState::Installed(self.installed_version())
+        } else {
+            ToolchainState::Corrupted(missing)
+        }
+    }
+
+    /// Verifies that the binaries of the toolchain installed in `toolchain` can be executed on
+    /// this host.
+    pub fn validate_binaries(&self, toolchain: &Path) -> Result<(), Error> {
+        info!("{} Validating Xtensa Rust binaries", emoji::WRENCH);
+        for (binary, args) in self.key_binaries(toolchain) {
+            check_binary(&binary, args)?;
+        }
+        Ok(())
+    }
 }
 
 #[async_trait]
 impl Installable for XtensaRust {
-    async fn install(&self) -> Result<Vec<String>, Error> {
-        if self.toolchain_destination.exists() {
-            let toolchain_name = format!(
-                "+{}",
-                self.toolchain_destination
-                    .file_name()
-                    .unwrap()
-                    .to_str()
-                    .unwrap(),
-            );
-            let rustc_version = Command::new("rustc")
-                .args([&toolchain_name, "--version"])
-                .stdout(Stdio::piped())
-                .output()?;
-            let output = String::from_utf8_lossy(&rustc_version.stdout);
-            if rustc_version.status.success() && output.contains(&self.version) {
-                warn!(
-                "{} Previous installation of Xtensa Rust {} exists in: '{}'. Reusing this installation.",
-                emoji::WARN,
-                &self.version,
-                &self.toolchain_destination.display()
-            );
+    async fn install(&self) -> Result<Vec<ExportItem>, Error> {
+        match self.state() {
+            ToolchainState::Installed(Some(version)) if version == self.version => {
+                warnings::push(
+                    WarningKind::ReusedInstallation,
+                    format!(
+                        "Previous installation of Xtensa Rust {} exists in: '{}'. Reusing this installation.",
+                        &self.version,
+                        &self.toolchain_destination.display()
+                    ),
+                );
                 return Ok(vec![]);
-            } else {
-                Self::uninstall(&self.toolchain_destination)?;
             }
+            ToolchainState::Corrupted(missing) => {
+                let backup = quarantine(
+                    &self.toolchain_destination,
+                    &self.rustup_home.join(QUARANTINE_DIR),
+                )?;
+                warnings::push(
+                    WarningKind::Quarantined,
+                    format!(
+                        "The Xtensa Rust toolchain in '{}' is corrupted, missing '{}'. Moved it to '{}' and installing it again.",
+                        self.toolchain_destination.display(),
+                        missing
+                            .iter()
+                            .map(|binary| binary.display().to_string())
+                            .collect::<Vec<_>>()
+                            .join("', '"),
+                        backup.display()
+                    ),
+                );
+            }
+            _ => {}
         }
 
         info!(
@@ -207,20 +407,21 @@ impl Installable for XtensaRust {
             emoji::WRENCH,
             self.version
         );
+        // The previous toolchain is only replaced once the new one is fully installed
+        let staging = self.staging_dir()?;
 
-        #[cfg(unix)]
-        if cfg!(unix) {
-            let temp_rust_dir = tempfile::TempDir::new()
-                .unwrap()
-                .into_path()
-                .display()
-                .to_string();
+        let platform = self.platform()?;
+        if platform.ships_rust_src() {
+            let dist_url = preferred_variant(&self.dist_url, platform, &self.checksums_url).await;
+            let temp_rust = stage()?;
+            let temp_rust_dir = temp_rust.path().display().to_string();
             download_file(
-                self.dist_url.clone(),
-                "rust.tar.xz",
+                dist_url.clone(),
+                &archive_name("rust", &dist_url),
                 &temp_rust_dir,
                 true,
                 false,
+                Checksum::Published(self.checksums_url.clone()),
             )
             .await?;
 
@@ -229,80 +430,143 @@ impl Installable for XtensaRust {
                 emoji::WRENCH
             );
 
-            if !Command::new("/usr/bin/env")
+            if !command("/usr/bin/env")
                 .arg("bash")
                 .arg(format!(
                     "{}/rust-nightly-{}/install.sh",
                     temp_rust_dir, &self.host_triple,
                 ))
-                .arg(format!(
-                    "--destdir={}",
-                    self.toolchain_destination.display()
-                ))
+                .arg(format!("--destdir={}", staging.path().display()))
                 .arg("--prefix=''")
-                .arg("--without=rust-docs-json-preview,rust-docs")
+                .arg(self.without_components())
                 .arg("--disable-ldconfig")
                 .stdout(Stdio::null())
                 .output()?
                 .status
                 .success()
             {
-                Self::uninstall(&self.toolchain_destination)?;
                 return Err(Error::XtensaRust);
             }
 
-            let temp_rust_src_dir = tempfile::TempDir::new()
-                .unwrap()
-                .into_path()
-                .display()
-                .to_string();
-            download_file(
-                self.src_dist_url.clone(),
-                "rust-src.tar.xz",
-                &temp_rust_src_dir,
-                true,
-                false,
-            )
-            .await?;
-            info!(
-                "{} Installing 'rust-src' component for Xtensa Rust toolchain",
-                emoji::WRENCH
-            );
-            if !Command::new("/usr/bin/env")
-                .arg("bash")
-                .arg(format!("{}/rust-src-nightly/install.sh", temp_rust_src_dir))
-                .arg(format!(
-                    "--destdir={}",
-                    self.toolchain_destination.display()
-                ))
-                .arg("--prefix=''")
-                .arg("--disable-ldconfig")
-                .stdout(Stdio::null())
-                .output()?
-                .status
-                .success()
-            {
-                Self::uninstall(&self.toolchain_destination)?;
-                return Err(Error::XtensaRustSrc);
+            if !self.skip_src {
+                let temp_rust_src = stage()?;
+                let temp_rust_src_dir = temp_rust_src.path().display().to_string();
+                let src_dist_url =
+                    preferred_variant(&self.src_dist_url, platform, &self.checksums_url).await;
+                download_file(
+                    src_dist_url.clone(),
+                    &archive_name("rust-src", &src_dist_url),
+                    &temp_rust_src_dir,
+                    true,
+                    false,
+                    Checksum::Published(self.checksums_url.clone()),
+                )
+                .await?;
+                info!(
+                    "{} Installing 'rust-src' component for Xtensa Rust toolchain",
+                    emoji::WRENCH
+                );
+                if !command("/usr/bin/env")
+                    .arg("bash")
+                    .arg(format!("{}/rust-src-nightly/install.sh", temp_rust_src_dir))
+                    .arg(format!("--destdir={}", staging.path().display()))
+                    .arg("--prefix=''")
+                    .arg("--disable-ldconfig")
+                    .stdout(Stdio::null())
+                    .output()?
+                    .status
+                    .success()
+                {
+                    return Err(Error::XtensaRustSrc);
+                }
             }
-        }
-        // Some platfroms like Windows are available in single bundle rust + src, because install
-        // script in dist is not available for the plaform. It's sufficient to extract the toolchain
-        #[cfg(windows)]
-        if cfg!(windows) {
+        } else {
+            // Some platfroms like Windows are available in single bundle rust + src, because
+            // install script in dist is not available for the plaform. It's sufficient to extract
+            // the toolchain
             download_file(
                 self.dist_url.clone(),
-                "rust.zip",
-                &self.toolchain_destination.display().to_string(),
+                &format!("rust.{}", platform.archive_extension()),
+                &staging.path().display().to_string(),
                 true,
                 true,
+                Checksum::Published(self.checksums_url.clone()),
             )
             .await?;
         }
 
+        self.validate_binaries(staging.path())?;
+        write(staging.path().join(VERSION_FILE), &self.version)?;
+        replace_entries(staging.path(), &self.toolchain_destination)?;
+
         Ok(vec![]) // No exports
     }
 
+    fn has_script(&self) -> bool {
+        true
+    }
+
+    async fn script(&self) -> Result<Vec<ScriptStep>, Error> {
+        let mut steps = Vec::new();
+        // The script may be generated for another host, e.g. with `--default-host`
+        let platform = self.platform()?;
+        // Hosts without a separate `rust-src` artifact have no installer either, the single
+        // bundle is extracted into the toolchain directory
+        if !platform.ships_rust_src() {
+            let file = format!("rust.{}", platform.archive_extension());
+            steps.push(ScriptStep::Fetch {
+                url: self.dist_url.clone(),
+                sha256: published_digest(&self.dist_url, &self.checksums_url).await?,
+                file: file.clone(),
+            });
+            steps.push(ScriptStep::Extract {
+                file,
+                destination: self.toolchain_destination.clone(),
+                strip: true,
+            });
+            return Ok(steps);
+        }
+        for (url, component, installer, without) in [
+            (
+                &self.dist_url,
+                "rust",
+                format!("rust-nightly-{}", self.host_triple),
+                Some(self.without_components()),
+            ),
+            (
+                &self.src_dist_url,
+                "rust-src",
+                "rust-src-nightly".to_string(),
+                None,
+            ),
+        ]
+        .into_iter()
+        .filter(|(_, component, _, _)| !self.skip_src || *component != "rust-src")
+        {
+            let file = archive_name(component, url);
+            steps.push(ScriptStep::Fetch {
+                url: url.clone(),
+                sha256: published_digest(url, &self.checksums_url).await?,
+                file: file.clone(),
+            });
+            steps.push(ScriptStep::Extract {
+                file,
+                destination: PathBuf::from(component),
+                strip: false,
+            });
+            let mut args = vec![
+                "bash".to_string(),
+                format!("{component}/{installer}/install.sh"),
+                format!("--destdir={}", self.toolchain_destination.display()),
+                "--prefix=''".to_string(),
+            ];
+            args.extend(without.map(str::to_string));
+            args.push("--disable-ldconfig".to_string());
+            steps.push(ScriptStep::Run(args));
+        }
+        Ok(steps)
+    }
+
     fn name(&self) -> String {
         "Xtensa Rust".to_string()
     }
@@ -312,6 +576,28 @@ impl Installable for XtensaRust {
 pub struct RiscVTarget {
     /// Nightly version.
     pub nightly_version: String,
+    /// If `true`, the `rust-src` component is not installed.
+    pub skip_src: bool,
+}
+
+/// Rustup artifacts added by espup, which are the only ones removed when uninstalling.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct RustupArtifacts {
+    /// Nightly toolchain version the artifacts belong to.
+    pub nightly_version: String,
+    /// If `true`, the whole nightly toolchain was installed by espup.
+    pub toolchain: bool,
+    /// Targets added to an existing nightly toolchain.
+    pub targets: Vec<String>,
+    /// Components added to an existing nightly toolchain.
+    pub components: Vec<String>,
+}
+
+impl RustupArtifacts {
+    /// Returns true if espup did not add any artifact.
+    pub fn is_empty(&self) -> bool {
+        !self.toolchain && self.targets.is_empty() && self.components.is_empty()
+    }
 }
 
 impl RiscVTarget {
@@ -319,27 +605,133 @@ impl RiscVTarget {
     pub fn new(nightly_version: &str) -> Self {
         RiscVTarget {
             nightly_version: nightly_version.to_string(),
+            skip_src: false,
         }
     }
 
-    /// Uninstalls the RISC-V target.
-    pub fn uninstall(nightly_version: &str) -> Result<(), Error> {
+    /// Returns the components installed in the nightly toolchain.
+    fn components(&self) -> &'static [&'static str] {
+        if self.skip_src {
+            &[]
+        } else {
+            &RISCV_COMPONENTS
+        }
+    }
+
+    /// Returns the rustup arguments installing the toolchain with its targets and components.
+    fn install_args(&self) -> Vec<String> {
+        let mut args = vec![
+            "toolchain",
+            "install",
+            &self.nightly_version,
+            "--profile",
+            "minimal",
+        ];
+        for component in self.components() {
+            args.extend(["--component", *component]);
+        }
+        args.push("--target");
+        args.extend(RISCV_TARGETS);
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    /// Returns the rustup arguments installing the toolchain, its targets and its components one
+    /// by one, with the basic commands supported by every rustup version.
+    fn basic_install_args(&self) -> Vec<Vec<String>> {
+        let toolchain = vec![
+            "toolchain".to_string(),
+            "install".to_string(),
+            self.nightly_version.clone(),
+        ];
+        let mut targets = vec![
+            "target".to_string(),
+            "add".to_string(),
+            "--toolchain".to_string(),
+            self.nightly_version.clone(),
+        ];
+        targets.extend(RISCV_TARGETS.iter().map(|target| target.to_string()));
+        let mut components = vec![
+            "component".to_string(),
+            "add".to_string(),
+            "--toolchain".to_string(),
+            self.nightly_version.clone(),
+        ];
+        components.extend(
+            self.components()
+                .iter()
+                .map(|component| component.to_string()),
+        );
+        let mut commands = vec![toolchain, targets];
+        if !self.components().is_empty() {
+            commands.push(components);
+        }
+        commands
+    }
+
+    /// Returns the rustup artifacts that the installation will add.
+    pub fn missing_artifacts(&self) -> Result<RustupArtifacts, Error> {
+        let mut artifacts = RustupArtifacts {
+            nightly_version: self.nightly_version.clone(),
+            ..Default::default()
+        };
+        let toolchains = rustup_output(&["toolchain", "list"])?;
+        if !has_toolchain(&toolchains, &self.nightly_version) {
+            artifacts.toolchain = true;
+            return Ok(artifacts);
+        }
+
+        let targets = rustup_output(&[
+            "target",
+            "list",
+            "--installed",
+            "--toolchain",
+            &self.nightly_version,
+        ])?;
+        artifacts.targets = missing_lines(&targets, &RISCV_TARGETS);
+        let components = rustup_output(&[
+            "component",
+            "list",
+            "--installed",
+            "--toolchain",
+            &self.nightly_version,
+        ])?;
+        artifacts.components = missing_lines(&components, self.components());
+        Ok(artifacts)
+    }
+
+    /// Uninstalls the rustup artifacts added by espup, leaving pre-existing ones intact.
+    pub fn uninstall(artifacts: &RustupArtifacts) -> Result<(), Error> {
+        if artifacts.is_empty() {
+            return Ok(());
+        }
         info!("{} Uninstalling RISC-V target", emoji::WRENCH);
 
-        if !Command::new("rustup")
-            .args([
-                "target",
-                "remove",
-                "--toolchain",
-                nightly_version,
-                "riscv32imc-unknown-none-elf",
-                "riscv32imac-unknown-none-elf",
-            ])
-            .stdout(Stdio::null())
-            .status()?
-            .success()
-        {
-            return Err(Error::UninstallRiscvTarget);
+        let nightly_version = artifacts.nightly_version.as_str();
+        let mut commands: Vec<Vec<&str>> = Vec::new();
+        if artifacts.toolchain {
+            commands.push(vec!["toolchain", "uninstall", nightly_version]);
+        } else {
+            if !artifacts.targets.is_empty() {
+                let mut args = vec!["target", "remove", "--toolchain", nightly_version];
+                args.extend(artifacts.targets.iter().map(String::as_str));
+                commands.push(args);
+            }
+            if !artifacts.components.is_empty() {
+                let mut args = vec!["component", "remove", "--toolchain", nightly_version];
+                args.extend(artifacts.components.iter().map(String::as_str));
+                commands.push(args);
+            }
+        }
+
+        for args in commands {
+            if !command("rustup")
+                .args(&args)
+                .stdout(Stdio::null())
+                .status()?
+                .success()
+            {
+                return Err(Error::UninstallRiscvTarget);
+            }
         }
         Ok(())
     }
@@ -347,79 +739,417 @@ impl RiscVTarget {
 
 #[async_trait]
 impl Installable for RiscVTarget {
-    async fn install(&self) -> Result<Vec<String>, Error> {
+    async fn install(&self) -> Result<Vec<ExportItem>, Error> {
         info!(
             "{} Installing RISC-V targets ('riscv32imc-unknown-none-elf' and 'riscv32imac-unknown-none-elf') for '{}' toolchain",
             emoji::WRENCH,
             &self.nightly_version
         );
 
-        if !Command::new("rustup")
-            .args([
-                "toolchain",
-                "install",
-                &self.nightly_version,
-                "--profile",
-                "minimal",
-                "--component",
-                "rust-src",
-                "--target",
-                "riscv32imc-unknown-none-elf",
-                "riscv32imac-unknown-none-elf",
-            ])
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .status()?
-            .success()
-        {
-            return Err(Error::InstallRiscvTarget(self.nightly_version.clone()));
+        // Installing, or only checking, a channel makes rustup reach its distribution server
+        if is_frozen() {
+            if self.missing_artifacts()?.is_empty() {
+                return Ok(vec![]);
+            }
+            ensure_network(&format!(
+                "rustup toolchain install {}",
+                self.nightly_version
+            ))?;
+        }
+
+        let commands = match rustup_version() {
+            RustupVersion::Full => vec![self.install_args()],
+            RustupVersion::Limited(version) => {
+                warnings::push(
+                    WarningKind::LimitedRustup,
+                    format!(
+                        "'rustup --version' reported '{version}', installing the RISC-V targets with separate rustup commands"
+                    ),
+                );
+                self.basic_install_args()
+            }
+        };
+        for args in commands {
+            if !command("rustup")
+                .args(&args)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()?
+                .success()
+            {
+                return Err(Error::InstallRiscvTarget(self.nightly_version.clone()));
+            }
+        }
+
+        // Some wrappers exit successfully without installing anything
+        let missing = self.missing_artifacts()?;
+        if !missing.is_empty() {
+            let artifacts = if missing.toolchain {
+                self.nightly_version.clone()
+            } else {
+                [missing.targets, missing.components].concat().join(", ")
+            };
+            let instructions = self
+                .basic_install_args()
+                .iter()
+                .map(|args| format!("rustup {}", args.join(" ")))
+                .collect::<Vec<_>>()
+                .join(" && ");
+            return Err(Error::RiscvTargetNotInstalled(
+                self.nightly_version.clone(),
+                artifacts,
+                instructions,
+            ));
         }
 
         Ok(vec![]) // No exports
     }
 
+    fn has_script(&self) -> bool {
+        true
+    }
+
+    async fn script(&self) -> Result<Vec<ScriptStep>, Error> {
+        let mut args = vec!["rustup".to_string()];
+        args.extend(self.install_args());
+        Ok(vec![ScriptStep::Run(args)])
+    }
+
     fn name(&self) -> String {
         "RISC-V Rust target".to_string()
     }
 }
 
-/// Gets the artifact extension based on the host architecture.
-fn get_artifact_extension(host_triple: &HostTriple) -> &str {
-    match host_triple {
-        HostTriple::X86_64PcWindowsMsvc | HostTriple::X86_64PcWindowsGnu => "zip",
-        _ => "tar.xz",
+/// Returns the name of the downloaded archive of a component, keeping the extension of its URL.
+fn archive_name(component: &str, url: &str) -> String {
+    let extension = if url.ends_with(".tar.zst") {
+        "tar.zst"
+    } else {
+        "tar.xz"
+    };
+    format!("{component}.{extension}")
+}
+
+/// Returns true if an entry of the toolchain directory belongs to Xtensa Rust, rather than to the
+/// GCC and LLVM toolchains, plugins, manifest and installation state stored along with it.
+fn is_rust_entry(path: &Path) -> bool {
+    let name = path.display().to_string();
+    !name.contains(RISCV_GCC)
+        && !name.contains(ESP32_GCC)
+        && !name.contains(ESP32S2_GCC)
+        && !name.contains(ESP32S3_GCC)
+        && !name.contains(CLANG_NAME)
+        && !name.contains(MANIFEST_FILE)
+        && !name.ends_with(STATE_FILE)
+        && !name.ends_with(PLUGINS_DIR)
+}
+
+/// Moves the Xtensa Rust entries of a corrupted toolchain into a new directory of
+/// `quarantine_dir`, keeping the GCC and LLVM toolchains installed along with it.
+fn quarantine(toolchain: &Path, quarantine_dir: &Path) -> Result<PathBuf, Error> {
+    let name = toolchain
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let backup = quarantine_dir.join(format!("{name}-{timestamp}"));
+    create_dir_all(&backup).map_err(|_| Error::CreateDirectory(backup.display().to_string()))?;
+    for entry in read_dir(toolchain)? {
+        let path = entry?.path();
+        if is_rust_entry(&path) {
+            rename(&path, backup.join(path.file_name().unwrap()))?;
+        }
+    }
+    Ok(backup)
+}
+
+/// Removes a file or directory.
+fn remove_entry(path: &Path) -> io::Result<()> {
+    if path.is_dir() {
+        remove_dir_all(path)
+    } else {
+        remove_file(path)
     }
 }
 
+/// Replaces the Xtensa Rust entries of the toolchain directory with the staged ones.
+///
+/// Every entry is moved with a rename, the previous ones into a backup directory, which are moved
+/// back if any staged entry can not be moved into place.
+fn replace_entries(staging: &Path, destination: &Path) -> Result<(), Error> {
+    create_dir_all(destination)
+        .map_err(|_| Error::CreateDirectory(destination.display().to_string()))?;
+    let backup = TempDir::new_in(staging)?;
+    let mut backed_up = Vec::new();
+    let mut moved = Vec::new();
+    let result = (|| -> io::Result<()> {
+        for entry in read_dir(destination)? {
+            let path = entry?.path();
+            if is_rust_entry(&path) {
+                let name = path.file_name().unwrap().to_owned();
+                rename(&path, backup.path().join(&name))?;
+                backed_up.push(name);
+            }
+        }
+        for entry in read_dir(staging)? {
+            let path = entry?.path();
+            if path == backup.path() {
+                continue;
+            }
+            let name = path.file_name().unwrap().to_owned();
+            rename(&path, destination.join(&name))?;
+            moved.push(name);
+        }
+        Ok(())
+    })();
+
+    if let Err(e) = result {
+        warn!(
+            "{} Restoring the previous Xtensa Rust toolchain",
+            emoji::WARN
+        );
+        for name in moved {
+            remove_entry(&destination.join(name))?;
+        }
+        for name in backed_up {
+            rename(backup.path().join(&name), destination.join(&name))?;
+        }
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Executes a binary to verify that it is compatible with the host.
+fn check_binary(binary: &Path, args: &[&str]) -> Result<(), Error> {
+    let name = binary.display().to_string();
+    if !binary.exists() {
+        return Err(Error::IncompatibleBinary(
+            name,
+            "file not found".to_string(),
+        ));
+    }
+    match command(binary)
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+    {
+        Ok(output) if output.status.success() => {
+            debug!("{} '{}' executed successfully", emoji::DEBUG, name);
+            Ok(())
+        }
+        Ok(output) => Err(Error::IncompatibleBinary(
+            name,
+            describe_failure(&decode_output(&output.stderr)),
+        )),
+        // The binary exists, so ENOENT means that its dynamic loader is missing
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(Error::IncompatibleBinary(
+            name,
+            "its dynamic loader is missing, only glibc based systems are supported".to_string(),
+        )),
+        // ENOEXEC
+        Err(e) if e.raw_os_error() == Some(8) => Err(Error::IncompatibleBinary(
+            name,
+            "exec format error, the binary was built for a different architecture".to_string(),
+        )),
+        Err(e) => Err(Error::IncompatibleBinary(name, e.to_string())),
+    }
+}
+
+/// Describes why a binary failed to run based on its standard error.
+fn describe_failure(stderr: &str) -> String {
+    let re_glibc = Regex::new(r"GLIBC_(\d+(?:\.\d+)*)' not found").unwrap();
+    let mut versions: Vec<&str> = re_glibc
+        .captures_iter(stderr)
+        .filter_map(|cap| cap.get(1).map(|version| version.as_str()))
+        .collect();
+    versions.sort_by_key(|version| {
+        version
+            .split('.')
+            .map(|n| n.parse::<u32>().unwrap_or(0))
+            .collect::<Vec<u32>>()
+    });
+    if let Some(version) = versions.last() {
+        return format!(
+            "it requires glibc >= {version}, which is newer than the one available on this host"
+        );
+    }
+    stderr
+        .lines()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or("unknown error")
+        .trim()
+        .to_string()
+}
+
+/// Gets the default rustup toolchain, if any.
+pub fn get_default_toolchain() -> Result<Option<String>, Error> {
+    Ok(parse_default_toolchain(&rustup_output(&["default"])?))
+}
+
+/// Parses the output of `rustup default`.
+fn parse_default_toolchain(output: &str) -> Option<String> {
+    let line = output.lines().next()?.trim();
+    if line.is_empty() || line.starts_with("no default") {
+        return None;
+    }
+    line.split_whitespace().next().map(str::to_string)
+}
+
+/// Sets the default rustup toolchain.
+pub fn set_default_toolchain(toolchain: &str) -> Result<(), Error> {
+    info!(
+        "{} Setting '{}' as default toolchain",
+        emoji::WRENCH,
+        toolchain
+    );
+    if !command("rustup")
+        .args(["default", toolchain])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?
+        .success()
+    {
+        return Err(Error::SetDefaultToolchain(toolchain.to_string()));
+    }
+    Ok(())
+}
+
+/// Sets a rustup directory override for the given path.
+pub fn set_override(toolchain: &str, path: &Path) -> Result<(), Error> {
+    info!(
+        "{} Setting '{}' as toolchain override for '{}'",
+        emoji::WRENCH,
+        toolchain,
+        path.display()
+    );
+    if !command("rustup")
+        .args(["override", "set", toolchain, "--path"])
+        .arg(path)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?
+        .success()
+    {
+        return Err(Error::SetOverride(path.display().to_string()));
+    }
+    Ok(())
+}
+
+/// Removes the rustup directory override of the given path.
+pub fn unset_override(path: &Path) -> Result<(), Error> {
+    info!(
+        "{} Removing toolchain override for '{}'",
+        emoji::WRENCH,
+        path.display()
+    );
+    if !command("rustup")
+        .args(["override", "unset", "--path"])
+        .arg(path)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?
+        .success()
+    {
+        return Err(Error::SetOverride(path.display().to_string()));
+    }
+    Ok(())
+}
+
+/// Capabilities of the `rustup` found in the `PATH`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RustupVersion {
+    /// Upstream rustup, recent enough for every command used by espup.
+    Full,
+    /// Old rustup or distribution wrapper, only trusted with the basic commands. Holds the reported
+    /// version.
+    Limited(String),
+}
+
+/// Parses the output of `rustup --version`, e.g. `rustup 1.26.0 (5af9b9484 2023-04-05)`.
+///
+/// Any output not reporting an upstream rustup version is treated as a wrapper.
+pub fn parse_rustup_version(output: &str) -> RustupVersion {
+    let line = output.lines().next().unwrap_or_default().trim();
+    let version = line
+        .strip_prefix("rustup ")
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|version| {
+            let mut numbers = version
+                .split(['.', '-'])
+                .map(|number| number.parse::<u32>().ok());
+            Some((numbers.next()??, numbers.next()??, numbers.next()??))
+        });
+    match version {
+        Some(version) if version >= MIN_RUSTUP_VERSION => RustupVersion::Full,
+        _ => RustupVersion::Limited(line.to_string()),
+    }
+}
+
+/// Returns the capabilities of the `rustup` found in the `PATH`.
+fn rustup_version() -> RustupVersion {
+    let version = rustup_output(&["--version"]).unwrap_or_default();
+    debug!(
+        "{} rustup version: {}",
+        emoji::DEBUG,
+        version.lines().next().unwrap_or_default()
+    );
+    parse_rustup_version(&version)
+}
+
+/// Runs a rustup command and returns its standard output.
+fn rustup_output(args: &[&str]) -> Result<String, Error> {
+    let output = command("rustup")
+        .args(args)
+        .stderr(Stdio::null())
+        .output()?;
+    Ok(decode_output(&output.stdout))
+}
+
+/// Returns true if `rustup toolchain list` output contains the given toolchain.
+fn has_toolchain(toolchains: &str, toolchain: &str) -> bool {
+    toolchains.lines().any(|line| {
+        let name = line.split_whitespace().next().unwrap_or_default();
+        // Toolchain names are suffixed with the host triple, which never starts with a digit
+        name == toolchain
+            || name
+                .strip_prefix(&format!("{toolchain}-"))
+                .and_then(|host| host.chars().next())
+                .is_some_and(|c| !c.is_ascii_digit())
+    })
+}
+
+/// Returns the expected items that are not listed in the output of a rustup command.
+fn missing_lines(output: &str, expected: &[&str]) -> Vec<String> {
+    expected
+        .iter()
+        .filter(|item| !output.lines().any(|line| line.trim().starts_with(*item)))
+        .map(|item| item.to_string())
+        .collect()
+}
+
 /// Gets the default cargo home path.
-fn get_cargo_home() -> PathBuf {
-    PathBuf::from(env::var("CARGO_HOME").unwrap_or_else(|_e| {
-        format!(
-            "{}",
-            BaseDirs::new().unwrap().home_dir().join(".cargo").display()
-        )
-    }))
+pub fn get_cargo_home() -> PathBuf {
+    env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home_dir().unwrap_or_default().join(".cargo"))
 }
 
 /// Gets the default rustup home path.
 pub fn get_rustup_home() -> PathBuf {
-    PathBuf::from(env::var("RUSTUP_HOME").unwrap_or_else(|_e| {
-        format!(
-            "{}",
-            BaseDirs::new()
-                .unwrap()
-                .home_dir()
-                .join(".rustup")
-                .display()
-        )
-    }))
+    env::var_os("RUSTUP_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home_dir().unwrap_or_default().join(".rustup"))
 }
 
 /// Checks if rustup is installed.
 pub async fn check_rust_installation() -> Result<(), Error> {
     info!("{} Checking Rust installation", emoji::WRENCH);
 
-    if let Err(e) = Command::new("rustup")
+    if let Err(e) = command("rustup")
         .arg("--version")
         .stdout(Stdio::piped())
         .output()
@@ -437,10 +1167,18 @@ pub async fn check_rust_installation() -> Result<(), Error> {
 #[cfg(test)]
 mod tests {
     use crate::{
+        host_triple::HostTriple,
         logging::initialize_logger,
-        toolchain::rust::{get_cargo_home, get_rustup_home, XtensaRust},
+        manifest::InstallRecord,
+        resume::{InstallState, STATE_FILE},
+        toolchain::rust::{
+            describe_failure, get_cargo_home, get_rustup_home, has_toolchain, missing_lines,
+            parse_default_toolchain, parse_rustup_version, quarantine, replace_entries,
+            RiscVTarget, RustupVersion, ToolchainState, XtensaRust, RISCV_TARGETS, VERSION_FILE,
+        },
     };
     use directories::BaseDirs;
+    use std::fs;
 
     #[test]
     fn test_xtensa_rust_parse_version() {
@@ -459,6 +1197,212 @@ mod tests {
         assert!(XtensaRust::parse_version("1._.*.1").is_err());
     }
 
+    #[test]
+    fn test_xtensa_rust_parse_version_syntax() {
+        assert_eq!(
+            XtensaRust::parse_version_syntax("1.65.0.1").unwrap(),
+            "1.65.0.1"
+        );
+        assert_eq!(
+            XtensaRust::parse_version_syntax("1.65.0").unwrap(),
+            "1.65.0"
+        );
+        assert!(XtensaRust::parse_version_syntax("a.1.1.1").is_err());
+        assert!(XtensaRust::parse_version_syntax("1.1.1.1.1").is_err());
+        assert!(XtensaRust::parse_version_syntax("1..1.1").is_err());
+    }
+
+    #[test]
+    fn test_installed_version() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let xtensa_rust = XtensaRust::new(
+            "1.76.0.1",
+            &HostTriple::X86_64UnknownLinuxGnu,
+            temp_dir.path(),
+        );
+        assert_eq!(xtensa_rust.installed_version(), None);
+        fs::create_dir_all(temp_dir.path().join("bin")).unwrap();
+        fs::write(temp_dir.path().join("bin").join("rustc"), "").unwrap();
+        fs::create_dir_all(temp_dir.path().join("lib/rustlib")).unwrap();
+        fs::write(temp_dir.path().join(VERSION_FILE), "1.76.0.1\n").unwrap();
+        assert_eq!(
+            xtensa_rust.installed_version(),
+            Some("1.76.0.1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_replace_entries() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let destination = temp_dir.path().join("esp");
+        fs::create_dir_all(destination.join("bin")).unwrap();
+        fs::write(destination.join("bin/rustc"), "previous").unwrap();
+        fs::create_dir_all(destination.join("share")).unwrap();
+        fs::create_dir_all(destination.join("xtensa-esp32-elf/bin")).unwrap();
+        let staging = temp_dir.path().join(".xtensa-rust-staging");
+        fs::create_dir_all(staging.join("bin")).unwrap();
+        fs::write(staging.join("bin/rustc"), "staged").unwrap();
+        fs::create_dir_all(staging.join("lib")).unwrap();
+
+        replace_entries(&staging, &destination).unwrap();
+        assert_eq!(
+            fs::read_to_string(destination.join("bin/rustc")).unwrap(),
+            "staged"
+        );
+        assert!(destination.join("lib").is_dir());
+        // Previous Xtensa Rust entries are removed, the GCC toolchains are kept
+        assert!(!destination.join("share").exists());
+        assert!(destination.join("xtensa-esp32-elf/bin").is_dir());
+        assert_eq!(fs::read_dir(&staging).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_corrupted_toolchain() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let host_triple = HostTriple::X86_64UnknownLinuxGnu;
+        let destination = temp_dir.path().join("esp");
+        let xtensa_rust = XtensaRust::new("1.75.0.0", &host_triple, &destination);
+        assert_eq!(xtensa_rust.state(), ToolchainState::Missing);
+        // GCC toolchains alone are not an Xtensa Rust installation
+        fs::create_dir_all(destination.join("xtensa-esp32-elf/bin")).unwrap();
+        assert_eq!(xtensa_rust.state(), ToolchainState::Missing);
+
+        fs::create_dir_all(destination.join("bin")).unwrap();
+        fs::write(destination.join("bin/rustc"), "").unwrap();
+        let rust_lld = destination.join(format!("lib/rustlib/{host_triple}/bin/rust-lld"));
+        assert_eq!(
+            xtensa_rust.state(),
+            ToolchainState::Corrupted(vec![rust_lld.clone()])
+        );
+
+        let quarantine_dir = temp_dir.path().join("espup-quarantine");
+        let backup = quarantine(&destination, &quarantine_dir).unwrap();
+        assert!(backup.starts_with(&quarantine_dir));
+        assert!(backup.join("bin/rustc").is_file());
+        assert!(destination.join("xtensa-esp32-elf/bin").is_dir());
+        assert_eq!(xtensa_rust.state(), ToolchainState::Missing);
+
+        fs::create_dir_all(destination.join("bin")).unwrap();
+        fs::write(destination.join("bin/rustc"), "").unwrap();
+        fs::create_dir_all(rust_lld.parent().unwrap()).unwrap();
+        fs::write(&rust_lld, "").unwrap();
+        fs::write(destination.join(VERSION_FILE), "1.75.0.0\n").unwrap();
+        assert_eq!(
+            xtensa_rust.state(),
+            ToolchainState::Installed(Some("1.75.0.0".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_fresh_install_state() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let destination = temp_dir.path().join("esp");
+        let xtensa_rust =
+            XtensaRust::new("1.75.0.0", &HostTriple::X86_64UnknownLinuxGnu, &destination);
+        // A fresh installation records its state before installing Xtensa Rust
+        fs::create_dir_all(&destination).unwrap();
+        InstallState::resume(&destination, InstallRecord::default())
+            .save(&destination)
+            .unwrap();
+        assert_eq!(xtensa_rust.state(), ToolchainState::Missing);
+
+        let quarantine_dir = temp_dir.path().join("espup-quarantine");
+        quarantine(&destination, &quarantine_dir).unwrap();
+        assert!(destination.join(STATE_FILE).is_file());
+    }
+
+    #[test]
+    fn test_describe_failure() {
+        let stderr = "rust-lld: /lib/x86_64-linux-gnu/libc.so.6: version `GLIBC_2.29' not found (required by rust-lld)
+rust-lld: /lib/x86_64-linux-gnu/libc.so.6: version `GLIBC_2.32' not found (required by rust-lld)
+rust-lld: /lib/x86_64-linux-gnu/libc.so.6: version `GLIBC_2.9' not found (required by rust-lld)";
+        assert_eq!(
+            describe_failure(stderr),
+            "it requires glibc >= 2.32, which is newer than the one available on this host"
+        );
+        assert_eq!(
+            describe_failure("\nsegmentation fault\n"),
+            "segmentation fault"
+        );
+        assert_eq!(describe_failure(""), "unknown error");
+    }
+
+    #[test]
+    fn test_rustup_artifacts_detection() {
+        let toolchains = "stable-x86_64-unknown-linux-gnu (default)\nnightly-2023-08-01-x86_64-unknown-linux-gnu\n";
+        assert!(has_toolchain(toolchains, "stable"));
+        assert!(has_toolchain(toolchains, "nightly-2023-08-01"));
+        assert!(!has_toolchain(toolchains, "nightly"));
+
+        let targets = "riscv32imc-unknown-none-elf\nx86_64-unknown-linux-gnu\n";
+        assert_eq!(
+            missing_lines(targets, &RISCV_TARGETS),
+            vec!["riscv32imac-unknown-none-elf"]
+        );
+        assert!(missing_lines("rust-src\n", &["rust-src"]).is_empty());
+    }
+
+    #[test]
+    fn test_riscv_install_args() {
+        let riscv_target = RiscVTarget::new("nightly");
+        assert_eq!(
+            riscv_target.install_args().join(" "),
+            "toolchain install nightly --profile minimal --component rust-src --target riscv32imc-unknown-none-elf riscv32imac-unknown-none-elf"
+        );
+        assert_eq!(riscv_target.basic_install_args().len(), 3);
+
+        let riscv_target = RiscVTarget {
+            skip_src: true,
+            ..riscv_target
+        };
+        assert_eq!(
+            riscv_target.install_args().join(" "),
+            "toolchain install nightly --profile minimal --target riscv32imc-unknown-none-elf riscv32imac-unknown-none-elf"
+        );
+        assert_eq!(riscv_target.basic_install_args().len(), 2);
+    }
+
+    #[test]
+    fn test_parse_default_toolchain() {
+        assert_eq!(
+            parse_default_toolchain("stable-x86_64-unknown-linux-gnu (default)\n"),
+            Some("stable-x86_64-unknown-linux-gnu".to_string())
+        );
+        assert_eq!(
+            parse_default_toolchain("esp (default)"),
+            Some("esp".to_string())
+        );
+        assert_eq!(
+            parse_default_toolchain("no default toolchain configured"),
+            None
+        );
+        assert_eq!(parse_default_toolchain(""), None);
+    }
+
+    #[test]
+    fn test_parse_rustup_version() {
+        assert_eq!(
+            parse_rustup_version("rustup 1.26.0 (5af9b9484 2023-04-05)\n"),
+            RustupVersion::Full
+        );
+        assert_eq!(
+            parse_rustup_version("rustup 1.27.0-beta.1 (2024-02-15)"),
+            RustupVersion::Full
+        );
+        assert_eq!(
+            parse_rustup_version("rustup 1.18.3 (435397f48 2019-05-22)"),
+            RustupVersion::Limited("rustup 1.18.3 (435397f48 2019-05-22)".to_string())
+        );
+        assert_eq!(
+            parse_rustup_version("rustup-wrapper: use apt to manage Rust toolchains\n"),
+            RustupVersion::Limited("rustup-wrapper: use apt to manage Rust toolchains".to_string())
+        );
+        assert_eq!(
+            parse_rustup_version(""),
+            RustupVersion::Limited(String::new())
+        );
+    }
+
     #[test]
     fn test_get_cargo_home() {
         // No CARGO_HOME set
@@ -0,0 +1,300 @@
+//! Interactive dashboard of the installed toolchains, their available updates and disk usage,
+//! with one-key actions running the matching subcommands.
+
+use crate::{
+    cache::{entries as cache_entries, get_cache_dir, usage as cache_usage},
+    error::Error,
+    footprint::format_size,
+    list::{installed_toolchains, InstalledToolchain},
+    prune::disk_usage,
+};
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{
+    backend::CrosstermBackend,
+    layout::{Constraint, Direction, Layout},
+    style::{Modifier, Style},
+    widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState},
+    Frame, Terminal,
+};
+use std::{
+    env::current_exe,
+    io::{stdin, stdout, Stdout},
+    path::Path,
+    process::Command,
+};
+
+/// Keys of the actions, shown in the footer of the dashboard.
+const HELP: &str = "↑/↓ select  u update  v verify  g prune  r refresh  q quit";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Updates the selected toolchain.
+    Update,
+    /// Verifies the selected toolchain.
+    Verify,
+    /// Removes the unused versions, temporary files and cached artifacts.
+    Prune,
+}
+
+impl Action {
+    /// Returns the action bound to a key, if any.
+    pub fn from_key(key: char) -> Option<Self> {
+        match key {
+            'u' => Some(Self::Update),
+            'v' => Some(Self::Verify),
+            'g' => Some(Self::Prune),
+            _ => None,
+        }
+    }
+
+    /// Returns the arguments of the espup subcommand running the action on a toolchain.
+    pub fn args(&self, toolchain: &str) -> Vec<String> {
+        let args = match self {
+            Self::Update => vec!["update", "--name", toolchain],
+            Self::Verify => vec!["verify", "--name", toolchain],
+            Self::Prune => vec!["prune"],
+        };
+        args.into_iter().map(str::to_string).collect()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Dashboard {
+    /// Installed toolchains, with their disk usage in bytes.
+    pub toolchains: Vec<(InstalledToolchain, u64)>,
+    /// Latest Xtensa Rust version, `None` if it is not known.
+    pub latest: Option<String>,
+    /// Size of the download cache, in bytes.
+    pub cache_size: u64,
+    /// Index of the selected toolchain.
+    pub selected: usize,
+}
+
+impl Dashboard {
+    /// Scans the installed toolchains and the download cache.
+    pub fn load(toolchains_dir: &Path, latest: Option<String>, selected: &str) -> Self {
+        let toolchains: Vec<(InstalledToolchain, u64)> = installed_toolchains(toolchains_dir)
+            .into_iter()
+            .map(|toolchain| {
+                let size = disk_usage(&toolchain.path);
+                (toolchain, size)
+            })
+            .collect();
+        let selected = toolchains
+            .iter()
+            .position(|(toolchain, _)| toolchain.name == selected)
+            .unwrap_or(0);
+        Self {
+            toolchains,
+            latest,
+            cache_size: cache_entries(&get_cache_dir())
+                .map(|entries| cache_usage(&entries))
+                .unwrap_or(0),
+            selected,
+        }
+    }
+
+    /// Returns the name of the selected toolchain, if any.
+    pub fn selected_name(&self) -> Option<&str> {
+        self.toolchains
+            .get(self.selected)
+            .map(|(toolchain, _)| toolchain.name.as_str())
+    }
+
+    /// Moves the selection by an offset, staying within the toolchains.
+    pub fn select(&mut self, offset: isize) {
+        let last = self.toolchains.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(offset).min(last);
+    }
+
+    /// Describes the update available for a toolchain.
+    pub fn update_status(&self, toolchain: &InstalledToolchain) -> String {
+        match (&toolchain.xtensa_rust_version, &self.latest) {
+            (None, _) => "-".to_string(),
+            (Some(_), None) => "unknown".to_string(),
+            (Some(installed), Some(latest)) if installed == latest => "up to date".to_string(),
+            (Some(_), Some(latest)) => format!("{latest} available"),
+        }
+    }
+
+    /// Draws the dashboard.
+    fn render(&self, frame: &mut Frame) {
+        let areas = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(3),
+                Constraint::Length(3),
+                Constraint::Length(1),
+            ])
+            .split(frame.size());
+
+        let rows: Vec<Row> = self
+            .toolchains
+            .iter()
+            .map(|(toolchain, size)| {
+                let gcc: Vec<&str> = toolchain
+                    .gcc
+                    .iter()
+                    .map(|(_, release)| release.as_str())
+                    .collect();
+                Row::new(vec![
+                    Cell::from(toolchain.name.clone()),
+                    Cell::from(toolchain.xtensa_rust_version.clone().unwrap_or_default()),
+                    Cell::from(toolchain.llvm_versions.join(", ")),
+                    Cell::from(gcc.join(", ")),
+                    Cell::from(self.update_status(toolchain)),
+                    Cell::from(format_size(*size)),
+                ])
+            })
+            .collect();
+        let widths = [
+            Constraint::Percentage(12),
+            Constraint::Percentage(14),
+            Constraint::Percentage(24),
+            Constraint::Percentage(22),
+            Constraint::Percentage(16),
+            Constraint::Percentage(12),
+        ];
+        let table = Table::new(rows)
+            .header(
+                Row::new(vec![
+                    "Toolchain",
+                    "Xtensa Rust",
+                    "LLVM",
+                    "GCC",
+                    "Update",
+                    "Size",
+                ])
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+            )
+            .widths(&widths)
+            .block(Block::default().borders(Borders::ALL).title(" espup "))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        let mut state = TableState::default();
+        state.select((!self.toolchains.is_empty()).then_some(self.selected));
+        frame.render_stateful_widget(table, areas[0], &mut state);
+
+        let installed: u64 = self.toolchains.iter().map(|(_, size)| size).sum();
+        let usage = Paragraph::new(format!(
+            "Toolchains: {}    Download cache: {}",
+            format_size(installed),
+            format_size(self.cache_size)
+        ))
+        .block(Block::default().borders(Borders::ALL).title(" Disk usage "));
+        frame.render_widget(usage, areas[1]);
+        frame.render_widget(Paragraph::new(HELP), areas[2]);
+    }
+}
+
+/// Switches the terminal to the dashboard screen.
+fn enter() -> Result<Terminal<CrosstermBackend<Stdout>>, Error> {
+    enable_raw_mode()?;
+    execute!(stdout(), EnterAlternateScreen)?;
+    Ok(Terminal::new(CrosstermBackend::new(stdout()))?)
+}
+
+/// Restores the terminal.
+fn leave() -> Result<(), Error> {
+    disable_raw_mode()?;
+    execute!(stdout(), LeaveAlternateScreen)?;
+    Ok(())
+}
+
+/// Runs an action in the restored terminal, so its logs and prompts are shown as usual.
+fn run_action(action: Action, toolchain: &str) -> Result<(), Error> {
+    leave()?;
+    let status = Command::new(current_exe()?)
+        .args(action.args(toolchain))
+        .status()?;
+    println!("\nespup exited with {status}, press Enter to return to the dashboard");
+    stdin().read_line(&mut String::new())?;
+    Ok(())
+}
+
+/// Shows the dashboard until it is quit, reloading it after every action.
+pub fn run(toolchains_dir: &Path, latest: Option<String>, selected: &str) -> Result<(), Error> {
+    let mut dashboard = Dashboard::load(toolchains_dir, latest.clone(), selected);
+    let mut terminal = enter()?;
+    let result = loop {
+        if let Err(e) = terminal.draw(|frame| dashboard.render(frame)) {
+            break Err(e.into());
+        }
+        let key = match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => key.code,
+            Ok(_) => continue,
+            Err(e) => break Err(e.into()),
+        };
+        let action = match key {
+            KeyCode::Char('q') | KeyCode::Esc => break Ok(()),
+            KeyCode::Up | KeyCode::Char('k') => {
+                dashboard.select(-1);
+                continue;
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                dashboard.select(1);
+                continue;
+            }
+            KeyCode::Char('r') => None,
+            KeyCode::Char(key) => match Action::from_key(key) {
+                Some(action) => Some(action),
+                None => continue,
+            },
+            _ => continue,
+        };
+        let selected = dashboard.selected_name().unwrap_or(selected).to_string();
+        if let Some(action) = action {
+            run_action(action, &selected)?;
+            terminal = enter()?;
+            terminal.clear()?;
+        }
+        dashboard = Dashboard::load(toolchains_dir, latest.clone(), &selected);
+    };
+    leave()?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        list::InstalledToolchain,
+        tui::{Action, Dashboard},
+    };
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_dashboard() {
+        assert_eq!(Action::from_key('g'), Some(Action::Prune));
+        assert_eq!(Action::from_key('x'), None);
+        assert_eq!(Action::Update.args("esp"), vec!["update", "--name", "esp"]);
+
+        let toolchain = |name: &str, version: Option<&str>| InstalledToolchain {
+            name: name.to_string(),
+            path: PathBuf::from(name),
+            xtensa_rust_version: version.map(str::to_string),
+            llvm_versions: Vec::new(),
+            gcc: Vec::new(),
+            has_manifest: true,
+        };
+        let mut dashboard = Dashboard {
+            toolchains: vec![
+                (toolchain("esp", Some("1.76.0.1")), 0),
+                (toolchain("riscv", None), 0),
+            ],
+            latest: Some("1.77.0.0".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            dashboard.update_status(&dashboard.toolchains[0].0),
+            "1.77.0.0 available"
+        );
+        assert_eq!(dashboard.update_status(&dashboard.toolchains[1].0), "-");
+        dashboard.select(5);
+        assert_eq!(dashboard.selected_name(), Some("riscv"));
+        dashboard.select(-5);
+        assert_eq!(dashboard.selected_name(), Some("esp"));
+    }
+}
@@ -0,0 +1,210 @@
+//! Validation of the GCC toolchains installed for each chip.
+//!
+//! Missing multilib variants, runtime libraries or linker scripts are the symptom of a wrong GCC
+//! artifact, and otherwise only show up as confusing link errors in the first build.
+
+use crate::{
+    emoji,
+    host_triple::HostTriple,
+    locale::{command, decode_output},
+    targets::Target,
+    toolchain::gcc::Gcc,
+};
+use log::debug;
+use std::{
+    fs::read_dir,
+    path::{Path, PathBuf},
+};
+
+/// Runtime libraries every multilib variant has to provide.
+const RUNTIME_LIBRARIES: [&str; 2] = ["libc.a", "libm.a"];
+/// Directory, relative to the target directory of a GCC toolchain, containing the linker scripts.
+const LINKER_SCRIPTS_DIR: &str = "lib/ldscripts";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Multilib {
+    /// Directory of the variant, relative to the library directory, `.` for the default one.
+    pub dir: String,
+    /// Compiler flags selecting the variant.
+    pub flags: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChipReport {
+    /// Verified chip.
+    pub target: Target,
+    /// Missing pieces, empty if the installation supports the chip.
+    pub missing: Vec<String>,
+}
+
+/// Parses the output of `gcc -print-multi-lib`, formatted as `dir;@flag@flag` lines.
+fn parse_multilibs(output: &str) -> Vec<Multilib> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (dir, flags) = line.trim().split_once(';')?;
+            Some(Multilib {
+                dir: dir.to_string(),
+                flags: flags
+                    .split('@')
+                    .filter(|flag| !flag.is_empty())
+                    .map(|flag| format!("-{flag}"))
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+/// Returns true if a multilib directory provides the variant, e.g. `rv32imc` is provided by both
+/// `rv32imc/ilp32` and `rv32imc_zicsr_zifencei/ilp32`.
+fn provides(dir: &str, variant: &str) -> bool {
+    dir.strip_prefix(variant)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(['/', '_']))
+}
+
+/// Returns the GCC toolchains used by a chip, along with the multilib variants it requires.
+fn required_toolchains(
+    target: &Target,
+    host_triple: &HostTriple,
+    install_path: &Path,
+) -> Vec<(Gcc, &'static [&'static str])> {
+    let xtensa =
+        |variants: &'static [&'static str]| (Gcc::new(target, host_triple, install_path), variants);
+    let riscv =
+        |variants: &'static [&'static str]| (Gcc::new_riscv(host_triple, install_path), variants);
+    match target {
+        Target::ESP32 => vec![xtensa(&["esp32-psram"])],
+        // The ULP coprocessor of ESP32S2 and ESP32S3 is a RISC-V core
+        Target::ESP32S2 | Target::ESP32S3 => vec![xtensa(&[]), riscv(&["rv32imc"])],
+        Target::ESP32C2 | Target::ESP32C3 => vec![riscv(&["rv32imc"])],
+        Target::ESP32C6 | Target::ESP32H2 => vec![riscv(&["rv32imac"])],
+    }
+}
+
+/// Runs the compiler with the given arguments, returning its trimmed output.
+fn run_gcc(gcc: &Path, args: &[String]) -> Option<String> {
+    let output = command(gcc).args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| decode_output(&output.stdout).trim().to_string())
+}
+
+/// Checks a GCC toolchain, returning its missing pieces.
+fn check_toolchain(gcc: &Gcc, variants: &[&str]) -> Vec<String> {
+    let root = gcc.path.join(&gcc.name);
+    let compiler = root.join("bin").join(format!(
+        "{}-gcc{}",
+        gcc.name,
+        gcc.host_triple.platform().exe_suffix()
+    ));
+    debug!("{} Checking '{}'", emoji::DEBUG, compiler.display());
+    let Some(output) = run_gcc(&compiler, &["-print-multi-lib".to_string()]) else {
+        return vec![format!("{} compiler", gcc.name)];
+    };
+
+    let mut missing = Vec::new();
+    let multilibs = parse_multilibs(&output);
+    let mut checked: Vec<&Multilib> = multilibs.iter().filter(|m| m.dir == ".").collect();
+    for variant in variants {
+        match multilibs.iter().find(|m| provides(&m.dir, variant)) {
+            Some(multilib) => checked.push(multilib),
+            None => missing.push(format!("{} multilib '{}'", gcc.name, variant)),
+        }
+    }
+    for multilib in checked {
+        let queries = RUNTIME_LIBRARIES
+            .iter()
+            .map(|library| (format!("-print-file-name={library}"), *library))
+            .chain([("-print-libgcc-file-name".to_string(), "libgcc.a")]);
+        for (query, file) in queries {
+            let mut args = multilib.flags.clone();
+            args.push(query);
+            // Files that are not found are printed back as their bare name
+            let found = run_gcc(&compiler, &args)
+                .map(PathBuf::from)
+                .is_some_and(|path| path.is_absolute() && path.is_file());
+            if !found {
+                missing.push(format!("{} {}/{}", gcc.name, multilib.dir, file));
+            }
+        }
+    }
+
+    let linker_scripts = root.join(&gcc.name).join(LINKER_SCRIPTS_DIR);
+    let has_linker_scripts = read_dir(&linker_scripts)
+        .map(|entries| entries.filter_map(Result::ok).next().is_some())
+        .unwrap_or(false);
+    if !has_linker_scripts {
+        missing.push(format!("{} linker scripts", gcc.name));
+    }
+    missing
+}
+
+/// Verifies that the GCC toolchains installed for a chip provide the multilib variants, runtime
+/// libraries and linker scripts it requires.
+pub fn verify_target(target: &Target, host_triple: &HostTriple, install_path: &Path) -> ChipReport {
+    let mut missing = Vec::new();
+    for (gcc, variants) in required_toolchains(target, host_triple, install_path) {
+        // The RISC-V toolchain is shared, report each missing piece once per chip
+        for piece in check_toolchain(&gcc, variants) {
+            if !missing.contains(&piece) {
+                missing.push(piece);
+            }
+        }
+    }
+    ChipReport {
+        target: *target,
+        missing,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        host_triple::HostTriple,
+        targets::Target,
+        verify::{parse_multilibs, provides, verify_target, Multilib},
+    };
+
+    #[test]
+    fn test_verify_target() {
+        let multilibs = parse_multilibs(
+            ".;\nrv32imc_zicsr_zifencei/ilp32;@march=rv32imc_zicsr_zifencei@mabi=ilp32\n",
+        );
+        assert_eq!(
+            multilibs,
+            vec![
+                Multilib {
+                    dir: ".".to_string(),
+                    flags: vec![],
+                },
+                Multilib {
+                    dir: "rv32imc_zicsr_zifencei/ilp32".to_string(),
+                    flags: vec![
+                        "-march=rv32imc_zicsr_zifencei".to_string(),
+                        "-mabi=ilp32".to_string()
+                    ],
+                },
+            ]
+        );
+        assert!(provides("rv32imc_zicsr_zifencei/ilp32", "rv32imc"));
+        assert!(provides("rv32imc/ilp32", "rv32imc"));
+        assert!(!provides("rv32imac/ilp32", "rv32imc"));
+        assert!(!provides("rv32imc/ilp32", "rv32imac"));
+        assert!(provides("esp32-psram", "esp32-psram"));
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let report = verify_target(
+            &Target::ESP32S3,
+            &HostTriple::X86_64UnknownLinuxGnu,
+            temp_dir.path(),
+        );
+        assert_eq!(
+            report.missing,
+            vec![
+                "xtensa-esp32s3-elf compiler".to_string(),
+                "riscv32-esp-elf compiler".to_string()
+            ]
+        );
+    }
+}
@@ -0,0 +1,131 @@
+//! Collection of the non-fatal issues found during an installation.
+
+use crate::{
+    emoji,
+    error::Error,
+    output::{emit, Event},
+};
+use log::warn;
+use serde::Serialize;
+use std::{fs::write, path::Path, sync::Mutex};
+use strum::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum WarningKind {
+    /// A deprecated flag or option was used.
+    Deprecated,
+    /// A fallback source was used to download an artifact.
+    Fallback,
+    /// `rustup` is a distribution wrapper or an old version with limited functionality.
+    LimitedRustup,
+    /// A directory may not have enough space for the installation.
+    LowDiskSpace,
+    /// A corrupted installation was moved aside before installing it again.
+    Quarantined,
+    /// A previous installation was reused instead of being reinstalled.
+    ReusedInstallation,
+    /// An operation failed and was retried.
+    Retried,
+    /// A tool found in the `PATH` shadows an installed one.
+    ShadowedTool,
+    /// A component or check was skipped.
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Warning {
+    /// Kind of warning.
+    pub kind: WarningKind,
+    /// Description of the issue.
+    pub message: String,
+}
+
+static WARNINGS: Mutex<Vec<Warning>> = Mutex::new(Vec::new());
+
+/// Logs a warning and records it for the final summary.
+pub fn push(kind: WarningKind, message: impl Into<String>) {
+    let message = message.into();
+    warn!("{} {}", emoji::WARN, message);
+    emit(Event::Warning {
+        kind,
+        message: message.clone(),
+    });
+    WARNINGS.lock().unwrap().push(Warning { kind, message });
+}
+
+/// Returns the warnings recorded so far.
+pub fn collected() -> Vec<Warning> {
+    WARNINGS.lock().unwrap().clone()
+}
+
+/// Removes every recorded warning.
+pub fn clear() {
+    WARNINGS.lock().unwrap().clear();
+}
+
+/// Prints a summary block with every recorded warning.
+pub fn print_summary() {
+    let warnings = collected();
+    if warnings.is_empty() {
+        return;
+    }
+    warn!(
+        "{} Finished with {} warning(s):",
+        emoji::WARN,
+        warnings.len()
+    );
+    for warning in &warnings {
+        warn!("{}   [{}] {}", emoji::WARN, warning.kind, warning.message);
+    }
+}
+
+/// Writes the recorded warnings as JSON to the given file.
+pub fn write_json(path: &Path) -> Result<(), Error> {
+    write_warnings(path, &collected())
+}
+
+/// Writes warnings as JSON to the given file.
+fn write_warnings(path: &Path, warnings: &[Warning]) -> Result<(), Error> {
+    let json = serde_json::to_string_pretty(warnings).map_err(|_| Error::SerializeJson)?;
+    write(path, json)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::warnings::{collected, push, write_warnings, Warning, WarningKind};
+
+    #[test]
+    fn test_warnings() {
+        // Other tests record warnings concurrently, only the ones of this test are checked
+        let messages = ["test_warnings: GCC skipped", "test_warnings: LLVM reused"];
+        push(WarningKind::Skipped, messages[0]);
+        push(WarningKind::ReusedInstallation, messages[1]);
+        let warnings: Vec<Warning> = collected()
+            .into_iter()
+            .filter(|warning| messages.contains(&warning.message.as_str()))
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![
+                Warning {
+                    kind: WarningKind::Skipped,
+                    message: messages[0].to_string()
+                },
+                Warning {
+                    kind: WarningKind::ReusedInstallation,
+                    message: messages[1].to_string()
+                }
+            ]
+        );
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("warnings.json");
+        write_warnings(&path, &warnings).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(json[1]["kind"], "reused-installation");
+    }
+}
@@ -41,3 +41,74 @@ fn verify_uninstall_help() {
         .assert()
         .success();
 }
+
+#[test]
+fn verify_gha_install_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["gha-install", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn verify_mirror_bench_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["mirror", "bench", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn verify_mirror_push_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["mirror", "push", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn verify_bundle_verify_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["bundle", "verify", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn rebuild_exports_only_fails_without_installation() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .env("RUSTUP_HOME", temp_dir.path())
+        .args(["install", "--rebuild-exports-only", "-f"])
+        .arg(temp_dir.path().join("export-esp.sh"))
+        .assert()
+        .failure();
+}
+
+#[test]
+fn verify_cache_status_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["cache", "status", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn verify_explain() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["explain", "missing_rust", "--language", "en"])
+        .assert()
+        .success();
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["explain", "unknown_code"])
+        .assert()
+        .failure();
+}